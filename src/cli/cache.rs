@@ -0,0 +1,49 @@
+//! `clgn cache dir` and `clgn cache clean`: print the location of, and empty, the cache
+//! of derived artifacts (such as cropped images) that's shared by all skeletons.
+
+use crate::utils::cache::AssetCache;
+use crate::utils::paths::write_path_line;
+use crate::{to_svg::svg_writable::ClgnDecodingError, ClgnDecodingResult};
+use clap::{App, AppSettings, ArgMatches, SubCommand};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub(super) fn get_subcommand() -> App<'static, 'static> {
+	SubCommand::with_name("cache")
+		.about("Manage the cache of derived artifacts, such as cropped images")
+		.setting(AppSettings::SubcommandRequiredElseHelp)
+		.subcommand(SubCommand::with_name("dir").about("Print the location of the cache"))
+		.subcommand(SubCommand::with_name("clean").about("Remove everything in the cache"))
+}
+
+/// The cache in the user's cache folder, or an error if there's no such folder
+fn user_cache() -> ClgnDecodingResult<AssetCache> {
+	AssetCache::in_user_cache_dir().ok_or_else(|| {
+		ClgnDecodingError::IoRead(
+			io::Error::new(
+				io::ErrorKind::NotFound,
+				"could not locate the cache; neither $XDG_CACHE_HOME nor $HOME is set",
+			),
+			PathBuf::from("~/.cache"),
+		)
+	})
+}
+
+pub(super) fn handle_matches(matches: &ArgMatches) -> ClgnDecodingResult<()> {
+	let cache = user_cache()?;
+
+	let stdout = io::stdout();
+	let mut stdout = stdout.lock();
+	let result = match matches.subcommand_name() {
+		Some("clean") => {
+			let n_bytes = cache
+				.clean()
+				.map_err(|e| ClgnDecodingError::IoWrite(e, cache.dir().to_owned()))?;
+			writeln!(stdout, "Removed {} bytes from {:?}", n_bytes, cache.dir())
+		}
+		// safe so long as a subcommand is required, and `dir` is the only other one
+		_ => write_path_line(&mut stdout, cache.dir()),
+	};
+
+	result.map_err(|e| ClgnDecodingError::IoWrite(e, PathBuf::from("<stdout>")))
+}
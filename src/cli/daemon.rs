@@ -0,0 +1,710 @@
+//! `clgn daemon`: a long-running process that renders skeletons on request, so that
+//! callers rendering many SVGs don't pay the cost of starting `clgn` for each one.
+//!
+//! Requests and responses are [JSON-RPC 2.0](https://www.jsonrpc.org/specification)
+//! objects, one per line. By default requests are read from stdin and responses are
+//! written to stdout; on Unix, `--socket <path>` listens on a Unix socket instead, in
+//! which case each connection is served in turn.
+//!
+//! # Methods
+//!
+//! - `render`
+//!   - Params: `{ "skeleton": "path/to/skeleton", "vars": { "accent": "red" },
+//!     "out_file": "path/to/out.svg", "include_svg": true, "include_hash": true }`. All
+//!     but `skeleton` are optional (but see `files` below).
+//!     - `vars`, if given, is an object of variables that override the root's, just
+//!       like those of a `--vars-file` (see [`ManifestPatch::from_vars`]).
+//!     - Instead of `skeleton`, the skeleton's files may be given inline as `files`, a
+//!       map from each file's path (relative to the skeleton's folder) to its contents,
+//!       e.g., `{ "collagen.json": "{ \"children\": [...] }", "logo.png": { "base64":
+//!       "iVBORw0..." } }`. Text files are given as strings, and binary files as objects
+//!       whose `base64` is their base64-encoded contents. The skeleton is then decoded
+//!       without touching disk: files missing from the map are missing, even if they
+//!       exist on disk. (Google fonts are the exception; they're still read from the
+//!       font cache, as always.) Such skeletons aren't cached, since there's no path to
+//!       key them by. Exactly one of `skeleton` and `files` must be given.
+//!   - Result: If `out_file` was given, the SVG is written there and the result is `{
+//!     "out_file": "path/to/out.svg" }`, which, if `include_svg` is `true`, also has the
+//!     SVG as its `svg` (rendered once, for both). Otherwise the result is `{ "svg":
+//!     "<svg ...>...</svg>" }`. If `include_hash` is `true`, the result also has the
+//!     hash of the skeleton's inputs as its `hash` (see
+//!     [`Fibroblast::input_hash`](crate::Fibroblast::input_hash)), which is stamped on
+//!     the SVG's root tag as `data-clgn-hash`.
+//! - `shutdown`
+//!   - Params: none.
+//!   - Result: `null`. The daemon exits after responding.
+//!
+//! Decoded manifests are kept in a [`TemplateCache`] for the lifetime of the daemon, so
+//! rendering the same skeleton (with the same `vars`) repeatedly only re-does the work
+//! of writing the SVG.
+//!
+//! A request that fails to render gets an error response with code `-32000`, the
+//! error's message, and `{ "exit_code": ... }` as its `data`, where `exit_code` is what
+//! `clgn` would have exited with had it been asked to render the same skeleton.
+
+use super::{write_fibroblast_to_file, write_fibroblast_to_file_and_string};
+use crate::{
+	fibroblast::data_types::Map,
+	from_json::{patch::ManifestPatch, template_cache::TemplateCache},
+	to_svg::svg_writable::ClgnDecodingError,
+	ClgnDecodingResult, Fibroblast,
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const RENDER_ERROR: i64 = -32000;
+
+pub(super) fn get_subcommand() -> App<'static, 'static> {
+	let subcommand = SubCommand::with_name("daemon")
+		.about("Render skeletons on request, reading JSON-RPC requests line by line");
+
+	if cfg!(unix) {
+		subcommand.arg(
+			Arg::with_name("socket")
+				.long("socket")
+				.takes_value(true)
+				.help("Listen on a Unix socket at this path instead of reading stdin"),
+		)
+	} else {
+		subcommand
+	}
+}
+
+pub(super) fn handle_matches(matches: &ArgMatches) -> ClgnDecodingResult<()> {
+	match matches.value_of_os("socket") {
+		Some(socket_path) => serve_unix_socket(Path::new(socket_path)),
+		None => {
+			let stdin = io::stdin();
+			let stdout = io::stdout();
+			serve(
+				stdin.lock(),
+				stdout.lock(),
+				Path::new("<stdin>"),
+				Path::new("<stdout>"),
+			)
+			.map(|_| ())
+		}
+	}
+}
+
+#[cfg(unix)]
+fn serve_unix_socket(path: &Path) -> ClgnDecodingResult<()> {
+	use std::os::unix::net::UnixListener;
+
+	let to_err = |e| ClgnDecodingError::IoRead(e, path.to_owned());
+
+	let listener =
+		UnixListener::bind(path).map_err(|e| ClgnDecodingError::IoWrite(e, path.to_owned()))?;
+	let result = (|| {
+		for stream in listener.incoming() {
+			let stream = stream.map_err(to_err)?;
+			let reader = io::BufReader::new(stream.try_clone().map_err(to_err)?);
+			if let ServeOutcome::Shutdown = serve(reader, stream, path, path)? {
+				break;
+			}
+		}
+		Ok(())
+	})();
+
+	// Best effort; the socket file is useless once we stop listening
+	let _ = std::fs::remove_file(path);
+
+	result
+}
+
+#[cfg(not(unix))]
+fn serve_unix_socket(_: &Path) -> ClgnDecodingResult<()> {
+	unreachable!("`--socket` is only accepted on Unix")
+}
+
+/// Why [`serve`] stopped reading requests
+#[derive(Debug, PartialEq, Eq)]
+enum ServeOutcome {
+	EndOfInput,
+	Shutdown,
+}
+
+/// Handle requests, one per line of `reader`, writing one response per line to
+/// `writer`, until either `reader` is exhausted or a `shutdown` request arrives.
+/// `reader_path` and `writer_path` name the streams in errors.
+fn serve(
+	mut reader: impl BufRead,
+	mut writer: impl Write,
+	reader_path: &Path,
+	writer_path: &Path,
+) -> ClgnDecodingResult<ServeOutcome> {
+	let mut cache = TemplateCache::new();
+
+	let mut line = Vec::new();
+	loop {
+		line.clear();
+		let n_bytes = reader
+			.read_until(b'\n', &mut line)
+			.map_err(|e| ClgnDecodingError::IoRead(e, reader_path.to_owned()))?;
+		if n_bytes == 0 {
+			break;
+		}
+
+		// A line that isn't UTF-8 can't be JSON, but it doesn't stop us reading the rest
+		let (response, action) = match std::str::from_utf8(&line) {
+			Ok(line) if line.trim().is_empty() => continue,
+			Ok(line) => handle_request(line, &mut cache),
+			Err(e) => (
+				Some(Response::without_id(RpcError::new(
+					PARSE_ERROR,
+					format!("Request is not valid UTF-8: {}", e),
+				))),
+				Action::Continue,
+			),
+		};
+		if let Some(response) = response {
+			(|| {
+				json::to_writer(&mut writer, &response)?;
+				writer.write_all(b"\n")?;
+				writer.flush()
+			})()
+			.map_err(|e| ClgnDecodingError::IoWrite(e, writer_path.to_owned()))?;
+		}
+
+		if let Action::Shutdown = action {
+			return Ok(ServeOutcome::Shutdown);
+		}
+	}
+
+	Ok(ServeOutcome::EndOfInput)
+}
+
+#[derive(Deserialize, Debug)]
+struct Request {
+	jsonrpc: String,
+	/// Absent for notifications, which get no response. (An `id` of `null` is present,
+	/// and so gets a response.)
+	#[serde(default, deserialize_with = "deserialize_present")]
+	id: Option<json::Value>,
+	method: String,
+	#[serde(default)]
+	params: Option<json::Value>,
+}
+
+/// Deserialize a field that's present, even as `null`, as `Some`. (Serde's default for
+/// an `Option` field would turn `null` into `None`, same as if it were absent.)
+fn deserialize_present<'de, D: serde::Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Option<json::Value>, D::Error> {
+	json::Value::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RenderParams {
+	#[serde(default)]
+	skeleton: Option<PathBuf>,
+	#[serde(default)]
+	files: Option<Map<PathBuf, FileContents>>,
+	#[serde(default)]
+	vars: Option<json::Value>,
+	#[serde(default)]
+	out_file: Option<PathBuf>,
+	#[serde(default)]
+	include_svg: bool,
+	#[serde(default)]
+	include_hash: bool,
+}
+
+/// The contents of a file given inline in [`RenderParams::files`]
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum FileContents {
+	Text(String),
+	Binary { base64: String },
+}
+
+#[derive(Serialize, Debug)]
+struct Response {
+	jsonrpc: &'static str,
+	id: json::Value,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	result: Option<json::Value>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	error: Option<RpcError>,
+}
+
+#[derive(Serialize, Debug)]
+struct RpcError {
+	code: i64,
+	message: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	data: Option<json::Value>,
+}
+
+impl Response {
+	/// The response to a request that couldn't be read well enough to find its `id`
+	fn without_id(error: RpcError) -> Self {
+		Self {
+			jsonrpc: "2.0",
+			id: json::Value::Null,
+			result: None,
+			error: Some(error),
+		}
+	}
+}
+
+impl RpcError {
+	fn new(code: i64, message: impl Into<String>) -> Self {
+		Self {
+			code,
+			message: message.into(),
+			data: None,
+		}
+	}
+}
+
+impl From<ClgnDecodingError> for RpcError {
+	fn from(err: ClgnDecodingError) -> Self {
+		Self {
+			code: RENDER_ERROR,
+			message: err.to_string(),
+			data: Some(json::json!({ "exit_code": err.exit_code() })),
+		}
+	}
+}
+
+enum Action {
+	Continue,
+	Shutdown,
+}
+
+fn handle_request(line: &str, cache: &mut TemplateCache) -> (Option<Response>, Action) {
+	let request = match json::from_str::<Request>(line) {
+		Ok(request) => request,
+		Err(e) => {
+			let code = if e.is_data() {
+				INVALID_REQUEST
+			} else {
+				PARSE_ERROR
+			};
+			let response = Response::without_id(RpcError::new(code, e.to_string()));
+			return (Some(response), Action::Continue);
+		}
+	};
+
+	let (result, action) = if request.jsonrpc != "2.0" {
+		(
+			Err(RpcError::new(
+				INVALID_REQUEST,
+				format!(
+					r#"Unsupported JSON-RPC version {:?}; expected "2.0""#,
+					request.jsonrpc
+				),
+			)),
+			Action::Continue,
+		)
+	} else {
+		match request.method.as_str() {
+			"render" => (render(request.params, cache), Action::Continue),
+			"shutdown" => (Ok(json::Value::Null), Action::Shutdown),
+			method => (
+				Err(RpcError::new(
+					METHOD_NOT_FOUND,
+					format!("Unknown method {:?}", method),
+				)),
+				Action::Continue,
+			),
+		}
+	};
+
+	let response = request.id.map(|id| {
+		let (result, error) = match result {
+			Ok(result) => (Some(result), None),
+			Err(error) => (None, Some(error)),
+		};
+		Response {
+			jsonrpc: "2.0",
+			id,
+			result,
+			error,
+		}
+	});
+
+	(response, action)
+}
+
+fn render(params: Option<json::Value>, cache: &mut TemplateCache) -> Result<json::Value, RpcError> {
+	let params = params.unwrap_or(json::Value::Null);
+	let RenderParams {
+		skeleton,
+		files,
+		vars,
+		out_file,
+		include_svg,
+		include_hash,
+	} = json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+
+	let patches = match vars {
+		Some(vars) => vec![ManifestPatch::from_vars("<vars>", vars)?],
+		None => Vec::new(),
+	};
+	let fibroblast = match (skeleton, files) {
+		(Some(skeleton), None) => cache.fibroblast_with_patches(&skeleton, &patches)?,
+		(None, Some(files)) => {
+			let files = files
+				.into_iter()
+				.map(|(path, contents)| match contents {
+					FileContents::Text(text) => Ok((path, text.into_bytes())),
+					FileContents::Binary { base64 } => match base64::decode(&base64) {
+						Ok(bytes) => Ok((path, bytes)),
+						Err(e) => Err(RpcError::new(
+							INVALID_PARAMS,
+							format!("The file {:?} is not valid base64: {}", path, e),
+						)),
+					},
+				})
+				.collect::<Result<Vec<_>, _>>()?;
+			Fibroblast::from_files(files, &patches)?
+		}
+		_ => {
+			return Err(RpcError::new(
+				INVALID_PARAMS,
+				"Exactly one of `skeleton` and `files` must be given",
+			))
+		}
+	};
+	let hash = if include_hash {
+		Some(fibroblast.stamp_input_hash()?)
+	} else {
+		None
+	};
+	let mut result = match out_file {
+		Some(out_file) if include_svg => {
+			let svg = write_fibroblast_to_file_and_string(&fibroblast, &out_file)?;
+			json::json!({ "out_file": out_file, "svg": svg })
+		}
+		Some(out_file) => {
+			write_fibroblast_to_file(&fibroblast, &out_file)?;
+			json::json!({ "out_file": out_file })
+		}
+		None => {
+			let svg = fibroblast.to_svg_string()?;
+			json::json!({ "svg": svg })
+		}
+	};
+	if let Some(hash) = hash {
+		result["hash"] = json::Value::String(hash);
+	}
+
+	Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+	use std::path::Path;
+
+	/// Feed `input` to [`serve`] and return its outcome and the parsed responses
+	fn serve_str(input: &str) -> (ServeOutcome, Vec<json::Value>) {
+		serve_bytes(input.as_bytes())
+	}
+
+	/// Like [`serve_str`], except that `input` needn't be UTF-8
+	fn serve_bytes(input: &[u8]) -> (ServeOutcome, Vec<json::Value>) {
+		let mut output = Vec::new();
+		let outcome = serve(
+			Cursor::new(input),
+			&mut output,
+			Path::new("<input>"),
+			Path::new("<output>"),
+		)
+		.unwrap();
+		let responses = std::str::from_utf8(&output)
+			.unwrap()
+			.lines()
+			.map(|line| json::from_str(line).unwrap())
+			.collect();
+
+		(outcome, responses)
+	}
+
+	fn error_code(response: &json::Value) -> i64 {
+		response["error"]["code"].as_i64().unwrap()
+	}
+
+	#[test]
+	fn render_to_string() {
+		let (outcome, responses) = serve_str(
+			r#"{"jsonrpc": "2.0", "id": 1, "method": "render", "params": {"skeleton": "tests/examples/basic-smiley-pure-svg/skeleton"}}"#,
+		);
+		let expected =
+			std::fs::read_to_string("tests/examples/basic-smiley-pure-svg/out.svg").unwrap();
+
+		assert_eq!(outcome, ServeOutcome::EndOfInput);
+		assert_eq!(responses.len(), 1);
+		assert_eq!(responses[0]["id"], 1);
+		assert_eq!(responses[0]["result"]["svg"], expected.as_str());
+	}
+
+	#[test]
+	fn render_with_hash() {
+		let skeleton = "tests/examples/basic-smiley-pure-svg/skeleton";
+		let (_, responses) = serve_str(&format!(
+			r#"{{"jsonrpc": "2.0", "id": 1, "method": "render", "params": {{"skeleton": {:?}, "include_hash": true}}}}"#,
+			skeleton
+		));
+		let hash = crate::Fibroblast::from_dir(skeleton)
+			.unwrap()
+			.input_hash()
+			.unwrap();
+
+		assert_eq!(responses[0]["result"]["hash"], hash.as_str());
+		assert!(responses[0]["result"]["svg"]
+			.as_str()
+			.unwrap()
+			.contains(&format!(r#" data-clgn-hash="{}""#, hash)));
+	}
+
+	#[test]
+	fn render_to_file() {
+		let out_dir = tempfile::tempdir().unwrap();
+		let out_file = out_dir.path().join("out.svg");
+		let request = json::json!({
+			"jsonrpc": "2.0",
+			"id": "a",
+			"method": "render",
+			"params": { "skeleton": "tests/examples/empty/skeleton", "out_file": out_file },
+		});
+
+		let (_, responses) = serve_str(&request.to_string());
+
+		assert_eq!(responses[0]["id"], "a");
+		assert_eq!(responses[0]["result"]["out_file"], json::json!(out_file));
+		assert_eq!(
+			std::fs::read(&out_file).unwrap(),
+			std::fs::read("tests/examples/empty/out.svg").unwrap()
+		);
+		assert!(responses[0]["result"].get("svg").is_none());
+
+		let request = json::json!({
+			"jsonrpc": "2.0",
+			"id": "b",
+			"method": "render",
+			"params": {
+				"skeleton": "tests/examples/basic-smiley-pure-svg/skeleton",
+				"out_file": out_file,
+				"include_svg": true,
+			},
+		});
+
+		let (_, responses) = serve_str(&request.to_string());
+
+		let expected =
+			std::fs::read_to_string("tests/examples/basic-smiley-pure-svg/out.svg").unwrap();
+		assert_eq!(responses[0]["result"]["out_file"], json::json!(out_file));
+		assert_eq!(responses[0]["result"]["svg"], expected.as_str());
+		assert_eq!(std::fs::read_to_string(&out_file).unwrap(), expected);
+	}
+
+	#[test]
+	fn render_with_vars() {
+		let skeleton = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton.path().join("collagen.json"),
+			r#"{ "vars": { "fill": "red" }, "children": [{ "tag": "rect", "attrs": { "fill": "{fill}" } }] }"#,
+		)
+		.unwrap();
+		let request = |vars: Option<json::Value>| {
+			let mut request = json::json!({
+				"jsonrpc": "2.0",
+				"id": 1,
+				"method": "render",
+				"params": { "skeleton": skeleton.path() },
+			});
+			if let Some(vars) = vars {
+				request["params"]["vars"] = vars;
+			}
+			request.to_string()
+		};
+		let svg = |fill: &str| {
+			format!(
+				r#"<svg xmlns="http://www.w3.org/2000/svg"><rect fill="{}"></rect></svg>"#,
+				fill
+			)
+		};
+
+		// Alternating vars mustn't get the cached manifest decoded with the others
+		let (_, responses) = serve_str(
+			&[
+				request(None),
+				request(Some(json::json!({ "fill": "blue" }))),
+				request(None),
+				request(Some(json::json!({ "fill": 1 }))),
+				request(Some(json::json!({ "fill": ["blue"] }))),
+			]
+			.join("\n"),
+		);
+
+		assert_eq!(responses[0]["result"]["svg"], svg("red"));
+		assert_eq!(responses[1]["result"]["svg"], svg("blue"));
+		assert_eq!(responses[2]["result"]["svg"], svg("red"));
+		assert_eq!(responses[3]["result"]["svg"], svg("1"));
+		assert_eq!(error_code(&responses[4]), RENDER_ERROR);
+		assert_eq!(
+			responses[4]["error"]["data"]["exit_code"],
+			ClgnDecodingError::Patch { msg: String::new() }.exit_code()
+		);
+	}
+
+	/// The files of the skeleton at `dir`, as they'd be given in a request's `files`
+	fn inline_files(dir: &Path) -> json::Map<String, json::Value> {
+		fn add_files(dir: &Path, prefix: &Path, files: &mut json::Map<String, json::Value>) {
+			for entry in std::fs::read_dir(dir).unwrap() {
+				let path = entry.unwrap().path();
+				let rel_path = prefix.join(path.file_name().unwrap());
+				if path.is_dir() {
+					add_files(&path, &rel_path, files);
+				} else {
+					let bytes = std::fs::read(&path).unwrap();
+					let contents = match String::from_utf8(bytes) {
+						Ok(text) => json::Value::String(text),
+						Err(e) => json::json!({ "base64": base64::encode(e.into_bytes()) }),
+					};
+					files.insert(rel_path.to_str().unwrap().to_owned(), contents);
+				}
+			}
+		}
+
+		let mut files = json::Map::new();
+		add_files(dir, Path::new(""), &mut files);
+		files
+	}
+
+	#[test]
+	fn render_inline_files() {
+		for example in ["include", "kitty-nesting-smiley"] {
+			let example_dir = Path::new("tests/examples").join(example);
+			let request = json::json!({
+				"jsonrpc": "2.0",
+				"id": 1,
+				"method": "render",
+				"params": { "files": inline_files(&example_dir.join("skeleton")) },
+			});
+
+			let (_, responses) = serve_str(&request.to_string());
+
+			let expected = std::fs::read_to_string(example_dir.join("out.svg")).unwrap();
+			assert_eq!(
+				responses[0]["result"]["svg"],
+				expected.as_str(),
+				"{}",
+				example
+			);
+		}
+
+		// Files that exist on disk, but not in `files`, are missing
+		let request = json::json!({
+			"jsonrpc": "2.0",
+			"id": 1,
+			"method": "render",
+			"params": {
+				"files": {
+					"collagen.json": r#"{ "children": [{ "image_path": "tests/examples/kitty-nesting-smiley/skeleton/kitty.jpg" }] }"#,
+				},
+				"vars": { "x": 1 },
+			},
+		});
+
+		let (_, responses) = serve_str(&request.to_string());
+
+		assert_eq!(error_code(&responses[0]), RENDER_ERROR);
+		assert!(
+			responses[0]["error"]["message"]
+				.as_str()
+				.unwrap()
+				.contains("kitty.jpg"),
+			"{}",
+			responses[0]
+		);
+	}
+
+	#[test]
+	fn errors() {
+		let (outcome, responses) = serve_str(
+			&[
+				r#"not json"#,
+				r#"{"id": 1, "method": "render"}"#,
+				r#"{"jsonrpc": "1.0", "id": 2, "method": "render"}"#,
+				r#"{"jsonrpc": "2.0", "id": 3, "method": "rendre"}"#,
+				r#"{"jsonrpc": "2.0", "id": 4, "method": "render", "params": {}}"#,
+				r#"{"jsonrpc": "2.0", "id": 5, "method": "render", "params": {"skeleton": "does/not/exist"}}"#,
+				r#"{"jsonrpc": "2.0", "id": 6, "method": "render", "params": {"skeleton": "tests/examples/empty/skeleton", "files": {}}}"#,
+				r#"{"jsonrpc": "2.0", "id": 7, "method": "render", "params": {"files": {"collagen.json": {"base64": "not base64!"}}}}"#,
+			]
+			.join("\n"),
+		);
+
+		assert_eq!(outcome, ServeOutcome::EndOfInput);
+		assert_eq!(
+			responses.iter().map(error_code).collect::<Vec<_>>(),
+			vec![
+				PARSE_ERROR,
+				INVALID_REQUEST,
+				INVALID_REQUEST,
+				METHOD_NOT_FOUND,
+				INVALID_PARAMS,
+				RENDER_ERROR,
+				INVALID_PARAMS,
+				INVALID_PARAMS
+			]
+		);
+		assert_eq!(
+			responses[5]["error"]["data"]["exit_code"],
+			ClgnDecodingError::IoRead(io::ErrorKind::NotFound.into(), PathBuf::new()).exit_code()
+		);
+	}
+
+	#[test]
+	fn non_utf8_requests() {
+		let mut input =
+			b"{\"jsonrpc\": \"2.0\", \"id\": \"\xff\", \"method\": \"shutdown\"}\n".to_vec();
+		input.extend_from_slice(
+			br#"{"jsonrpc": "2.0", "id": 1, "method": "render", "params": {"skeleton": "tests/examples/empty/skeleton"}}"#,
+		);
+
+		let (outcome, responses) = serve_bytes(&input);
+
+		assert_eq!(outcome, ServeOutcome::EndOfInput);
+		assert_eq!(responses.len(), 2);
+		assert_eq!(error_code(&responses[0]), PARSE_ERROR);
+		assert_eq!(responses[0]["id"], json::Value::Null);
+		assert_eq!(responses[1]["id"], 1);
+		assert!(responses[1]["result"]["svg"].is_string());
+	}
+
+	#[test]
+	fn notifications_and_shutdown() {
+		let (outcome, responses) = serve_str(
+			&[
+				r#"{"jsonrpc": "2.0", "method": "render", "params": {"skeleton": "tests/examples/empty/skeleton"}}"#,
+				"",
+				r#"{"jsonrpc": "2.0", "id": null, "method": "render", "params": {}}"#,
+				r#"{"jsonrpc": "2.0", "id": 1, "method": "shutdown"}"#,
+				r#"{"jsonrpc": "2.0", "id": 2, "method": "render"}"#,
+			]
+			.join("\n"),
+		);
+
+		assert_eq!(outcome, ServeOutcome::Shutdown);
+		assert_eq!(responses.len(), 2);
+		// A `null` id isn't a notification's absent one
+		assert_eq!(responses[0]["id"], json::Value::Null);
+		assert_eq!(error_code(&responses[0]), INVALID_PARAMS);
+		assert_eq!(responses[1]["id"], 1);
+		assert_eq!(responses[1]["result"], json::Value::Null);
+	}
+}
@@ -0,0 +1,45 @@
+//! `clgn diff old new`: render two skeletons and print the differences between the
+//! element trees they produce (see [`SnapshotChange`](crate::fibroblast::SnapshotChange)),
+//! one per line. Prints nothing if they produce the same SVG.
+
+use crate::{to_svg::svg_writable::ClgnDecodingError, ClgnDecodingResult, Fibroblast};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub(super) fn get_subcommand() -> App<'static, 'static> {
+	SubCommand::with_name("diff")
+		.about(
+			"Print the elements and attributes that differ between the SVGs that two skeletons \
+			 produce",
+		)
+		.arg(
+			Arg::with_name("old")
+				.required(true)
+				.help("The path to the old skeleton folder"),
+		)
+		.arg(
+			Arg::with_name("new")
+				.required(true)
+				.help("The path to the new skeleton folder"),
+		)
+}
+
+pub(super) fn handle_matches(matches: &ArgMatches) -> ClgnDecodingResult<()> {
+	// safe so long as both are required
+	let old_dir = matches.value_of_os("old").unwrap();
+	let new_dir = matches.value_of_os("new").unwrap();
+
+	let old = Fibroblast::from_dir(old_dir)?;
+	let new = Fibroblast::from_dir(new_dir)?;
+	let changes = old.snapshot()?.diff(&new.snapshot()?);
+
+	let stdout = io::stdout();
+	let mut stdout = stdout.lock();
+	for change in changes {
+		writeln!(stdout, "{}", change)
+			.map_err(|e| ClgnDecodingError::IoWrite(e, PathBuf::from("<stdout>")))?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,113 @@
+//! `clgn completions <shell>` and `clgn man`, which print a shell completion script and
+//! a man page, respectively. Both are generated from the CLI definition in
+//! [`get_cli_parser`], so they can't fall out of date as flags are added.
+
+use super::get_cli_parser;
+use crate::{to_svg::svg_writable::ClgnDecodingError, ClgnDecodingResult};
+use clap::{App, Arg, ArgMatches, Shell, SubCommand};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// The name of the executable, as it should appear in completions and the man page
+const BIN_NAME: &str = "clgn";
+
+pub(super) fn get_completions_subcommand() -> App<'static, 'static> {
+	SubCommand::with_name("completions")
+		.about("Print a completion script for the given shell")
+		.arg(
+			Arg::with_name("shell")
+				.required(true)
+				.possible_values(&Shell::variants())
+				.help("The shell to generate completions for"),
+		)
+}
+
+pub(super) fn get_man_subcommand() -> App<'static, 'static> {
+	SubCommand::with_name("man").about("Print a man page for clgn, in roff format")
+}
+
+fn stdout_error(e: io::Error) -> ClgnDecodingError {
+	ClgnDecodingError::IoWrite(e, PathBuf::from("<stdout>"))
+}
+
+pub(super) fn handle_completions_matches(matches: &ArgMatches) -> ClgnDecodingResult<()> {
+	// safe so long as shell is required and restricted to `Shell::variants()`
+	let shell = matches.value_of("shell").unwrap().parse::<Shell>().unwrap();
+	get_cli_parser().gen_completions_to(BIN_NAME, shell, &mut io::stdout());
+	Ok(())
+}
+
+pub(super) fn handle_man_matches(_: &ArgMatches) -> ClgnDecodingResult<()> {
+	let stdout = io::stdout();
+	write_man_page(&mut stdout.lock()).map_err(stdout_error)
+}
+
+/// Escape `line` for inclusion in roff: backslashes must be doubled, and a line
+/// starting with `.` or `'` would otherwise be read as a request
+fn escape_roff_line(line: &str) -> String {
+	let line = line.replace('\\', "\\e");
+	if line.starts_with('.') || line.starts_with('\'') {
+		format!("\\&{}", line)
+	} else {
+		line
+	}
+}
+
+/// Write a man page for `clgn` to `out`. The body of the page is `clgn --help`,
+/// verbatim.
+fn write_man_page(out: &mut impl Write) -> io::Result<()> {
+	let mut app = get_cli_parser();
+
+	let mut help = Vec::new();
+	app.write_long_help(&mut help)
+		.map_err(|e| io::Error::new(io::ErrorKind::Other, e.message))?;
+	let help = String::from_utf8_lossy(&help);
+
+	writeln!(
+		out,
+		".TH {} 1 \"\" \"{} {}\"",
+		BIN_NAME.to_uppercase(),
+		BIN_NAME,
+		env!("CARGO_PKG_VERSION")
+	)?;
+	writeln!(out, ".SH NAME")?;
+	writeln!(out, "{} \\- {}", BIN_NAME, env!("CARGO_PKG_DESCRIPTION"))?;
+	writeln!(out, ".SH DESCRIPTION")?;
+	writeln!(out, ".nf")?;
+	for line in help.lines() {
+		writeln!(out, "{}", escape_roff_line(line))?;
+	}
+	writeln!(out, ".fi")?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn escape() {
+		assert_eq!(escape_roff_line("plain"), "plain");
+		assert_eq!(escape_roff_line(r"a\b"), r"a\eb");
+		assert_eq!(escape_roff_line(".TH"), r"\&.TH");
+		assert_eq!(escape_roff_line("'quoted'"), r"\&'quoted'");
+	}
+
+	#[test]
+	fn man_page() {
+		let mut page = Vec::new();
+		write_man_page(&mut page).unwrap();
+		let page = String::from_utf8(page).unwrap();
+
+		assert!(page.starts_with(".TH CLGN 1"));
+		for subcommand in ["completions", "daemon", "man"] {
+			assert!(
+				page.contains(subcommand),
+				"{:?} missing from man page",
+				subcommand
+			);
+		}
+		assert!(page.contains("--out-dir"));
+	}
+}
@@ -0,0 +1,456 @@
+//! `clgn explain <exit-code>`: print a longer explanation of the error that `clgn` exited
+//! with, much as `rustc --explain` does. Each kind of error has its own exit code (see
+//! [`ClgnDecodingError::exit_code`]); its explanation says what causes it, gives a
+//! manifest that fails with it (when a manifest alone can), and says how to fix it.
+
+use crate::{to_svg::svg_writable::ClgnDecodingError, ClgnDecodingResult};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// The explanation of an exit code
+struct Explanation {
+	code: i32,
+	title: &'static str,
+	/// What causes the error, at more length than its message
+	description: &'static str,
+	/// A manifest (a skeleton's `collagen.json`) that fails with this error, if a
+	/// manifest alone can
+	example: Option<&'static str>,
+	fix: &'static str,
+}
+
+const EXPLANATIONS: [Explanation; 35] = [
+	Explanation {
+		code: 1,
+		title: "invalid command line",
+		description: "The command line arguments could not be parsed: a required argument \
+		              is missing, a flag is misspelled, or a flag's value isn't one it \
+		              accepts.",
+		example: None,
+		fix: "Run `clgn --help` (or `clgn <subcommand> --help`) to see the arguments that \
+		      are accepted.",
+	},
+	Explanation {
+		code: 3,
+		title: "invalid variable substitution",
+		description: "A string that undergoes variable substitution (an attribute value, \
+		              a tag's `text`, etc.) refers to a variable that isn't in scope, \
+		              has something between braces that isn't a variable name (only \
+		              letters, digits, and underscores are allowed, plus dots in the \
+		              names of the variables exported by named containers), or has an invalid \
+		              escape sequence, such as an unmatched `}` or a backslash before a \
+		              character that can't be escaped.",
+		example: Some(r#"{ "children": [{ "tag": "text", "text": "Hello, {name}!" }] }"#),
+		fix: "Define the variable in the `vars` of the tag or one of its ancestors. To \
+		      write a literal brace or backslash, escape it with a backslash, which is \
+		      itself doubled in JSON: `\"\\\\{\"`. A string with many literal braces, \
+		      such as CSS, can be given as `{ \"raw\": \"...\" }` instead, which isn't \
+		      substituted into.",
+	},
+	Explanation {
+		code: 4,
+		title: "invalid manifest",
+		description: "A manifest (or a fragment it includes, or a patch) is not valid \
+		              JSON, or is valid JSON that doesn't describe a skeleton: a value has \
+		              the wrong type, a required key is missing, an object has the same \
+		              key twice, or a child is not any kind of tag. Since a child's kind is determined from its keys, a child \
+		              with a mistake in it is often reported as matching no kind of tag \
+		              at all.",
+		example: Some(r#"{ "children": [{ "tag": "rect", "attrs": { "width": [10] } }] }"#),
+		fix: "Check the value the error points to against the documentation of the \
+		      tag it's in. `clgn normalize` shows how a valid manifest is interpreted.",
+	},
+	Explanation {
+		code: 5,
+		title: "error writing XML",
+		description: "The SVG could not be written, usually because the file or stream \
+		              it was being written to failed (e.g., the disk filled up or the \
+		              pipe was closed).",
+		example: None,
+		fix: "Check that the output can be written to.",
+	},
+	Explanation {
+		code: 6,
+		title: "invalid path",
+		description: "A path could not be used, such as an output path with no file \
+		              name.",
+		example: None,
+		fix: "Check the paths given on the command line.",
+	},
+	Explanation {
+		code: 7,
+		title: "file could not be read or written",
+		description: "A file could not be read (a manifest, image, font, fragment, \
+		              etc.) or written (the SVG, a cached artifact, etc.). If the file \
+		              doesn't exist, the message lists the existing paths closest to \
+		              the one given, since the likeliest cause is a typo.",
+		example: Some(r#"{ "children": [{ "image_path": "missing.png" }] }"#),
+		fix: "Check the path, which is relative to the skeleton folder (the one \
+		      containing `collagen.json`), and the file's permissions. To render a \
+		      skeleton whose images are missing anyway, pass `--placeholder-missing-assets`.",
+	},
+	Explanation {
+		code: 8,
+		title: "invalid image",
+		description: "An image could not be embedded: its kind (its media type) could \
+		              not be deduced from its extension and no `kind` was given, the \
+		              `kind` isn't a known one, or it could not be cropped or converted \
+		              to sRGB.",
+		example: Some(r#"{ "children": [{ "image_path": "photo.jpg", "kind": "jpeg2000" }] }"#),
+		fix: "Give the image a `kind` that is one of the media types listed in the \
+		      message, such as `png` or `jpeg`.",
+	},
+	Explanation {
+		code: 9,
+		title: "invalid text",
+		description: "A text-only option was used on a tag other than a `<text>`, a \
+		              `<text>` with a `writing_mode` sets an attribute that the \
+		              `writing_mode` sets, or text to be wrapped has a non-numeric `x`, \
+		              `y`, or font size.",
+		example: Some(r#"{ "children": [{ "tag": "rect", "writing_mode": "vertical" }] }"#),
+		fix: "Move the option to a `<text>` tag, or remove the attribute that conflicts \
+		      with it.",
+	},
+	Explanation {
+		code: 10,
+		title: "invalid shape",
+		description: "A shape tag's parameters don't describe a shape, e.g., a regular \
+		              polygon with fewer than three sides, or a parameter that isn't a \
+		              number.",
+		example: Some(
+			r#"{ "children": [{ "shape": "regular-polygon", "cx": 0, "cy": 0, "r": 10, "sides": 2 }] }"#,
+		),
+		fix: "Check the shape's parameters against the documentation of shape tags.",
+	},
+	Explanation {
+		code: 11,
+		title: "invalid connector",
+		description: "A connector refers to an `id` that no tag has, or to a tag whose \
+		              position can't be determined from its attributes.",
+		example: Some(r#"{ "children": [{ "connect": { "from": "a", "to": "b" } }] }"#),
+		fix: "Give the tags being connected those `id`s, and numeric attributes that \
+		      determine their positions (e.g., a `<circle>`'s `cx`, `cy`, and `r`).",
+	},
+	Explanation {
+		code: 12,
+		title: "unknown palette color",
+		description: "An attribute value of the form `\"@name\"` refers to a color that \
+		              isn't in the palette.",
+		example: Some(r#"{ "children": [{ "tag": "rect", "attrs": { "fill": "@primary" } }] }"#),
+		fix: "Add the color to the root tag's `palette`, e.g., `\"palette\": { \
+		      \"primary\": \"#336699\" }`, or pass `--palette primary=#336699`.",
+	},
+	Explanation {
+		code: 13,
+		title: "invalid themed value",
+		description: "A themed value (one with separate `light` and `dark` values) is \
+		              missing one of the two.",
+		example: Some(
+			r#"{ "children": [{ "tag": "rect", "attrs": { "fill": { "light": "white", "dark": false } } }] }"#,
+		),
+		fix: "Give the themed value both a `light` and a `dark` value.",
+	},
+	Explanation {
+		code: 14,
+		title: "invalid animation",
+		description: "An animation is inconsistent, e.g., it gives both `values` and \
+		              `from`/`to`, or neither, or has a non-positive duration.",
+		example: Some(
+			r#"{ "children": [{ "tag": "rect", "animate": [{ "attr": "x", "from": 0, "to": 10, "values": [0, 10], "dur": 1 }] }] }"#,
+		),
+		fix: "Check the animation against the documentation of animations.",
+	},
+	Explanation {
+		code: 15,
+		title: "non-finite attribute",
+		description: "A numeric attribute came out as infinite or NaN, usually because \
+		              of a relative unit (such as `%w`) or a computed value whose inputs \
+		              are zero.",
+		example: None,
+		fix: "Check the variables and relative units the attribute is computed from.",
+	},
+	Explanation {
+		code: 16,
+		title: "invalid JSON pointer",
+		description: "A JSON pointer given on the command line (e.g., to `clgn vars` or \
+		              `--select`) is malformed, or doesn't point to a tag.",
+		example: None,
+		fix: "Point to a tag, as in `/children/0/children/2`; the empty pointer is the \
+		      root.",
+	},
+	Explanation {
+		code: 17,
+		title: "size limit exceeded",
+		description: "An attribute, embedded asset, or the whole SVG is larger than its \
+		              limit, and `--strict` was passed. (Without `--strict`, this is only \
+		              a warning.)",
+		example: None,
+		fix: "Shrink the offending asset, or raise the limit with `--max-attr-size` or \
+		      `--max-output-size`.",
+	},
+	Explanation {
+		code: 18,
+		title: "invalid font",
+		description: "A font could not be embedded: its file isn't a font, or a Google \
+		              font isn't in the font cache.",
+		example: None,
+		fix: "Check the font's path, or pass `--font-cache <dir>` with the font \
+		      downloaded to the path given in the message.",
+	},
+	Explanation {
+		code: 19,
+		title: "invalid UTF-8",
+		description: "The SVG was not valid UTF-8 when converting it to a string.",
+		example: None,
+		fix: "Check raw text and markup for bytes that aren't UTF-8.",
+	},
+	Explanation {
+		code: 20,
+		title: "undeclared namespace",
+		description: "A tag or attribute name has a namespace prefix, as in \
+		              `inkscape:label`, that hasn't been declared.",
+		example: Some(
+			r#"{ "children": [{ "tag": "g", "attrs": { "inkscape:label": "Layer 1" } }] }"#,
+		),
+		fix: "Declare the prefix in the root tag's `namespaces`, e.g., `\"namespaces\": \
+		      { \"inkscape\": \"http://www.inkscape.org/namespaces/inkscape\" }`.",
+	},
+	Explanation {
+		code: 21,
+		title: "invalid clip or mask",
+		description: "A tag's `clip` or `mask` has an unknown shape, or the tag already \
+		              has the `clip-path` or `mask` attribute that it would set.",
+		example: Some(
+			r#"{ "children": [{ "tag": "rect", "clip": { "shape": "hexagon", "r": 5 } }] }"#,
+		),
+		fix: "Use one of the shapes listed in the message, and remove the conflicting \
+		      attribute.",
+	},
+	Explanation {
+		code: 22,
+		title: "bundled font not found",
+		description: "A font tag asked for a bundled font that this build of `clgn` \
+		              wasn't built with.",
+		example: None,
+		fix: "Build `clgn` with the font's feature (e.g., `--features bundled_fonts`), \
+		      or embed the font from a file instead.",
+	},
+	Explanation {
+		code: 23,
+		title: "invalid repeat",
+		description: "A repeat tag's `count` isn't a non-negative integer, one of its \
+		              other parameters isn't a number, its `var` isn't a valid variable \
+		              name, or it has `children` (its children are the copies of its \
+		              template).",
+		example: Some(
+			r#"{ "children": [{ "repeat": { "count": 1.5 }, "template": { "tag": "rect" } }] }"#,
+		),
+		fix: "Check the repeat's parameters against the documentation of repeat tags.",
+	},
+	Explanation {
+		code: 24,
+		title: "invalid include",
+		description: "An include directive's fragment isn't an object with only `vars` \
+		              and `children`, includes itself, or defines a variable that the \
+		              including tag (or another fragment it includes) also defines.",
+		example: None,
+		fix: "Rename one of the conflicting variables, or break the cycle of includes.",
+	},
+	Explanation {
+		code: 25,
+		title: "invalid patch",
+		description: "A patch passed with `--patch` isn't a JSON Merge Patch (an object) \
+		              or a JSON Patch (an array of operations), or one of its operations \
+		              failed, e.g., because its path doesn't exist or a `test` failed. \
+		              Or, a vars file passed with `--vars-file` isn't an object whose \
+		              keys are variable names and whose values are numbers or strings.",
+		example: None,
+		fix: "Run `clgn normalize` on the skeleton to see the manifest the patch applies \
+		      to, and check the patch's paths against it.",
+	},
+	Explanation {
+		code: 26,
+		title: "nested too deeply",
+		description: "Tags are nested more deeply than allowed (counting the tags of \
+		              included skeletons), which usually means that a skeleton includes \
+		              itself.",
+		example: None,
+		fix: "Break the cycle of skeletons including each other, or raise the limit \
+		      with `--max-depth`.",
+	},
+	Explanation {
+		code: 27,
+		title: "malformed markup",
+		description: "Text given as markup (e.g., `{ \"raw\": \"...\" }` in an element \
+		              that's written unescaped) isn't well-formed XML, and `--strict` was \
+		              passed. (Without `--strict`, this is only a warning.)",
+		example: None,
+		fix: "Close the markup's tags and escape its `<` and `&`s.",
+	},
+	Explanation {
+		code: 28,
+		title: "unsupported by target",
+		description: "The SVG uses a feature that the viewers given by `--target` don't \
+		              support, and `--strict` was passed. (Without `--strict`, this is \
+		              only a warning.)",
+		example: None,
+		fix: "Avoid the feature, or choose a target that supports it.",
+	},
+	Explanation {
+		code: 29,
+		title: "variable cannot be exported",
+		description: "A variable passed to `--var-export` isn't defined by the root tag, \
+		              or its value contains a `;`, `{`, or `}`, which would end the CSS \
+		              custom property early.",
+		example: None,
+		fix: "Export only the root tag's variables, and only those with values that are \
+		      valid in CSS.",
+	},
+	Explanation {
+		code: 30,
+		title: "invalid link",
+		description: "A tag's `link` is invalid: it's on the root tag, or its \
+		              `hit_padding` is negative or given for a tag whose size can't be \
+		              determined from its attributes.",
+		example: Some(r#"{ "link": { "href": "https://example.com" } }"#),
+		fix: "Move the link to a child of the root, and give `hit_padding` only to tags \
+		      such as `<rect>` and `<circle>` whose size is in their attributes.",
+	},
+	Explanation {
+		code: 31,
+		title: "invalid symbol library",
+		description: "A `use_from` tag names a symbol that its library doesn't export, \
+		              gives its `<use>` an `href` of its own, or uses a symbol that gives \
+		              its `<symbol>` an `id` (which is generated).",
+		example: None,
+		fix: "Check the symbol's name against the library's `symbols`, which the \
+		      message lists.",
+	},
+	Explanation {
+		code: 32,
+		title: "reserved variable",
+		description: "A tag defines a variable that's set automatically, such as \
+		              `canvas_width`, either in its `vars` or as a repeat's `var`. The \
+		              definition would silently shadow the automatic value.",
+		example: Some(r#"{ "vars": { "canvas_width": 100 } }"#),
+		fix: "Rename the variable.",
+	},
+	Explanation {
+		code: 33,
+		title: "invalid zip archive",
+		description: "A zipped skeleton could not be read as a zip archive.",
+		example: None,
+		fix: "Check that the file is a zip archive containing a skeleton folder.",
+	},
+	Explanation {
+		code: 34,
+		title: "invalid container",
+		description: "A container tag's `name` isn't a valid variable name. A named \
+		              container exports its skeleton's size as the variables \
+		              `<name>.width` and `<name>.height`, so its name is restricted in the \
+		              same way as variable names.",
+		example: Some(r#"{ "children": [{ "clgn_path": "logo", "name": "the logo" }] }"#),
+		fix: "Use only letters, digits, and underscores in the name.",
+	},
+	Explanation {
+		code: 35,
+		title: "more than one manifest",
+		description: "A skeleton folder has both a `collagen.json` and a \
+		              `collagen.json5`, so it's unclear which is its manifest.",
+		example: None,
+		fix: "Remove (or rename) all but one of the manifests.",
+	},
+	Explanation {
+		code: 36,
+		title: "invalid credits",
+		description: "The root tag's `credits` can't be written: its `font_size` isn't \
+		              positive, its `margin` is negative, or the size of the canvas, \
+		              which they're placed in a corner of, can't be determined.",
+		example: Some(r#"{ "credits": true }"#),
+		fix: "Give the root tag a `viewBox`, or a `width` and `height`, and check the \
+		      credits' `font_size` and `margin`.",
+	},
+];
+
+fn explanation(code: i32) -> Option<&'static Explanation> {
+	EXPLANATIONS.iter().find(|e| e.code == code)
+}
+
+pub(super) fn get_subcommand() -> App<'static, 'static> {
+	SubCommand::with_name("explain")
+		.about("Explain an exit code: what causes it, an example, and how to fix it")
+		.arg(
+			Arg::with_name("code")
+				.required(true)
+				.validator(|s| match s.parse().ok().and_then(explanation) {
+					Some(_) => Ok(()),
+					None => Err(format!("{:?} is not one of clgn's exit codes", s)),
+				})
+				.help("The exit code to explain"),
+		)
+}
+
+/// Write the explanation of `code` to `out`
+fn write_explanation(explanation: &Explanation, out: &mut impl Write) -> io::Result<()> {
+	writeln!(out, "Exit code {}: {}", explanation.code, explanation.title)?;
+	writeln!(out)?;
+	writeln!(out, "{}", explanation.description)?;
+	if let Some(example) = explanation.example {
+		writeln!(out)?;
+		writeln!(out, "For example, this manifest fails with this error:")?;
+		writeln!(out)?;
+		writeln!(out, "    {}", example)?;
+	}
+	writeln!(out)?;
+	writeln!(out, "To fix it: {}", explanation.fix)
+}
+
+pub(super) fn handle_matches(matches: &ArgMatches) -> ClgnDecodingResult<()> {
+	// safe so long as code is required and the validator checks that it's explained
+	let code = matches.value_of("code").unwrap().parse().unwrap();
+	let explanation = explanation(code).unwrap();
+
+	let stdout = io::stdout();
+	write_explanation(explanation, &mut stdout.lock())
+		.map_err(|e| ClgnDecodingError::IoWrite(e, PathBuf::from("<stdout>")))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Fibroblast;
+
+	#[test]
+	fn explanations() {
+		// Every exit code is explained, once
+		let codes = EXPLANATIONS.iter().map(|e| e.code).collect::<Vec<_>>();
+		let expected = std::iter::once(1).chain(3..=36).collect::<Vec<_>>();
+		assert_eq!(codes, expected);
+
+		// And the examples fail with the error they're an example of
+		for explanation in EXPLANATIONS.iter() {
+			let example = match explanation.example {
+				Some(example) => example,
+				None => continue,
+			};
+
+			let skeleton_dir = tempfile::tempdir().unwrap();
+			std::fs::write(skeleton_dir.path().join("collagen.json"), example).unwrap();
+			let render = || -> ClgnDecodingResult<String> {
+				Fibroblast::from_dir(skeleton_dir.path())?.to_svg_string()
+			};
+			let err = match render() {
+				Ok(svg) => panic!("{} didn't fail; it made {}", example, svg),
+				Err(err) => err,
+			};
+			assert_eq!(err.exit_code(), explanation.code, "{}: {}", example, err);
+		}
+
+		let mut out = Vec::new();
+		write_explanation(explanation(12).unwrap(), &mut out).unwrap();
+		let out = String::from_utf8(out).unwrap();
+		assert!(out.starts_with("Exit code 12: unknown palette color\n\n"));
+		assert!(out.contains("\n    { \"children\": [{ \"tag\": \"rect\""));
+	}
+}
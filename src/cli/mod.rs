@@ -1,35 +1,366 @@
 //! The command line interface for this app
 
-use std::path::PathBuf;
+mod cache;
+mod daemon;
+mod diff;
+mod docs;
+mod explain;
+mod normalize;
+mod out_name;
+mod stats;
+mod tee;
+mod vars;
 
-use clap::{App, Arg, ArgMatches};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use crate::from_json::patch::ManifestPatch;
+use crate::to_svg::output_format::{self, Indent, OutputFormat};
+use crate::to_svg::profile::{self, Profile};
+use crate::to_svg::render_timings::RenderProfile;
+use crate::to_svg::size_limits::{self, SizeLimits};
+use crate::to_svg::target::{self, Target};
+use crate::utils::cache::AssetCache;
+use crate::utils::paths::long_path_safe;
 use crate::{to_svg::svg_writable::ClgnDecodingError, ClgnDecodingResult, Fibroblast};
 use quick_xml::Writer as XmlWriter;
+use tee::Tee;
 
 pub fn get_cli_parser() -> App<'static, 'static> {
 	App::new("clgn")
 		.about("Collagen: The Collage Generator")
-		.arg(
-			Arg::with_name("skeleton")
-				.alias("in-folder")
-				.short("i")
-				.required(true)
-				.takes_value(true)
-				.help("The path to the input skeleton folder"),
+		.setting(AppSettings::SubcommandsNegateReqs)
+		.args(&render_args())
+		.subcommand(render_subcommand())
+		.subcommand(daemon::get_subcommand())
+		.subcommand(docs::get_completions_subcommand())
+		.subcommand(docs::get_man_subcommand())
+		.subcommand(vars::get_subcommand())
+		.subcommand(stats::get_subcommand())
+		.subcommand(normalize::get_subcommand())
+		.subcommand(diff::get_subcommand())
+		.subcommand(cache::get_subcommand())
+		.subcommand(explain::get_subcommand())
+}
+
+/// The arguments for rendering a skeleton, which are accepted both by `clgn` itself and
+/// by `clgn render`
+fn render_args() -> Vec<Arg<'static, 'static>> {
+	vec![
+		Arg::with_name("skeleton")
+			.alias("in-folder")
+			.short("i")
+			.required(true)
+			.takes_value(true)
+			.help("The path to the input skeleton folder"),
+		Arg::with_name("out-file")
+			.short("o")
+			.required_unless("out-dir")
+			.conflicts_with("out-dir")
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1)
+			.help(
+				"The path to save the resulting SVG to, or - for stdout; may be given multiple \
+					 times to write the SVG to each path from a single render",
+			),
+		Arg::with_name("out-dir")
+			.long("out-dir")
+			.takes_value(true)
+			.help("The folder to save the resulting SVG to, under the name given by --out-name"),
+		Arg::with_name("out-name")
+			.long("out-name")
+			.requires("out-dir")
+			.takes_value(true)
+			.help(
+				"The name of the resulting SVG, in which {skeleton} is replaced with the \
+					 name of the skeleton folder and {date} with today's date (YYYY-MM-DD) \
+					 [default: {skeleton}.svg]",
+			),
+		patch_arg(),
+		vars_file_arg(),
+		Arg::with_name("palette")
+			.long("palette")
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1)
+			.value_name("NAME=COLOR")
+			.validator(|s| match s.split_once('=') {
+				Some((name, _)) if !name.is_empty() => Ok(()),
+				_ => Err(format!("expected NAME=COLOR, got {:?}", s)),
+			})
+			.help("Override the palette color NAME with COLOR; may be given multiple times"),
+		Arg::with_name("var-export")
+			.long("var-export")
+			.takes_value(true)
+			.multiple(true)
+			.use_delimiter(true)
+			.require_delimiter(true)
+			.value_name("NAMES")
+			.validator(|s| {
+				if !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_') {
+					Ok(())
+				} else {
+					Err(format!("{:?} is not a valid variable name", s))
+				}
+			})
+			.help(
+				"Export the root tag's variables NAMES, separated by commas, as CSS custom \
+					 properties (e.g., --theme:dark) in the root tag's style",
+			),
+		Arg::with_name("stamp-hash").long("stamp-hash").help(
+			"Give the root tag a data-clgn-hash attribute, a hash of the skeleton's manifest, \
+					 assets, and the options that affect the SVG, which changes whenever the SVG \
+					 might",
+		),
+		Arg::with_name("placeholder-missing-assets")
+			.long("placeholder-missing-assets")
+			.help("Draw a placeholder in place of each missing image instead of failing"),
+		Arg::with_name("max-attr-size")
+			.long("max-attr-size")
+			.takes_value(true)
+			.value_name("BYTES")
+			.validator(validate_byte_count)
+			.help(
+				"Warn (or, with --strict, fail) if any attribute value is longer than this \
+					 [default: 10000000]",
+			),
+		Arg::with_name("max-output-size")
+			.long("max-output-size")
+			.takes_value(true)
+			.value_name("BYTES")
+			.validator(validate_byte_count)
+			.help(
+				"Warn (or, with --strict, fail) if the resulting SVG is larger than this \
+					 [default: 25000000]",
+			),
+		Arg::with_name("max-depth")
+			.long("max-depth")
+			.takes_value(true)
+			.value_name("N")
+			.validator(|s| {
+				s.parse::<usize>()
+					.map(|_| ())
+					.map_err(|_| format!("expected a number, got {:?}", s))
+			})
+			.help(
+				"Fail if tags are nested more than N deep, counting the tags of nested \
+					 skeletons (as happens when a skeleton contains itself) [default: 256]",
+			),
+		Arg::with_name("buffer-size")
+			.long("buffer-size")
+			.takes_value(true)
+			.value_name("BYTES")
+			.validator(validate_byte_count)
+			.help(
+				"The size of the buffer through which the SVG is written to each output \
+					 [default: 65536]",
+			),
+		Arg::with_name("strict").long("strict").help(
+			"Fail instead of warning when a size limit is exceeded, when the SVG uses features \
+					 that --target doesn't support, or when text written unescaped \
+					 (\"should_escape_text\": false) isn't well-formed XML",
+		),
+		Arg::with_name("font-cache")
+			.long("font-cache")
+			.takes_value(true)
+			.value_name("DIR")
+			.help(
+				"The folder to read Google fonts from; the font FAMILY at weight WEIGHT is \
+					 read from DIR/FAMILY/WEIGHT.woff2",
+			),
+		Arg::with_name("no-cache").long("no-cache").help(
+			"Derive artifacts such as cropped images from scratch instead of reading them from, \
+					 and writing them to, the cache shared by all skeletons (see clgn cache)",
+		),
+		Arg::with_name("xml-declaration")
+			.long("xml-declaration")
+			.help(r#"Begin the SVG with <?xml version="1.0" encoding="UTF-8"?>"#),
+		Arg::with_name("xlink-href").long("xlink-href").help(
+			"Give each image an xlink:href in addition to its href, for viewers that \
+					 only support SVG 1.1",
+		),
+		Arg::with_name("srgb").long("srgb").help(
+			"Convert images with embedded color profiles (e.g., Display P3 photos) to sRGB, so \
+					 that their colors are the same in viewers that ignore color profiles",
+		),
+		Arg::with_name("indent")
+			.long("indent")
+			.takes_value(true)
+			.validator(|s| s.parse::<Indent>().map(|_| ()))
+			.help(
+				"Indent the SVG, one tag per line, with a tab (tab) or the given number of \
+					 spaces, overriding the root tag's output.indent",
+			),
+		Arg::with_name("newline")
+			.long("newline")
+			.takes_value(true)
+			.possible_values(&output_format::NEWLINE_NAMES)
+			.help("End the SVG's lines with LF or CRLF, overriding the root tag's output.newline"),
+		Arg::with_name("profile")
+			.long("profile")
+			.takes_value(true)
+			.possible_values(&profile::PROFILE_NAMES)
+			.help(
+				"Fill in the root tag's missing viewBox, width, height, preserveAspectRatio, \
+					 and rendering hints with defaults suited to the web, print, or icons",
+			),
+		Arg::with_name("target")
+			.long("target")
+			.takes_value(true)
+			.possible_values(&target::TARGET_NAMES)
+			.help(
+				"Warn (or, with --strict, fail) if the SVG uses features, such as embedded WOFF2 \
+					 fonts or mesh gradients, that the viewers it's meant for don't support. The \
+					 browser target also lazy-loads images and gives top-level <g>s ids",
+			),
+		Arg::with_name("profile-render")
+			.long("profile-render")
+			.takes_value(true)
+			.value_name("FILE")
+			.help(
+				"Write a JSON report of the time taken to decode the skeleton and to render each \
+					 tag (and each kind of tag), including reading and base64-encoding assets, \
+					 to FILE",
+			),
+		Arg::with_name("select")
+			.long("select")
+			.takes_value(true)
+			.value_name("SELECTOR")
+			.help(
+				"Render only the tag at SELECTOR, a JSON pointer such as /children/2/children/0 \
+					 or the equivalent children[2].children[0], in an otherwise empty <svg>",
+			),
+	]
+}
+
+/// The `--patch` argument, which is accepted by the commands that read a skeleton's
+/// manifest for rendering or normalizing it
+fn patch_arg() -> Arg<'static, 'static> {
+	Arg::with_name("patch")
+		.long("patch")
+		.takes_value(true)
+		.multiple(true)
+		.number_of_values(1)
+		.value_name("FILE")
+		.help(
+			"Apply the JSON Merge Patch or JSON Patch in FILE to the skeleton's manifest before \
+				 decoding it; may be given multiple times, in which case the patches are \
+				 applied in order",
 		)
-		.arg(
-			Arg::with_name("out-file")
-				.short("o")
-				.required(true)
-				.takes_value(true)
-				.help("The path to save the resulting SVG to"),
+}
+
+/// The `--vars-file` argument, which is accepted wherever `--patch` is
+fn vars_file_arg() -> Arg<'static, 'static> {
+	Arg::with_name("vars-file")
+		.long("vars-file")
+		.takes_value(true)
+		.multiple(true)
+		.number_of_values(1)
+		.value_name("FILE")
+		.help(
+			"Set the root tag's variables to those in FILE, a JSON object of variables; may be \
+				 given multiple times, in which case later files take precedence. Variables \
+				 set this way take precedence over those set by --patch",
 		)
 }
 
+/// The patches given with `--patch`, read from their files, followed by those given with
+/// `--vars-file`
+fn patches(matches: &ArgMatches) -> ClgnDecodingResult<Vec<ManifestPatch>> {
+	let patches = matches
+		.values_of_os("patch")
+		.into_iter()
+		.flatten()
+		.map(ManifestPatch::from_file);
+	let vars_files = matches
+		.values_of_os("vars-file")
+		.into_iter()
+		.flatten()
+		.map(ManifestPatch::from_vars_file);
+
+	patches.chain(vars_files).collect()
+}
+
+fn render_subcommand() -> App<'static, 'static> {
+	SubCommand::with_name("render")
+		.about("Render a skeleton to SVG; the same as running clgn without a subcommand")
+		.args(&render_args())
+}
+
+fn validate_byte_count(s: String) -> Result<(), String> {
+	s.parse::<usize>()
+		.map(|_| ())
+		.map_err(|_| format!("expected a number of bytes, got {:?}", s))
+}
+
+/// The size limits given on the command line, falling back to the defaults. (Not clap
+/// default values, for the same reason as `--out-name`'s.)
+fn size_limits(matches: &ArgMatches) -> SizeLimits {
+	// safe so long as the validators check that these parse
+	let bytes = |name, default| {
+		matches
+			.value_of(name)
+			.map_or(default, |s| s.parse::<usize>().unwrap())
+	};
+
+	SizeLimits {
+		max_attr_bytes: bytes("max-attr-size", size_limits::DEFAULT_MAX_ATTR_BYTES),
+		max_output_bytes: bytes("max-output-size", size_limits::DEFAULT_MAX_OUTPUT_BYTES),
+		strict: matches.is_present("strict"),
+	}
+}
+
 pub fn handle_cli_matches(matches: ArgMatches) -> ClgnDecodingResult<()> {
-	let in_file = matches.value_of("skeleton").unwrap(); // safe so long as in-file is required (.takes_value(true))
-	let out_file = matches.value_of("out-file").unwrap();
+	match matches.subcommand() {
+		("daemon", Some(daemon_matches)) => return daemon::handle_matches(daemon_matches),
+		("completions", Some(completions_matches)) => {
+			return docs::handle_completions_matches(completions_matches)
+		}
+		("man", Some(man_matches)) => return docs::handle_man_matches(man_matches),
+		("vars", Some(vars_matches)) => return vars::handle_matches(vars_matches),
+		("stats", Some(stats_matches)) => return stats::handle_matches(stats_matches),
+		("normalize", Some(normalize_matches)) => {
+			return normalize::handle_matches(normalize_matches)
+		}
+		("diff", Some(diff_matches)) => return diff::handle_matches(diff_matches),
+		("cache", Some(cache_matches)) => return cache::handle_matches(cache_matches),
+		("explain", Some(explain_matches)) => return explain::handle_matches(explain_matches),
+		("render", Some(render_matches)) => return render(render_matches),
+		_ => {}
+	}
+
+	render(&matches)
+}
+
+/// Render the skeleton given in `matches` to SVG, as directed by the rest of `matches`
+fn render(matches: &ArgMatches) -> ClgnDecodingResult<()> {
+	let in_file = matches.value_of_os("skeleton").unwrap(); // safe so long as in-file is required (.takes_value(true))
+	let out_files = match matches.value_of_os("out-dir") {
+		Some(out_dir) => {
+			// Not a clap default value, since it would trigger out-name's requirement of
+			// out-dir even when a subcommand is given
+			let template = matches
+				.value_of("out-name")
+				.unwrap_or(out_name::DEFAULT_OUT_NAME);
+			std::fs::create_dir_all(out_dir)
+				.map_err(|e| ClgnDecodingError::IoWrite(e, PathBuf::from(out_dir)))?;
+			vec![out_name::out_path(
+				Path::new(in_file),
+				Path::new(out_dir),
+				template,
+			)?]
+		}
+		// safe so long as out-file is required unless out-dir is given
+		None => matches
+			.values_of_os("out-file")
+			.unwrap()
+			.map(PathBuf::from)
+			.collect::<Vec<_>>(),
+	};
 	// let out_file = match out_file {
 	// 	Some(value) => Cow::Borrowed(value),
 	// 	None => PathBuf::from(in_file)
@@ -37,17 +368,218 @@ pub fn handle_cli_matches(matches: ArgMatches) -> ClgnDecodingResult<()> {
 	// 		.to_string_lossy(),
 	// };
 
-	let file_writer = std::fs::OpenOptions::new()
+	let decode_start = Instant::now();
+	let fibroblast = Fibroblast::from_dir_with_patches(in_file, &patches(matches)?)?;
+	let decode_time = decode_start.elapsed();
+	for name_and_color in matches.values_of("palette").into_iter().flatten() {
+		// safe so long as the validator checks for a `=`
+		let (name, color) = name_and_color.split_once('=').unwrap();
+		fibroblast.context.override_palette_color(name, color);
+	}
+	fibroblast.context.set_exported_vars(
+		matches
+			.values_of("var-export")
+			.into_iter()
+			.flatten()
+			.map(str::to_owned)
+			.collect(),
+	);
+	fibroblast
+		.context
+		.set_placeholder_missing_assets(matches.is_present("placeholder-missing-assets"));
+	fibroblast.context.set_size_limits(size_limits(matches));
+	if let Some(max_depth) = matches.value_of("max-depth") {
+		// safe so long as the validator checks that this parses
+		fibroblast
+			.context
+			.set_max_nesting_depth(max_depth.parse().unwrap());
+	}
+	fibroblast
+		.context
+		.set_force_xml_declaration(matches.is_present("xml-declaration"));
+	fibroblast
+		.context
+		.set_xlink_href(matches.is_present("xlink-href"));
+	fibroblast
+		.context
+		.set_convert_to_srgb(matches.is_present("srgb"));
+	// safe so long as the validator checks that indent parses and newline is restricted to
+	// `NEWLINE_NAMES`
+	fibroblast.context.set_output_format(OutputFormat {
+		indent: matches.value_of("indent").map(|i| i.parse().unwrap()),
+		newline: matches.value_of("newline").map(|n| n.parse().unwrap()),
+	});
+	// safe so long as profile is restricted to `PROFILE_NAMES`
+	let profile = matches
+		.value_of("profile")
+		.map(|p| p.parse::<Profile>().unwrap());
+	fibroblast.context.set_profile(profile);
+	// safe so long as target is restricted to `TARGET_NAMES`
+	let target = matches
+		.value_of("target")
+		.map(|t| t.parse::<Target>().unwrap());
+	fibroblast.context.set_target(target);
+	if !matches.is_present("no-cache") {
+		fibroblast
+			.context
+			.set_asset_cache(AssetCache::in_user_cache_dir());
+	}
+	if let Some(font_cache) = matches.value_of_os("font-cache") {
+		fibroblast.context.set_font_cache_dir(font_cache);
+	}
+	// After all the other options, as they're part of the hash
+	if matches.is_present("stamp-hash") {
+		fibroblast.stamp_input_hash()?;
+	}
+
+	// safe so long as the validator checks that this parses
+	let buffer_size = matches
+		.value_of("buffer-size")
+		.map_or(tee::DEFAULT_BUFFER_SIZE, |size| size.parse().unwrap());
+	let write = || {
+		let mut tee = Tee::with_buffer_size(buffer_size);
+		for out_file in &out_files {
+			tee.add_path(out_file)?;
+		}
+		write_fibroblast_through_tee(&fibroblast, matches.value_of("select"), tee)
+	};
+
+	match matches.value_of_os("profile-render") {
+		Some(report_file) => {
+			let render_start = Instant::now();
+			let ((), timings) = fibroblast.with_render_timings(write)?;
+			let profile = RenderProfile::new(decode_time, render_start.elapsed(), timings);
+			write_render_profile(&profile, Path::new(report_file))
+		}
+		None => write(),
+	}
+}
+
+/// Write `profile` to `report_file` as JSON
+fn write_render_profile(profile: &RenderProfile, report_file: &Path) -> ClgnDecodingResult<()> {
+	let to_err = |e| ClgnDecodingError::IoWrite(e, report_file.to_owned());
+
+	let file = std::fs::File::create(report_file).map_err(to_err)?;
+	let mut writer = std::io::BufWriter::new(file);
+	serde_json::to_writer_pretty(&mut writer, profile).map_err(|e| to_err(e.into()))?;
+	writer.flush().map_err(to_err)
+}
+
+/// Open `out_file` for writing, truncating it if it already exists
+fn open_out_file(out_file: &Path) -> ClgnDecodingResult<std::fs::File> {
+	std::fs::OpenOptions::new()
 		.read(false)
 		.create(true)
 		.truncate(true)
 		.write(true)
-		.open(out_file)
-		// TODO: replace `unwrap` with `into_ok` when stabilized
-		.map_err(|e| ClgnDecodingError::Io(e, in_file.parse::<PathBuf>().unwrap()))?;
-	let mut xml_writer = XmlWriter::new(file_writer);
+		.open(long_path_safe(out_file.to_owned()))
+		.map_err(|e| ClgnDecodingError::IoWrite(e, out_file.to_owned()))
+}
+
+/// Write `fibroblast` as SVG (or, if `selector` is given, just the tag it selects, as
+/// with `--select`) to each of `tee`'s sinks. If the SVG exceeds a size limit in strict
+/// mode, the files it was being written to are removed.
+fn write_fibroblast_through_tee<'a>(
+	fibroblast: &'a Fibroblast<'a>,
+	selector: Option<&str>,
+	tee: Tee,
+) -> ClgnDecodingResult<()> {
+	let mut xml_writer = XmlWriter::new(tee);
+	let result = match selector {
+		Some(selector) => fibroblast.subtree_to_svg_through_writer(selector, &mut xml_writer),
+		None => fibroblast.to_svg_through_writer(&mut xml_writer),
+	}
+	.and_then(|()| {
+		xml_writer
+			.inner()
+			.flush()
+			.map_err(|e| ClgnDecodingError::Xml(e.into()))
+	});
+
+	result.map_err(|e| {
+		let tee = xml_writer.into_inner();
+		let err = tee.attribute_error(e);
+		// Writing stops once the SVG is too big, but what was written until then is left
+		if let ClgnDecodingError::SizeLimit { .. } = err {
+			tee.remove_files();
+		}
+		err
+	})
+}
+
+/// Write `fibroblast` to `out_file` as SVG, truncating `out_file` if it already exists
+pub(crate) fn write_fibroblast_to_file<'a>(
+	fibroblast: &'a Fibroblast<'a>,
+	out_file: impl AsRef<Path>,
+) -> ClgnDecodingResult<()> {
+	let mut tee = Tee::new();
+	tee.add_file(out_file.as_ref())?;
+	write_fibroblast_through_tee(fibroblast, None, tee)
+}
+
+/// Write `fibroblast` to `out_file` as SVG, as [`write_fibroblast_to_file`] does, and
+/// also return the SVG, from a single render
+pub(crate) fn write_fibroblast_to_file_and_string<'a>(
+	fibroblast: &'a Fibroblast<'a>,
+	out_file: impl AsRef<Path>,
+) -> ClgnDecodingResult<String> {
+	let mut svg = Vec::new();
+	let mut tee = Tee::new();
+	tee.add_file(out_file.as_ref())?;
+	tee.add_sink("<string>", &mut svg);
+	write_fibroblast_through_tee(fibroblast, None, tee)?;
+
+	Ok(String::from_utf8(svg).map_err(|e| e.utf8_error())?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::ffi::OsString;
+
+	fn run(args: &[OsString]) -> ClgnDecodingResult<()> {
+		let args = std::iter::once(OsString::from("clgn")).chain(args.iter().cloned());
+		handle_cli_matches(get_cli_parser().get_matches_from(args))
+	}
+
+	#[test]
+	fn strict_size_limits_leave_nothing_behind() {
+		let out_dir = tempfile::tempdir().unwrap();
+		let out_file = out_dir.path().join("out.svg");
+		let named_out_dir = out_dir.path().join("named");
+		let n_outputs = || {
+			let n_named = std::fs::read_dir(&named_out_dir).map_or(0, |entries| entries.count());
+			usize::from(out_file.exists()) + n_named
+		};
+
+		for out_args in [
+			vec![OsString::from("-o"), out_file.clone().into()],
+			vec![OsString::from("--out-dir"), named_out_dir.clone().into()],
+		] {
+			for limit in ["--max-output-size", "--max-attr-size"] {
+				let mut args = vec![
+					OsString::from("-i"),
+					"tests/examples/basic-smiley-pure-svg/skeleton".into(),
+					"--no-cache".into(),
+					limit.into(),
+					"10".into(),
+				];
+				args.extend(out_args.iter().cloned());
 
-	Fibroblast::from_dir(in_file)?.to_svg_through_writer(&mut xml_writer)?;
+				// Without --strict, exceeding a limit is only a warning
+				run(&args).unwrap();
+				assert_eq!(n_outputs(), 1, "{:?}", args);
 
-	Ok(())
+				args.push("--strict".into());
+				let err = run(&args).unwrap_err();
+				assert!(
+					matches!(err, ClgnDecodingError::SizeLimit { .. }),
+					"{:?}: {:?}",
+					args,
+					err
+				);
+				assert_eq!(n_outputs(), 0, "{:?}", args);
+			}
+		}
+	}
 }
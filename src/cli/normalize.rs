@@ -0,0 +1,50 @@
+//! `clgn normalize -i skeleton [-o out]`: write a skeleton's manifest back out from the
+//! validated model, with a predictable layout, for other tools to consume.
+
+use crate::{to_svg::svg_writable::ClgnDecodingError, ClgnDecodingResult, Fibroblast};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::io;
+use std::path::PathBuf;
+
+pub(super) fn get_subcommand() -> App<'static, 'static> {
+	SubCommand::with_name("normalize")
+		.about("Print a skeleton's manifest in normal form")
+		.arg(
+			Arg::with_name("skeleton")
+				.short("i")
+				.required(true)
+				.takes_value(true)
+				.help("The path to the input skeleton folder"),
+		)
+		.arg(
+			Arg::with_name("out-file")
+				.short("o")
+				.takes_value(true)
+				.help("The path to save the normalized manifest to (default: stdout)"),
+		)
+		.arg(super::patch_arg())
+		.arg(super::vars_file_arg())
+}
+
+pub(super) fn handle_matches(matches: &ArgMatches) -> ClgnDecodingResult<()> {
+	// safe so long as skeleton is required
+	let in_file = matches.value_of_os("skeleton").unwrap();
+	let fibroblast = Fibroblast::from_dir_with_patches(in_file, &super::patches(matches)?)?;
+
+	match matches.value_of_os("out-file") {
+		Some(out_file) => {
+			let out_file = PathBuf::from(out_file);
+			let file = std::fs::File::create(&out_file)
+				.map_err(|e| ClgnDecodingError::IoWrite(e, out_file.clone()))?;
+			fibroblast
+				.write_manifest(io::BufWriter::new(file))
+				.map_err(|e| ClgnDecodingError::IoWrite(e, out_file))
+		}
+		None => {
+			let stdout = io::stdout();
+			fibroblast
+				.write_manifest(stdout.lock())
+				.map_err(|e| ClgnDecodingError::IoWrite(e, PathBuf::from("<stdout>")))
+		}
+	}
+}
@@ -0,0 +1,181 @@
+//! Naming the output file from a template, for `clgn -i skeleton --out-dir dir
+//! --out-name template`. The template uses the same `{name}` syntax as variable
+//! substitution in a skeleton, with the following variables available:
+//!
+//! - `skeleton`: the name of the skeleton folder
+//! - `date`: today's date (in UTC), as `YYYY-MM-DD`
+
+use crate::fibroblast::data_types::VariableValue;
+use crate::fibroblast::DecodingContext;
+use crate::utils::paths::pathsep_aware_join;
+use crate::{to_svg::svg_writable::ClgnDecodingError, ClgnDecodingResult};
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub(super) const DEFAULT_OUT_NAME: &str = "{skeleton}.svg";
+
+/// What the template's `skeleton` is filled with when the skeleton's name isn't valid
+/// UTF-8 (as a file name on Linux needn't be), to be replaced with the name itself once
+/// the template has been made into a path. No file name contains a NUL, so this can't be
+/// mistaken for part of one.
+const SKELETON_NAME_PLACEHOLDER: &str = "\0skeleton\0";
+
+/// The path, inside `out_dir`, of the output file for the skeleton at `skeleton_path`,
+/// named according to `template`
+pub(super) fn out_path(
+	skeleton_path: &Path,
+	out_dir: &Path,
+	template: &str,
+) -> ClgnDecodingResult<PathBuf> {
+	let skeleton_name = skeleton_name(skeleton_path)?;
+	if let Some(name) = skeleton_name.to_str() {
+		let name = fill_template(template, name.to_owned(), today())?;
+		return pathsep_aware_join(out_dir, name);
+	}
+
+	let name = fill_template(template, SKELETON_NAME_PLACEHOLDER.to_owned(), today())?;
+	let path = pathsep_aware_join(out_dir, name)?;
+	Ok(path
+		.components()
+		.map(|component| match component {
+			Component::Normal(part) => match part.to_str() {
+				Some(part) if part.contains(SKELETON_NAME_PLACEHOLDER) => {
+					let mut filled = OsString::new();
+					for (i, piece) in part.split(SKELETON_NAME_PLACEHOLDER).enumerate() {
+						if i > 0 {
+							filled.push(&skeleton_name);
+						}
+						filled.push(piece);
+					}
+					filled
+				}
+				_ => part.to_owned(),
+			},
+			_ => component.as_os_str().to_owned(),
+		})
+		.collect())
+}
+
+/// The name of the folder at `skeleton_path`. The path is canonicalized first, so that
+/// `.` gets the name of the current directory.
+fn skeleton_name(skeleton_path: &Path) -> ClgnDecodingResult<OsString> {
+	let abs_path = skeleton_path
+		.canonicalize()
+		.map_err(|e| ClgnDecodingError::IoRead(e, skeleton_path.to_owned()))?;
+
+	match abs_path.file_name() {
+		Some(name) => Ok(name.to_owned()),
+		None => Err(ClgnDecodingError::InvalidPath(abs_path)),
+	}
+}
+
+fn fill_template(
+	template: &str,
+	skeleton_name: String,
+	date: String,
+) -> ClgnDecodingResult<String> {
+	let skeleton_name = VariableValue::from(skeleton_name);
+	let date = VariableValue::from(date);
+	let context = DecodingContext::new(
+		PathBuf::new(),
+		vec![("skeleton", &skeleton_name), ("date", &date)],
+	);
+
+	Ok(context.sub_vars_into_str(template)?.into_owned())
+}
+
+/// Today's date in UTC, as `YYYY-MM-DD`
+fn today() -> String {
+	let secs = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |d| d.as_secs());
+	let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+	format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The (year, month, day) that is `days` days after 1970-01-01. From Howard Hinnant's
+/// [`civil_from_days`](https://howardhinnant.github.io/date_algorithms.html#civil_from_days).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+	let z = days + 719_468;
+	let era = z.div_euclid(146_097);
+	let doe = z.rem_euclid(146_097);
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+	let year = yoe + era * 400 + i64::from(month <= 2);
+
+	(year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn dates() {
+		assert_eq!(civil_from_days(0), (1970, 1, 1));
+		assert_eq!(civil_from_days(59), (1970, 3, 1));
+		assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+		assert_eq!(civil_from_days(19_000), (2022, 1, 8));
+		assert_eq!(civil_from_days(-1), (1969, 12, 31));
+	}
+
+	#[test]
+	fn templates() {
+		let fill = |template| fill_template(template, "logo".to_owned(), "2022-01-08".to_owned());
+
+		assert_eq!(fill(DEFAULT_OUT_NAME).unwrap(), "logo.svg");
+		assert_eq!(
+			fill("{skeleton}-{date}.svg").unwrap(),
+			"logo-2022-01-08.svg"
+		);
+		assert_eq!(fill("out.svg").unwrap(), "out.svg");
+		assert!(matches!(
+			fill("{skeleton}-{version}.svg"),
+			Err(ClgnDecodingError::Parse(..))
+		));
+	}
+
+	#[test]
+	fn skeleton_names() {
+		assert_eq!(
+			out_path(
+				Path::new("tests/examples/empty/skeleton/"),
+				Path::new("build"),
+				"{skeleton}.svg"
+			)
+			.unwrap(),
+			Path::new("build").join("skeleton.svg")
+		);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn non_utf8_skeleton_names() {
+		use std::ffi::OsStr;
+		use std::os::unix::ffi::OsStrExt;
+
+		let dir = tempfile::tempdir().unwrap();
+		let name = OsStr::from_bytes(b"logo\xff");
+		let skeleton = dir.path().join(name);
+		if std::fs::create_dir(&skeleton).is_err() {
+			// Some file systems only allow UTF-8 names
+			return;
+		}
+
+		let mut expected = name.to_owned();
+		expected.push("-v1.svg");
+		assert_eq!(
+			out_path(
+				&skeleton,
+				Path::new("build"),
+				"{skeleton}/{skeleton}-v1.svg"
+			)
+			.unwrap(),
+			Path::new("build").join(name).join(expected)
+		);
+	}
+}
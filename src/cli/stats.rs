@@ -0,0 +1,70 @@
+//! `clgn stats -i skeleton [--by-tag]`: print the size of the SVG that the skeleton
+//! produces and, with `--by-tag`, how much of it each tag is responsible for.
+
+use crate::{to_svg::svg_writable::ClgnDecodingError, ClgnDecodingResult, Fibroblast};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub(super) fn get_subcommand() -> App<'static, 'static> {
+	SubCommand::with_name("stats")
+		.about("Print the size of the SVG that a skeleton produces")
+		.arg(
+			Arg::with_name("skeleton")
+				.short("i")
+				.required(true)
+				.takes_value(true)
+				.help("The path to the input skeleton folder"),
+		)
+		.arg(Arg::with_name("by-tag").long("by-tag").help(
+			"Also print the number of bytes each tag is responsible for, both including (TOTAL) \
+			 and excluding (OWN) its descendants",
+		))
+}
+
+pub(super) fn handle_matches(matches: &ArgMatches) -> ClgnDecodingResult<()> {
+	// safe so long as skeleton is required
+	let in_file = matches.value_of_os("skeleton").unwrap();
+
+	let fibroblast = Fibroblast::from_dir(in_file)?;
+	let sizes = fibroblast.tag_sizes()?;
+	let total_bytes = sizes.first().map_or(0, |root| root.total_bytes);
+
+	let stdout = io::stdout();
+	let mut stdout = stdout.lock();
+	let mut print = |line: String| {
+		writeln!(stdout, "{}", line)
+			.map_err(|e| ClgnDecodingError::IoWrite(e, PathBuf::from("<stdout>")))
+	};
+
+	print(format!("{} bytes", total_bytes))?;
+	if !matches.is_present("by-tag") {
+		return Ok(());
+	}
+
+	let width = total_bytes.to_string().len().max("TOTAL".len());
+	print(String::new())?;
+	print(format!(
+		"{:>width$}  {:>width$}  TAG",
+		"TOTAL",
+		"OWN",
+		width = width
+	))?;
+	for size in sizes {
+		let pointer = if size.pointer.is_empty() {
+			"(root)"
+		} else {
+			&size.pointer
+		};
+		print(format!(
+			"{:>width$}  {:>width$}  {} <{}>",
+			size.total_bytes,
+			size.own_bytes,
+			pointer,
+			size.tag_name,
+			width = width
+		))?;
+	}
+
+	Ok(())
+}
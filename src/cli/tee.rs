@@ -0,0 +1,198 @@
+//! Writing one render to several places at once, e.g., with `clgn -i skeleton -o out.svg
+//! -o -`, which saves the SVG to `out.svg` and also prints it, without rendering it
+//! twice.
+//!
+//! The SVG is written in many small pieces (each tag name, attribute, etc.), so each
+//! file or stdout is written to through a buffer, whose size can be set with
+//! `--buffer-size`.
+
+use super::open_out_file;
+use crate::utils::paths::long_path_safe;
+use crate::{to_svg::svg_writable::ClgnDecodingError, ClgnDecodingResult};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The path that stands for stdout
+pub(super) const STDOUT_PATH: &str = "-";
+
+/// The size of the buffer in front of each file or stdout, in bytes, unless given with
+/// `--buffer-size`
+pub(super) const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A writer that passes everything written to it on to each of its sinks
+pub(super) struct Tee<'w> {
+	/// Each sink, with the path used to refer to it in errors
+	sinks: Vec<(PathBuf, Box<dyn Write + 'w>)>,
+	/// The sink that an error occurred in, if any
+	failed: Option<usize>,
+	/// The paths of the sinks that are files, which [`Tee::remove_files`] removes
+	files: Vec<PathBuf>,
+	/// The size of the buffer in front of each sink added with `add_path`
+	buffer_size: usize,
+}
+
+impl<'w> Tee<'w> {
+	pub(super) fn new() -> Self {
+		Self::with_buffer_size(DEFAULT_BUFFER_SIZE)
+	}
+
+	pub(super) fn with_buffer_size(buffer_size: usize) -> Self {
+		Self {
+			sinks: Vec::new(),
+			failed: None,
+			files: Vec::new(),
+			buffer_size,
+		}
+	}
+
+	/// Add the sink `path`: stdout if it's [`STDOUT_PATH`], and otherwise the file at
+	/// `path`, which is truncated if it already exists
+	pub(super) fn add_path(&mut self, path: &Path) -> ClgnDecodingResult<()> {
+		if path == Path::new(STDOUT_PATH) {
+			let stdout = BufWriter::with_capacity(self.buffer_size, io::stdout());
+			self.add_sink("<stdout>", stdout);
+			Ok(())
+		} else {
+			self.add_file(path)
+		}
+	}
+
+	/// Add the file at `path`, which is truncated if it already exists
+	pub(super) fn add_file(&mut self, path: &Path) -> ClgnDecodingResult<()> {
+		let file = BufWriter::with_capacity(self.buffer_size, open_out_file(path)?);
+		self.add_sink(path, file);
+		self.files.push(path.to_owned());
+		Ok(())
+	}
+
+	/// Close every sink and remove the files among them, e.g., so that an SVG that was
+	/// rejected partway through being written isn't left behind. Only regular files are
+	/// removed, so that, e.g., `-o /dev/stdout` is left alone. Best effort; files that
+	/// can't be removed are left as they are.
+	pub(super) fn remove_files(self) {
+		let Self { sinks, files, .. } = self;
+		drop(sinks);
+
+		for path in files {
+			let path = long_path_safe(path);
+			if std::fs::symlink_metadata(&path).map_or(false, |m| m.is_file()) {
+				let _ = std::fs::remove_file(path);
+			}
+		}
+	}
+
+	/// Add `sink`, referred to as `name` in errors
+	pub(super) fn add_sink(&mut self, name: impl Into<PathBuf>, sink: impl Write + 'w) {
+		self.sinks.push((name.into(), Box::new(sink)));
+	}
+
+	/// Call `op` on each sink in turn, stopping at (and remembering) the first to fail
+	fn for_each_sink(
+		&mut self,
+		mut op: impl FnMut(&mut dyn Write) -> io::Result<()>,
+	) -> io::Result<()> {
+		for (i, (_, sink)) in self.sinks.iter_mut().enumerate() {
+			if let Err(e) = op(sink) {
+				self.failed = Some(i);
+				return Err(e);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Attribute `err`, which occurred while writing through this `Tee`, to the sink it
+	/// occurred in if it was a failure to write. If no sink failed, the error came from
+	/// somewhere else (e.g., an image that couldn't be read), and is returned unchanged.
+	pub(super) fn attribute_error(&self, err: ClgnDecodingError) -> ClgnDecodingError {
+		let path = match self.failed.and_then(|i| self.sinks.get(i)) {
+			Some((path, _)) => path.clone(),
+			None => return err,
+		};
+
+		match err {
+			ClgnDecodingError::Xml(quick_xml::Error::Io(e)) | ClgnDecodingError::IoWrite(e, _) => {
+				ClgnDecodingError::IoWrite(e, path)
+			}
+			err => err,
+		}
+	}
+}
+
+impl Write for Tee<'_> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.for_each_sink(|sink| sink.write_all(buf))?;
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.for_each_sink(|sink| sink.flush())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tee() {
+		let out_dir = tempfile::tempdir().unwrap();
+		let out_file = out_dir.path().join("out.svg");
+		let mut copy = Vec::new();
+
+		let mut tee = Tee::new();
+		tee.add_path(&out_file).unwrap();
+		tee.add_sink("<copy>", &mut copy);
+		tee.write_all(b"<svg/>").unwrap();
+		tee.flush().unwrap();
+		drop(tee);
+
+		assert_eq!(std::fs::read(&out_file).unwrap(), b"<svg/>");
+		assert_eq!(copy, b"<svg/>");
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn tee_errors_name_the_failed_sink() {
+		let mut copy = Vec::new();
+		let mut tee = Tee::new();
+		tee.add_sink("<copy>", &mut copy);
+		tee.add_path(Path::new("/dev/full")).unwrap();
+
+		let err = tee.write_all(b"<svg/>").and_then(|()| tee.flush());
+		let err = tee.attribute_error(ClgnDecodingError::Xml(err.unwrap_err().into()));
+		assert!(
+			matches!(&err, ClgnDecodingError::IoWrite(_, path) if path == Path::new("/dev/full")),
+			"{:?}",
+			err
+		);
+	}
+
+	#[test]
+	fn tee_leaves_other_errors_alone() {
+		let mut copy = Vec::new();
+		let mut tee = Tee::new();
+		tee.add_sink("<copy>", &mut copy);
+		tee.write_all(b"<svg/>").unwrap();
+
+		// No sink failed, so I/O errors are someone else's, and keep their paths
+		let err = tee.attribute_error(ClgnDecodingError::IoWrite(
+			io::ErrorKind::PermissionDenied.into(),
+			PathBuf::from("report.json"),
+		));
+		assert!(
+			matches!(&err, ClgnDecodingError::IoWrite(_, path) if path == Path::new("report.json")),
+			"{:?}",
+			err
+		);
+
+		let err = tee.attribute_error(ClgnDecodingError::Xml(
+			io::Error::from(io::ErrorKind::Other).into(),
+		));
+		assert!(
+			matches!(&err, ClgnDecodingError::Xml(quick_xml::Error::Io(_))),
+			"{:?}",
+			err
+		);
+	}
+}
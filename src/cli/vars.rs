@@ -0,0 +1,51 @@
+//! `clgn vars -i skeleton [pointer]`: print the variables in scope at the tag at
+//! `pointer` (a JSON pointer such as `/children/0/children/2`; the root if omitted),
+//! along with the scope each one's value came from.
+
+use crate::{to_svg::svg_writable::ClgnDecodingError, ClgnDecodingResult, Fibroblast};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub(super) fn get_subcommand() -> App<'static, 'static> {
+	SubCommand::with_name("vars")
+		.about("Print the variables in scope at a tag, and where each was defined")
+		.arg(
+			Arg::with_name("skeleton")
+				.short("i")
+				.required(true)
+				.takes_value(true)
+				.help("The path to the input skeleton folder"),
+		)
+		.arg(
+			Arg::with_name("pointer").default_value("").help(
+				"A JSON pointer to the tag, e.g., /children/0/children/2 (default: the root)",
+			),
+		)
+}
+
+pub(super) fn handle_matches(matches: &ArgMatches) -> ClgnDecodingResult<()> {
+	// safe so long as skeleton is required and pointer has a default value
+	let in_file = matches.value_of_os("skeleton").unwrap();
+	let pointer = matches.value_of("pointer").unwrap();
+
+	let fibroblast = Fibroblast::from_dir(in_file)?;
+	let vars = fibroblast.variables_at(pointer)?;
+
+	let stdout = io::stdout();
+	let mut stdout = stdout.lock();
+	let name_width = vars.iter().map(|v| v.name.len()).max().unwrap_or(0);
+	for var in vars {
+		writeln!(
+			stdout,
+			"{:width$} = {}  ({})",
+			var.name,
+			var.value,
+			var.scope,
+			width = name_width
+		)
+		.map_err(|e| ClgnDecodingError::IoWrite(e, PathBuf::from("<stdout>")))?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,227 @@
+//! Anchors are the positions and sizes of elements that have an `id`, as far as they
+//! can be determined from the elements' attributes alone. They let a
+//! [`ConnectorTag`](crate::fibroblast::tags::ConnectorTag) refer to its endpoints by
+//! `id` instead of by coordinates. Anchors are collected from the whole document before
+//! it is written (since a connector may refer to an element that comes after it) and
+//! stored in the [`DecodingContext`](super::DecodingContext).
+//!
+//! Positions are read straight from attributes; `transform`s (on the element itself or
+//! on any of its ancestors) are not taken into account.
+
+use super::{AttrKVValueVec, Map};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum AnchorOutline {
+	Rect,
+	Ellipse,
+}
+
+/// The region occupied by an element: a rectangle or ellipse centered at `(cx, cy)`
+/// whose half-width and half-height are `rx` and `ry`. A point has `rx == ry == 0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Anchor {
+	pub(crate) cx: f64,
+	pub(crate) cy: f64,
+	pub(crate) rx: f64,
+	pub(crate) ry: f64,
+	pub(crate) outline: AnchorOutline,
+}
+
+/// What is known about the element(s) with a given `id`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum AnchorEntry {
+	Found(Anchor),
+	/// The element's position couldn't be determined from its attributes. Contains the
+	/// element's tag name.
+	Unsupported(String),
+	/// More than one element has this `id`
+	Duplicate,
+}
+
+/// All of the anchors in a document, keyed by `id`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Anchors {
+	pub(crate) entries: Map<String, AnchorEntry>,
+	/// Whether any connector in the document needs an arrowhead marker
+	pub(crate) needs_arrowhead: bool,
+}
+
+impl Anchors {
+	pub(crate) fn insert(&mut self, id: String, entry: AnchorEntry) {
+		self.entries
+			.entry(id)
+			.and_modify(|e| *e = AnchorEntry::Duplicate)
+			.or_insert(entry);
+	}
+}
+
+impl Anchor {
+	/// The rectangle whose top left corner is `(x, y)`
+	pub(crate) fn rect(x: f64, y: f64, width: f64, height: f64) -> Self {
+		Self {
+			cx: x + width / 2.0,
+			cy: y + height / 2.0,
+			rx: width / 2.0,
+			ry: height / 2.0,
+			outline: AnchorOutline::Rect,
+		}
+	}
+
+	pub(crate) fn circle(cx: f64, cy: f64, r: f64) -> Self {
+		Self {
+			cx,
+			cy,
+			rx: r,
+			ry: r,
+			outline: AnchorOutline::Ellipse,
+		}
+	}
+
+	/// The anchor of a tag with name `tag_name` and attributes `attrs`, if it can be
+	/// determined
+	pub(crate) fn from_attrs(tag_name: &str, attrs: &AttrKVValueVec) -> Option<Self> {
+		let num = |name: &str| {
+			attrs
+				.iter()
+				.find(|(k, _)| *k == name)
+				.and_then(|(_, v)| v.to_number())
+		};
+		// Missing coordinates default to 0, as they do in SVG
+		let coord = |name: &str| match attrs.iter().find(|(k, _)| *k == name) {
+			None => Some(0.0),
+			Some((_, v)) => v.to_number(),
+		};
+
+		let anchor = match tag_name {
+			"rect" | "image" | "use" | "svg" | "foreignObject" => {
+				Self::rect(coord("x")?, coord("y")?, num("width")?, num("height")?)
+			}
+			"circle" => Self::circle(coord("cx")?, coord("cy")?, num("r")?),
+			"ellipse" => Self {
+				cx: coord("cx")?,
+				cy: coord("cy")?,
+				rx: num("rx")?,
+				ry: num("ry")?,
+				outline: AnchorOutline::Ellipse,
+			},
+			"line" => {
+				let (x1, y1, x2, y2) = (coord("x1")?, coord("y1")?, coord("x2")?, coord("y2")?);
+				Self::rect(x1.min(x2), y1.min(y2), (x1 - x2).abs(), (y1 - y2).abs())
+			}
+			"text" => Self::rect(coord("x")?, coord("y")?, 0.0, 0.0),
+			_ => return None,
+		};
+
+		Some(anchor)
+	}
+
+	pub(crate) fn center(&self) -> (f64, f64) {
+		(self.cx, self.cy)
+	}
+
+	/// The point where the ray from this anchor's center toward `(x, y)` leaves the
+	/// anchor's outline
+	pub(crate) fn boundary_toward(&self, (x, y): (f64, f64)) -> (f64, f64) {
+		let (dx, dy) = (x - self.cx, y - self.cy);
+		if (dx == 0.0 && dy == 0.0) || self.rx <= 0.0 || self.ry <= 0.0 {
+			return self.center();
+		}
+
+		let t = match self.outline {
+			AnchorOutline::Rect => {
+				let tx = if dx == 0.0 {
+					f64::INFINITY
+				} else {
+					self.rx / dx.abs()
+				};
+				let ty = if dy == 0.0 {
+					f64::INFINITY
+				} else {
+					self.ry / dy.abs()
+				};
+				tx.min(ty)
+			}
+			AnchorOutline::Ellipse => {
+				1.0 / ((dx / self.rx).powi(2) + (dy / self.ry).powi(2)).sqrt()
+			}
+		};
+
+		(self.cx + t * dx, self.cy + t * dy)
+	}
+
+	/// The midpoint of the side of this anchor facing in the direction `(dx, dy)`, which
+	/// must be horizontal or vertical
+	pub(crate) fn side_toward(&self, (dx, dy): (f64, f64)) -> (f64, f64) {
+		// Unlike `f64::signum`, this is 0 at 0
+		let sign = |d: f64| {
+			if d > 0.0 {
+				1.0
+			} else if d < 0.0 {
+				-1.0
+			} else {
+				0.0
+			}
+		};
+		(self.cx + sign(dx) * self.rx, self.cy + sign(dy) * self.ry)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fibroblast::data_types::{ConcreteNumber, SimpleValue};
+	use std::borrow::Cow;
+
+	fn attrs<'a>(kvs: &[(&'a str, f64)]) -> AttrKVValueVec<'a> {
+		AttrKVValueVec(
+			kvs.iter()
+				.map(|&(k, v)| (k, Cow::Owned(SimpleValue::Number(ConcreteNumber::Float(v)))))
+				.collect(),
+		)
+	}
+
+	#[test]
+	fn from_attrs() {
+		assert_eq!(
+			Anchor::from_attrs(
+				"rect",
+				&attrs(&[("x", 10.0), ("width", 20.0), ("height", 10.0)])
+			),
+			Some(Anchor::rect(10.0, 0.0, 20.0, 10.0))
+		);
+		assert_eq!(
+			Anchor::from_attrs("circle", &attrs(&[("cx", 1.0), ("cy", 2.0), ("r", 3.0)])),
+			Some(Anchor::circle(1.0, 2.0, 3.0))
+		);
+		assert_eq!(Anchor::from_attrs("rect", &attrs(&[("width", 1.0)])), None);
+		assert_eq!(Anchor::from_attrs("path", &attrs(&[])), None);
+	}
+
+	#[test]
+	fn boundaries() {
+		let rect = Anchor::rect(-10.0, -5.0, 20.0, 10.0);
+		assert_eq!(rect.boundary_toward((100.0, 0.0)), (10.0, 0.0));
+		assert_eq!(rect.boundary_toward((0.0, -100.0)), (0.0, -5.0));
+		assert_eq!(rect.boundary_toward((100.0, 100.0)), (5.0, 5.0));
+		assert_eq!(rect.side_toward((-1.0, 0.0)), (-10.0, 0.0));
+		assert_eq!(rect.side_toward((0.0, 1.0)), (0.0, 5.0));
+
+		let circle = Anchor::circle(0.0, 0.0, 10.0);
+		assert_eq!(circle.boundary_toward((0.0, 30.0)), (0.0, 10.0));
+		let (x, y) = circle.boundary_toward((30.0, 30.0));
+		assert!((x - 50f64.sqrt()).abs() < 1e-9 && (y - 50f64.sqrt()).abs() < 1e-9);
+	}
+
+	#[test]
+	fn duplicates() {
+		let mut anchors = Anchors::default();
+		anchors.insert("a".to_owned(), AnchorEntry::Unsupported("path".to_owned()));
+		anchors.insert("b".to_owned(), AnchorEntry::Unsupported("path".to_owned()));
+		anchors.insert("a".to_owned(), AnchorEntry::Unsupported("path".to_owned()));
+		assert_eq!(anchors.entries["a"], AnchorEntry::Duplicate);
+		assert_eq!(
+			anchors.entries["b"],
+			AnchorEntry::Unsupported("path".to_owned())
+		);
+	}
+}
@@ -0,0 +1,177 @@
+//! The names of attributes, which are interned. A skeleton uses the same few dozen
+//! attribute names (`x`, `fill`, `transform`, ...) over and over, and tags that are
+//! copied, such as the copies of a [`RepeatTag`](crate::fibroblast::tags::RepeatTag)'s
+//! template, copy all of their attributes. Interning the names means that each distinct
+//! name is allocated once, and copying one just copies a pointer to it.
+//!
+//! Each thread interns names on its own, so interning takes no lock. Names that are no
+//! longer used by any tag are freed as more are interned, so that a long-running process
+//! (e.g., `clgn daemon`) doesn't hold on to the name of every attribute of every
+//! skeleton it's ever rendered.
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// The fewest names an [`Interner`] holds before it frees those no longer in use
+const MIN_PRUNE_LEN: usize = 1024;
+
+/// The attribute names interned so far on one thread
+struct Interner {
+	names: HashSet<Arc<str>>,
+	/// The number of names at which to next free those no longer in use. Doubling it
+	/// each time keeps the cost of freeing them proportional to the number interned.
+	prune_at: usize,
+}
+
+impl Interner {
+	fn intern(&mut self, name: &str) -> Arc<str> {
+		if let Some(interned) = self.names.get(name) {
+			return Arc::clone(interned);
+		}
+
+		if self.names.len() >= self.prune_at {
+			// A name that only the interner refers to isn't used by any tag. (No other
+			// thread can start using it, either, since doing so requires a reference to
+			// it.)
+			self.names.retain(|name| Arc::strong_count(name) > 1);
+			self.prune_at = (2 * self.names.len()).max(MIN_PRUNE_LEN);
+		}
+
+		let interned = Arc::<str>::from(name);
+		self.names.insert(Arc::clone(&interned));
+		interned
+	}
+}
+
+thread_local! {
+	static INTERNER: RefCell<Interner> = RefCell::new(Interner {
+		names: HashSet::new(),
+		prune_at: MIN_PRUNE_LEN,
+	});
+}
+
+/// The (interned) name of an attribute. Derefs to `str`, and can be looked up in maps by
+/// `&str`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct AttrName(Arc<str>);
+
+impl AttrName {
+	pub(crate) fn new(name: &str) -> Self {
+		AttrName(INTERNER.with(|interner| interner.borrow_mut().intern(name)))
+	}
+}
+
+impl Deref for AttrName {
+	type Target = str;
+
+	fn deref(&self) -> &Self::Target {
+		&self.0
+	}
+}
+
+impl AsRef<str> for AttrName {
+	fn as_ref(&self) -> &str {
+		&self.0
+	}
+}
+
+impl Borrow<str> for AttrName {
+	fn borrow(&self) -> &str {
+		&self.0
+	}
+}
+
+impl From<&str> for AttrName {
+	fn from(name: &str) -> Self {
+		AttrName::new(name)
+	}
+}
+
+impl fmt::Debug for AttrName {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Debug::fmt(&*self.0, f)
+	}
+}
+
+impl fmt::Display for AttrName {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+impl Serialize for AttrName {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serializer.serialize_str(&self.0)
+	}
+}
+
+impl<'de> Deserialize<'de> for AttrName {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		struct AttrNameVisitor;
+
+		impl<'de> Visitor<'de> for AttrNameVisitor {
+			type Value = AttrName;
+
+			fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+				f.write_str("an attribute name")
+			}
+
+			// Interning a borrowed name doesn't allocate unless the name is new
+			fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+				Ok(AttrName::new(name))
+			}
+		}
+
+		deserializer.deserialize_str(AttrNameVisitor)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fibroblast::data_types::XmlAttrs;
+
+	#[test]
+	fn interning() {
+		let attrs: XmlAttrs = serde_json::from_str(r#"{ "fill": "red", "x": 1 }"#).unwrap();
+		let other_attrs: XmlAttrs = serde_json::from_str(r#"{ "fill": "blue" }"#).unwrap();
+		let name = |attrs: &XmlAttrs, name: &str| attrs.keys().find(|k| &***k == name).cloned();
+
+		let fill = name(&attrs, "fill").unwrap();
+		assert!(Arc::ptr_eq(&fill.0, &name(&other_attrs, "fill").unwrap().0));
+		assert!(Arc::ptr_eq(&fill.0, &AttrName::new("fill").0));
+		assert!(!Arc::ptr_eq(&fill.0, &name(&attrs, "x").unwrap().0));
+
+		assert_eq!(attrs.get("fill"), attrs.get(&AttrName::new("fill")));
+		assert_eq!(
+			serde_json::to_string(&attrs).unwrap(),
+			r#"{"fill":"red","x":1}"#
+		);
+	}
+
+	#[test]
+	fn unused_names_are_freed() {
+		let kept = AttrName::new("data-kept");
+		let dropped = Arc::downgrade(&AttrName::new("data-dropped").0);
+
+		for i in 0..2 * MIN_PRUNE_LEN {
+			AttrName::new(&format!("data-{}", i));
+		}
+
+		assert!(dropped.upgrade().is_none());
+		assert!(Arc::ptr_eq(&kept.0, &AttrName::new("data-kept").0));
+		INTERNER.with(|interner| assert!(interner.borrow().names.len() <= MIN_PRUNE_LEN));
+	}
+}
@@ -0,0 +1,74 @@
+//! Class styles are sets of CSS properties, e.g., `"class_styles": { "fill": "red",
+//! "stroke": "blue" }`, that are moved out of the elements that have them and into a
+//! `<style>` at the end of the document. Each distinct set of properties becomes one
+//! generated class, e.g., `.clgn-class-0{fill:red;stroke:blue}`, which is added to the
+//! `class` attribute of every element with that set. A document with thousands of
+//! similar elements, such as a scatter plot, then spells out the properties once
+//! instead of thousands of times.
+//!
+//! Like themed values, class styles are collected in the
+//! [`DecodingContext`](super::DecodingContext) as the document is written.
+
+/// All of the distinct sets of CSS properties in a document, each with the index of the
+/// class it's assigned to
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ClassStyles {
+	/// CSS declarations, e.g., `fill:red;stroke:blue`; the declarations at index `i` are
+	/// assigned to the class `clgn-class-{i}`
+	declarations: Vec<String>,
+}
+
+impl ClassStyles {
+	/// Get the name of the class whose style is `declarations`, creating the class if it
+	/// doesn't already exist
+	pub(crate) fn class_for(&mut self, declarations: String) -> String {
+		let index = match self.declarations.iter().position(|d| *d == declarations) {
+			Some(index) => index,
+			None => {
+				self.declarations.push(declarations);
+				self.declarations.len() - 1
+			}
+		};
+
+		Self::class_name(index)
+	}
+
+	fn class_name(index: usize) -> String {
+		format!("clgn-class-{}", index)
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.declarations.is_empty()
+	}
+
+	/// The CSS that gives each class its style
+	pub(crate) fn to_css(&self) -> String {
+		self.declarations
+			.iter()
+			.enumerate()
+			.map(|(i, declarations)| format!(".{}{{{}}}", Self::class_name(i), declarations))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn class_for_dedups() {
+		let mut class_styles = ClassStyles::default();
+		assert!(class_styles.is_empty());
+
+		let red = class_styles.class_for("fill:red".to_owned());
+		let blue = class_styles.class_for("fill:blue;stroke:red".to_owned());
+		assert_eq!(red, "clgn-class-0");
+		assert_eq!(blue, "clgn-class-1");
+		assert_eq!(class_styles.class_for("fill:red".to_owned()), red);
+
+		assert_eq!(
+			class_styles.to_css(),
+			".clgn-class-0{fill:red}.clgn-class-1{fill:blue;stroke:red}"
+		);
+	}
+}
@@ -79,6 +79,19 @@ impl std::cmp::PartialEq for ConcreteNumber {
 	}
 }
 
+impl ConcreteNumber {
+	/// This number as an `f64`; an integer too big to be represented exactly becomes the
+	/// nearest `f64`
+	pub(crate) fn as_f64(self) -> f64 {
+		use self::ConcreteNumber::*;
+		match self {
+			Int(x) => x as f64,
+			UInt(x) => x as f64,
+			Float(x) => x,
+		}
+	}
+}
+
 impl std::fmt::Display for ConcreteNumber {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		use self::ConcreteNumber::*;
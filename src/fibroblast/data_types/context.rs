@@ -10,20 +10,163 @@
 //! a deserialized `path`, the root path must also be supplied; only then can decoding
 //! proceed.
 
-use super::{AttrKVValueVec, SimpleValue, TagVariables, VariableValue};
-use crate::fibroblast::data_types::{Map, MapEntry};
-use crate::to_svg::svg_writable::ClgnDecodingResult;
+use super::{
+	AnchorEntry, Anchors, AttrKVValueVec, ClassStyles, SimpleValue, TagVariables, ThemedValue,
+	Themes, VariableValue,
+};
+use crate::fibroblast::data_types::{ConcreteNumber, Map, MapEntry};
+use crate::to_svg::output_format::OutputFormat;
+use crate::to_svg::profile::Profile;
+use crate::to_svg::render_timings::{RenderPhase, RenderTimer};
+use crate::to_svg::size_limits::SizeLimits;
+use crate::to_svg::svg_writable::{ClgnDecodingError, ClgnDecodingResult};
+use crate::to_svg::tag_sizes::TagSizeRecorder;
+use crate::to_svg::target::{Feature, Target};
+use crate::utils::cache::AssetCache;
+use crate::utils::file_source::FileSource;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::borrow::Cow;
-use std::cell::{Ref, RefCell};
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 #[cfg(test)]
 use std::str::FromStr;
 
 lazy_static! {
 	static ref VAR_NAME_CHAR_RE: Regex = Regex::new(r"\w").unwrap();
+	static ref PALETTE_REF_RE: Regex = Regex::new(r"^@([\w-]+)$").unwrap();
+	static ref RELATIVE_UNIT_RE: Regex =
+		Regex::new(r"^\s*([+-]?(?:\d+\.?\d*|\.\d+)(?:[eE][+-]?\d+)?)\s*%([wh])\s*$").unwrap();
+	static ref UNICODE_ESCAPE_RE: Regex = Regex::new(r"^u\{([[:xdigit:]]{1,6})\}").unwrap();
+	static ref ENTITY_ESCAPE_RE: Regex = Regex::new(r"^&([[:alpha:]][[:alnum:]]*);").unwrap();
+}
+
+/// The deepest that tags may be nested (counting the tags of nested skeletons) unless
+/// otherwise specified. Deeper nesting is almost surely a skeleton that includes itself.
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 256;
+
+/// The named character entities that may be written `\&name;`: those predefined in
+/// XML, along with some of the more commonly used ones from HTML
+const NAMED_ENTITIES: &[(&str, char)] = &[
+	("amp", '&'),
+	("lt", '<'),
+	("gt", '>'),
+	("quot", '"'),
+	("apos", '\''),
+	("nbsp", '\u{a0}'),
+	("copy", '©'),
+	("reg", '®'),
+	("trade", '™'),
+	("deg", '°'),
+	("plusmn", '±'),
+	("times", '×'),
+	("divide", '÷'),
+	("minus", '−'),
+	("micro", 'µ'),
+	("middot", '·'),
+	("bull", '•'),
+	("hellip", '…'),
+	("ndash", '–'),
+	("mdash", '—'),
+	("lsquo", '‘'),
+	("rsquo", '’'),
+	("ldquo", '“'),
+	("rdquo", '”'),
+	("laquo", '«'),
+	("raquo", '»'),
+	("sect", '§'),
+	("para", '¶'),
+	("cent", '¢'),
+	("pound", '£'),
+	("euro", '€'),
+	("yen", '¥'),
+	("larr", '←'),
+	("uarr", '↑'),
+	("rarr", '→'),
+	("darr", '↓'),
+	("harr", '↔'),
+	("le", '≤'),
+	("ge", '≥'),
+	("ne", '≠'),
+	("asymp", '≈'),
+	("infin", '∞'),
+	("check", '✓'),
+];
+
+/// Whether `c` may appear in an XML document
+/// (<https://www.w3.org/TR/xml/#charsets>)
+fn is_xml_char(c: char) -> bool {
+	matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{d7ff}' | '\u{e000}'..='\u{fffd}' | '\u{10000}'..)
+}
+
+/// Parse the escape sequence at the start of `rest`, which immediately follows a
+/// backslash at `position`. The sequence is either `u{<hex>}`, the Unicode code point
+/// `<hex>`, or `&<name>;`, the named entity `<name>`. Returns the escaped character and
+/// the length of the sequence (not including the backslash).
+fn parse_escape(rest: &str, position: usize) -> Result<(char, usize), ParseError> {
+	if let Some(caps) = UNICODE_ESCAPE_RE.captures(rest) {
+		let sequence = &caps[0];
+		// The regex ensures this parses
+		let code_point = u32::from_str_radix(&caps[1], 16).unwrap();
+		return match char::from_u32(code_point).filter(|c| is_xml_char(*c)) {
+			Some(c) => Ok((c, sequence.len())),
+			None => Err(ParseError::InvalidUnicodeEscape {
+				position,
+				sequence: format!("\\{}", sequence),
+			}),
+		};
+	}
+
+	if let Some(caps) = ENTITY_ESCAPE_RE.captures(rest) {
+		let name = &caps[1];
+		return match NAMED_ENTITIES.iter().find(|(n, _)| *n == name) {
+			Some((_, c)) => Ok((*c, caps[0].len())),
+			None => Err(ParseError::UnknownEntity {
+				position,
+				name: name.to_owned(),
+			}),
+		};
+	}
+
+	let sequence = if rest.starts_with('u') { "\\u" } else { "\\&" };
+	Err(ParseError::InvalidUnicodeEscape {
+		position,
+		sequence: sequence.to_owned(),
+	})
+}
+
+/// The width and height of the viewport established by an `<svg>` with attributes
+/// `attrs`, taken from its `viewBox` if it has one and its `width` and `height`
+/// otherwise
+pub(crate) fn viewport_size(attrs: &AttrKVValueVec) -> Option<(f64, f64)> {
+	let attr = |name: &str| attrs.iter().find(|(k, _)| *k == name).map(|(_, v)| v);
+
+	if let Some([_, _, width, height]) = attr("viewBox")
+		.and_then(|v| v.to_maybe_string())
+		.and_then(|view_box| parse_view_box(&view_box))
+	{
+		return Some((width, height));
+	}
+
+	Some((attr("width")?.to_number()?, attr("height")?.to_number()?))
+}
+
+/// The `[min-x, min-y, width, height]` of the `viewBox` attribute value `view_box`, or
+/// `None` if it's malformed
+pub(crate) fn parse_view_box(view_box: &str) -> Option<[f64; 4]> {
+	let dims = view_box
+		.split(|c: char| c == ',' || c.is_whitespace())
+		.filter(|s| !s.is_empty())
+		.map(|s| s.parse::<f64>().ok())
+		.collect::<Option<Vec<_>>>()?;
+
+	match dims.as_slice() {
+		&[x, y, width, height] => Some([x, y, width, height]),
+		_ => None,
+	}
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -42,6 +185,14 @@ pub enum ParseError {
 		position: (usize, usize),
 		char: char,
 	},
+	InvalidUnicodeEscape {
+		position: usize,
+		sequence: String,
+	},
+	UnknownEntity {
+		position: usize,
+		name: String,
+	},
 }
 
 impl std::fmt::Display for ParseError {
@@ -65,10 +216,34 @@ impl std::fmt::Display for ParseError {
 				r#"Invalid escape sequence "\{}" at position {:?}"#,
 				char, position
 			)),
+			InvalidUnicodeEscape { position, sequence } => f.write_fmt(format_args!(
+				r#"Invalid escape sequence {:?} at position {}; expected "\u{{<hex>}}" or "\&<name>;", denoting a character allowed in XML"#,
+				sequence, position
+			)),
+			UnknownEntity { position, name } => f.write_fmt(format_args!(
+				r#"Unknown named entity "\&{};" at position {}"#,
+				name, position
+			)),
 		}
 	}
 }
 
+/// Whether `name` may be defined as a variable (in `vars`, or as a repeat tag's `var`),
+/// i.e., whether `"{name}"` would refer to it rather than being an illegal variable name
+pub(crate) fn is_valid_var_name(name: &str) -> bool {
+	!name.is_empty()
+		&& name
+			.chars()
+			.all(|c| VAR_NAME_CHAR_RE.is_match(&c.to_string()))
+}
+
+/// Whether `"{name}"` refers to a variable: one that may be defined (see
+/// [`is_valid_var_name`]), or one exported by a named container, such as `logo.width`
+/// (see [`ContainerTag`](crate::fibroblast::tags::ContainerTag))
+fn is_valid_var_ref(name: &str) -> bool {
+	name.split('.').all(is_valid_var_name)
+}
+
 /// It's really tempting to want to change these `String`s to `&'a str`s, but if you do
 /// that, then [`ClgnDecodingError`] — and hence [`ClgnDecodingResult`] — need lifetimes
 /// too. Yech.
@@ -100,15 +275,87 @@ impl VariableSubstitutionError {
 
 /// A context in which something can be decoded
 ///
-/// Consists of the root path (for resolving relative paths) and a variable key-value
-/// map for performing variable substitution
+/// Consists of the root path (for resolving relative paths), a variable key-value map
+/// for performing variable substitution, the anchors of the document being decoded
+/// (for connecting elements by `id`), the palette of named colors, the themed values
+/// and class styles encountered so far (for writing the document's CSS), and options
+/// affecting how decoding proceeds
 #[derive(Debug, Clone)]
 pub struct DecodingContext<'a> {
 	root_path: RefCell<PathBuf>, // can this be turned into a `Cow<'a, Path>`?
 	vars_map: RefCell<Map<&'a str, &'a VariableValue>>,
+	anchors: RefCell<Anchors>,
+	palette: RefCell<Map<String, String>>,
+	/// Palette entries that take precedence over those defined in the skeleton, e.g.,
+	/// those given on the command line
+	palette_overrides: RefCell<Map<String, String>>,
+	themes: RefCell<Themes>,
+	class_styles: RefCell<ClassStyles>,
+	/// Whether a missing image should be replaced with a placeholder instead of being an
+	/// error
+	placeholder_missing_assets: Cell<bool>,
+	size_limits: Cell<SizeLimits>,
+	/// The folder that Google fonts are read from
+	font_cache_dir: RefCell<Option<PathBuf>>,
+	/// The cache that derived artifacts, such as cropped images, are stored in, if any
+	asset_cache: RefCell<Option<AssetCache>>,
+	/// Whether to write an XML declaration even if the root tag doesn't ask for one
+	force_xml_declaration: Cell<bool>,
+	/// Whether to give images an `xlink:href` in addition to an `href`
+	xlink_href: Cell<bool>,
+	/// Whether to convert images with embedded color profiles to sRGB
+	convert_to_srgb: Cell<bool>,
+	/// The format of the SVG given on the command line, which takes precedence over the
+	/// root tag's `output`
+	output_format: Cell<OutputFormat>,
+	/// The profile whose defaults fill in the root tag's missing attributes, if any
+	profile: Cell<Option<Profile>>,
+	/// The target whose support for the document's features is checked, if any
+	target: Cell<Option<Target>>,
+	/// The names of the variables to export as CSS custom properties on the root tag
+	exported_vars: RefCell<Vec<String>>,
+	/// The hash of the document's inputs to stamp on the root tag as `data-clgn-hash`,
+	/// if any
+	stamped_hash: RefCell<Option<String>>,
+	/// The features, among those that not every target supports, used by the document
+	/// being written
+	used_features: RefCell<BTreeSet<Feature>>,
+	/// Records the size of each tag as it's written, if sizes are being recorded
+	tag_size_recorder: RefCell<Option<TagSizeRecorder>>,
+	/// Records the time taken to write each tag, if times are being recorded
+	render_timer: RefCell<Option<RenderTimer>>,
+	/// The namespace prefixes declared by the tags currently being written
+	namespaces: RefCell<Vec<String>>,
+	/// The number of `id`s generated so far in the document being written
+	n_generated_ids: Cell<usize>,
+	/// The `id` of the `<symbol>` of each symbol used in the document being written, by
+	/// the (canonical) path of the library it's from and its name
+	imported_symbols: RefCell<Map<(PathBuf, String), String>>,
+	/// The credits of the assets embedded in the document being written, in the order
+	/// in which they were first embedded, without duplicates
+	credits: RefCell<Vec<String>>,
+	/// The variables that have been found shadowing a variable of a different type, each
+	/// of which is only warned about once
+	shadowed_vars: RefCell<BTreeSet<String>>,
+	/// The number of tags enclosing the one currently being visited, counting those in
+	/// the skeletons that contain this one
+	nesting_depth: Cell<usize>,
+	/// The most that `nesting_depth` may be
+	max_nesting_depth: Cell<usize>,
+	/// Where the skeleton's files are read from
+	file_source: RefCell<FileSource>,
 }
 
 impl<'a> DecodingContext<'a> {
+	/// The names of the variables that are set automatically (see
+	/// [`Self::with_viewport_vars`]), which manifests may not define themselves
+	pub(crate) const RESERVED_VAR_NAMES: [&'static str; 4] = [
+		"parent_width",
+		"parent_height",
+		"canvas_width",
+		"canvas_height",
+	];
+
 	pub(crate) fn new(
 		root_path: PathBuf,
 		vars_intoiter: impl IntoIterator<Item = (&'a str, &'a VariableValue)>,
@@ -118,6 +365,34 @@ impl<'a> DecodingContext<'a> {
 		Self {
 			root_path: RefCell::new(root_path),
 			vars_map: RefCell::new(vars_ref_map),
+			anchors: RefCell::new(Anchors::default()),
+			palette: RefCell::new(Map::new()),
+			palette_overrides: RefCell::new(Map::new()),
+			themes: RefCell::new(Themes::default()),
+			class_styles: RefCell::new(ClassStyles::default()),
+			placeholder_missing_assets: Cell::new(false),
+			size_limits: Cell::new(SizeLimits::default()),
+			font_cache_dir: RefCell::new(None),
+			asset_cache: RefCell::new(None),
+			force_xml_declaration: Cell::new(false),
+			xlink_href: Cell::new(false),
+			convert_to_srgb: Cell::new(false),
+			output_format: Cell::new(OutputFormat::default()),
+			profile: Cell::new(None),
+			target: Cell::new(None),
+			exported_vars: RefCell::new(Vec::new()),
+			stamped_hash: RefCell::new(None),
+			used_features: RefCell::new(BTreeSet::new()),
+			tag_size_recorder: RefCell::new(None),
+			render_timer: RefCell::new(None),
+			namespaces: RefCell::new(Vec::new()),
+			credits: RefCell::new(Vec::new()),
+			n_generated_ids: Cell::new(0),
+			imported_symbols: RefCell::new(Map::new()),
+			shadowed_vars: RefCell::new(BTreeSet::new()),
+			nesting_depth: Cell::new(0),
+			max_nesting_depth: Cell::new(DEFAULT_MAX_NESTING_DEPTH),
+			file_source: RefCell::new(FileSource::default()),
 		}
 	}
 
@@ -152,22 +427,31 @@ impl<'a> DecodingContext<'a> {
 		result
 	}
 
-	pub(crate) fn get_root(&self) -> Ref<PathBuf> {
+	pub(crate) fn get_root(&self) -> Ref<'_, PathBuf> {
 		self.root_path.borrow()
 	}
 
-	#[cfg(test)]
-	pub(crate) fn vars_map(&self) -> Ref<Map<&str, &VariableValue>> {
+	pub(crate) fn set_file_source(&self, file_source: FileSource) {
+		self.file_source.replace(file_source);
+	}
+
+	pub(crate) fn file_source(&self) -> FileSource {
+		self.file_source.borrow().clone()
+	}
+
+	pub(crate) fn vars_map(&self) -> Ref<'_, Map<&str, &VariableValue>> {
 		self.vars_map.borrow()
 	}
 
 	/// Append the given variables to self (i.e., introduce them as a nested scope),
-	/// call `f()` in this scope. `self` is not mutated.
+	/// call `f()` in this scope. `self` is not mutated. A variable that shadows one of a
+	/// different type (e.g., a string shadowing a number) is probably a mistake, and is
+	/// warned about.
 	///
 	/// > Actually `self` *is* mutated via `RefCell`, but only temporarily -- it is
-	/// modified to create the correct state for `f` to be called in and then it's
-	/// restored to its original state so that it is as if it had never changed at all.
-	/// (It might be regarded as "net non-mutating".)
+	/// > modified to create the correct state for `f` to be called in and then it's
+	/// > restored to its original state so that it is as if it had never changed at all.
+	/// > (It might be regarded as "net non-mutating".)
 	///
 	/// > *CAUTION*: For this reason, this function is almost certainly not thread safe.
 	pub(crate) fn with_new_vars<T, F: FnOnce() -> ClgnDecodingResult<T>>(
@@ -195,6 +479,7 @@ impl<'a> DecodingContext<'a> {
 				let entry = my_vars.entry(&*k);
 				match entry {
 					MapEntry::Occupied(mut occ) => {
+						self.check_shadowing(&*k, occ.get(), &*v);
 						orig_vars.push((&*k, Some(occ.insert(&*v))));
 					}
 					MapEntry::Vacant(vac) => {
@@ -223,6 +508,573 @@ impl<'a> DecodingContext<'a> {
 		result
 	}
 
+	/// Warn (once per variable name) if `inner`, the value of the variable `name`, shadows
+	/// `outer`, a value of a different type
+	fn check_shadowing(&self, name: &str, outer: &VariableValue, inner: &VariableValue) {
+		if outer.type_name() == inner.type_name()
+			|| !self.shadowed_vars.borrow_mut().insert(name.to_owned())
+		{
+			return;
+		}
+
+		eprintln!(
+			"Warning: the variable `{}`, a {}, shadows a variable of the same name that's a {} \
+			 (with value {:?}) in an enclosing scope",
+			name,
+			inner.type_name(),
+			outer.type_name(),
+			outer.as_str()
+		);
+	}
+
+	/// The variables that have been found shadowing a variable of a different type
+	#[cfg(test)]
+	pub(crate) fn shadowed_vars(&self) -> Ref<'_, BTreeSet<String>> {
+		self.shadowed_vars.borrow()
+	}
+
+	pub(crate) fn replace_anchors(&self, anchors: Anchors) -> Anchors {
+		self.anchors.replace(anchors)
+	}
+
+	/// Start generating `id`s afresh, for a new document
+	pub(crate) fn reset_generated_ids(&self) {
+		self.n_generated_ids.set(0);
+		self.imported_symbols.borrow_mut().clear();
+	}
+
+	/// A new `id`, `<prefix>-<n>`, that's different from every other `id` generated for
+	/// this document and every `id` among the anchors
+	pub(crate) fn generate_id(&self, prefix: &str) -> String {
+		loop {
+			let n = self.n_generated_ids.get();
+			self.n_generated_ids.set(n + 1);
+
+			let id = format!("{}-{}", prefix, n);
+			if !self.anchors.borrow().entries.contains_key(&id) {
+				return id;
+			}
+		}
+	}
+
+	/// The `id` of the `<symbol>` of the symbol `symbol` from the library at `library`,
+	/// which is generated the first time the symbol is used in a document, along with
+	/// whether this is that first time
+	pub(crate) fn import_symbol(&self, library: PathBuf, symbol: &str) -> (String, bool) {
+		let key = (library, symbol.to_owned());
+		if let Some(id) = self.imported_symbols.borrow().get(&key) {
+			return (id.clone(), false);
+		}
+
+		let id = self.generate_id(&format!("clgn-symbol-{}", symbol));
+		self.imported_symbols.borrow_mut().insert(key, id.clone());
+		(id, true)
+	}
+
+	/// Note that the document being written embeds an asset whose credit is `credit`
+	pub(crate) fn note_credit(&self, credit: String) {
+		let mut credits = self.credits.borrow_mut();
+		if !credits.contains(&credit) {
+			credits.push(credit);
+		}
+	}
+
+	/// The credits of the assets embedded in the document being written, which are
+	/// forgotten
+	pub(crate) fn take_credits(&self) -> Vec<String> {
+		self.credits.take()
+	}
+
+	pub(crate) fn get_anchor(&self, id: &str) -> Option<AnchorEntry> {
+		self.anchors.borrow().entries.get(id).cloned()
+	}
+
+	pub(crate) fn needs_arrowhead(&self) -> bool {
+		self.anchors.borrow().needs_arrowhead
+	}
+
+	/// Call `f` with the colors in `palette` added to the current palette (replacing any
+	/// existing colors with the same names). As with [`Self::with_new_vars`], the
+	/// palette is restored once `f` returns.
+	pub(crate) fn with_palette<T>(
+		&self,
+		palette: &Map<String, String>,
+		f: impl FnOnce() -> ClgnDecodingResult<T>,
+	) -> ClgnDecodingResult<T> {
+		if palette.is_empty() {
+			return f();
+		}
+
+		let orig_palette = self.palette.replace_with(|p| {
+			let mut p = p.clone();
+			p.extend(palette.iter().map(|(k, v)| (k.clone(), v.clone())));
+			p
+		});
+		let result = f();
+		self.palette.replace(orig_palette);
+
+		result
+	}
+
+	/// Set the palette color `name` to `color`, overriding any color of the same name
+	/// defined in the skeleton
+	pub(crate) fn override_palette_color(&self, name: impl Into<String>, color: impl Into<String>) {
+		self.palette_overrides
+			.borrow_mut()
+			.insert(name.into(), color.into());
+	}
+
+	/// Replace each attribute value of the form `"@name"` (including either variant of
+	/// a themed value) with the palette color `name`. It is an error if there is no such
+	/// color.
+	pub(crate) fn resolve_palette_colors(
+		&self,
+		attrs: &mut AttrKVValueVec,
+	) -> ClgnDecodingResult<()> {
+		for (_, value) in attrs.iter_mut() {
+			if let Some(color) = self.palette_color(value)? {
+				*value = Cow::Owned(color);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// If `value` refers to any palette colors, a copy of it with those colors filled
+	/// in; otherwise `None`
+	fn palette_color(&self, value: &SimpleValue) -> ClgnDecodingResult<Option<SimpleValue>> {
+		let name = match value {
+			SimpleValue::Text(s) => match PALETTE_REF_RE.captures(s) {
+				Some(caps) => caps[1].to_owned(),
+				None => return Ok(None),
+			},
+			SimpleValue::Themed(themed) => {
+				return Ok(
+					match (
+						self.palette_color(&themed.light)?,
+						self.palette_color(&themed.dark)?,
+					) {
+						(None, None) => None,
+						(light, dark) => Some(SimpleValue::Themed(Box::new(ThemedValue {
+							light: light.unwrap_or_else(|| themed.light.clone()),
+							dark: dark.unwrap_or_else(|| themed.dark.clone()),
+						}))),
+					},
+				)
+			}
+			_ => return Ok(None),
+		};
+
+		let color = match self.palette_overrides.borrow().get(&name) {
+			Some(color) => Some(color.clone()),
+			None => self.palette.borrow().get(&name).cloned(),
+		};
+		match color {
+			Some(color) => Ok(Some(SimpleValue::Text(color))),
+			None => Err(ClgnDecodingError::UnknownPaletteColor { name }),
+		}
+	}
+
+	pub(crate) fn set_placeholder_missing_assets(&self, placeholder: bool) {
+		self.placeholder_missing_assets.set(placeholder);
+	}
+
+	pub(crate) fn placeholder_missing_assets(&self) -> bool {
+		self.placeholder_missing_assets.get()
+	}
+
+	pub(crate) fn set_size_limits(&self, limits: SizeLimits) {
+		self.size_limits.set(limits);
+	}
+
+	pub(crate) fn size_limits(&self) -> SizeLimits {
+		self.size_limits.get()
+	}
+
+	pub(crate) fn set_font_cache_dir(&self, dir: impl Into<PathBuf>) {
+		self.font_cache_dir.replace(Some(dir.into()));
+	}
+
+	pub(crate) fn font_cache_dir(&self) -> Option<PathBuf> {
+		self.font_cache_dir.borrow().clone()
+	}
+
+	pub(crate) fn set_asset_cache(&self, cache: Option<AssetCache>) {
+		self.asset_cache.replace(cache);
+	}
+
+	pub(crate) fn asset_cache(&self) -> Option<AssetCache> {
+		self.asset_cache.borrow().clone()
+	}
+
+	pub(crate) fn set_force_xml_declaration(&self, force: bool) {
+		self.force_xml_declaration.set(force);
+	}
+
+	pub(crate) fn force_xml_declaration(&self) -> bool {
+		self.force_xml_declaration.get()
+	}
+
+	pub(crate) fn set_xlink_href(&self, xlink_href: bool) {
+		self.xlink_href.set(xlink_href);
+	}
+
+	pub(crate) fn xlink_href(&self) -> bool {
+		self.xlink_href.get()
+	}
+
+	pub(crate) fn set_convert_to_srgb(&self, convert: bool) {
+		self.convert_to_srgb.set(convert);
+	}
+
+	pub(crate) fn convert_to_srgb(&self) -> bool {
+		self.convert_to_srgb.get()
+	}
+
+	pub(crate) fn set_output_format(&self, format: OutputFormat) {
+		self.output_format.set(format);
+	}
+
+	pub(crate) fn output_format(&self) -> OutputFormat {
+		self.output_format.get()
+	}
+
+	pub(crate) fn set_profile(&self, profile: Option<Profile>) {
+		self.profile.set(profile);
+	}
+
+	pub(crate) fn profile(&self) -> Option<Profile> {
+		self.profile.get()
+	}
+
+	pub(crate) fn set_target(&self, target: Option<Target>) {
+		self.target.set(target);
+	}
+
+	pub(crate) fn target(&self) -> Option<Target> {
+		self.target.get()
+	}
+
+	pub(crate) fn set_exported_vars(&self, names: Vec<String>) {
+		*self.exported_vars.borrow_mut() = names;
+	}
+
+	pub(crate) fn exported_vars(&self) -> Ref<'_, Vec<String>> {
+		self.exported_vars.borrow()
+	}
+
+	pub(crate) fn set_stamped_hash(&self, hash: Option<String>) {
+		*self.stamped_hash.borrow_mut() = hash;
+	}
+
+	pub(crate) fn stamped_hash(&self) -> Option<String> {
+		self.stamped_hash.borrow().clone()
+	}
+
+	/// A description of the options that change the SVG that's written (as opposed to,
+	/// say, the size limits, which only determine whether it's written at all), which
+	/// differs whenever they do
+	pub(crate) fn output_options_fingerprint(&self) -> String {
+		format!(
+			"{:?}",
+			(
+				&*self.palette_overrides.borrow(),
+				&*self.exported_vars.borrow(),
+				self.placeholder_missing_assets.get(),
+				self.force_xml_declaration.get(),
+				self.xlink_href.get(),
+				self.convert_to_srgb.get(),
+				self.output_format.get(),
+				self.profile.get(),
+				self.target.get(),
+			)
+		)
+	}
+
+	/// Note that the document being written uses `feature`
+	pub(crate) fn note_feature(&self, feature: Feature) {
+		self.used_features.borrow_mut().insert(feature);
+	}
+
+	#[cfg(test)]
+	pub(crate) fn used_features(&self) -> BTreeSet<Feature> {
+		self.used_features.borrow().clone()
+	}
+
+	/// Forget the features used by a previously written document
+	pub(crate) fn reset_used_features(&self) {
+		self.used_features.borrow_mut().clear();
+	}
+
+	/// Check that the target, if any, supports the features used by the document that
+	/// was just written
+	pub(crate) fn check_target(&self) -> ClgnDecodingResult<()> {
+		match self.target.get() {
+			Some(target) => {
+				target.check_features(&self.used_features.borrow(), self.size_limits().strict)
+			}
+			None => Ok(()),
+		}
+	}
+
+	pub(crate) fn replace_tag_size_recorder(
+		&self,
+		recorder: Option<TagSizeRecorder>,
+	) -> Option<TagSizeRecorder> {
+		self.tag_size_recorder.replace(recorder)
+	}
+
+	pub(crate) fn replace_render_timer(&self, timer: Option<RenderTimer>) -> Option<RenderTimer> {
+		self.render_timer.replace(timer)
+	}
+
+	/// Call `f`, which writes the tag named `tag_name` of kind `kind` (the child at
+	/// `index` of the tag currently being written, or the root if `None`), recording the
+	/// tag's size and the time it takes to write if those are being recorded
+	pub(crate) fn with_recorded_tag<T>(
+		&self,
+		index: Option<usize>,
+		tag_name: &str,
+		kind: &'static str,
+		f: impl FnOnce() -> ClgnDecodingResult<T>,
+	) -> ClgnDecodingResult<T> {
+		if let Some(recorder) = self.tag_size_recorder.borrow_mut().as_mut() {
+			recorder.open(index, tag_name);
+		}
+		if let Some(timer) = self.render_timer.borrow_mut().as_mut() {
+			timer.open(index, tag_name, kind);
+		}
+		let result = f();
+		if let Some(timer) = self.render_timer.borrow_mut().as_mut() {
+			timer.close();
+		}
+		if let Some(recorder) = self.tag_size_recorder.borrow_mut().as_mut() {
+			recorder.close();
+		}
+
+		result
+	}
+
+	/// Call `f`, attributing the time it takes to `phase` of writing the current tag if
+	/// times are being recorded
+	pub(crate) fn time_phase<T>(&self, phase: RenderPhase, f: impl FnOnce() -> T) -> T {
+		if self.render_timer.borrow().is_none() {
+			return f();
+		}
+
+		let start = Instant::now();
+		let result = f();
+		if let Some(timer) = self.render_timer.borrow_mut().as_mut() {
+			timer.add_phase_time(phase, start.elapsed());
+		}
+
+		result
+	}
+
+	/// Whether the tag currently being visited is a child of the document's root
+	pub(crate) fn is_top_level(&self) -> bool {
+		self.nesting_depth.get() == 1
+	}
+
+	pub(crate) fn set_max_nesting_depth(&self, max_depth: usize) {
+		self.max_nesting_depth.set(max_depth);
+	}
+
+	/// Call `f`, which visits a child of the tag currently being visited (and the child's
+	/// descendants). Fails if that would nest tags more deeply than allowed, rather than
+	/// risk overflowing the stack.
+	pub(crate) fn with_nested_tag<T>(
+		&self,
+		f: impl FnOnce() -> ClgnDecodingResult<T>,
+	) -> ClgnDecodingResult<T> {
+		let depth = self.nesting_depth.get() + 1;
+		let max_depth = self.max_nesting_depth.get();
+		if depth > max_depth {
+			return Err(ClgnDecodingError::Nesting {
+				msg: format!(
+					"Tags are nested more than {} deep (counting the tags of included \
+					 skeletons). If a skeleton includes itself, directly or indirectly, remove \
+					 the cycle; otherwise, raise the limit with --max-depth.",
+					max_depth
+				),
+			});
+		}
+
+		self.nesting_depth.set(depth);
+		let result = f();
+		self.nesting_depth.set(depth - 1);
+
+		result
+	}
+
+	/// Call `f` with the namespace prefixes declared by the `xmlns:<prefix>` attributes
+	/// in `attrs` in scope
+	pub(crate) fn with_namespaces<T>(
+		&self,
+		attrs: &AttrKVValueVec,
+		f: impl FnOnce() -> ClgnDecodingResult<T>,
+	) -> ClgnDecodingResult<T> {
+		let n_orig = self.namespaces.borrow().len();
+		self.namespaces.borrow_mut().extend(
+			attrs
+				.iter()
+				.filter_map(|(k, _)| k.strip_prefix("xmlns:"))
+				.map(str::to_owned),
+		);
+		let result = f();
+		self.namespaces.borrow_mut().truncate(n_orig);
+
+		result
+	}
+
+	/// Whether the namespace prefix `prefix` is in scope. `xml` and `xmlns` always are.
+	pub(crate) fn is_namespace_declared(&self, prefix: &str) -> bool {
+		prefix == "xml" || prefix == "xmlns" || self.namespaces.borrow().iter().any(|p| p == prefix)
+	}
+
+	/// Forget the themed values encountered so far, returning them
+	pub(crate) fn take_themes(&self) -> Themes {
+		self.themes.take()
+	}
+
+	/// Forget the class styles encountered so far, returning them
+	pub(crate) fn take_class_styles(&self) -> ClassStyles {
+		self.class_styles.take()
+	}
+
+	/// Get the name of the generated class whose style is `declarations`. See
+	/// [`ClassStyles`].
+	pub(crate) fn class_for(&self, declarations: String) -> String {
+		self.class_styles.borrow_mut().class_for(declarations)
+	}
+
+	/// Replace each themed value in `attrs`, which are CSS properties, with a reference
+	/// to a CSS custom property that takes on the appropriate value for the user's
+	/// preferred color scheme. See [`Themes`].
+	pub(crate) fn resolve_themed_values(
+		&self,
+		attrs: &mut AttrKVValueVec,
+	) -> ClgnDecodingResult<()> {
+		for (k, value) in attrs.iter_mut() {
+			let themed = match value.as_ref() {
+				SimpleValue::Themed(themed) => themed,
+				_ => continue,
+			};
+
+			let (light, dark) = match (
+				themed.light.to_maybe_string(),
+				themed.dark.to_maybe_string(),
+			) {
+				(Some(light), Some(dark)) => (light.into_owned(), dark.into_owned()),
+				_ => {
+					return Err(ClgnDecodingError::Theme {
+						msg: format!(
+							"The themed value of `{}` must have both a light and a dark value; \
+							 `false` is not allowed",
+							k
+						),
+					})
+				}
+			};
+
+			let var = self.themes.borrow_mut().var_for(light, dark);
+			*value = Cow::Owned(SimpleValue::Text(var));
+		}
+
+		Ok(())
+	}
+
+	/// If the tag named `tag_name` with (variable-substituted) attributes `attrs`
+	/// establishes a viewport of known size, i.e., it's an `<svg>` with a `viewBox` or a
+	/// numeric `width` and `height`, call `f` with the pseudo-variables
+	/// `parent_width` and `parent_height` set to that size (and, if `is_root`, also
+	/// `canvas_width` and `canvas_height`). Otherwise, just call `f`.
+	pub(crate) fn with_viewport_vars<T>(
+		&self,
+		tag_name: &str,
+		is_root: bool,
+		attrs: &AttrKVValueVec,
+		f: impl FnOnce() -> ClgnDecodingResult<T>,
+	) -> ClgnDecodingResult<T> {
+		if tag_name != "svg" {
+			return f();
+		}
+
+		let (width, height) = match viewport_size(attrs) {
+			Some(size) => size,
+			None => return f(),
+		};
+
+		let names: &[_] = if is_root {
+			&[
+				("parent_width", width),
+				("parent_height", height),
+				("canvas_width", width),
+				("canvas_height", height),
+			]
+		} else {
+			&[("parent_width", width), ("parent_height", height)]
+		};
+		let vars = TagVariables(
+			names
+				.iter()
+				.map(|&(name, x)| {
+					(
+						name.to_owned(),
+						VariableValue::Number(ConcreteNumber::Float(x)),
+					)
+				})
+				.collect(),
+		);
+
+		self.with_new_vars(&vars, f)
+	}
+
+	/// Replace each attribute value of the form `"<number>%w"` (resp. `"<number>%h"`)
+	/// with that percentage of `parent_width` (resp. `parent_height`)
+	pub(crate) fn resolve_relative_units(
+		&self,
+		attrs: &mut AttrKVValueVec,
+	) -> ClgnDecodingResult<()> {
+		for (_, value) in attrs.iter_mut() {
+			let (percent, var_name) = match value.as_ref() {
+				SimpleValue::Text(s) => match RELATIVE_UNIT_RE.captures(s) {
+					Some(caps) => (
+						caps[1].parse::<f64>().unwrap(),
+						if &caps[2] == "w" {
+							"parent_width"
+						} else {
+							"parent_height"
+						},
+					),
+					None => continue,
+				},
+				_ => continue,
+			};
+
+			let parent_size = match self.get_var(var_name) {
+				Some(VariableValue::Number(n)) => n.to_string().parse::<f64>().unwrap(),
+				Some(VariableValue::String(s)) => match s.trim().parse::<f64>() {
+					Ok(x) => x,
+					Err(_) => continue,
+				},
+				None => {
+					return Err(VariableSubstitutionError::VariableName {
+						illegal_names: vec![],
+						missing_from_context: vec![var_name.to_owned()],
+					}
+					.into())
+				}
+			};
+
+			*value = Cow::Owned(SimpleValue::Number(ConcreteNumber::Float(
+				percent / 100.0 * parent_size,
+			)));
+		}
+
+		Ok(())
+	}
+
 	pub(crate) fn get_var(&self, var: &str) -> Option<&'a VariableValue> {
 		// Nothing is really copied here; self.vars_map.borrow().get(var) returns a
 		// double reference `&&T`, which we just want to turn into a `&T` (so, sure, a
@@ -250,13 +1102,20 @@ impl<'a> DecodingContext<'a> {
 		let mut parse_state = ParseState::Normal;
 		let mut prev_was_backslash = false;
 		let mut left = 0;
+		// The end of the escape sequence most recently parsed, whose characters should be
+		// skipped
+		let mut skip_until = 0;
 
 		let mut missing_var_names = vec![];
 		let mut illegal_var_names = vec![];
 
 		// Don't really know what I'm doing when it comes to parsing, but this works, so
 		// ¯\_(ツ)_/¯
-		for (i, c) in s.chars().into_iter().enumerate() {
+		for (i, c) in s.char_indices() {
+			if i < skip_until {
+				continue;
+			}
+
 			let pat = (prev_was_backslash, &parse_state, c);
 			match pat {
 				(_, InsideBracesValid | InsideBracesInvalid, '\\') => {
@@ -272,7 +1131,7 @@ impl<'a> DecodingContext<'a> {
 					left = i + c.len_utf8();
 					parse_state = InsideBracesValid;
 				}
-				(false, InsideBracesValid, '}') if i > left => {
+				(false, InsideBracesValid, '}') if is_valid_var_ref(&s[left..i]) => {
 					modified_from_original = true;
 
 					let var_name = &s[left..i];
@@ -294,7 +1153,9 @@ impl<'a> DecodingContext<'a> {
 					illegal_var_names.push(s[left..i].to_string());
 					parse_state = Normal;
 				}
-				(false, InsideBracesValid, c) if !VAR_NAME_CHAR_RE.is_match(&c.to_string()) => {
+				(false, InsideBracesValid, c)
+					if c != '.' && !VAR_NAME_CHAR_RE.is_match(&c.to_string()) =>
+				{
 					parse_state = InsideBracesInvalid;
 				}
 				(true, Normal, '{' | '}' | '\\') => {
@@ -305,6 +1166,19 @@ impl<'a> DecodingContext<'a> {
 					prev_was_backslash = false;
 					modified_from_original = true;
 				}
+				(true, Normal, 'u' | '&') => {
+					let backslash_position = i - '\\'.len_utf8();
+					let (escaped, len) = parse_escape(&s[i..], backslash_position)
+						.map_err(VariableSubstitutionError::Parse)?;
+
+					string_result.push_str(&s[left..backslash_position]);
+					string_result.push(escaped);
+
+					left = i + len;
+					skip_until = left;
+					prev_was_backslash = false;
+					modified_from_original = true;
+				}
 				(true, Normal, _) => {
 					return Err(VariableSubstitutionError::Parse(InvalidEscapeSequence {
 						position: (i - 1, i),
@@ -358,15 +1232,9 @@ impl<'a> DecodingContext<'a> {
 		let mut subd_attrs = Vec::with_capacity(n_attrs);
 
 		for (k, orig_val) in attrs_iter {
-			let new_val = match orig_val.as_ref() {
-				SimpleValue::Text(text) => {
-					let subd_text = self.sub_vars_into_str(text)?;
-					match subd_text {
-						Cow::Owned(s) => Cow::Owned(SimpleValue::Text(s)),
-						_orig_text => orig_val,
-					}
-				}
-				_wasnt_text => orig_val,
+			let new_val = match self.sub_vars_into_value(orig_val.as_ref())? {
+				Some(v) => Cow::Owned(v),
+				None => orig_val,
 			};
 
 			subd_attrs.push((k, new_val));
@@ -374,6 +1242,28 @@ impl<'a> DecodingContext<'a> {
 
 		Ok(AttrKVValueVec(subd_attrs))
 	}
+
+	/// If substituting variables into `value` changed it, the changed value; otherwise
+	/// `None`
+	fn sub_vars_into_value(&self, value: &SimpleValue) -> ClgnDecodingResult<Option<SimpleValue>> {
+		Ok(match value {
+			SimpleValue::Text(text) => match self.sub_vars_into_str(text)? {
+				Cow::Owned(s) => Some(SimpleValue::Text(s)),
+				Cow::Borrowed(_) => None,
+			},
+			SimpleValue::Themed(themed) => match (
+				self.sub_vars_into_value(&themed.light)?,
+				self.sub_vars_into_value(&themed.dark)?,
+			) {
+				(None, None) => None,
+				(light, dark) => Some(SimpleValue::Themed(Box::new(ThemedValue {
+					light: light.unwrap_or_else(|| themed.light.clone()),
+					dark: dark.unwrap_or_else(|| themed.dark.clone()),
+				}))),
+			},
+			_ => None,
+		})
+	}
 }
 
 #[cfg(test)]
@@ -518,6 +1408,34 @@ mod tests {
 				.unwrap();
 			assert_unchanged_0();
 		}
+
+		#[test]
+		fn shadowing() {
+			let outer = VV::Number(CN::Int(1));
+			let context = DecodingContext::new_with_vars(vec![("a", &outer), ("b", &outer)]);
+			let shadow = |vars: Vec<(&str, VV)>| {
+				let vars = TagVariables(Map::from_iter(
+					vars.into_iter().map(|(k, v)| (k.to_owned(), v)),
+				));
+				context.with_new_vars(&vars, || Ok(())).unwrap();
+			};
+
+			shadow(vec![
+				("a", VV::Number(CN::Float(2.0))),
+				("c", VV::String("x".into())),
+			]);
+			assert!(context.shadowed_vars().is_empty());
+
+			shadow(vec![("a", VV::String("x".into()))]);
+			shadow(vec![
+				("a", VV::String("y".into())),
+				("b", VV::String("x".into())),
+			]);
+			assert_eq!(
+				context.shadowed_vars().iter().collect::<Vec<_>>(),
+				vec!["a", "b"]
+			);
+		}
 	}
 
 	mod root {
@@ -603,6 +1521,46 @@ mod tests {
 				empty_context.sub_vars_into_str(r"\\\\\{\\\\\\").unwrap(),
 				r"\\{\\\"
 			);
+
+			// Unicode escapes and named entities
+			assert_eq!(empty_context.sub_vars_into_str(r"\u{1F600}").unwrap(), "😀");
+			assert_eq!(empty_context.sub_vars_into_str(r"a\u{e9}b").unwrap(), "aéb");
+			assert_eq!(
+				nonempty_context
+					.sub_vars_into_str(r"{a}\u{2013}{b}\u{a}")
+					.unwrap(),
+				"1–2\n"
+			);
+			assert_eq!(
+				empty_context
+					.sub_vars_into_str(r"\&copy; 2022 \&mdash; \&lt;\&amp;\&gt;")
+					.unwrap(),
+				"© 2022 — <&>"
+			);
+			assert_eq!(
+				empty_context.sub_vars_into_str(r"\\u\{41\}").unwrap(),
+				r"\u{41}"
+			);
+			assert!(empty_context.sub_vars_into_str(r"\u{41}{").is_err());
+		}
+
+		#[test]
+		fn multibyte_chars() {
+			// Variables following multibyte characters are sliced out at byte offsets,
+			// not char positions
+			let xyz_string = VV::String("xyz".to_string());
+			let context = DecodingContext::new_with_vars(vec![
+				("a", &VV::Number(CN::Int(1))),
+				("e", &xyz_string),
+			]);
+
+			assert_eq!(context.sub_vars_into_str("é {a}").unwrap(), "é 1");
+			assert_eq!(context.sub_vars_into_str("ツ{e}ツ{a}").unwrap(), "ツxyzツ1");
+			assert_eq!(
+				context.sub_vars_into_str(r"¯\\_(ツ)_/¯ \{{a}\}").unwrap(),
+				r"¯\_(ツ)_/¯ {1}"
+			);
+			assert_eq!(context.sub_vars_into_str("😀{e}").unwrap(), "😀xyz");
 		}
 
 		#[test]
@@ -719,6 +1677,36 @@ mod tests {
 					char: '|',
 				},
 			);
+
+			for (s, position, sequence) in [
+				(r"\u", 0, r"\u"),
+				(r"ab\u41", 2, r"\u"),
+				(r"\u{}", 0, r"\u"),
+				(r"\u{1234567}", 0, r"\u"),
+				(r"\u{110000}", 0, r"\u{110000}"),
+				(r"\u{D800}", 0, r"\u{D800}"),
+				(r"x\u{0}", 1, r"\u{0}"),
+				(r"\u{fffe}", 0, r"\u{fffe}"),
+				(r"\&copy", 0, r"\&"),
+				(r"\&;", 0, r"\&"),
+			] {
+				test(
+					&empty_context,
+					s,
+					ParseError::InvalidUnicodeEscape {
+						position,
+						sequence: sequence.to_owned(),
+					},
+				);
+			}
+			test(
+				&empty_context,
+				r"a\&bogus;",
+				ParseError::UnknownEntity {
+					position: 1,
+					name: "bogus".to_owned(),
+				},
+			);
 		}
 
 		#[test]
@@ -800,6 +1788,8 @@ mod tests {
 			test(&empty_context, "{ a }", Illegal(vec![" a "]));
 			test(&empty_context, "{a.}", Illegal(vec!["a."]));
 			test(&empty_context, "{ .}", Illegal(vec![" ."]));
+			test(&empty_context, "{.a}", Illegal(vec![".a"]));
+			test(&empty_context, "{a..b}", Illegal(vec!["a..b"]));
 
 			test(&empty_context, "{} {}", Illegal(vec!["", ""]));
 			test(&empty_context, "{ } {}", Illegal(vec![" ", ""]));
@@ -6,24 +6,39 @@ pub(crate) use std::collections::btree_map::Entry as MapEntry;
 pub(crate) use std::collections::BTreeMap as Map;
 use std::ops::{Deref, DerefMut};
 
+mod anchor;
+pub(crate) use anchor::{Anchor, AnchorEntry, Anchors};
+
+mod class_styles;
+pub(crate) use class_styles::ClassStyles;
+
 pub(crate) mod context;
 pub use context::DecodingContext;
 
 mod concrete_number;
 pub(crate) use concrete_number::ConcreteNumber;
 
+mod raw_text;
+pub(crate) use raw_text::{MaybeRawText, RawText};
+
 mod simple_value;
 pub(crate) use simple_value::SimpleValue;
 
+mod theme;
+pub(crate) use theme::{ThemedValue, Themes};
+
 mod variable_value;
 pub(crate) use variable_value::VariableValue;
 
+mod attr_name;
+pub(crate) use attr_name::AttrName;
+
 /// A type alias for storing XML attribute key-value pairs
-#[derive(Serialize, Deserialize, Debug)]
-pub(crate) struct XmlAttrs(pub(crate) Map<String, SimpleValue>);
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct XmlAttrs(pub(crate) Map<AttrName, SimpleValue>);
 
 impl Deref for XmlAttrs {
-	type Target = Map<String, SimpleValue>;
+	type Target = Map<AttrName, SimpleValue>;
 
 	fn deref(&self) -> &Self::Target {
 		&self.0
@@ -33,6 +48,7 @@ impl Deref for XmlAttrs {
 type AttrKVPair<'a> = (&'a str, Cow<'a, SimpleValue>);
 
 /// A vector of key, value pairs representing attributes
+#[derive(Default)]
 pub(crate) struct AttrKVValueVec<'a>(Vec<AttrKVPair<'a>>);
 
 impl<'a> Deref for AttrKVValueVec<'a> {
@@ -59,5 +75,5 @@ impl<'a> IntoIterator for AttrKVValueVec<'a> {
 }
 
 /// Map of `String` -> `VariableValue`
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct TagVariables(pub(crate) Map<String, VariableValue>);
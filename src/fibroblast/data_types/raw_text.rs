@@ -0,0 +1,67 @@
+//! Raw text is text that variables are not substituted into, written `{ "raw": "..." }`
+//! in place of a string. It's an escape hatch for text with many literal braces, such
+//! as CSS or code, which would otherwise need each brace to be escaped.
+
+use super::DecodingContext;
+use crate::ClgnDecodingResult;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Text that variables are not substituted into
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RawText {
+	pub(crate) raw: String,
+}
+
+/// Text that variables are substituted into, unless it's raw
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(untagged)]
+pub(crate) enum MaybeRawText {
+	Plain(String),
+	Raw(RawText),
+}
+
+impl MaybeRawText {
+	/// The text, as given (i.e., before variable substitution)
+	pub(crate) fn as_str(&self) -> &str {
+		match self {
+			MaybeRawText::Plain(s) => s,
+			MaybeRawText::Raw(RawText { raw }) => raw,
+		}
+	}
+
+	/// The text, with variables substituted in if it's not raw
+	pub(crate) fn substituted<'b>(
+		&'b self,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<Cow<'b, str>> {
+		match self {
+			MaybeRawText::Plain(s) => Ok(context.sub_vars_into_str(s)?),
+			MaybeRawText::Raw(RawText { raw }) => Ok(Cow::Borrowed(raw)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fibroblast::data_types::VariableValue;
+
+	#[test]
+	fn raw_text() {
+		let x = VariableValue::from("1".to_owned());
+		let context = DecodingContext::new_with_vars(vec![("x", &x)]);
+
+		let plain: MaybeRawText = serde_json::from_str(r#""{x}""#).unwrap();
+		assert_eq!(plain.substituted(&context).unwrap(), "1");
+
+		let raw: MaybeRawText = serde_json::from_str(r#"{ "raw": "a { b: {x} }" }"#).unwrap();
+		assert_eq!(raw.as_str(), "a { b: {x} }");
+		assert_eq!(raw.substituted(&context).unwrap(), "a { b: {x} }");
+
+		assert!(serde_json::from_str::<MaybeRawText>(r#"{ "raw": "a", "b": 1 }"#).is_err());
+	}
+}
@@ -2,6 +2,7 @@ use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::borrow::Cow;
 
 use super::concrete_number::{ConcreteNumber, ConcreteNumberVisitor};
+use super::{Map, RawText, ThemedValue};
 
 /// An enum whose variants represent "simple" (indivisible) values. This owns all of its
 /// values (*maybe* could be replaced with `SimpleValue<'a> { Text(Cow<'a, str>) }` but
@@ -17,30 +18,64 @@ pub(crate) enum SimpleValue {
 	/// attribute altogether? Having an explicit option to drop attribtues may come in
 	/// handy if we end up wanting to explicitly opt out of an attribute
 	Absent,
+	/// A value that depends on whether the user prefers a light or dark color scheme.
+	/// See [`ThemedValue`].
+	Themed(Box<ThemedValue>),
+	/// Text that variables are not substituted into. See [`RawText`].
+	Raw(String),
 }
 
 impl SimpleValue {
 	/// If anything other than `Absent`, return a stringified verion wrapped in a
-	/// `Some`. If `Absent` then `None`.
+	/// `Some`. If `Absent` then `None`. `Themed` values should have been turned into CSS
+	/// by this point; if one wasn't, its light variant is used.
 	pub fn to_maybe_string(&self) -> Option<Cow<'_, str>> {
 		use SimpleValue::*;
 
 		match self {
 			Number(n) => Some(Cow::Owned(n.to_string())),
-			Text(s) => Some(Cow::Borrowed(s.as_ref())),
+			Text(s) | Raw(s) => Some(Cow::Borrowed(s.as_ref())),
 			Present => Some(Cow::Borrowed("")),
 			Absent => None,
+			Themed(t) => t.light.to_maybe_string(),
 		}
 	}
+
+	/// Whether `self` is a number that's infinite or NaN (or is themed and either of its
+	/// variants is). Such a number can't be written meaningfully to SVG.
+	pub(crate) fn is_non_finite(&self) -> bool {
+		use SimpleValue::*;
+
+		match self {
+			Number(ConcreteNumber::Float(x)) => !x.is_finite(),
+			Themed(t) => t.light.is_non_finite() || t.dark.is_non_finite(),
+			Number(_) | Text(_) | Raw(_) | Present | Absent => false,
+		}
+	}
+
+	/// Parse `self` as a number of user units, if possible. Text with a trailing `px` is
+	/// allowed. Infinite and NaN values aren't numbers of user units.
+	pub(crate) fn to_number(&self) -> Option<f64> {
+		use SimpleValue::*;
+
+		let x = match self {
+			Number(n) => n.as_f64(),
+			Text(s) | Raw(s) => s.trim().trim_end_matches("px").trim_end().parse().ok()?,
+			Present | Absent | Themed(_) => return None,
+		};
+		Some(x).filter(|x| x.is_finite())
+	}
 }
 
 impl Clone for SimpleValue {
-	/// Everything but `Text` is `Copy`; `Text` needs to be cloned
+	/// Everything but `Text`, `Themed`, and `Raw` is `Copy`; those need to be cloned
 	fn clone(&self) -> Self {
 		use SimpleValue::*;
 
 		match self {
 			Text(s) => Text(s.clone()),
+			Themed(t) => Themed(t.clone()),
+			Raw(s) => Raw(s.clone()),
 			Number(x) => Number(*x),
 			Present => Present,
 			Absent => Absent,
@@ -60,6 +95,8 @@ impl Serialize for SimpleValue {
 			Text(s) => serializer.serialize_str(s),
 			Present => serializer.serialize_bool(true),
 			Absent => serializer.serialize_bool(false),
+			Themed(t) => t.serialize(serializer),
+			Raw(s) => RawText { raw: s.clone() }.serialize(serializer),
 		}
 	}
 }
@@ -75,7 +112,10 @@ impl<'de> Deserialize<'de> for SimpleValue {
 			type Value = SimpleValue;
 
 			fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-				formatter.write_str("a string, a number, or a bool")
+				formatter.write_str(
+					"a string, a number, a bool, an object with keys `light` and `dark`, or an \
+					 object with the single key `raw`",
+				)
 			}
 
 			fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
@@ -117,6 +157,43 @@ impl<'de> Deserialize<'de> for SimpleValue {
 					SimpleValue::Absent
 				})
 			}
+
+			/// `{ "light": ..., "dark": ... }` -> Themed; `{ "raw": "..." }` -> Raw
+			fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+			where
+				A: de::MapAccess<'de>,
+			{
+				let mut entries = Map::<String, SimpleValue>::deserialize(
+					de::value::MapAccessDeserializer::new(map),
+				)?;
+
+				if entries.contains_key("raw") {
+					return match (entries.remove("raw"), entries.is_empty()) {
+						(Some(SimpleValue::Text(raw)), true) => Ok(SimpleValue::Raw(raw)),
+						(_, true) => Err(de::Error::custom("the value of `raw` must be a string")),
+						(_, false) => Err(de::Error::custom(
+							"an object with the key `raw` may not have any other keys",
+						)),
+					};
+				}
+
+				let (light, dark) = match (entries.remove("light"), entries.remove("dark")) {
+					(Some(light), Some(dark)) => (light, dark),
+					(None, _) => return Err(de::Error::missing_field("light")),
+					(_, None) => return Err(de::Error::missing_field("dark")),
+				};
+				if let Some(key) = entries.keys().next() {
+					return Err(de::Error::unknown_field(key, &["light", "dark"]));
+				}
+				let themed = ThemedValue { light, dark };
+				if let (SimpleValue::Themed(_), _) | (_, SimpleValue::Themed(_)) =
+					(&themed.light, &themed.dark)
+				{
+					return Err(de::Error::custom("themed values may not be nested"));
+				}
+
+				Ok(SimpleValue::Themed(Box::new(themed)))
+			}
 		}
 
 		deserializer.deserialize_any(SimpleValueVisitor)
@@ -185,10 +262,84 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn to_number() {
+		assert_eq!(
+			SimpleValue::Number(ConcreteNumber::Int(-3)).to_number(),
+			Some(-3.0)
+		);
+		assert_eq!(
+			SimpleValue::Number(ConcreteNumber::UInt(3)).to_number(),
+			Some(3.0)
+		);
+		assert_eq!(
+			SimpleValue::Text(" 2.5px ".to_owned()).to_number(),
+			Some(2.5)
+		);
+		assert_eq!(SimpleValue::Present.to_number(), None);
+
+		assert_eq!(
+			SimpleValue::Number(ConcreteNumber::Float(f64::NAN)).to_number(),
+			None
+		);
+		assert_eq!(SimpleValue::Text("inf".to_owned()).to_number(), None);
+		assert_eq!(SimpleValue::Text("1e999".to_owned()).to_number(), None);
+	}
+
 	#[test]
 	fn bool() {
 		// Present/absent
 		assert_tokens(&SimpleValue::Present, &[Token::Bool(true)]);
 		assert_tokens(&SimpleValue::Absent, &[Token::Bool(false)]);
 	}
+
+	#[test]
+	fn themed() {
+		assert_tokens(
+			&SimpleValue::Themed(Box::new(ThemedValue {
+				light: SimpleValue::Text("#000".to_owned()),
+				dark: SimpleValue::Text("#fff".to_owned()),
+			})),
+			&[
+				Token::Struct {
+					name: "ThemedValue",
+					len: 2,
+				},
+				Token::Str("light"),
+				Token::String("#000"),
+				Token::Str("dark"),
+				Token::String("#fff"),
+				Token::StructEnd,
+			],
+		);
+	}
+
+	#[test]
+	fn raw() {
+		assert_tokens(
+			&SimpleValue::Raw("{x}".to_owned()),
+			&[
+				Token::Struct {
+					name: "RawText",
+					len: 1,
+				},
+				Token::Str("raw"),
+				Token::String("{x}"),
+				Token::StructEnd,
+			],
+		);
+
+		for json in [
+			r#"{ "raw": 1 }"#,
+			r#"{ "raw": "a", "light": "b" }"#,
+			r#"{ "light": "a" }"#,
+			r#"{ "light": "a", "dark": "b", "dim": "c" }"#,
+		] {
+			assert!(
+				serde_json::from_str::<SimpleValue>(json).is_err(),
+				"{}",
+				json
+			);
+		}
+	}
 }
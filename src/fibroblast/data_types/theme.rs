@@ -0,0 +1,103 @@
+//! Themed values are attribute (or `style`) values that differ between light and dark
+//! mode, e.g., `"fill": { "light": "#000", "dark": "#fff" }`. SVG attributes can't
+//! depend on the color scheme, but CSS can, so each distinct themed value is turned
+//! into a CSS custom property (variable) whose value is set in a `<style>` containing a
+//! `@media (prefers-color-scheme: dark)` block. The element then refers to the
+//! variable in its `style` attribute, e.g., `style="fill:var(--clgn-theme-0)"`.
+//!
+//! Themed values are collected in the [`DecodingContext`](super::DecodingContext) as
+//! the document is written, and the `<style>` is written at the end of the root tag.
+
+use super::SimpleValue;
+use serde::{Deserialize, Serialize};
+
+/// A value with one variant for light mode and another for dark mode
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ThemedValue {
+	pub(crate) light: SimpleValue,
+	pub(crate) dark: SimpleValue,
+}
+
+/// All of the distinct themed values in a document, each with the index of the CSS
+/// custom property it's assigned to
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Themes {
+	/// `(light, dark)` pairs; the pair at index `i` is assigned to `--clgn-theme-{i}`
+	values: Vec<(String, String)>,
+}
+
+impl Themes {
+	/// Get the CSS reference, e.g., `var(--clgn-theme-0)`, to the custom property whose
+	/// value is `light` in light mode and `dark` in dark mode, creating the property if
+	/// it doesn't already exist
+	pub(crate) fn var_for(&mut self, light: String, dark: String) -> String {
+		let index = match self
+			.values
+			.iter()
+			.position(|(l, d)| *l == light && *d == dark)
+		{
+			Some(index) => index,
+			None => {
+				self.values.push((light, dark));
+				self.values.len() - 1
+			}
+		};
+
+		format!("var({})", Self::property_name(index))
+	}
+
+	fn property_name(index: usize) -> String {
+		format!("--clgn-theme-{}", index)
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.values.is_empty()
+	}
+
+	/// The CSS that declares each custom property, in light mode by default and in dark
+	/// mode if that's what the user prefers
+	pub(crate) fn to_css(&self) -> String {
+		let declarations = |pick: fn(&(String, String)) -> &str| {
+			self.values
+				.iter()
+				.enumerate()
+				.map(|(i, value)| format!("{}:{}", Self::property_name(i), pick(value)))
+				.collect::<Vec<_>>()
+				.join(";")
+		};
+
+		format!(
+			":root{{{}}}@media (prefers-color-scheme: dark){{:root{{{}}}}}",
+			declarations(|(light, _)| light.as_str()),
+			declarations(|(_, dark)| dark.as_str()),
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn var_for_dedups() {
+		let mut themes = Themes::default();
+		assert!(themes.is_empty());
+
+		let black_white = themes.var_for("#000".to_owned(), "#fff".to_owned());
+		let red_blue = themes.var_for("red".to_owned(), "blue".to_owned());
+		assert_eq!(black_white, "var(--clgn-theme-0)");
+		assert_eq!(red_blue, "var(--clgn-theme-1)");
+		assert_eq!(
+			themes.var_for("#000".to_owned(), "#fff".to_owned()),
+			black_white
+		);
+
+		assert_eq!(
+			themes.to_css(),
+			":root{--clgn-theme-0:#000;--clgn-theme-1:red}\
+			 @media (prefers-color-scheme: dark){:root{--clgn-theme-0:#fff;--clgn-theme-1:blue}}"
+		);
+	}
+}
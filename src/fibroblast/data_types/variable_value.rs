@@ -3,8 +3,8 @@ use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 
 /// The value of a variable; either a number or a string
-#[derive(Serialize, Deserialize, Debug)]
-#[cfg_attr(test, derive(Clone, PartialEq))]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
 #[serde(untagged)]
 pub(crate) enum VariableValue {
 	Number(ConcreteNumber),
@@ -19,6 +19,14 @@ impl VariableValue {
 			String(s) => Cow::Borrowed(s.as_ref()),
 		}
 	}
+
+	/// The name of the kind of value this is, for use in messages
+	pub(crate) fn type_name(&self) -> &'static str {
+		match self {
+			VariableValue::Number(_) => "number",
+			VariableValue::String(_) => "string",
+		}
+	}
 }
 
 impl From<ConcreteNumber> for VariableValue {
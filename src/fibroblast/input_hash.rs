@@ -0,0 +1,261 @@
+//! A hash of everything that goes into rendering a skeleton, so that callers that cache
+//! rendered SVGs can tell whether rendering a skeleton again would produce anything new
+//! without rendering it (or comparing the result to what they already have).
+//!
+//! The hash covers the manifest (after its includes and patches have been applied), the
+//! manifests of the skeletons it includes and the libraries it uses symbols from, the
+//! contents of the images and fonts it
+//! embeds, and the options that change the SVG that's written, such as `--palette` and
+//! `--srgb`. It also covers the version of Collagen, since a different version may
+//! render the same inputs differently. It's stable across runs and machines; in
+//! particular, the skeleton may be moved without changing its hash.
+//!
+//! The hash can be stamped on the root tag as `data-clgn-hash` (`clgn --stamp-hash`), so
+//! that it travels with the SVG.
+
+use super::tags::{AnyChildTag, SiblingExports, TagLike};
+use super::{DecodingContext, Fibroblast};
+use crate::to_svg::svg_writable::ClgnDecodingResult;
+use crate::utils::hash::ContentHasher;
+use serde::Serialize;
+use std::io;
+use std::path::PathBuf;
+
+/// Hashes a skeleton's inputs as its tags are walked
+struct InputHasher {
+	hasher: ContentHasher,
+	/// The files hashed so far, in the order in which they were first hashed
+	files: Vec<PathBuf>,
+}
+
+impl InputHasher {
+	fn new() -> Self {
+		Self {
+			hasher: ContentHasher::new(),
+			files: Vec::new(),
+		}
+	}
+
+	fn hash_json(&mut self, value: &impl Serialize) {
+		// safe, since tags are only ever serialized to JSON objects with string keys
+		self.hasher.write_input(&serde_json::to_vec(value).unwrap());
+	}
+
+	/// Hash the contents of the file at `path`. A file that's embedded more than once is
+	/// only read the first time; after that, only the fact that it was seen again is
+	/// hashed.
+	fn hash_file(&mut self, path: PathBuf, context: &DecodingContext) -> ClgnDecodingResult<()> {
+		if let Some(i) = self.files.iter().position(|file| *file == path) {
+			self.hasher.write_input(b"seen");
+			self.hasher.write_input(&(i as u64).to_le_bytes());
+			return Ok(());
+		}
+
+		let files = context.file_source();
+		match files.read(&path) {
+			Ok(bytes) => {
+				self.hasher.write_input(b"file");
+				self.hasher.write_input(&bytes);
+			}
+			// The placeholder drawn in place of a missing image depends only on the tag
+			Err(e)
+				if e.kind() == io::ErrorKind::NotFound && context.placeholder_missing_assets() =>
+			{
+				self.hasher.write_input(b"missing");
+			}
+			Err(e) => return Err(files.read_error(e, path)),
+		}
+		self.files.push(path);
+
+		Ok(())
+	}
+
+	fn hash_children<'a>(
+		&mut self,
+		children: &'a [AnyChildTag<'a>],
+		context: &'a DecodingContext<'a>,
+	) -> ClgnDecodingResult<()> {
+		let exports = SiblingExports::of(children, context)?;
+		for (index, child) in children.iter().enumerate() {
+			context.with_nested_tag(|| {
+				exports.with_exports_for(index, context, || self.hash_child(child, context))
+			})?;
+		}
+
+		Ok(())
+	}
+
+	fn hash_child<'a>(
+		&mut self,
+		child: &'a AnyChildTag<'a>,
+		context: &'a DecodingContext<'a>,
+	) -> ClgnDecodingResult<()> {
+		for path in child.asset_paths(context)? {
+			self.hash_file(path, context)?;
+		}
+
+		match child {
+			AnyChildTag::Container(container) => {
+				let children = child.children(context)?;
+				let fb = container.as_fibroblast();
+				self.hash_json(&fb.root);
+				context.with_new_root(fb.context.get_root().as_path(), || {
+					self.hash_children(children, context)
+				})
+			}
+			AnyChildTag::UseFrom(tag) => {
+				let library = tag.library(context)?;
+				let symbol = tag.symbol_def(context)?;
+				self.hash_json(&library.root);
+				context.with_new_root(library.context.get_root().as_path(), || {
+					context.with_new_vars(library.vars()?, || {
+						self.hash_children(symbol.children(), context)
+					})
+				})?;
+
+				context.with_new_vars(child.vars(context)?, || {
+					self.hash_children(child.children(context)?, context)
+				})
+			}
+			_ => context.with_new_vars(child.vars(context)?, || {
+				self.hash_children(child.children(context)?, context)
+			}),
+		}
+	}
+}
+
+impl<'a> Fibroblast<'a> {
+	/// The hash of this skeleton's inputs, as 32 hex digits. See the
+	/// [module docs](self) for what it covers.
+	///
+	/// The options set on this skeleton's context are part of the hash, so it should be
+	/// computed after they've been set.
+	pub fn input_hash(&'a self) -> ClgnDecodingResult<String> {
+		let context = &self.context;
+		let mut hasher = InputHasher::new();
+		hasher
+			.hasher
+			.write_input(env!("CARGO_PKG_VERSION").as_bytes());
+		hasher
+			.hasher
+			.write_input(context.output_options_fingerprint().as_bytes());
+		hasher.hash_json(&self.root);
+
+		context.with_new_vars(self.vars()?, || {
+			hasher.hash_children(self.children(), context)
+		})?;
+
+		Ok(format!("{:032x}", hasher.hasher.finish()))
+	}
+
+	/// Compute the hash of this skeleton's inputs (see [`Fibroblast::input_hash`]) and
+	/// stamp it on the root tag as `data-clgn-hash` whenever the skeleton is written.
+	/// Returns the hash.
+	pub fn stamp_input_hash(&'a self) -> ClgnDecodingResult<String> {
+		let hash = self.input_hash()?;
+		self.context.set_stamped_hash(Some(hash.clone()));
+		Ok(hash)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::to_svg::svg_writable::ClgnDecodingError;
+	use std::path::Path;
+
+	fn hash(skeleton_dir: &Path) -> String {
+		Fibroblast::from_dir(skeleton_dir)
+			.unwrap()
+			.input_hash()
+			.unwrap()
+	}
+
+	#[test]
+	fn input_hashes() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		let path = |name: &str| skeleton_dir.path().join(name);
+		std::fs::write(
+			path("collagen.json"),
+			r#"{
+				"vars": { "side": 10 },
+				"children": [
+					{ "image_path": "sprites.png" },
+					{ "tag": "g", "children": [{ "image_path": "sprites.png" }] },
+					{ "clgn_path": "nested" }
+				]
+			}"#,
+		)
+		.unwrap();
+		std::fs::create_dir(path("nested")).unwrap();
+		std::fs::write(
+			path("nested/collagen.json"),
+			r#"{ "children": [{ "image_path": "../sprites.png" }] }"#,
+		)
+		.unwrap();
+		std::fs::copy(
+			"tests/examples/sprite-sheet/skeleton/sprites.png",
+			path("sprites.png"),
+		)
+		.unwrap();
+
+		let original = hash(skeleton_dir.path());
+		assert_eq!(original.len(), 32);
+		assert_eq!(hash(skeleton_dir.path()), original);
+
+		// Moving the skeleton doesn't change its hash
+		let moved_dir = tempfile::tempdir().unwrap();
+		let moved = moved_dir.path().join("moved");
+		std::fs::rename(skeleton_dir.path(), &moved).unwrap();
+		assert_eq!(hash(&moved), original);
+		std::fs::rename(&moved, skeleton_dir.path()).unwrap();
+
+		// But changing any of its inputs does
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+		fibroblast.context.set_convert_to_srgb(true);
+		assert_ne!(fibroblast.input_hash().unwrap(), original);
+
+		std::fs::write(
+			path("nested/collagen.json"),
+			r#"{ "children": [{ "image_path": "../sprites.png", "z": 1 }] }"#,
+		)
+		.unwrap();
+		let nested_changed = hash(skeleton_dir.path());
+		assert_ne!(nested_changed, original);
+
+		std::fs::write(path("sprites.png"), b"not really a png").unwrap();
+		assert_ne!(hash(skeleton_dir.path()), nested_changed);
+
+		// Missing images are an error, unless they're to be replaced with placeholders
+		std::fs::remove_file(path("sprites.png")).unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+		assert!(matches!(
+			fibroblast.input_hash(),
+			Err(ClgnDecodingError::MissingPath { .. })
+		));
+		fibroblast.context.set_placeholder_missing_assets(true);
+		assert!(fibroblast.input_hash().is_ok());
+	}
+
+	#[test]
+	fn stamped_hash() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			r#"{ "children": [{ "tag": "rect" }] }"#,
+		)
+		.unwrap();
+
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+		assert!(!fibroblast
+			.to_svg_string()
+			.unwrap()
+			.contains("data-clgn-hash"));
+
+		let hash = fibroblast.stamp_input_hash().unwrap();
+		assert!(fibroblast.to_svg_string().unwrap().starts_with(&format!(
+			r#"<svg xmlns="http://www.w3.org/2000/svg" data-clgn-hash="{}">"#,
+			hash
+		)));
+	}
+}
@@ -5,12 +5,20 @@
 //! Collagen file, [`Fibroblast`]. See its docs for more info.
 
 pub(super) mod data_types;
+mod input_hash;
+mod normalize;
+pub(crate) mod scope_inspector;
+mod snapshot;
+mod snapshot_diff;
 pub mod tags;
 
 pub use super::from_json::decoding_error::ClgnDecodingResult;
 pub use crate::fibroblast::data_types::DecodingContext;
+use crate::to_svg::xml_text::XmlText;
 use data_types::TagVariables;
-use std::borrow::Cow;
+pub use scope_inspector::ResolvedVariable;
+pub use snapshot::ElementSnapshot;
+pub use snapshot_diff::{ChangeKind, SnapshotChange};
 pub(crate) use tags::TagLike;
 use tags::{AnyChildTag, RootTag};
 
@@ -48,15 +56,15 @@ pub struct Fibroblast<'a> {
 }
 
 impl<'a> Fibroblast<'a> {
-	pub(crate) fn vars(&'a self) -> ClgnDecodingResult<&TagVariables> {
+	pub(crate) fn vars(&'a self) -> ClgnDecodingResult<&'a TagVariables> {
 		self.root.vars(&self.context)
 	}
 
-	pub(crate) fn children(&'a self) -> &[AnyChildTag<'a>] {
+	pub(crate) fn children(&'a self) -> &'a [AnyChildTag<'a>] {
 		self.root.children()
 	}
 
-	pub(crate) fn text(&'a self) -> ClgnDecodingResult<Cow<'a, str>> {
+	pub(crate) fn text(&'a self) -> ClgnDecodingResult<XmlText<'a>> {
 		self.root.text(&self.context)
 	}
 }
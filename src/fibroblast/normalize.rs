@@ -0,0 +1,89 @@
+//! Writing a skeleton's manifest back out from the validated model, for `clgn
+//! normalize`. This lets other tools transform manifests programmatically: read the
+//! manifest with Collagen, then edit a manifest whose layout is predictable.
+//!
+//! The normalized manifest is equivalent to the original: decoding it produces the same
+//! SVG. Normalizing is also idempotent. The normalized form differs from the original
+//! in that:
+//!
+//! - Keys appear in a fixed order: a tag's identifying key (e.g., `tag` or
+//!   `image_path`) first, then its other keys, then the keys common to all tags (`vars`,
+//!   `attrs`, etc.). The keys of `vars`, `attrs`, and other dictionaries are sorted.
+//! - Optional keys that were omitted (or given as `null`) are omitted, as are empty
+//!   font `attrs`
+//! - Numbers may be written differently, e.g., `2` as `2.0` for a `z`
+//!
+//! Included skeletons (`clgn_path`) are referred to, not inlined. Include directives
+//! (`include`), on the other hand, are expanded, so the normalized manifest is a single
+//! file.
+
+use super::Fibroblast;
+use serde::Serialize;
+use serde_json::ser::{PrettyFormatter, Serializer};
+use std::io;
+
+impl<'a> Fibroblast<'a> {
+	/// Write this skeleton's manifest, normalized, to `out`, as JSON indented with tabs
+	pub fn write_manifest(&self, out: impl io::Write) -> io::Result<()> {
+		let mut serializer = Serializer::with_formatter(out, PrettyFormatter::with_indent(b"\t"));
+		self.root.serialize(&mut serializer)?;
+
+		let mut out = serializer.into_inner();
+		writeln!(out)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fibroblast::DecodingContext;
+	use crate::from_json::decode_dir::decode_manifest;
+	use crate::utils::file_source::FileSource;
+	use std::path::Path;
+
+	#[test]
+	fn round_trip() {
+		for entry in std::fs::read_dir("tests/examples").unwrap() {
+			let example_dir = entry.unwrap().path();
+			let skeleton_dir = example_dir.join("skeleton");
+			if !skeleton_dir.is_dir() {
+				continue;
+			}
+
+			let fibroblast = Fibroblast::from_dir(&skeleton_dir).unwrap();
+			let mut manifest = Vec::new();
+			fibroblast.write_manifest(&mut manifest).unwrap();
+
+			let normalized = Fibroblast {
+				root: decode_manifest(&manifest, Path::new("normalized"), &[], &FileSource::Disk)
+					.unwrap()
+					.0,
+				context: DecodingContext::new_at_root(&skeleton_dir),
+			};
+			let mut renormalized = Vec::new();
+			normalized.write_manifest(&mut renormalized).unwrap();
+			assert_eq!(
+				String::from_utf8(manifest).unwrap(),
+				String::from_utf8(renormalized).unwrap(),
+				"normalizing {:?} twice changed it",
+				skeleton_dir
+			);
+
+			// Examples that need features that aren't enabled fail both ways
+			match (fibroblast.to_svg_string(), normalized.to_svg_string()) {
+				(Ok(original), Ok(normalized)) => assert_eq!(
+					original, normalized,
+					"normalizing {:?} changed its SVG",
+					skeleton_dir
+				),
+				(Err(_), Err(_)) => {}
+				(original, normalized) => panic!(
+					"normalizing {:?} changed whether it can be rendered: {:?} vs {:?}",
+					skeleton_dir,
+					original.err(),
+					normalized.err()
+				),
+			}
+		}
+	}
+}
@@ -0,0 +1,311 @@
+//! Inspecting the variables in scope at a given tag, and where each came from. When
+//! variable substitution produces an unexpected value deep in a document, this shows
+//! which of the (possibly many) enclosing scopes defining that variable won.
+//!
+//! A tag is identified by a [JSON pointer](https://datatracker.ietf.org/doc/html/rfc6901)
+//! into the manifest, such as `/children/2/children/0`; the empty pointer `""` is the
+//! root. Only `children` can be descended into. A pointer that passes through a
+//! [`ContainerTag`](super::tags::ContainerTag) continues into the children of the
+//! skeleton that it includes.
+//!
+//! The tree is walked just as it is when it's written to SVG, so the scopes considered
+//! are the same: each tag's `vars` and, for an `<svg>` of known size, the viewport
+//! variables (`parent_width`, etc.) it introduces for its descendants.
+
+use super::data_types::{DecodingContext, Map, VariableValue};
+use super::tags::{AnyChildTag, SiblingExports, TagLike};
+use super::Fibroblast;
+use crate::to_svg::svg_writable::{ClgnDecodingError, ClgnDecodingResult};
+
+/// A variable in scope at some tag
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedVariable {
+	pub name: String,
+	pub value: String,
+	/// A description of the scope the variable's value came from, e.g., `/children/0
+	/// vars`
+	pub scope: String,
+}
+
+/// A scope that was entered on the way to the tag being inspected, along with the
+/// names of the variables it defined (or redefined)
+struct Scope {
+	label: String,
+	names: Vec<String>,
+}
+
+/// The identities of the values of the variables currently in scope. Comparing
+/// snapshots taken before and after entering a scope tells us which variables that
+/// scope defined, even if it defined a variable to a value equal to its previous one.
+type Snapshot = Map<String, *const VariableValue>;
+
+fn snapshot(context: &DecodingContext) -> Snapshot {
+	context
+		.vars_map()
+		.iter()
+		.map(|(k, v)| ((*k).to_owned(), *v as *const VariableValue))
+		.collect()
+}
+
+impl Scope {
+	/// The scope labeled `label`, which defined the variables that have changed since
+	/// `before` was taken
+	fn new(label: String, before: &Snapshot, context: &DecodingContext) -> Self {
+		let names = snapshot(context)
+			.into_iter()
+			.filter(|(k, v)| before.get(k).map_or(true, |old| !std::ptr::eq(*old, *v)))
+			.map(|(k, _)| k)
+			.collect();
+
+		Self { label, names }
+	}
+}
+
+pub(crate) fn pointer_error(pointer: &str, msg: impl Into<String>) -> ClgnDecodingError {
+	ClgnDecodingError::JsonPointer {
+		pointer: pointer.to_owned(),
+		msg: msg.into(),
+	}
+}
+
+/// Parse `pointer`, which must be of the form `/children/i/children/j/...`, into the
+/// list of child indices `[i, j, ...]`
+pub(crate) fn parse_pointer(pointer: &str) -> ClgnDecodingResult<Vec<usize>> {
+	if pointer.is_empty() {
+		return Ok(Vec::new());
+	}
+	let rest = match pointer.strip_prefix('/') {
+		Some(rest) => rest,
+		None => {
+			return Err(pointer_error(
+				pointer,
+				"a nonempty pointer must start with `/`",
+			))
+		}
+	};
+
+	let tokens = rest.split('/').collect::<Vec<_>>();
+	tokens
+		.chunks(2)
+		.map(|pair| match pair {
+			["children", index] => index.parse::<usize>().map_err(|_| {
+				pointer_error(pointer, format!("{:?} is not a valid child index", index))
+			}),
+			["children"] => Err(pointer_error(
+				pointer,
+				"`children` must be followed by an index",
+			)),
+			[key, ..] => Err(pointer_error(
+				pointer,
+				format!("only `children` can be descended into, not {:?}", key),
+			)),
+			[] => unreachable!("chunks are never empty"),
+		})
+		.collect()
+}
+
+/// The variables in scope now, each attributed to the innermost of `scopes` that
+/// defined it
+fn resolve(context: &DecodingContext, scopes: &[Scope]) -> Vec<ResolvedVariable> {
+	context
+		.vars_map()
+		.iter()
+		.map(|(name, value)| ResolvedVariable {
+			name: (*name).to_owned(),
+			value: value.as_str().into_owned(),
+			scope: scopes
+				.iter()
+				.rev()
+				.find(|scope| scope.names.iter().any(|n| n == name))
+				.map_or_else(String::new, |scope| scope.label.clone()),
+		})
+		.collect()
+}
+
+/// Enter the scopes of the child of `children` at `indices[0]` (whose parent is at
+/// `pointer`), then that child's child at `indices[1]`, etc., and resolve the
+/// variables in scope at the last one
+fn descend<'a>(
+	children: &'a [AnyChildTag<'a>],
+	indices: &[usize],
+	pointer: String,
+	context: &'a DecodingContext<'a>,
+	scopes: &mut Vec<Scope>,
+) -> ClgnDecodingResult<Vec<ResolvedVariable>> {
+	let (&index, rest) = match indices.split_first() {
+		Some(split) => split,
+		None => return Ok(resolve(context, scopes)),
+	};
+
+	let pointer = format!("{}/children/{}", pointer, index);
+	let child = match children.get(index) {
+		Some(child) => child,
+		None => {
+			return Err(pointer_error(
+				&pointer,
+				format!("its parent has only {} children", children.len()),
+			))
+		}
+	};
+
+	let exports = SiblingExports::of(children, context)?;
+	let before = snapshot(context);
+	exports.with_exports_for(index, context, || {
+		scopes.push(Scope::new(
+			format!("{} sibling exports", pointer),
+			&before,
+			context,
+		));
+
+		let before = snapshot(context);
+		context.with_new_vars(child.vars(context)?, || {
+			scopes.push(Scope::new(format!("{} vars", pointer), &before, context));
+
+			match child {
+				AnyChildTag::Container(container) => {
+					let fb = container.as_fibroblast();
+					context.with_new_root(fb.context.get_root().as_path(), || {
+						descend(child.children(context)?, rest, pointer, context, scopes)
+					})
+				}
+				_ => {
+					let tag_name = child.tag_name();
+					if tag_name != "svg" || rest.is_empty() {
+						return descend(child.children(context)?, rest, pointer, context, scopes);
+					}
+
+					let attrs = child.explicit_attrs(context)?;
+					let before = snapshot(context);
+					context.with_viewport_vars(tag_name, false, &attrs, || {
+						scopes.push(Scope::new(
+							format!("{} viewport", pointer),
+							&before,
+							context,
+						));
+						descend(child.children(context)?, rest, pointer, context, scopes)
+					})
+				}
+			}
+		})
+	})
+}
+
+impl<'a> Fibroblast<'a> {
+	/// The variables in scope at the tag at `pointer`, a JSON pointer of the form
+	/// `/children/i/children/j/...` (or `""` for the root), sorted by name, each with
+	/// the scope its value came from. These are the variables available to the tag's
+	/// own `attrs`, `text`, etc.
+	pub fn variables_at(&'a self, pointer: &str) -> ClgnDecodingResult<Vec<ResolvedVariable>> {
+		let indices = parse_pointer(pointer)?;
+		let context = &self.context;
+		let root = &self.root;
+		let mut scopes = Vec::new();
+
+		let before = snapshot(context);
+		context.with_new_vars(root.vars(context)?, || {
+			scopes.push(Scope::new("(root) vars".to_owned(), &before, context));
+			if indices.is_empty() {
+				return Ok(resolve(context, &scopes));
+			}
+
+			let attrs = root.attrs(context)?;
+			let before = snapshot(context);
+			context.with_viewport_vars(root.tag_name(), true, &attrs, || {
+				scopes.push(Scope::new("(root) viewport".to_owned(), &before, context));
+				descend(
+					root.children(),
+					&indices,
+					String::new(),
+					context,
+					&mut scopes,
+				)
+			})
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn pointers() {
+		assert_eq!(parse_pointer("").unwrap(), Vec::<usize>::new());
+		assert_eq!(parse_pointer("/children/0").unwrap(), vec![0]);
+		assert_eq!(
+			parse_pointer("/children/2/children/10").unwrap(),
+			vec![2, 10]
+		);
+
+		for pointer in ["children/0", "/children", "/children/x", "/attrs/fill", "/"] {
+			assert!(
+				matches!(
+					parse_pointer(pointer),
+					Err(ClgnDecodingError::JsonPointer { .. })
+				),
+				"{:?}",
+				pointer
+			);
+		}
+	}
+
+	#[test]
+	fn variables_at() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			r#"{
+				"vars": { "a": 1, "b": 2 },
+				"attrs": { "viewBox": "0 0 100 50" },
+				"children": [
+					{
+						"tag": "svg",
+						"vars": { "b": "two" },
+						"attrs": { "width": 10, "height": 20 },
+						"children": [{ "tag": "rect", "vars": { "a": 1 } }]
+					}
+				]
+			}"#,
+		)
+		.unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+
+		let var = |name: &str, value: &str, scope: &str| ResolvedVariable {
+			name: name.to_owned(),
+			value: value.to_owned(),
+			scope: scope.to_owned(),
+		};
+
+		assert_eq!(
+			fibroblast.variables_at("").unwrap(),
+			vec![var("a", "1", "(root) vars"), var("b", "2", "(root) vars")]
+		);
+		assert_eq!(
+			fibroblast.variables_at("/children/0").unwrap(),
+			vec![
+				var("a", "1", "(root) vars"),
+				var("b", "two", "/children/0 vars"),
+				var("canvas_height", "50", "(root) viewport"),
+				var("canvas_width", "100", "(root) viewport"),
+				var("parent_height", "50", "(root) viewport"),
+				var("parent_width", "100", "(root) viewport"),
+			]
+		);
+		assert_eq!(
+			fibroblast.variables_at("/children/0/children/0").unwrap(),
+			vec![
+				var("a", "1", "/children/0/children/0 vars"),
+				var("b", "two", "/children/0 vars"),
+				var("canvas_height", "50", "(root) viewport"),
+				var("canvas_width", "100", "(root) viewport"),
+				var("parent_height", "20", "/children/0 viewport"),
+				var("parent_width", "10", "/children/0 viewport"),
+			]
+		);
+
+		assert!(matches!(
+			fibroblast.variables_at("/children/1"),
+			Err(ClgnDecodingError::JsonPointer { .. })
+		));
+	}
+}
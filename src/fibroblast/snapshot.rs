@@ -0,0 +1,208 @@
+//! A snapshot of the element tree that a skeleton resolves to: each element's tag name,
+//! its final attributes (after variable substitution, palette resolution, generated
+//! attributes such as an image's `href`, etc.), its text, and its children. This makes
+//! it possible to test the geometry a skeleton computes without parsing the SVG it
+//! produces.
+//!
+//! The tree is walked just as it is when it's written to SVG, so the snapshot matches
+//! the SVG element for element, except that markup that Collagen generates on its own
+//! behalf (the arrowhead `<marker>` used by connectors, the `<clipPath>`s and
+//! `<mask>`s of tags' `clip` and `mask`, the `<symbol>`s of the symbols that tags'
+//! `use_from` take from libraries, the `<style>` that implements themed values, and the
+//! root's `credits`) is omitted. (The `<a>`s that tags' `link`s wrap them in are included, since
+//! they're part of the document's structure.)
+
+use super::data_types::{Anchors, AttrKVValueVec, DecodingContext, Map};
+use super::tags::{
+	clip::resolve_clips, link::resolve_tag_link, AnyChildTag, SiblingExports, TagLike,
+};
+use super::Fibroblast;
+use crate::to_svg::svg_writable::ClgnDecodingResult;
+use serde::{Deserialize, Serialize};
+
+/// A resolved element and its descendants
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ElementSnapshot {
+	pub tag: String,
+	/// The element's attributes, as they'd be written to SVG
+	pub attrs: Map<String, String>,
+	/// The element's text. If the tag's `should_escape_text` is `false`, this is raw XML.
+	#[serde(default, skip_serializing_if = "String::is_empty")]
+	pub text: String,
+	/// The element's children, in paint order (i.e., the order they'd be written in)
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub children: Vec<ElementSnapshot>,
+}
+
+fn attr_map(attrs: &AttrKVValueVec) -> Map<String, String> {
+	attrs
+		.iter()
+		.filter_map(|(k, v)| {
+			v.to_maybe_string()
+				.map(|s| ((*k).to_owned(), s.into_owned()))
+		})
+		.collect()
+}
+
+/// Snapshot `tag`, calling `snapshot_children` to snapshot its children once its
+/// variables and viewport are in scope
+fn snapshot_tag<'a, T: TagLike<'a>>(
+	tag: &'a T,
+	context: &'a DecodingContext<'a>,
+	snapshot_children: impl FnOnce() -> ClgnDecodingResult<Vec<ElementSnapshot>>,
+) -> ClgnDecodingResult<ElementSnapshot> {
+	context.with_new_vars(tag.vars(context)?, || {
+		let mut attrs = tag.attrs(context)?;
+		resolve_clips(&tag.clips(), context, &mut attrs)?;
+
+		let mut children = tag
+			.descriptions(context)?
+			.into_iter()
+			.map(|(name, text)| ElementSnapshot {
+				tag: name.to_owned(),
+				attrs: Map::new(),
+				text,
+				children: Vec::new(),
+			})
+			.collect::<Vec<_>>();
+		for animation in tag.animations() {
+			children.push(ElementSnapshot {
+				tag: animation.tag_name().to_owned(),
+				attrs: attr_map(&animation.attrs(context)?),
+				text: String::new(),
+				children: Vec::new(),
+			});
+		}
+		children.extend(context.with_viewport_vars(
+			tag.tag_name(),
+			tag.is_root(),
+			&attrs,
+			snapshot_children,
+		)?);
+
+		let link = resolve_tag_link(tag, &attrs, context)?;
+		let elem = ElementSnapshot {
+			tag: tag.tag_name().to_owned(),
+			attrs: attr_map(&attrs),
+			text: tag.text(context)?.as_str().to_owned(),
+			children,
+		};
+
+		Ok(match link {
+			Some(link) => link.snapshot(elem),
+			None => elem,
+		})
+	})
+}
+
+fn snapshot_children<'a>(
+	children: &'a [AnyChildTag<'a>],
+	context: &'a DecodingContext<'a>,
+) -> ClgnDecodingResult<Vec<ElementSnapshot>> {
+	let exports = SiblingExports::of(children, context)?;
+	AnyChildTag::in_paint_order(children)
+		.into_iter()
+		.map(|(index, child)| {
+			context.with_nested_tag(|| {
+				exports.with_exports_for(index, context, || snapshot_child(child, context))
+			})
+		})
+		.collect()
+}
+
+fn snapshot_child<'a>(
+	child: &'a AnyChildTag<'a>,
+	context: &'a DecodingContext<'a>,
+) -> ClgnDecodingResult<ElementSnapshot> {
+	snapshot_tag(child, context, || match child {
+		AnyChildTag::Container(container) => {
+			let fb = container.as_fibroblast();
+			context.with_new_root(fb.context.get_root().as_path(), || {
+				context.with_palette(fb.root.base_palette(), || {
+					snapshot_children(child.children(context)?, context)
+				})
+			})
+		}
+		_ => context.with_new_vars(child.vars(context)?, || {
+			snapshot_children(child.children(context)?, context)
+		}),
+	})
+}
+
+impl<'a> Fibroblast<'a> {
+	/// The element tree that this skeleton resolves to, i.e., the SVG it would be
+	/// written as, but not as XML
+	pub fn snapshot(&'a self) -> ClgnDecodingResult<ElementSnapshot> {
+		let context = &self.context;
+		let root = &self.root;
+		context.take_themes();
+		context.take_class_styles();
+		context.reset_generated_ids();
+
+		let snapshot = context.with_palette(root.base_palette(), || {
+			snapshot_tag(root, context, || {
+				let mut anchors = Anchors::default();
+				AnyChildTag::collect_all_anchors(root.children(), context, &mut anchors)?;
+				context.replace_anchors(anchors);
+
+				snapshot_children(root.children(), context)
+			})
+		});
+		context.take_themes();
+		context.take_class_styles();
+
+		snapshot
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn snapshot() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			r##"{
+				"vars": { "side": 10 },
+				"attrs": { "viewBox": "0 0 100 50" },
+				"children": [
+					{ "tag": "text", "z": 1, "text": "side: {side}", "link": { "href": "#{side}" } },
+					{
+						"tag": "g",
+						"children": [
+							{
+								"tag": "rect",
+								"attrs": { "width": "{side}", "height": "50%h" }
+							}
+						]
+					}
+				]
+			}"##,
+		)
+		.unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+
+		let expected: ElementSnapshot = serde_json::from_str(
+			r##"{
+				"tag": "svg",
+				"attrs": { "viewBox": "0 0 100 50", "xmlns": "http://www.w3.org/2000/svg" },
+				"children": [
+					{
+						"tag": "g",
+						"attrs": {},
+						"children": [{ "tag": "rect", "attrs": { "height": "25", "width": "10" } }]
+					},
+					{
+						"tag": "a",
+						"attrs": { "href": "#10" },
+						"children": [{ "tag": "text", "attrs": {}, "text": "side: 10" }]
+					}
+				]
+			}"##,
+		)
+		.unwrap();
+		assert_eq!(fibroblast.snapshot().unwrap(), expected);
+	}
+}
@@ -0,0 +1,318 @@
+//! A structural diff of two [`ElementSnapshot`]s, for `clgn diff`. Two renders of a
+//! skeleton usually differ in only a few elements and attributes, but a text diff of the
+//! SVGs can be unreadable: the SVG may be a single line, and an embedded image is a
+//! single base64 blob. Comparing the element trees instead lists just the elements that
+//! were added or removed and the attributes and text that changed.
+//!
+//! The children of matching elements are matched by their tag name and `id` (if any),
+//! preserving their order as far as possible, so that inserting an element reports one
+//! addition rather than a change to every element after it. Elements are identified by
+//! XPath-like paths such as `/svg/g[2]/rect[1]` or `/svg/g[@id='legend']`, in which the
+//! index is 1-based and counts only siblings with the same tag name.
+
+use super::ElementSnapshot;
+use std::fmt;
+
+/// Attribute values and text longer than this many characters (such as the `href` of an
+/// embedded image) are abbreviated when displayed
+const MAX_DISPLAYED_CHARS: usize = 60;
+
+/// How an element changed from one snapshot to another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeKind {
+	ElementAdded,
+	ElementRemoved,
+	AttrAdded {
+		name: String,
+		value: String,
+	},
+	AttrRemoved {
+		name: String,
+		value: String,
+	},
+	AttrChanged {
+		name: String,
+		old: String,
+		new: String,
+	},
+	TextChanged {
+		old: String,
+		new: String,
+	},
+}
+
+/// A change to the element at `path`. Removed elements' paths are in the old snapshot;
+/// all other paths are in the new one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotChange {
+	pub path: String,
+	pub kind: ChangeKind,
+}
+
+/// `value`, quoted, and abbreviated if it's long
+fn display_value(value: &str) -> String {
+	if value.chars().count() <= MAX_DISPLAYED_CHARS {
+		return format!("{:?}", value);
+	}
+
+	let start = value.chars().take(MAX_DISPLAYED_CHARS).collect::<String>();
+	format!("{:?}… ({} bytes)", start, value.len())
+}
+
+impl fmt::Display for SnapshotChange {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let path = &self.path;
+		match &self.kind {
+			ChangeKind::ElementAdded => write!(f, "+ {}", path),
+			ChangeKind::ElementRemoved => write!(f, "- {}", path),
+			ChangeKind::AttrAdded { name, value } => {
+				write!(f, "+ {}/@{}: {}", path, name, display_value(value))
+			}
+			ChangeKind::AttrRemoved { name, value } => {
+				write!(f, "- {}/@{}: {}", path, name, display_value(value))
+			}
+			ChangeKind::AttrChanged { name, old, new } => write!(
+				f,
+				"~ {}/@{}: {} -> {}",
+				path,
+				name,
+				display_value(old),
+				display_value(new)
+			),
+			ChangeKind::TextChanged { old, new } => write!(
+				f,
+				"~ {}/text(): {} -> {}",
+				path,
+				display_value(old),
+				display_value(new)
+			),
+		}
+	}
+}
+
+/// The key by which elements are matched with their counterparts
+fn match_key(elem: &ElementSnapshot) -> (&str, Option<&str>) {
+	(&elem.tag, elem.attrs.get("id").map(String::as_str))
+}
+
+/// The path of each of `children`, whose parent's path is `parent_path`
+fn child_paths(parent_path: &str, children: &[ElementSnapshot]) -> Vec<String> {
+	children
+		.iter()
+		.enumerate()
+		.map(|(i, child)| match child.attrs.get("id") {
+			Some(id) => format!("{}/{}[@id='{}']", parent_path, child.tag, id),
+			None => {
+				let n = children[..=i].iter().filter(|c| c.tag == child.tag).count();
+				format!("{}/{}[{}]", parent_path, child.tag, n)
+			}
+		})
+		.collect()
+}
+
+/// The pairs of indices of `old` and `new` that match, in order: a longest common
+/// subsequence of their match keys
+fn matching_children(old: &[ElementSnapshot], new: &[ElementSnapshot]) -> Vec<(usize, usize)> {
+	// lcs_lens[i][j] is the length of the LCS of old[i..] and new[j..]
+	let mut lcs_lens = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+	for i in (0..old.len()).rev() {
+		for j in (0..new.len()).rev() {
+			lcs_lens[i][j] = if match_key(&old[i]) == match_key(&new[j]) {
+				lcs_lens[i + 1][j + 1] + 1
+			} else {
+				lcs_lens[i + 1][j].max(lcs_lens[i][j + 1])
+			};
+		}
+	}
+
+	let mut pairs = Vec::new();
+	let (mut i, mut j) = (0, 0);
+	while i < old.len() && j < new.len() {
+		if match_key(&old[i]) == match_key(&new[j]) {
+			pairs.push((i, j));
+			i += 1;
+			j += 1;
+		} else if lcs_lens[i][j + 1] >= lcs_lens[i + 1][j] {
+			j += 1;
+		} else {
+			i += 1;
+		}
+	}
+
+	pairs
+}
+
+/// Push the changes from `old` to `new`, which match, onto `changes`. `old_path` and
+/// `new_path` are their paths.
+fn diff_elements(
+	old: &ElementSnapshot,
+	new: &ElementSnapshot,
+	old_path: &str,
+	new_path: &str,
+	changes: &mut Vec<SnapshotChange>,
+) {
+	let mut push = |kind| {
+		changes.push(SnapshotChange {
+			path: new_path.to_owned(),
+			kind,
+		})
+	};
+
+	for (name, old_value) in &old.attrs {
+		match new.attrs.get(name) {
+			None => push(ChangeKind::AttrRemoved {
+				name: name.clone(),
+				value: old_value.clone(),
+			}),
+			Some(new_value) if new_value != old_value => push(ChangeKind::AttrChanged {
+				name: name.clone(),
+				old: old_value.clone(),
+				new: new_value.clone(),
+			}),
+			Some(_) => {}
+		}
+	}
+	for (name, new_value) in &new.attrs {
+		if !old.attrs.contains_key(name) {
+			push(ChangeKind::AttrAdded {
+				name: name.clone(),
+				value: new_value.clone(),
+			});
+		}
+	}
+	if old.text != new.text {
+		push(ChangeKind::TextChanged {
+			old: old.text.clone(),
+			new: new.text.clone(),
+		});
+	}
+
+	let old_paths = child_paths(old_path, &old.children);
+	let new_paths = child_paths(new_path, &new.children);
+	let pairs = matching_children(&old.children, &new.children);
+
+	// Walk both lists of children in order, reporting each unmatched child where it was
+	let (mut i, mut j) = (0, 0);
+	for (next_i, next_j) in pairs
+		.into_iter()
+		.chain(std::iter::once((old.children.len(), new.children.len())))
+	{
+		for path in &old_paths[i..next_i] {
+			changes.push(SnapshotChange {
+				path: path.clone(),
+				kind: ChangeKind::ElementRemoved,
+			});
+		}
+		for path in &new_paths[j..next_j] {
+			changes.push(SnapshotChange {
+				path: path.clone(),
+				kind: ChangeKind::ElementAdded,
+			});
+		}
+		if next_i < old.children.len() {
+			diff_elements(
+				&old.children[next_i],
+				&new.children[next_j],
+				&old_paths[next_i],
+				&new_paths[next_j],
+				changes,
+			);
+		}
+		i = next_i + 1;
+		j = next_j + 1;
+	}
+}
+
+impl ElementSnapshot {
+	/// The changes that turn this snapshot into `new`, in document order
+	pub fn diff(&self, new: &ElementSnapshot) -> Vec<SnapshotChange> {
+		let mut changes = Vec::new();
+		let old_path = format!("/{}", self.tag);
+		let new_path = format!("/{}", new.tag);
+		if self.tag == new.tag {
+			diff_elements(self, new, &old_path, &new_path, &mut changes);
+		} else {
+			changes.push(SnapshotChange {
+				path: old_path,
+				kind: ChangeKind::ElementRemoved,
+			});
+			changes.push(SnapshotChange {
+				path: new_path,
+				kind: ChangeKind::ElementAdded,
+			});
+		}
+
+		changes
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn snapshot(json: &str) -> ElementSnapshot {
+		serde_json::from_str(json).unwrap()
+	}
+
+	#[test]
+	fn diff() {
+		let old = snapshot(
+			r#"{
+				"tag": "svg",
+				"attrs": { "viewBox": "0 0 100 100" },
+				"children": [
+					{ "tag": "rect", "attrs": { "fill": "red", "opacity": "0.5" } },
+					{ "tag": "rect", "attrs": { "fill": "green" } },
+					{ "tag": "g", "attrs": { "id": "legend" }, "children": [
+						{ "tag": "text", "attrs": {}, "text": "old" }
+					] }
+				]
+			}"#,
+		);
+		assert_eq!(old.diff(&old), vec![]);
+
+		let new = snapshot(
+			r#"{
+				"tag": "svg",
+				"attrs": { "viewBox": "0 0 100 100" },
+				"children": [
+					{ "tag": "circle", "attrs": { "r": "5" } },
+					{ "tag": "rect", "attrs": { "fill": "blue", "stroke": "black" } },
+					{ "tag": "g", "attrs": { "id": "legend" }, "children": [
+						{ "tag": "text", "attrs": {}, "text": "new" }
+					] }
+				]
+			}"#,
+		);
+		let changes = old
+			.diff(&new)
+			.iter()
+			.map(|change| change.to_string())
+			.collect::<Vec<_>>();
+		assert_eq!(
+			changes,
+			vec![
+				"+ /svg/circle[1]",
+				r#"~ /svg/rect[1]/@fill: "red" -> "blue""#,
+				r#"- /svg/rect[1]/@opacity: "0.5""#,
+				r#"+ /svg/rect[1]/@stroke: "black""#,
+				"- /svg/rect[2]",
+				r#"~ /svg/g[@id='legend']/text[1]/text(): "old" -> "new""#,
+			]
+		);
+	}
+
+	#[test]
+	fn long_values_are_abbreviated() {
+		let change = SnapshotChange {
+			path: "/svg/image[1]".to_owned(),
+			kind: ChangeKind::AttrAdded {
+				name: "href".to_owned(),
+				value: format!("data:image/png;base64,{}", "A".repeat(1000)),
+			},
+		};
+		let displayed = change.to_string();
+		assert!(displayed.ends_with("… (1022 bytes)"), "{}", displayed);
+		assert!(displayed.len() < 120);
+	}
+}
@@ -0,0 +1,316 @@
+//! SMIL animations are written as `<animate>` (or, for transforms, `<animateTransform>`)
+//! children of the element they animate. This file provides [`Animation`], which
+//! describes one such animation in a more compact form than the raw SMIL attributes
+//! and validates it before it's written, so that mistakes such as giving both `values`
+//! and `to` are caught rather than silently producing an animation that does nothing.
+
+use super::{AttrKVValueVec, ClgnDecodingResult, DecodingContext};
+use crate::fibroblast::data_types::SimpleValue;
+use crate::to_svg::svg_writable::ClgnDecodingError;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// The kinds of transform that `<animateTransform>` can animate
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum TransformType {
+	Translate,
+	Scale,
+	Rotate,
+	SkewX,
+	SkewY,
+}
+
+impl TransformType {
+	fn as_str(self) -> &'static str {
+		use TransformType::*;
+		match self {
+			Translate => "translate",
+			Scale => "scale",
+			Rotate => "rotate",
+			SkewX => "skewX",
+			SkewY => "skewY",
+		}
+	}
+}
+
+/// A single animation of one of a tag's attributes, written as an `<animate>` child of
+/// the tag (or an `<animateTransform>`, if `type` is given). For instance, `{ "attr":
+/// "opacity", "from": 0, "to": 1, "dur": "1s", "repeat": "indefinite" }` becomes
+/// `<animate attributeName="opacity" from="0" to="1" dur="1s"
+/// repeatCount="indefinite"></animate>`. Variable substitution is performed on all
+/// values.
+///
+/// # Properties
+///
+/// - `attr`
+///   - Type: string
+///   - Required: Yes, unless `type` is given.
+///   - Description: The name of the attribute to animate.
+/// - `type`
+///   - Type: string, one of `"translate"`, `"scale"`, `"rotate"`, `"skewX"`, or
+///     `"skewY"`
+///   - Required: No.
+///   - Description: If given, the animation is of the tag's `transform`, using a
+///     transform of this type, and `attr` (if given) must be `"transform"`.
+/// - `from`, `to`
+///   - Type: number or string
+///   - Required: `to` is required unless `values` is given.
+///   - Description: The values to animate from and to. If `from` is missing, the
+///     animation starts from the attribute's current value.
+/// - `values`
+///   - Type: list of numbers or strings
+///   - Required: No. May not be given along with `from` or `to`.
+///   - Description: A list of values to animate through, in order.
+/// - `dur`
+///   - Type: number or string
+///   - Required: Yes.
+///   - Description: The duration of (one repetition of) the animation. A number is a
+///     number of seconds; a string is any SMIL clock value, such as `"500ms"`.
+/// - `begin`
+///   - Type: number or string
+///   - Required: No. Missing is equivalent to `0`.
+///   - Description: When the animation begins. A number is a number of seconds.
+/// - `repeat`
+///   - Type: number or string
+///   - Required: No. Missing is equivalent to `1`.
+///   - Description: The number of times to play the animation, or `"indefinite"` to
+///     repeat it forever.
+/// - `freeze`
+///   - Type: bool
+///   - Required: No. Missing is equivalent to `false`.
+///   - Description: Whether the attribute should keep its final value once the
+///     animation ends, rather than reverting to its original value.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Animation {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	attr: Option<String>,
+
+	#[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+	transform_type: Option<TransformType>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	from: Option<SimpleValue>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	to: Option<SimpleValue>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	values: Option<Vec<SimpleValue>>,
+
+	dur: SimpleValue,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	begin: Option<SimpleValue>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	repeat: Option<SimpleValue>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	freeze: Option<bool>,
+}
+
+/// The value of the `animate` field of a tag: either a single animation or a list of
+/// them
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub(crate) enum Animations {
+	One(Animation),
+	Many(Vec<Animation>),
+}
+
+impl Animations {
+	pub(crate) fn as_slice(&self) -> &[Animation] {
+		match self {
+			Animations::One(animation) => std::slice::from_ref(animation),
+			Animations::Many(animations) => animations,
+		}
+	}
+}
+
+/// SMIL reads a bare number in a clock value as a number of seconds, but be explicit
+fn to_clock_value(value: &SimpleValue) -> Cow<'_, SimpleValue> {
+	match value {
+		SimpleValue::Number(n) => Cow::Owned(SimpleValue::Text(format!("{}s", n))),
+		_ => Cow::Borrowed(value),
+	}
+}
+
+impl Animation {
+	pub(crate) fn tag_name(&self) -> &'static str {
+		match self.transform_type {
+			Some(_) => "animateTransform",
+			None => "animate",
+		}
+	}
+
+	fn error(msg: impl Into<String>) -> ClgnDecodingError {
+		ClgnDecodingError::Animation { msg: msg.into() }
+	}
+
+	/// The attributes of the `<animate>` or `<animateTransform>` tag, with variables
+	/// substituted in
+	pub(crate) fn attrs<'b>(
+		&'b self,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<AttrKVValueVec<'b>> {
+		let mut attrs = Vec::<(&str, Cow<SimpleValue>)>::new();
+
+		let attr = match (&self.attr, self.transform_type) {
+			(Some(attr), None) => attr.as_str(),
+			(None, Some(_)) => "transform",
+			(Some(attr), Some(_)) if attr == "transform" => "transform",
+			(Some(attr), Some(t)) => {
+				return Err(Self::error(format!(
+					"An animation of type {:?} animates the `transform` attribute, but \
+					 `attr` was {:?}",
+					t.as_str(),
+					attr
+				)))
+			}
+			(None, None) => {
+				return Err(Self::error(
+					"An animation must specify which attribute to animate with `attr` (or, \
+					 for an `<animateTransform>`, with `type`)",
+				))
+			}
+		};
+		attrs.push((
+			"attributeName",
+			Cow::Owned(SimpleValue::Text(attr.to_owned())),
+		));
+		if let Some(t) = self.transform_type {
+			attrs.push(("type", Cow::Owned(SimpleValue::Text(t.as_str().to_owned()))));
+		}
+
+		match (&self.values, &self.from, &self.to) {
+			(Some(values), None, None) => {
+				if values.is_empty() {
+					return Err(Self::error(format!(
+						"The animation of `{}` has an empty list of `values`",
+						attr
+					)));
+				}
+				let values = values
+					.iter()
+					.filter_map(|v| v.to_maybe_string())
+					.collect::<Vec<_>>()
+					.join(";");
+				attrs.push(("values", Cow::Owned(SimpleValue::Text(values))));
+			}
+			(Some(_), _, _) => {
+				return Err(Self::error(format!(
+					"The animation of `{}` may specify either `values` or `from` and `to`, \
+					 but not both",
+					attr
+				)))
+			}
+			(None, from, Some(to)) => {
+				if let Some(from) = from {
+					attrs.push(("from", Cow::Borrowed(from)));
+				}
+				attrs.push(("to", Cow::Borrowed(to)));
+			}
+			(None, _, None) => {
+				return Err(Self::error(format!(
+					"The animation of `{}` must specify either `to` or `values`",
+					attr
+				)))
+			}
+		}
+
+		attrs.push(("dur", to_clock_value(&self.dur)));
+		if let Some(begin) = &self.begin {
+			attrs.push(("begin", to_clock_value(begin)));
+		}
+		if let Some(repeat) = &self.repeat {
+			attrs.push(("repeatCount", Cow::Borrowed(repeat)));
+		}
+		if self.freeze.unwrap_or(false) {
+			attrs.push(("fill", Cow::Owned(SimpleValue::Text("freeze".to_owned()))));
+		}
+
+		let mut attrs = context.sub_vars_into_attrs(attrs)?;
+		context.resolve_palette_colors(&mut attrs)?;
+		context.resolve_relative_units(&mut attrs)?;
+
+		Ok(attrs)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[track_caller]
+	fn test_attrs(json: &str, expected: &[(&str, &str)]) {
+		let animation: Animation = serde_json::from_str(json).unwrap();
+		let context = DecodingContext::new_empty();
+		let attrs = animation
+			.attrs(&context)
+			.unwrap()
+			.iter()
+			.map(|(k, v)| (k.to_string(), v.to_maybe_string().unwrap().into_owned()))
+			.collect::<Vec<_>>();
+		let expected = expected
+			.iter()
+			.map(|(k, v)| (k.to_string(), v.to_string()))
+			.collect::<Vec<_>>();
+		assert_eq!(attrs, expected);
+	}
+
+	#[track_caller]
+	fn test_error(json: &str) {
+		let animation: Animation = serde_json::from_str(json).unwrap();
+		let context = DecodingContext::new_empty();
+		assert!(matches!(
+			animation.attrs(&context),
+			Err(ClgnDecodingError::Animation { .. })
+		));
+	}
+
+	#[test]
+	fn attrs() {
+		test_attrs(
+			r#"{"attr": "opacity", "from": 0, "to": 1, "dur": 1, "repeat": "indefinite"}"#,
+			&[
+				("attributeName", "opacity"),
+				("from", "0"),
+				("to", "1"),
+				("dur", "1s"),
+				("repeatCount", "indefinite"),
+			],
+		);
+		test_attrs(
+			r#"{"attr": "fill", "values": ["red", "blue", "red"], "dur": "500ms", "freeze": true}"#,
+			&[
+				("attributeName", "fill"),
+				("values", "red;blue;red"),
+				("dur", "500ms"),
+				("fill", "freeze"),
+			],
+		);
+		test_attrs(
+			r#"{"type": "rotate", "from": "0 50 50", "to": "360 50 50", "dur": 2, "begin": 1}"#,
+			&[
+				("attributeName", "transform"),
+				("type", "rotate"),
+				("from", "0 50 50"),
+				("to", "360 50 50"),
+				("dur", "2s"),
+				("begin", "1s"),
+			],
+		);
+	}
+
+	#[test]
+	fn errors() {
+		test_error(r#"{"to": 1, "dur": 1}"#);
+		test_error(r#"{"attr": "x", "dur": 1}"#);
+		test_error(r#"{"attr": "x", "from": 0, "dur": 1}"#);
+		test_error(r#"{"attr": "x", "values": [], "dur": 1}"#);
+		test_error(r#"{"attr": "x", "values": [0, 1], "to": 1, "dur": 1}"#);
+		test_error(r#"{"attr": "x", "type": "rotate", "to": 1, "dur": 1}"#);
+	}
+}
@@ -1,10 +1,18 @@
+use super::animation::Animation;
+use super::clip::{ClipKind, ClipShape};
+use super::common_tag_fields::{add_class_styles_to_attrs, merge_style_into_attrs};
+use super::link::Link;
 use super::{
-	container_tag::ContainerTag, font_tag::FontTag, image_tag::ImageTag, other_tag::OtherTag,
+	connector_tag::ConnectorTag, container_tag::ContainerTag, container_tag::SiblingExports,
+	font_tag::FontTag, image_tag::ImageTag, other_tag::OtherTag, repeat_tag::RepeatTag,
+	shape_tag::ShapeTag, use_from_tag::UseFromTag,
 };
-use super::{AttrKVValueVec, ClgnDecodingResult, TagLike, TagVariables};
-use crate::fibroblast::data_types::DecodingContext;
+use super::{AttrKVValueVec, ClgnDecodingResult, TagLike, TagVariables, XmlAttrs};
+use crate::fibroblast::data_types::{Anchor, AnchorEntry, Anchors, DecodingContext};
+use crate::to_svg::xml_text::XmlText;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::path::PathBuf;
 
 /// A wrapper around child tags. During deserialization, the type of child tag to
 /// deserialize an object into is determined solely from the object's set of keys.
@@ -17,6 +25,13 @@ use std::borrow::Cow;
 ///   ingested more or less as-is into the current SVG
 /// - [`FontTag`]: a tag used to include either a woff2 font file on disk or a font that
 ///   came bundled with the Collagen executable
+/// - [`ShapeTag`]: a tag whose geometry (e.g., the `d` of a `<path>`) is generated from
+///   a few parameters describing a shape such as an arc, star, or regular polygon
+/// - [`ConnectorTag`]: a tag that draws a path between two other elements, referred to
+///   by their `id`s
+/// - [`RepeatTag`]: a tag that repeats a template tag several times, each copy offset
+///   from the last
+/// - [`UseFromTag`]: a tag that uses a symbol exported by another skeleton, a library
 /// - [`OtherTag`]: the most general option; represents any kind of SVG tag that does
 ///   not need any special handling as the above tags do
 
@@ -27,10 +42,43 @@ pub enum AnyChildTag<'a> {
 	Image(ImageTag<'a>),
 	Container(ContainerTag<'a>),
 	Font(FontTag),
+	Shape(ShapeTag<'a>),
+	Connector(ConnectorTag<'a>),
+	Repeat(RepeatTag<'a>),
+	UseFrom(UseFromTag<'a>),
 	Other(OtherTag<'a>),
 }
 
 impl<'a> AnyChildTag<'a> {
+	/// The kind of tag this is, e.g., `image` for an [`ImageTag`]
+	pub(crate) fn kind(&self) -> &'static str {
+		use AnyChildTag::*;
+		match self {
+			Image(_) => "image",
+			Container(_) => "container",
+			Font(_) => "font",
+			Shape(_) => "shape",
+			Connector(_) => "connector",
+			Repeat(_) => "repeat",
+			UseFrom(_) => "use_from",
+			Other(_) => "other",
+		}
+	}
+
+	pub(crate) fn fresh_clone<'b>(&self) -> AnyChildTag<'b> {
+		use AnyChildTag::*;
+		match self {
+			Image(t) => Image(t.fresh_clone()),
+			Container(t) => Container(t.fresh_clone()),
+			Font(t) => Font(t.clone()),
+			Shape(t) => Shape(t.fresh_clone()),
+			Connector(t) => Connector(t.fresh_clone()),
+			Repeat(t) => Repeat(t.fresh_clone()),
+			UseFrom(t) => UseFrom(t.fresh_clone()),
+			Other(t) => Other(t.fresh_clone()),
+		}
+	}
+
 	fn initialize(&'a self, context: &DecodingContext<'a>) -> ClgnDecodingResult<()> {
 		if let AnyChildTag::Container(t) = self {
 			t.initialize(context)?;
@@ -38,10 +86,39 @@ impl<'a> AnyChildTag<'a> {
 		Ok(())
 	}
 
+	/// The paths of the files on disk (images, fonts) that this tag, but not its
+	/// children, embeds
+	pub(crate) fn asset_paths(
+		&self,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<Vec<PathBuf>> {
+		use AnyChildTag::*;
+		Ok(match &self {
+			Image(t) => vec![t.abs_image_path(context)?],
+			Font(t) => t.asset_paths(context)?,
+			Container(_) | Other(_) | Shape(_) | Connector(_) | Repeat(_) | UseFrom(_) => {
+				Vec::new()
+			}
+		})
+	}
+
+	/// The credits of the assets (images, fonts) that this tag, but not its children,
+	/// embeds, for the root's `credits`
+	pub(crate) fn credits(&self, context: &DecodingContext) -> ClgnDecodingResult<Vec<String>> {
+		use AnyChildTag::*;
+		Ok(match &self {
+			Image(t) => vec![t.credit(context)?],
+			Font(t) => t.credits(context)?,
+			Container(_) | Other(_) | Shape(_) | Connector(_) | Repeat(_) | UseFrom(_) => {
+				Vec::new()
+			}
+		})
+	}
+
 	pub(crate) fn children(
 		&'a self,
 		context: &'a DecodingContext<'a>,
-	) -> ClgnDecodingResult<&'a [AnyChildTag]> {
+	) -> ClgnDecodingResult<&'a [AnyChildTag<'a>]> {
 		self.initialize(context)?;
 
 		use AnyChildTag::*;
@@ -50,6 +127,201 @@ impl<'a> AnyChildTag<'a> {
 			Image(t) => t.base_children(),
 			Other(t) => t.base_children(),
 			Font(t) => t.base_children(),
+			Shape(t) => t.base_children(),
+			Connector(t) => t.base_children(),
+			Repeat(t) => t.children(context)?,
+			UseFrom(t) => t.base_children(),
+		})
+	}
+
+	/// This tag's position in the paint order relative to its siblings
+	pub(crate) fn z(&self) -> f64 {
+		use AnyChildTag::*;
+		match &self {
+			Container(t) => t.z(),
+			Image(t) => t.z(),
+			Other(t) => t.z(),
+			Font(_) => 0.0,
+			Shape(t) => t.z(),
+			Connector(t) => t.z(),
+			Repeat(t) => t.z(),
+			UseFrom(t) => t.z(),
+		}
+	}
+
+	/// The variables this tag exports to the siblings that follow it, if any (see
+	/// [`SiblingExports`])
+	pub(crate) fn exported_vars(
+		&'a self,
+		context: &DecodingContext<'a>,
+	) -> ClgnDecodingResult<Option<TagVariables>> {
+		match self {
+			AnyChildTag::Container(t) => {
+				self.initialize(context)?;
+				t.exported_vars()
+			}
+			_ => Ok(None),
+		}
+	}
+
+	/// `children`, each with its index, stably sorted by `z`
+	pub(crate) fn in_paint_order(
+		children: &'a [AnyChildTag<'a>],
+	) -> Vec<(usize, &'a AnyChildTag<'a>)> {
+		let mut children = children.iter().enumerate().collect::<Vec<_>>();
+		// `z` comes from JSON, so it can't be NaN
+		children.sort_by(|(_, a), (_, b)| a.z().partial_cmp(&b.z()).unwrap());
+		children
+	}
+
+	/// The attributes given explicitly in the skeleton, before variable substitution
+	fn raw_attrs(&'a self, context: &DecodingContext<'a>) -> ClgnDecodingResult<&'a XmlAttrs> {
+		self.initialize(context)?;
+
+		use AnyChildTag::*;
+		Ok(match &self {
+			Container(t) => t.as_fibroblast().root.base_attrs(),
+			Image(t) => t.base_attrs(),
+			Other(t) => t.base_attrs(),
+			Font(t) => t.base_attrs(),
+			Shape(t) => t.base_attrs(),
+			Connector(t) => t.base_attrs(),
+			Repeat(t) => t.base_attrs(),
+			UseFrom(t) => t.base_attrs(),
+		})
+	}
+
+	/// The attributes given explicitly in the skeleton, with variables substituted in.
+	/// (Does not include attributes generated by the tag, such as an image's `href`.)
+	pub(crate) fn explicit_attrs(
+		&'a self,
+		context: &DecodingContext<'a>,
+	) -> ClgnDecodingResult<AttrKVValueVec<'a>> {
+		if let AnyChildTag::Container(t) = self {
+			self.initialize(context)?;
+			return t.attrs();
+		}
+
+		let mut attrs = context.sub_vars_into_attrs(
+			self.raw_attrs(context)?
+				.0
+				.iter()
+				.map(|(k, v)| (k.as_ref(), Cow::Borrowed(v))),
+		)?;
+		context.resolve_palette_colors(&mut attrs)?;
+
+		use AnyChildTag::*;
+		let (style, class_styles) = match &self {
+			Image(t) => (t.base_style(), t.base_class_styles()),
+			Other(t) => (t.base_style(), t.base_class_styles()),
+			Shape(t) => (t.base_style(), t.base_class_styles()),
+			Connector(t) => (t.base_style(), t.base_class_styles()),
+			Repeat(t) => (t.base_style(), t.base_class_styles()),
+			UseFrom(t) => (t.base_style(), t.base_class_styles()),
+			Container(_) | Font(_) => return Ok(attrs),
+		};
+		merge_style_into_attrs(&mut attrs, style, context)?;
+		add_class_styles_to_attrs(&mut attrs, class_styles, context)?;
+		context.resolve_relative_units(&mut attrs)?;
+
+		Ok(attrs)
+	}
+
+	/// If this tag has an `id`, get it along with what's known about the tag's position.
+	/// Must be called in the same context (i.e., with the same variables in scope) as
+	/// the tag will be written in.
+	fn anchor(
+		&'a self,
+		context: &DecodingContext<'a>,
+	) -> ClgnDecodingResult<Option<(String, AnchorEntry)>> {
+		// Most tags don't have an `id`, so check for one before substituting variables
+		if !self.raw_attrs(context)?.contains_key("id") {
+			return Ok(None);
+		}
+
+		let attrs = self.explicit_attrs(context)?;
+		let id = match attrs
+			.iter()
+			.find(|(k, _)| *k == "id")
+			.and_then(|(_, v)| v.to_maybe_string())
+		{
+			Some(id) => id.into_owned(),
+			None => return Ok(None),
+		};
+
+		let tag_name = self.tag_name();
+		let anchor = match self {
+			AnyChildTag::Shape(t) => Some(t.anchor(context)?),
+			AnyChildTag::Connector(_) => None,
+			_ => Anchor::from_attrs(tag_name, &attrs),
+		};
+		let entry = match anchor {
+			Some(anchor) => AnchorEntry::Found(anchor),
+			None => AnchorEntry::Unsupported(tag_name.to_owned()),
+		};
+
+		Ok(Some((id, entry)))
+	}
+
+	/// Record the anchors of `children` and all of their descendants in `anchors`
+	pub(crate) fn collect_all_anchors(
+		children: &'a [AnyChildTag<'a>],
+		context: &'a DecodingContext<'a>,
+		anchors: &mut Anchors,
+	) -> ClgnDecodingResult<()> {
+		let exports = SiblingExports::of(children, context)?;
+		for (index, child) in children.iter().enumerate() {
+			exports.with_exports_for(index, context, || child.collect_anchors(context, anchors))?;
+		}
+
+		Ok(())
+	}
+
+	/// Record the anchors of this tag and all of its descendants in `anchors`. This
+	/// walks the tree the same way that writing it to SVG does, so that variables are
+	/// substituted into `id`s and positions just as they will be when written.
+	pub(crate) fn collect_anchors(
+		&'a self,
+		context: &'a DecodingContext<'a>,
+		anchors: &mut Anchors,
+	) -> ClgnDecodingResult<()> {
+		context.with_nested_tag(|| self.collect_own_and_descendant_anchors(context, anchors))
+	}
+
+	fn collect_own_and_descendant_anchors(
+		&'a self,
+		context: &'a DecodingContext<'a>,
+		anchors: &mut Anchors,
+	) -> ClgnDecodingResult<()> {
+		context.with_new_vars(self.vars(context)?, || {
+			if let AnyChildTag::Connector(t) = self {
+				anchors.needs_arrowhead |= t.has_arrowheads();
+			}
+			if let Some((id, entry)) = self.anchor(context)? {
+				anchors.insert(id, entry);
+			}
+
+			match self {
+				AnyChildTag::Container(container) => {
+					let fb = container.as_fibroblast();
+					context.with_new_root(fb.context.get_root().as_path(), || {
+						context.with_palette(fb.root.base_palette(), || {
+							Self::collect_all_anchors(self.children(context)?, context, anchors)
+						})
+					})
+				}
+				_ => {
+					let tag_name = self.tag_name();
+					let attrs = if tag_name == "svg" {
+						self.explicit_attrs(context)?
+					} else {
+						AttrKVValueVec::default()
+					};
+					context.with_viewport_vars(tag_name, false, &attrs, || {
+						Self::collect_all_anchors(self.children(context)?, context, anchors)
+					})
+				}
+			}
 		})
 	}
 }
@@ -62,10 +334,14 @@ impl<'a> TagLike<'a> for AnyChildTag<'a> {
 			Image(t) => t.tag_name(),
 			Other(t) => t.tag_name(),
 			Font(t) => t.tag_name(),
+			Shape(t) => t.tag_name(),
+			Connector(t) => t.tag_name(),
+			Repeat(t) => t.tag_name(),
+			UseFrom(t) => t.tag_name(),
 		}
 	}
 
-	fn vars(&'a self, context: &DecodingContext<'a>) -> ClgnDecodingResult<&TagVariables> {
+	fn vars(&'a self, context: &DecodingContext<'a>) -> ClgnDecodingResult<&'a TagVariables> {
 		self.initialize(context)?;
 
 		use AnyChildTag::*;
@@ -74,63 +350,105 @@ impl<'a> TagLike<'a> for AnyChildTag<'a> {
 			Image(t) => t.base_vars(),
 			Other(t) => t.base_vars(),
 			Font(t) => t.base_vars(),
+			Shape(t) => t.base_vars(),
+			Connector(t) => t.base_vars(),
+			Repeat(t) => t.base_vars(),
+			UseFrom(t) => t.base_vars(),
 		})
 	}
 
 	fn attrs(&'a self, context: &DecodingContext<'a>) -> ClgnDecodingResult<AttrKVValueVec<'a>> {
-		self.initialize(context)?;
+		let mut attrs = self.explicit_attrs(context)?;
 
+		// Some tags have attributes that are generated rather than specified directly
 		use AnyChildTag::*;
-		let mut attrs = match &self {
-			Container(t) => context.sub_vars_into_attrs(t.attrs()?),
-			Image(t) => context.sub_vars_into_attrs(
-				t.base_attrs()
-					.0
-					.iter()
-					.map(|(k, v)| (k.as_ref(), Cow::Borrowed(v))),
-			),
-			Other(t) => context.sub_vars_into_attrs(
-				t.base_attrs()
-					.0
-					.iter()
-					.map(|(k, v)| (k.as_ref(), Cow::Borrowed(v))),
-			),
-			Font(t) => context.sub_vars_into_attrs(
-				t.base_attrs()
-					.0
-					.iter()
-					.map(|(k, v)| (k.as_ref(), Cow::Borrowed(v))),
-			),
-		}?;
-
-		// If more cases arise, convert this to a match
-		if let AnyChildTag::Image(t) = self {
-			let (k, v) = t.get_image_attr_pair(context)?;
-			attrs.push((k, Cow::Owned(v)));
+		let mut generated_attrs = match self {
+			Image(t) => {
+				let (k, v) = t.get_image_attr_pair(context, &attrs)?;
+				let mut generated_attrs = if context.xlink_href() {
+					vec![(k, v.clone()), ("xlink:href", v)]
+				} else {
+					vec![(k, v)]
+				};
+				generated_attrs.extend(t.get_aria_label_attr_pair(context)?);
+				generated_attrs
+			}
+			Shape(t) => vec![t.get_geometry_attr_pair(context)?],
+			Connector(t) => t.get_generated_attrs(context)?,
+			UseFrom(t) => t.get_generated_attrs(context)?,
+			Other(t) => t.generated_attrs(context, &attrs)?,
+			Container(_) | Font(_) | Repeat(_) => vec![],
+		};
+		if let Some(target) = context.target() {
+			generated_attrs.extend(target.generated_attrs(context, self.tag_name(), &attrs));
 		}
+		attrs.extend(generated_attrs.into_iter().map(|(k, v)| (k, Cow::Owned(v))));
 
 		Ok(attrs)
 	}
 
-	fn text(&'a self, context: &DecodingContext<'a>) -> ClgnDecodingResult<Cow<'a, str>> {
+	fn text(&'a self, context: &DecodingContext<'a>) -> ClgnDecodingResult<XmlText<'a>> {
 		self.initialize(context)?;
 
 		use AnyChildTag::*;
 		match &self {
 			Container(t) => t.text(),
-			Image(t) => Ok(context.sub_vars_into_str(t.base_text())?),
-			Other(t) => Ok(context.sub_vars_into_str(t.base_text())?),
-			Font(t) => Ok(Cow::Owned(t.font_embed_text(context)?)),
+			Image(t) => t.text(context),
+			Other(t) => t.text(context, || self.attrs(context)),
+			Font(t) => Ok(XmlText::Markup(Cow::Owned(t.font_embed_text(context)?))),
+			Shape(t) => t.text(context),
+			Connector(t) => t.text(context),
+			Repeat(t) => t.text(context),
+			UseFrom(t) => t.text(context),
 		}
 	}
 
-	fn should_escape_text(&self) -> bool {
+	fn animations(&self) -> &[Animation] {
 		use AnyChildTag::*;
 		match &self {
-			Container(t) => t.should_escape_text(),
-			Image(t) => t.should_escape_text(),
-			Other(t) => t.should_escape_text(),
-			Font(t) => t.should_escape_text(),
+			Image(t) => t.base_animations(),
+			Other(t) => t.base_animations(),
+			Shape(t) => t.base_animations(),
+			Connector(t) => t.base_animations(),
+			Repeat(t) => t.base_animations(),
+			UseFrom(t) => t.base_animations(),
+			Container(_) | Font(_) => &[],
+		}
+	}
+
+	fn clips(&self) -> Vec<(ClipKind, &ClipShape)> {
+		use AnyChildTag::*;
+		match &self {
+			Image(t) => t.base_clips(),
+			Other(t) => t.base_clips(),
+			Shape(t) => t.base_clips(),
+			Connector(t) => t.base_clips(),
+			Repeat(t) => t.base_clips(),
+			UseFrom(t) => t.base_clips(),
+			Container(_) | Font(_) => Vec::new(),
+		}
+	}
+
+	fn link(&self) -> Option<&Link> {
+		use AnyChildTag::*;
+		match &self {
+			Image(t) => t.base_link(),
+			Other(t) => t.base_link(),
+			Shape(t) => t.base_link(),
+			Connector(t) => t.base_link(),
+			Repeat(t) => t.base_link(),
+			UseFrom(t) => t.base_link(),
+			Container(_) | Font(_) => None,
+		}
+	}
+
+	fn descriptions(
+		&self,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<Vec<(&'static str, String)>> {
+		match &self {
+			AnyChildTag::Image(t) => t.descriptions(context),
+			_ => Ok(Vec::new()),
 		}
 	}
 }
@@ -0,0 +1,130 @@
+//! Right-to-left text. The characters of Hebrew, Arabic, and other right-to-left
+//! scripts are displayed right to left regardless, but a `<text>`'s *base* direction,
+//! which determines where neutral characters (spaces, punctuation, digits) go and which
+//! end of the text its `x` refers to, is left to right unless its `direction` says
+//! otherwise. Without one, a Hebrew or Arabic label ending in punctuation comes out
+//! with the punctuation at the wrong end, and some viewers reorder it further.
+//!
+//! So a `<text>` or `<tspan>` whose text begins (ignoring neutral characters) with a
+//! right-to-left character is given `direction="rtl"`, as the Unicode Bidirectional
+//! Algorithm would choose for a paragraph of that text. A `<tspan>` also gets
+//! `unicode-bidi="embed"`, without which its `direction` has no effect. A tag that sets
+//! `direction` itself, as an attribute or in its `style`, is left alone.
+
+use crate::fibroblast::data_types::{AttrKVValueVec, SimpleValue};
+use crate::to_svg::xml_text::XmlText;
+
+/// The tags that may be given a `direction`
+const TEXT_TAGS: [&str; 2] = ["text", "tspan"];
+
+/// Whether `c` belongs to a right-to-left script
+fn is_rtl_char(c: char) -> bool {
+	matches!(
+		u32::from(c),
+		// Hebrew, Arabic, Syriac, Thaana, NKo, Samaritan, Mandaic, and their extensions
+		0x0590..=0x08FF
+		// Hebrew and Arabic presentation forms
+		| 0xFB1D..=0xFDFF
+		| 0xFE70..=0xFEFF
+		// Historic right-to-left scripts, and Adlam, Mende Kikakui, etc.
+		| 0x10800..=0x10FFF
+		| 0x1E800..=0x1EFFF
+	)
+}
+
+/// Whether the base direction of `text` is right to left, i.e., whether its first
+/// letter is from a right-to-left script. If `is_markup`, the contents of tags (e.g.,
+/// `<tspan x="1">`) are skipped.
+pub(crate) fn is_rtl(text: &str, is_markup: bool) -> bool {
+	let mut in_tag = false;
+	for c in text.chars() {
+		if is_markup {
+			in_tag |= c == '<';
+			let is_in_tag = in_tag;
+			in_tag &= c != '>';
+			if is_in_tag {
+				continue;
+			}
+		}
+
+		if c.is_alphabetic() {
+			return is_rtl_char(c);
+		}
+	}
+
+	false
+}
+
+/// The attributes that a tag named `tag_name`, with text `text` and attributes `attrs`,
+/// needs in order for its text to be displayed in the right direction
+pub(crate) fn direction_attrs(
+	tag_name: &str,
+	text: &XmlText,
+	attrs: &AttrKVValueVec,
+) -> Vec<(&'static str, SimpleValue)> {
+	if !TEXT_TAGS.contains(&tag_name) || !is_rtl(text.as_str(), text.is_markup()) {
+		return Vec::new();
+	}
+
+	let sets_direction = attrs.iter().any(|(k, v)| match *k {
+		"direction" => true,
+		"style" => v
+			.to_maybe_string()
+			.map_or(false, |style| style.contains("direction")),
+		_ => false,
+	});
+	if sets_direction {
+		return Vec::new();
+	}
+
+	let mut direction_attrs = vec![("direction", SimpleValue::Text("rtl".to_owned()))];
+	if tag_name == "tspan" {
+		direction_attrs.push(("unicode-bidi", SimpleValue::Text("embed".to_owned())));
+	}
+
+	direction_attrs
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::borrow::Cow;
+
+	#[test]
+	fn base_directions() {
+		assert!(is_rtl("שלום", false));
+		assert!(is_rtl("  123, «مرحبا» world", false));
+		assert!(!is_rtl("hello שלום", false));
+		assert!(!is_rtl("123 !?", false));
+		assert!(!is_rtl("", false));
+
+		assert!(is_rtl(r#"<tspan font-weight="bold">שלום</tspan>"#, true));
+		assert!(!is_rtl(r#"<tspan font-weight="bold">שלום</tspan>"#, false));
+	}
+
+	#[test]
+	fn direction_attributes() {
+		let no_attrs = AttrKVValueVec::default();
+		let attr_names = |tag_name, text, attrs: &AttrKVValueVec| {
+			direction_attrs(tag_name, &XmlText::new(text, true), attrs)
+				.into_iter()
+				.map(|(k, _)| k)
+				.collect::<Vec<_>>()
+		};
+
+		assert_eq!(attr_names("text", "مرحبا", &no_attrs), ["direction"]);
+		assert_eq!(
+			attr_names("tspan", "مرحبا", &no_attrs),
+			["direction", "unicode-bidi"]
+		);
+		assert!(attr_names("rect", "مرحبا", &no_attrs).is_empty());
+		assert!(attr_names("text", "hello", &no_attrs).is_empty());
+
+		let mut attrs = AttrKVValueVec::default();
+		attrs.push((
+			"style",
+			Cow::Owned(SimpleValue::Text("fill:red;direction:ltr".to_owned())),
+		));
+		assert!(attr_names("text", "مرحبا", &attrs).is_empty());
+	}
+}
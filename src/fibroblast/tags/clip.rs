@@ -0,0 +1,218 @@
+//! The `clip` and `mask` fields of a tag, which clip or mask the tag to a shape without
+//! requiring the shape to be wired up by hand. Each one is written as a `<clipPath>` (or
+//! `<mask>`), with an `id` generated by Collagen, in a `<defs>` right before the tag,
+//! and the tag is given a `clip-path` (or `mask`) attribute referring to it. Since the
+//! `id`s are generated, they can't collide with each other or with the `id`s given in
+//! the skeleton.
+
+use super::{AttrKVValueVec, ClgnDecodingResult, DecodingContext};
+use crate::fibroblast::data_types::{Map, SimpleValue};
+use crate::to_svg::svg_writable::ClgnDecodingError;
+use quick_xml::events::{BytesEnd, BytesStart, Event as XmlEvent};
+use quick_xml::Writer as XmlWriter;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// The tags that may be given as a clip's `shape`
+const CLIP_SHAPES: [&str; 7] = [
+	"circle", "ellipse", "line", "path", "polygon", "polyline", "rect",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ClipKind {
+	Clip,
+	Mask,
+}
+
+impl ClipKind {
+	/// The name of the field that gives a clip of this kind
+	fn field_name(self) -> &'static str {
+		match self {
+			ClipKind::Clip => "clip",
+			ClipKind::Mask => "mask",
+		}
+	}
+
+	/// The tag that a clip of this kind is written as
+	fn tag_name(self) -> &'static str {
+		match self {
+			ClipKind::Clip => "clipPath",
+			ClipKind::Mask => "mask",
+		}
+	}
+
+	/// The attribute of the clipped tag that refers to a clip of this kind
+	fn attr_name(self) -> &'static str {
+		match self {
+			ClipKind::Clip => "clip-path",
+			ClipKind::Mask => "mask",
+		}
+	}
+}
+
+/// The shape that a tag is clipped or masked to, given in the tag's `clip` or `mask`
+/// field. The shape is written as a `<shape>` tag whose attributes are the object's
+/// other keys. For instance, `{ "shape": "circle", "cx": 50, "cy": 50, "r": 40 }`
+/// becomes `<circle cx="50" cy="50"
+/// r="40"></circle>`. Variable substitution is performed on
+/// the attributes, which may also use palette colors and relative units (e.g., `"50%w"`).
+///
+/// # Properties
+///
+/// - `shape`
+///   - Type: string, one of `"circle"`, `"ellipse"`, `"line"`, `"path"`, `"polygon"`,
+///     `"polyline"`, or `"rect"`
+///   - Required: Yes.
+///   - Description: The kind of shape to clip or mask to.
+/// - Other: any other keys are the attributes of the shape, such as a circle's `cx`,
+///   `cy`, and `r`. A mask's shape is filled with white (so that the tag shows through
+///   it) unless it's given a `fill`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ClipShape {
+	shape: String,
+
+	#[serde(flatten)]
+	attrs: Map<String, SimpleValue>,
+}
+
+/// A clip whose `id` has been generated and whose shape's attributes have been
+/// substituted, ready to be written
+#[derive(Debug)]
+pub(crate) struct ResolvedClip {
+	kind: ClipKind,
+	id: String,
+	shape: String,
+	attrs: Vec<(String, String)>,
+}
+
+/// Generate an `id` for each of `clips` and add the attribute referring to it to
+/// `attrs`, the attributes of the clipped tag. Returns the clips, to be written with
+/// [`write_clip_defs`].
+pub(crate) fn resolve_clips(
+	clips: &[(ClipKind, &ClipShape)],
+	context: &DecodingContext,
+	attrs: &mut AttrKVValueVec,
+) -> ClgnDecodingResult<Vec<ResolvedClip>> {
+	let mut resolved = Vec::with_capacity(clips.len());
+	for &(kind, clip) in clips {
+		let clip_error = |msg: String| ClgnDecodingError::Clip {
+			msg: format!("Invalid `{}`: {}", kind.field_name(), msg),
+		};
+
+		if !CLIP_SHAPES.contains(&clip.shape.as_str()) {
+			return Err(clip_error(format!(
+				"its shape must be one of {}, not {:?}",
+				CLIP_SHAPES.join(", "),
+				clip.shape
+			)));
+		}
+		if attrs.iter().any(|(k, _)| *k == kind.attr_name()) {
+			return Err(clip_error(format!(
+				"the tag already has a `{}` attribute",
+				kind.attr_name()
+			)));
+		}
+
+		let mut shape_attrs = context.sub_vars_into_attrs(
+			clip.attrs
+				.iter()
+				.map(|(k, v)| (k.as_str(), Cow::Borrowed(v))),
+		)?;
+		context.resolve_palette_colors(&mut shape_attrs)?;
+		context.resolve_relative_units(&mut shape_attrs)?;
+		if kind == ClipKind::Mask && !shape_attrs.iter().any(|(k, _)| *k == "fill") {
+			shape_attrs.push(("fill", Cow::Owned(SimpleValue::Text("white".to_owned()))));
+		}
+
+		let id = context.generate_id(&format!("clgn-{}", kind.field_name()));
+		attrs.push((
+			kind.attr_name(),
+			Cow::Owned(SimpleValue::Text(format!("url(#{})", id))),
+		));
+		resolved.push(ResolvedClip {
+			kind,
+			id,
+			shape: clip.shape.clone(),
+			attrs: shape_attrs
+				.iter()
+				.filter_map(|(k, v)| {
+					v.to_maybe_string()
+						.map(|v| ((*k).to_owned(), v.into_owned()))
+				})
+				.collect(),
+		});
+	}
+
+	Ok(resolved)
+}
+
+/// Write `clips` as `<clipPath>`s and `<mask>`s in a `<defs>`
+pub(crate) fn write_clip_defs(
+	clips: &[ResolvedClip],
+	writer: &mut XmlWriter<impl std::io::Write>,
+) -> ClgnDecodingResult<()> {
+	if clips.is_empty() {
+		return Ok(());
+	}
+
+	writer.write_event(XmlEvent::Start(BytesStart::borrowed_name(b"defs")))?;
+	for clip in clips {
+		let tag_name = clip.kind.tag_name().as_bytes();
+		let mut elem = BytesStart::borrowed_name(tag_name);
+		elem.push_attribute(("id", clip.id.as_str()));
+		writer.write_event(XmlEvent::Start(elem))?;
+
+		let mut shape = BytesStart::borrowed_name(clip.shape.as_bytes());
+		shape.extend_attributes(clip.attrs.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+		writer.write_event(XmlEvent::Start(shape))?;
+		writer.write_event(XmlEvent::End(BytesEnd::borrowed(clip.shape.as_bytes())))?;
+
+		writer.write_event(XmlEvent::End(BytesEnd::borrowed(tag_name)))?;
+	}
+	writer.write_event(XmlEvent::End(BytesEnd::borrowed(b"defs")))?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn resolve(clip: &str, attrs: &mut AttrKVValueVec) -> ClgnDecodingResult<Vec<ResolvedClip>> {
+		let clip: ClipShape = serde_json::from_str(clip).unwrap();
+		resolve_clips(
+			&[(ClipKind::Clip, &clip)],
+			&DecodingContext::new_empty(),
+			attrs,
+		)
+	}
+
+	#[test]
+	fn invalid_clips() {
+		assert!(matches!(
+			resolve(
+				r#"{ "shape": "circel", "r": 1 }"#,
+				&mut AttrKVValueVec::default()
+			),
+			Err(ClgnDecodingError::Clip { .. })
+		));
+
+		let mut attrs = AttrKVValueVec::default();
+		attrs.push((
+			"clip-path",
+			Cow::Owned(SimpleValue::Text("url(#mine)".to_owned())),
+		));
+		assert!(matches!(
+			resolve(r#"{ "shape": "circle", "r": 1 }"#, &mut attrs),
+			Err(ClgnDecodingError::Clip { .. })
+		));
+
+		assert!(matches!(
+			resolve(
+				r#"{ "shape": "circle", "r": "{r}" }"#,
+				&mut AttrKVValueVec::default()
+			),
+			Err(ClgnDecodingError::Parse(..))
+		));
+	}
+}
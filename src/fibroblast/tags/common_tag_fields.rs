@@ -1,6 +1,15 @@
+use super::animation::{Animation, Animations};
+use super::clip::{ClipKind, ClipShape};
+use super::link::Link;
 use super::AnyChildTag;
-use super::{TagVariables, XmlAttrs, EMPTY_ATTRS, EMPTY_VARS};
+use super::{
+	AttrKVValueVec, ClgnDecodingResult, DecodingContext, TagVariables, XmlAttrs, EMPTY_ATTRS,
+	EMPTY_VARS,
+};
+use crate::fibroblast::data_types::{MaybeRawText, SimpleValue};
+use crate::to_svg::xml_text::XmlText;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 
 /// The properties common to most tags. Unless documented otherwise, all tag types are
 /// expected to accept at least the following keys. Note that none of these properties
@@ -23,7 +32,21 @@ use serde::{Deserialize, Serialize};
 ///     surround the variable name in curly braces (this is reminiscent of [Format Args
 ///     Implicit
 ///     Identifiers](https://rust-lang.github.io/rfcs/2795-format-args-implicit-identifiers.html),
-///     which enables `"{variable}"` in most macros).
+///     which enables `"{variable}"` in most macros). Literal braces and backslashes
+///     must be escaped with a backslash, as in `"\\{"`. Two other escape sequences
+///     insert special characters: `"\\u{<hex>}"` inserts the Unicode code point
+///     `<hex>`, e.g., `"\\u{1F600}"` for 😀, and `"\\&<name>;"` inserts a named
+///     entity (one of XML's or a common HTML one), e.g., `"\\&copy;"` for ©. (The
+///     backslashes are doubled in JSON.)\
+///     In addition to the variables defined in `vars`, the descendants of an `<svg>`
+///     (including the root) whose size is known, either from its `viewBox` or from its
+///     numeric `width` and `height`, may use the variables `parent_width` and
+///     `parent_height`, the size of the nearest such `<svg>`, and `canvas_width` and
+///     `canvas_height`, the size of the root `<svg>`. These names are reserved; `vars`
+///     may not define them.\
+///     A variable may shadow one of the same name in an enclosing tag, but shadowing a
+///     variable of a different type (e.g., a number with a string) is probably a mistake,
+///     and prints a warning.
 /// - `attrs`
 ///   - Type: object, with values either number or string
 ///   - Required: No. Missing is equivalent to `{}`.
@@ -31,7 +54,41 @@ use serde::{Deserialize, Serialize};
 ///     list of `name="value"` XML attributes. For instance, `{ "tag": "circle",
 ///     "attrs": { "cx": 10, "cy": 20, "r": 5 } }` will be turned into `<circle cx=10
 ///     cy=20 r=5></circle>`. Variable substitution is performed on the values in
-///     `attrs` using `vars`.
+///     `attrs` using `vars`, except in values of the form `{ "raw": "..." }`, whose
+///     text is used as-is (which is convenient for text with many literal braces, such
+///     as CSS). A value of the form `"<number>%w"` or `"<number>%h"`,
+///     such as `"50%w"`, is replaced with that percentage of `parent_width` or
+///     `parent_height`, respectively.
+/// - `style`
+///   - Type: object, with values either number or string
+///   - Required: No. Missing is equivalent to `{}`.
+///   - Description: A dictionary of CSS properties, which is turned into the tag's
+///     `style` attribute. For instance, `{ "opacity": 0.5, "fill": "{color}" }` becomes
+///     `style="opacity:0.5;fill:red"` (if `color` is `red`). Variable substitution is
+///     performed on each value, and properties whose value is `false` are omitted. If
+///     `attrs` also has a `style`, the two are merged, with the properties in `style`
+///     coming last (and so taking precedence).\
+///     The value of any property in `attrs` or `style` may also be an object of the
+///     form `{ "light": <value>, "dark": <value> }`, such as `"fill": { "light":
+///     "#000", "dark": "#fff" }`, to give it one value when the user prefers a light
+///     color scheme and another when they prefer a dark one. Such values are compiled
+///     into CSS custom properties, which are set in a `<style>` (with a `@media
+///     (prefers-color-scheme: dark)` block) at the end of the document and referred to
+///     from the tag's `style` attribute; a themed value in `attrs` is therefore moved
+///     into `style`.
+/// - `class_styles`
+///   - Type: object, with values either number or string
+///   - Required: No. Missing is equivalent to `{}`.
+///   - Description: A dictionary of CSS properties, like `style`, that are put in a
+///     generated class instead of in the tag's `style` attribute. Each distinct set of
+///     properties (after variable substitution) becomes one class, named e.g.
+///     `clgn-class-0`, that is added to the `class` attribute of every tag with that
+///     set, and the classes are defined in a `<style>` at the end of the document. For
+///     instance, giving each of a thousand `<circle>`s `"class_styles": { "fill":
+///     "red", "stroke": "blue" }` writes `fill:red;stroke:blue` once rather than a
+///     thousand times. Themed values are allowed, as in `style`. Per the rules of CSS,
+///     properties in `class_styles` take precedence over the same properties in
+///     `attrs`, but not over those in `style`.
 /// - `children`
 ///   - Type: list of the child tags of this tag, which are objects interpretable as
 ///     `AnyChildTag`
@@ -40,11 +97,12 @@ use serde::{Deserialize, Serialize};
 ///     interpretable as `AnyChildTag`. For example, `{ "tag": "g", "children": [{
 ///     "tag": "rect", "attrs": ... }, { "image_path": ... }] }`
 /// - `text`
-///   - Type: string
+///   - Type: string, or an object of the form `{ "raw": <string> }`
 ///   - Required: No. Missing is equivalent to `""`.
 ///   - Description The text contained inside this tag. For example, `{ "tag": "text",
 ///     "text": "hello" }` becomes `<text>hello</text>`. Variable substitution is
-///     performed on `text`.
+///     performed on `text`, unless it's given as `{ "raw": ... }`, as in `{ "tag":
+///     "style", "text": { "raw": "rect { fill: red; }" } }`.
 /// - `should_encode_text`
 ///   - Type: bool
 ///   - Required: No. Missing is equivalent to `true`.
@@ -52,37 +110,132 @@ use serde::{Deserialize, Serialize};
 ///     encoding characters that are have special meaning in XML, such as `<` and `>`,
 ///     in a safe representation, such as `&lt;` and `&gt;`, respectively. Text should
 ///     go through exactly one round of XML-encoding before inclusion in XML.
-#[derive(Serialize, Deserialize, Debug)]
+/// - `z`
+///   - Type: number
+///   - Required: No. Missing is equivalent to `0`.
+///   - Description: The tag's position in the paint order relative to its siblings.
+///     Before being written, siblings are sorted by `z` (lowest first, and so painted
+///     underneath the others); siblings with the same `z` keep their relative order.
+///     Has no effect on the root tag, which has no siblings.
+/// - `animate`
+///   - Type: object, as documented in [`Animation`], or a list of such objects
+///   - Required: No. Missing is equivalent to `[]`.
+///   - Description: Animations of this tag's attributes, each of which is written as an
+///     `<animate>` (or `<animateTransform>`) child of the tag. For instance, `{ "tag":
+///     "circle", "animate": { "attr": "r", "from": 0, "to": 10, "dur": 1 } }`.
+/// - `clip`, `mask`
+///   - Type: object, as documented in [`ClipShape`]
+///   - Required: No.
+///   - Description: A shape to clip the tag to, or to mask it with, respectively. For
+///     instance, `{ "image_path": "photo.jpg", "clip": { "shape": "circle", "cx": 50,
+///     "cy": 50, "r": 40 } }` shows only a circular region of the image. The shape is
+///     written as a `<clipPath>` (or `<mask>`) with a generated, unique `id` in a
+///     `<defs>` right before the tag, and the tag is given a `clip-path` (or `mask`)
+///     attribute referring to it, so the tag may not also have that attribute in
+///     `attrs`.
+/// - `link`
+///   - Type: object, as documented in [`Link`]
+///   - Required: No.
+///   - Description: A hyperlink to wrap the tag in. For instance, `{ "tag": "circle",
+///     "link": { "href": "https://example.com", "title": "Example" } }` becomes `<a
+///     href="https://example.com"><title>Example</title><circle></circle></a>`. The
+///     link may also enlarge the area that can be clicked to follow it. The root tag
+///     may not have a `link`.
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct CommonTagFields<'a> {
 	/// (Optional) A dictionary mapping variable names to their values. None is
 	/// equivalent to no variables.
-	#[serde(default)]
+	#[serde(default, skip_serializing_if = "Option::is_none")]
 	vars: Option<TagVariables>,
 
 	/// (Optional) A dictionary of name="value" XML attributes. NuNonell is equivalent to no
 	/// attributes.
-	#[serde(default)]
+	#[serde(default, skip_serializing_if = "Option::is_none")]
 	attrs: Option<XmlAttrs>,
 
+	/// (Optional) A dictionary of CSS properties to be put in the `style` attribute. None
+	/// is equivalent to no properties.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	style: Option<XmlAttrs>,
+
+	/// (Optional) A dictionary of CSS properties to be put in a generated class. None is
+	/// equivalent to no properties.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	class_styles: Option<XmlAttrs>,
+
 	/// (Optional) A list of children of this tag. None is equivalent to the empty list.
-	#[serde(default)]
+	#[serde(default, skip_serializing_if = "Option::is_none")]
 	children: Option<Vec<AnyChildTag<'a>>>,
 
 	/// (Optional) The text contained inside this tag, i.e., the "some text" in
 	/// `<tag>some text</tag>`. None is equivalent to the empty string.
-	#[serde(default)]
-	text: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	text: Option<MaybeRawText>,
 
 	/// (Optional) Whether `text` needs to be escaped before inclusion in XML. "Escaping"
 	/// means converting illegal characters, such as `<`, to a safe representation, such
 	/// as `&lt;`. Text should go through exactly one round of escaping before inclusion
 	/// in XML. None is equivalent to `true`.
-	#[serde(default)]
+	#[serde(default, skip_serializing_if = "Option::is_none")]
 	should_escape_text: Option<bool>,
+
+	/// (Optional) This tag's position in the paint order relative to its siblings. None
+	/// is equivalent to 0.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	z: Option<f64>,
+
+	/// (Optional) Animations of this tag's attributes. None is equivalent to no
+	/// animations.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	animate: Option<Animations>,
+
+	/// (Optional) The shape to clip this tag to. None is equivalent to no clipping.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	clip: Option<ClipShape>,
+
+	/// (Optional) The shape to mask this tag with. None is equivalent to no mask.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	mask: Option<ClipShape>,
+
+	/// (Optional) The link to wrap this tag in. None is equivalent to no link.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	link: Option<Link>,
 }
 
 impl<'a> CommonTagFields<'a> {
+	/// The fields of a tag with only variables, attributes, and children
+	pub(crate) fn new(vars: TagVariables, attrs: XmlAttrs, children: Vec<AnyChildTag<'a>>) -> Self {
+		Self {
+			vars: Some(vars),
+			attrs: Some(attrs),
+			children: Some(children),
+			..Self::default()
+		}
+	}
+
+	/// A deep copy of `self`, free of any lifetime constraints `self` might be under.
+	/// See [`RootTag::fresh_clone`](super::RootTag::fresh_clone).
+	pub(crate) fn fresh_clone<'b>(&self) -> CommonTagFields<'b> {
+		CommonTagFields {
+			vars: self.vars.clone(),
+			attrs: self.attrs.clone(),
+			style: self.style.clone(),
+			class_styles: self.class_styles.clone(),
+			children: self
+				.children
+				.as_ref()
+				.map(|children| children.iter().map(AnyChildTag::fresh_clone).collect()),
+			text: self.text.clone(),
+			should_escape_text: self.should_escape_text,
+			z: self.z,
+			animate: self.animate.clone(),
+			clip: self.clip.clone(),
+			mask: self.mask.clone(),
+			link: self.link.clone(),
+		}
+	}
+
 	pub(crate) fn base_vars(&self) -> &TagVariables {
 		match &self.vars {
 			None => &EMPTY_VARS,
@@ -97,6 +250,20 @@ impl<'a> CommonTagFields<'a> {
 		}
 	}
 
+	pub(crate) fn base_style(&self) -> &XmlAttrs {
+		match &self.style {
+			None => &EMPTY_ATTRS,
+			Some(style) => style,
+		}
+	}
+
+	pub(crate) fn base_class_styles(&self) -> &XmlAttrs {
+		match &self.class_styles {
+			None => &EMPTY_ATTRS,
+			Some(class_styles) => class_styles,
+		}
+	}
+
 	pub(crate) fn base_children(&self) -> &[AnyChildTag<'a>] {
 		match &self.children {
 			None => &[],
@@ -107,11 +274,154 @@ impl<'a> CommonTagFields<'a> {
 	pub(crate) fn base_text(&self) -> &str {
 		match &self.text {
 			None => "",
-			Some(t) => t.as_ref(),
+			Some(t) => t.as_str(),
 		}
 	}
 
-	pub(crate) fn should_escape_text(&self) -> bool {
+	/// The text of this tag, with variables substituted in (unless it's raw)
+	pub(crate) fn text(&self, context: &DecodingContext) -> ClgnDecodingResult<XmlText<'_>> {
+		let text = match &self.text {
+			None => Cow::Borrowed(""),
+			Some(t) => t.substituted(context)?,
+		};
+		Ok(XmlText::new(text, self.should_escape_text()))
+	}
+
+	/// The text of this tag, without variables substituted in
+	pub(crate) fn base_xml_text(&self) -> XmlText<'_> {
+		XmlText::new(self.base_text(), self.should_escape_text())
+	}
+
+	fn should_escape_text(&self) -> bool {
 		self.should_escape_text.unwrap_or(true)
 	}
+
+	pub(crate) fn z(&self) -> f64 {
+		self.z.unwrap_or(0.0)
+	}
+
+	pub(crate) fn base_animations(&self) -> &[Animation] {
+		match &self.animate {
+			None => &[],
+			Some(animate) => animate.as_slice(),
+		}
+	}
+
+	pub(crate) fn base_clips(&self) -> Vec<(ClipKind, &ClipShape)> {
+		let clip = self.clip.iter().map(|clip| (ClipKind::Clip, clip));
+		let mask = self.mask.iter().map(|mask| (ClipKind::Mask, mask));
+		clip.chain(mask).collect()
+	}
+
+	pub(crate) fn base_link(&self) -> Option<&Link> {
+		self.link.as_ref()
+	}
+}
+
+/// Merge `style`, a dictionary of CSS properties, into the `style` attribute in `attrs`
+/// (which must already have had variables substituted into it), adding the attribute if
+/// it's not already present. Since only CSS can depend on the color scheme, attributes
+/// with themed values are moved into the `style` attribute too. See [`CommonTagFields`].
+pub(crate) fn merge_style_into_attrs<'b>(
+	attrs: &mut AttrKVValueVec<'b>,
+	style: &XmlAttrs,
+	context: &DecodingContext,
+) -> ClgnDecodingResult<()> {
+	let (themed_attrs, other_attrs) = std::mem::take(&mut **attrs)
+		.into_iter()
+		.partition::<Vec<_>, _>(|(_, v)| matches!(v.as_ref(), SimpleValue::Themed(_)));
+	**attrs = other_attrs;
+
+	if style.is_empty() && themed_attrs.is_empty() {
+		return Ok(());
+	}
+
+	let mut style =
+		context.sub_vars_into_attrs(style.0.iter().map(|(k, v)| (k.as_ref(), Cow::Borrowed(v))))?;
+	context.resolve_palette_colors(&mut style)?;
+
+	// Properties in `style` come after (and so take precedence over) themed attributes
+	let mut properties = AttrKVValueVec::default();
+	properties.extend(themed_attrs);
+	properties.extend(style);
+	context.resolve_themed_values(&mut properties)?;
+
+	let declarations = css_declarations(&properties);
+	append_to_style(attrs, declarations);
+
+	Ok(())
+}
+
+/// Append `declarations`, a list of CSS declarations separated by `;`, to the `style` in
+/// `attrs`, adding one if there isn't one already
+pub(crate) fn append_to_style(attrs: &mut AttrKVValueVec, declarations: String) {
+	match attrs.iter_mut().find(|(k, _)| *k == "style") {
+		Some((_, existing)) => {
+			let merged = match existing.to_maybe_string() {
+				Some(existing) if !existing.trim().is_empty() => {
+					format!("{};{}", existing.trim().trim_end_matches(';'), declarations)
+				}
+				_ => declarations,
+			};
+			*existing = Cow::Owned(SimpleValue::Text(merged));
+		}
+		None => attrs.push(("style", Cow::Owned(SimpleValue::Text(declarations)))),
+	}
+}
+
+/// Add the generated class whose style is `class_styles`, a dictionary of CSS
+/// properties, to the `class` attribute in `attrs` (which must already have had
+/// variables substituted into it), adding the attribute if it's not already present.
+/// See [`CommonTagFields`].
+pub(crate) fn add_class_styles_to_attrs<'b>(
+	attrs: &mut AttrKVValueVec<'b>,
+	class_styles: &XmlAttrs,
+	context: &DecodingContext,
+) -> ClgnDecodingResult<()> {
+	if class_styles.is_empty() {
+		return Ok(());
+	}
+
+	let mut properties = context.sub_vars_into_attrs(
+		class_styles
+			.0
+			.iter()
+			.map(|(k, v)| (k.as_ref(), Cow::Borrowed(v))),
+	)?;
+	context.resolve_palette_colors(&mut properties)?;
+	context.resolve_themed_values(&mut properties)?;
+
+	let declarations = css_declarations(&properties);
+	if declarations.is_empty() {
+		return Ok(());
+	}
+	let class = context.class_for(declarations);
+
+	match attrs.iter_mut().find(|(k, _)| *k == "class") {
+		Some((_, existing)) => {
+			let merged = match existing.to_maybe_string() {
+				Some(existing) if !existing.trim().is_empty() => {
+					format!("{} {}", existing.trim(), class)
+				}
+				_ => class,
+			};
+			*existing = Cow::Owned(SimpleValue::Text(merged));
+		}
+		None => attrs.push(("class", Cow::Owned(SimpleValue::Text(class)))),
+	}
+
+	Ok(())
+}
+
+/// The CSS declarations, e.g., `fill:red;opacity:0.5`, setting each property in
+/// `properties`, except those that are absent (`false`)
+fn css_declarations(properties: &AttrKVValueVec) -> String {
+	properties
+		.iter()
+		.filter_map(|(k, v)| match v.as_ref() {
+			SimpleValue::Absent => None,
+			v => v.to_maybe_string().map(|v| format!("{}:{}", k, v)),
+		})
+		.collect::<Vec<_>>()
+		.join(";")
 }
@@ -0,0 +1,316 @@
+use super::animation::Animation;
+use super::any_child_tag::AnyChildTag;
+use super::clip::{ClipKind, ClipShape};
+use super::common_tag_fields::CommonTagFields;
+use super::link::Link;
+use super::shape_tag::{fmt_coord, fmt_point};
+use crate::fibroblast::data_types::{
+	Anchor, AnchorEntry, DecodingContext, SimpleValue, TagVariables, XmlAttrs,
+};
+use crate::to_svg::svg_writable::{ClgnDecodingError, ClgnDecodingResult};
+use crate::to_svg::xml_text::XmlText;
+use serde::{Deserialize, Serialize};
+
+/// The `id` of the arrowhead `<marker>` used by connectors
+pub(crate) const ARROWHEAD_MARKER_ID: &str = "clgn-arrowhead";
+
+/// The definition of the arrowhead `<marker>`, which is written once at the top of any
+/// document that has a connector with an arrowhead. `orient="auto-start-reverse"` lets
+/// the same marker be used at both ends of a path, and `context-stroke` makes it match
+/// the color of the path it's attached to.
+pub(crate) const ARROWHEAD_MARKER_DEFS: &str = concat!(
+	r#"<defs><marker id="clgn-arrowhead" viewBox="0 0 10 10" refX="10" refY="5" "#,
+	r#"markerWidth="6" markerHeight="6" orient="auto-start-reverse">"#,
+	r#"<path d="M0,0 L10,5 L0,10 Z" fill="context-stroke"></path></marker></defs>"#,
+);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Route {
+	Straight,
+	Orthogonal,
+	Curved,
+}
+
+impl Default for Route {
+	fn default() -> Self {
+		Self::Straight
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Arrowheads {
+	None,
+	Start,
+	End,
+	Both,
+}
+
+impl Default for Arrowheads {
+	fn default() -> Self {
+		Self::None
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Connection {
+	from: String,
+	to: String,
+
+	#[serde(default)]
+	route: Route,
+
+	#[serde(default)]
+	arrow: Arrowheads,
+}
+
+/// A tag that draws a `<path>` between two other elements, which are referred to by
+/// their `id`s. The endpoints are computed from the elements' attributes when the
+/// document is written, so they stay in sync with the elements no matter how the
+/// elements are moved or resized.
+///
+/// The position and size of the following elements can be determined (missing
+/// coordinates are treated as `0`): `<rect>`, `<image>`, `<use>`, `<svg>`, and
+/// `<foreignObject>` (from `x`, `y`, `width`, and `height`); `<circle>` and
+/// `<ellipse>`; `<line>`; `<text>` (from `x` and `y` only); and any
+/// [`ShapeTag`](super::ShapeTag). Referring to any other element is an error.
+/// `transform`s are not taken into account.
+///
+/// # Properties
+///
+/// - `connect`
+///   - Type: object
+///   - Required: Yes.
+///   - Description: Which elements to connect, and how. Its properties are:
+///     - `from`, `to` (string; required): The `id`s of the elements to connect. These
+///       undergo variable substitution.
+///     - `route` (string; optional): One of `"straight"`, `"orthogonal"`, or
+///       `"curved"`. Missing is equivalent to `"straight"`. A straight connector runs
+///       directly between the elements' outlines. Orthogonal and curved connectors
+///       leave `from` from the side facing `to` (horizontally if the elements are
+///       farther apart horizontally than vertically, and vertically otherwise) and
+///       enter `to` from the opposite side, with an elbow or bend halfway between.
+///     - `arrow` (string; optional): One of `"none"`, `"start"`, `"end"`, or
+///       `"both"`. Missing is equivalent to `"none"`. Where to put arrowheads. The
+///       arrowhead `<marker>` (with `id` `"clgn-arrowhead"`) is added to the document
+///       automatically and takes on the connector's `stroke` color.
+/// - Other: `ConnectorTag` accepts all properties in [`CommonTagFields`]. Since the
+///   connector is a `<path>`, `attrs` may not contain `d`; `fill` defaults to `"none"`.
+///   Don't forget to give it a `stroke`!
+///
+/// # Example
+///
+/// ```json
+/// { "connect": { "from": "box-a", "to": "box-b", "route": "orthogonal", "arrow": "end" }, "attrs": { "stroke": "black" } }
+/// ```
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConnectorTag<'a> {
+	connect: Connection,
+
+	#[serde(flatten)]
+	common_tag_fields: CommonTagFields<'a>,
+}
+
+/// The `d` of a path connecting `from` to `to` along the given route
+fn route_path(from: &Anchor, to: &Anchor, route: Route) -> String {
+	if route == Route::Straight {
+		let start = from.boundary_toward(to.center());
+		let end = to.boundary_toward(from.center());
+		return format!("M{} L{}", fmt_point(start), fmt_point(end));
+	}
+
+	let (dx, dy) = (to.cx - from.cx, to.cy - from.cy);
+	let horizontal = dx.abs() >= dy.abs();
+	let (start, end) = if horizontal {
+		(from.side_toward((dx, 0.0)), to.side_toward((-dx, 0.0)))
+	} else {
+		(from.side_toward((0.0, dy)), to.side_toward((0.0, -dy)))
+	};
+	let ((x0, y0), (x1, y1)) = (start, end);
+
+	match (route, horizontal) {
+		(Route::Orthogonal, true) => {
+			let mid = fmt_coord((x0 + x1) / 2.0);
+			format!(
+				"M{} H{} V{} H{}",
+				fmt_point(start),
+				mid,
+				fmt_coord(y1),
+				fmt_coord(x1)
+			)
+		}
+		(Route::Orthogonal, false) => {
+			let mid = fmt_coord((y0 + y1) / 2.0);
+			format!(
+				"M{} V{} H{} V{}",
+				fmt_point(start),
+				mid,
+				fmt_coord(x1),
+				fmt_coord(y1)
+			)
+		}
+		(_, true) => {
+			let mid = (x0 + x1) / 2.0;
+			format!(
+				"M{} C{} {} {}",
+				fmt_point(start),
+				fmt_point((mid, y0)),
+				fmt_point((mid, y1)),
+				fmt_point(end)
+			)
+		}
+		(_, false) => {
+			let mid = (y0 + y1) / 2.0;
+			format!(
+				"M{} C{} {} {}",
+				fmt_point(start),
+				fmt_point((x0, mid)),
+				fmt_point((x1, mid)),
+				fmt_point(end)
+			)
+		}
+	}
+}
+
+impl<'a> ConnectorTag<'a> {
+	pub(crate) fn fresh_clone<'b>(&self) -> ConnectorTag<'b> {
+		ConnectorTag {
+			connect: self.connect.clone(),
+			common_tag_fields: self.common_tag_fields.fresh_clone(),
+		}
+	}
+
+	pub(super) fn has_arrowheads(&self) -> bool {
+		self.connect.arrow != Arrowheads::None
+	}
+
+	fn error(&self, msg: impl std::fmt::Display) -> ClgnDecodingError {
+		ClgnDecodingError::Connector {
+			msg: format!(
+				"In connector from {:?} to {:?}: {}",
+				self.connect.from, self.connect.to, msg
+			),
+		}
+	}
+
+	fn resolve_anchor(&self, id: &str, context: &DecodingContext) -> ClgnDecodingResult<Anchor> {
+		let id = context.sub_vars_into_str(id)?;
+		match context.get_anchor(&id) {
+			Some(AnchorEntry::Found(anchor)) => Ok(anchor),
+			Some(AnchorEntry::Unsupported(tag_name)) => Err(self.error(format_args!(
+				"the position of the element with id {:?} (a <{}>) can't be determined from its attributes",
+				id, tag_name
+			))),
+			Some(AnchorEntry::Duplicate) => {
+				Err(self.error(format_args!("more than one element has id {:?}", id)))
+			}
+			None => Err(self.error(format_args!("no element has id {:?}", id))),
+		}
+	}
+
+	/// Get the attributes generated by this connector: its `d`, its markers, and (if
+	/// not given) its `fill`
+	pub(super) fn get_generated_attrs(
+		&self,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<Vec<(&'static str, SimpleValue)>> {
+		let base_attrs = self.base_attrs();
+		if base_attrs.contains_key("d") {
+			return Err(self.error(
+				"`d` is generated from the connected elements, so it may not be given in `attrs`",
+			));
+		}
+
+		let from = self.resolve_anchor(&self.connect.from, context)?;
+		let to = self.resolve_anchor(&self.connect.to, context)?;
+
+		let mut attrs = vec![(
+			"d",
+			SimpleValue::Text(route_path(&from, &to, self.connect.route)),
+		)];
+
+		let marker = || SimpleValue::Text(format!("url(#{})", ARROWHEAD_MARKER_ID));
+		if let Arrowheads::Start | Arrowheads::Both = self.connect.arrow {
+			attrs.push(("marker-start", marker()));
+		}
+		if let Arrowheads::End | Arrowheads::Both = self.connect.arrow {
+			attrs.push(("marker-end", marker()));
+		}
+
+		if !base_attrs.contains_key("fill") {
+			attrs.push(("fill", SimpleValue::Text("none".to_owned())));
+		}
+
+		Ok(attrs)
+	}
+
+	pub(super) fn tag_name(&self) -> &str {
+		"path"
+	}
+
+	pub(super) fn base_vars(&self) -> &TagVariables {
+		self.common_tag_fields.base_vars()
+	}
+
+	pub(super) fn base_attrs(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_attrs()
+	}
+
+	pub(super) fn base_style(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_style()
+	}
+
+	pub(super) fn base_class_styles(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_class_styles()
+	}
+
+	pub(super) fn base_children(&self) -> &[AnyChildTag<'a>] {
+		self.common_tag_fields.base_children()
+	}
+
+	pub(super) fn text(&self, context: &DecodingContext) -> ClgnDecodingResult<XmlText<'_>> {
+		self.common_tag_fields.text(context)
+	}
+
+	pub(super) fn z(&self) -> f64 {
+		self.common_tag_fields.z()
+	}
+
+	pub(super) fn base_animations(&self) -> &[Animation] {
+		self.common_tag_fields.base_animations()
+	}
+
+	pub(super) fn base_clips(&self) -> Vec<(ClipKind, &ClipShape)> {
+		self.common_tag_fields.base_clips()
+	}
+
+	pub(super) fn base_link(&self) -> Option<&Link> {
+		self.common_tag_fields.base_link()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn square(cx: f64, cy: f64) -> Anchor {
+		Anchor::rect(cx - 5.0, cy - 5.0, 10.0, 10.0)
+	}
+
+	#[test]
+	fn routes() {
+		let a = square(0.0, 0.0);
+		let b = square(50.0, 20.0);
+
+		assert_eq!(route_path(&a, &b, Route::Straight), "M5,2 L45,18");
+		assert_eq!(route_path(&a, &b, Route::Orthogonal), "M5,0 H25 V20 H45");
+		assert_eq!(route_path(&a, &b, Route::Curved), "M5,0 C25,0 25,20 45,20");
+
+		// Mostly vertical
+		let c = square(10.0, 50.0);
+		assert_eq!(route_path(&a, &c, Route::Orthogonal), "M0,5 V25 H10 V45");
+		assert_eq!(route_path(&c, &a, Route::Curved), "M10,45 C10,25 0,25 0,5");
+	}
+}
@@ -1,7 +1,10 @@
 use super::{
 	any_child_tag::AnyChildTag, AttrKVValueVec, ClgnDecodingResult, DecodingContext, TagVariables,
 };
+use crate::fibroblast::data_types::context::viewport_size;
+use crate::fibroblast::data_types::{ConcreteNumber, Map, VariableValue};
 use crate::fibroblast::Fibroblast;
+use crate::to_svg::xml_text::XmlText;
 use lazycell::LazyCell;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
@@ -28,9 +31,22 @@ use std::borrow::Cow;
 ///   - Required: Yes.
 ///   - Description: The path, relative to `collagen.json`, of the skeleton to include
 ///     in this skeleton.
-/// - Other: `ContainerTag` does *not* accept the fields in `CommonTagFields`. To apply
-///   attributes to a `ContainerTag`, wrap it in a `<g>` tag, e.g., `{ "tag": "g",
-///   "attrs": ..., "children": [{ "clgn_path": ... }] }`
+/// - `name`
+///   - Type: string
+///   - Required: No.
+///   - Description: If given, the size of the included skeleton is exported to the
+///     siblings of this tag that follow it as the variables `<name>.width` and
+///     `<name>.height`, taken from the included skeleton's root `viewBox` (or its `width`
+///     and `height`, if it has no `viewBox`). See [Exported sizes](#exported-sizes).
+///     Names are restricted in the same way as variable names.
+/// - `z`
+///   - Type: number
+///   - Required: No. Missing is equivalent to `0`.
+///   - Description: The tag's position in the paint order relative to its siblings. See
+///     [`CommonTagFields`](super::CommonTagFields).
+/// - Other: `ContainerTag` does *not* accept the other fields in `CommonTagFields`. To
+///   apply attributes to a `ContainerTag`, wrap it in a `<g>` tag, e.g., `{ "tag":
+///   "g", "attrs": ..., "children": [{ "clgn_path": ... }] }`
 ///
 /// # Notes
 ///
@@ -89,17 +105,54 @@ use std::borrow::Cow;
 /// (The `xmnls="..."` is added automatically if not present in the `collagen.json` file.)
 ///
 /// This specific example is in `tests/examples/simple-nesting`.
+///
+/// # Exported sizes
+///
+/// A named container's size lets the tags after it be laid out relative to it, e.g., to
+/// put a caption below a logo whose height isn't known to the skeleton that includes it:
+///
+/// ```json
+/// {
+///   "children": [
+///     { "clgn_path": "logo", "name": "logo" },
+///     { "tag": "text", "attrs": { "y": "{logo.height}" }, "text": "Our logo" }
+///   ]
+/// }
+/// ```
+///
+/// The exported variables are in scope for the siblings that follow the container in
+/// the manifest (and their descendants), regardless of their `z`; they aren't in scope
+/// for the container's preceding siblings or its parent. A later container of the same
+/// name takes over the name for the siblings that follow it. If the included skeleton's
+/// root has no size, nothing is exported.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ContainerTag<'a> {
 	// TODO: Should this be renamed "{import,include}{,_path,ing,s}"? Leaning towards simply "include"
 	clgn_path: String,
 
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	name: Option<String>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	z: Option<f64>,
+
 	#[serde(skip)]
 	#[serde(default)]
-	_child_clgn: LazyCell<Fibroblast<'a>>,
+	_child_clgn: LazyCell<Box<Fibroblast<'a>>>,
 }
 
 impl<'a> ContainerTag<'a> {
+	/// A copy of `self` whose contained skeleton has not yet been loaded, so that it
+	/// will be read from disk afresh when needed
+	pub(crate) fn fresh_clone<'b>(&self) -> ContainerTag<'b> {
+		ContainerTag {
+			clgn_path: self.clgn_path.clone(),
+			name: self.name.clone(),
+			z: self.z,
+			_child_clgn: LazyCell::new(),
+		}
+	}
+
 	/// If not filled, fill in this `ContainerTag` with the `Fibroblast` given by
 	/// `self.clgn_path`. Always returns the contained `Fibroblast`
 	pub(super) fn initialize(
@@ -116,7 +169,7 @@ impl<'a> ContainerTag<'a> {
 				context.replace_root(&abs_clgn_path);
 
 				let subroot = Fibroblast::from_dir_with_context(&abs_clgn_path, context)?;
-				self._child_clgn.fill(subroot).unwrap();
+				self._child_clgn.fill(Box::new(subroot)).unwrap();
 				Ok(self._child_clgn.borrow().unwrap())
 			}
 		}
@@ -130,30 +183,162 @@ impl<'a> ContainerTag<'a> {
 		"g"
 	}
 
-	pub(super) fn vars(&'a self) -> ClgnDecodingResult<&TagVariables> {
+	pub(super) fn vars(&'a self) -> ClgnDecodingResult<&'a TagVariables> {
 		self.as_fibroblast().vars()
 	}
 
 	pub(super) fn attrs(&'a self) -> ClgnDecodingResult<AttrKVValueVec<'a>> {
 		let fb = self.as_fibroblast();
-		fb.context.sub_vars_into_attrs(
+		let mut attrs = fb.context.sub_vars_into_attrs(
 			fb.root
 				.base_attrs()
 				.0
 				.iter()
 				.map(|(k, v)| (k.as_ref(), Cow::Borrowed(v))),
-		)
+		)?;
+		// The included skeleton's namespaces are declared on the `<g>` that holds it
+		attrs.extend(fb.root.namespace_attrs(&fb.context));
+
+		Ok(attrs)
 	}
 
-	pub(super) fn children(&'a self) -> &[AnyChildTag<'a>] {
+	pub(super) fn children(&'a self) -> &'a [AnyChildTag<'a>] {
 		self.as_fibroblast().children()
 	}
 
-	pub(super) fn text(&'a self) -> ClgnDecodingResult<Cow<'a, str>> {
+	/// The variables this tag exports to the siblings that follow it: if it's named, the
+	/// size of the included skeleton (see [Exported sizes](#exported-sizes))
+	pub(super) fn exported_vars(&'a self) -> ClgnDecodingResult<Option<TagVariables>> {
+		let name = match &self.name {
+			Some(name) => name,
+			None => return Ok(None),
+		};
+		let (width, height) = match viewport_size(&self.attrs()?) {
+			Some(size) => size,
+			None => return Ok(None),
+		};
+
+		Ok(Some(TagVariables(
+			vec![("width", width), ("height", height)]
+				.into_iter()
+				.map(|(dim, x)| {
+					(
+						format!("{}.{}", name, dim),
+						VariableValue::Number(ConcreteNumber::Float(x)),
+					)
+				})
+				.collect(),
+		)))
+	}
+
+	pub(super) fn text(&'a self) -> ClgnDecodingResult<XmlText<'a>> {
 		self.as_fibroblast().text()
 	}
 
-	pub(super) fn should_escape_text(&self) -> bool {
-		false
+	pub(super) fn z(&self) -> f64 {
+		self.z.unwrap_or(0.0)
+	}
+}
+
+/// The variables that the named containers among some siblings export to the siblings
+/// that follow them (see [Exported sizes](ContainerTag#exported-sizes)). Everything that
+/// walks the tree must visit each tag with its exports in scope, so that variables are
+/// substituted the same way everywhere.
+pub(crate) struct SiblingExports(Vec<TagVariables>);
+
+impl SiblingExports {
+	/// The exports in scope for each of `children`, by index. (Nothing is stored if no
+	/// child exports anything, which is by far the most common case.)
+	pub(crate) fn of<'a>(
+		children: &'a [AnyChildTag<'a>],
+		context: &DecodingContext<'a>,
+	) -> ClgnDecodingResult<Self> {
+		let mut exports = Vec::new();
+		let mut in_scope = TagVariables(Map::new());
+		let mut any_exported = false;
+		for child in children {
+			exports.push(in_scope.clone());
+			if let Some(vars) = child.exported_vars(context)? {
+				in_scope.0.extend(vars.0);
+				any_exported = true;
+			}
+		}
+
+		if !any_exported {
+			exports.clear();
+		}
+		Ok(Self(exports))
+	}
+
+	/// Call `f` with the exports in scope for the child at `index`
+	pub(crate) fn with_exports_for<T>(
+		&self,
+		index: usize,
+		context: &DecodingContext,
+		f: impl FnOnce() -> ClgnDecodingResult<T>,
+	) -> ClgnDecodingResult<T> {
+		match self.0.get(index) {
+			Some(vars) => context.with_new_vars(vars, f),
+			None => f(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::to_svg::svg_writable::ClgnDecodingError;
+	use crate::Fibroblast;
+
+	#[test]
+	fn exported_sizes() {
+		let dir = tempfile::tempdir().unwrap();
+		for (name, view_box) in [("small", "0 0 1 2"), ("big", "0 0 10 20")] {
+			std::fs::create_dir(dir.path().join(name)).unwrap();
+			std::fs::write(
+				dir.path().join(name).join("collagen.json"),
+				format!(r#"{{ "attrs": {{ "viewBox": "{}" }} }}"#, view_box),
+			)
+			.unwrap();
+		}
+		let render = |children: &str| {
+			std::fs::write(
+				dir.path().join("collagen.json"),
+				format!(r#"{{ "children": {} }}"#, children),
+			)
+			.unwrap();
+			Fibroblast::from_dir(dir.path())?.to_svg_string()
+		};
+
+		// A later container of the same name takes over the name
+		assert_eq!(
+			render(
+				r#"[
+					{ "clgn_path": "small", "name": "c" },
+					{ "tag": "rect", "attrs": { "width": "{c.width}" } },
+					{ "clgn_path": "big", "name": "c" },
+					{ "tag": "rect", "attrs": { "width": "{c.width}", "height": "{c.height}" } }
+				]"#
+			)
+			.unwrap(),
+			r#"<svg xmlns="http://www.w3.org/2000/svg"><g viewBox="0 0 1 2"></g><rect width="1"></rect><g viewBox="0 0 10 20"></g><rect height="20" width="10"></rect></svg>"#
+		);
+
+		// Only the siblings that follow the container can refer to its size
+		for children in [
+			r#"[{ "tag": "rect", "attrs": { "width": "{c.width}" } }, { "clgn_path": "small", "name": "c" }]"#,
+			r#"[{ "tag": "g", "attrs": { "width": "{c.width}" }, "children": [{ "clgn_path": "small", "name": "c" }] }]"#,
+			r#"[{ "tag": "g", "children": [{ "clgn_path": "small", "name": "c" }] }, { "tag": "rect", "attrs": { "width": "{c.width}" } }]"#,
+		] {
+			assert!(
+				matches!(render(children), Err(ClgnDecodingError::Parse(_))),
+				"{}",
+				children
+			);
+		}
+
+		assert!(matches!(
+			render(r#"[{ "clgn_path": "small", "name": "c.d" }]"#),
+			Err(ClgnDecodingError::Container { .. })
+		));
 	}
 }
@@ -0,0 +1,281 @@
+//! The root tag's `credits`: a block listing the images and fonts that the document
+//! embeds, for collages made from stock or Creative Commons assets that must be
+//! attributed. Each asset is listed by its `credit`, if it was given one, and otherwise
+//! by its path (or, for bundled and Google fonts, its name). An asset is listed once, no
+//! matter how many times it's embedded, in the order in which it's first embedded.
+//!
+//! The block is a `<g class="clgn-credits">` containing one `<text>` per asset. It's
+//! written at the end of the document, so that it's drawn on top of everything else, in
+//! a corner of the canvas, whose size is taken from the root's `viewBox`, or its `width`
+//! and `height`.
+
+use super::{AttrKVValueVec, ClgnDecodingResult, DecodingContext, XmlAttrs};
+use crate::fibroblast::data_types::context::parse_view_box;
+use crate::to_svg::svg_writable::ClgnDecodingError;
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event as XmlEvent};
+use quick_xml::Writer as XmlWriter;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+const DEFAULT_FONT_SIZE: f64 = 10.0;
+const DEFAULT_MARGIN: f64 = 4.0;
+/// The distance between the baselines of consecutive lines, as a multiple of the font
+/// size
+const LINE_HEIGHT: f64 = 1.2;
+
+/// The corner of the canvas that the credits are placed in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Corner {
+	TopLeft,
+	TopRight,
+	BottomLeft,
+	BottomRight,
+}
+
+/// The options of the credits block, given in the root tag's `credits`.
+///
+/// # Properties
+///
+/// - `corner`
+///   - Type: one of `"top-left"`, `"top-right"`, `"bottom-left"`, or `"bottom-right"`
+///   - Required: No. Missing is equivalent to `"bottom-right"`.
+///   - Description: The corner of the canvas to place the credits in. The credits are
+///     aligned to that corner's side of the canvas.
+/// - `font_size`
+///   - Type: positive number
+///   - Required: No. Missing is equivalent to `10`.
+///   - Description: The font size of the credits, in user units.
+/// - `margin`
+///   - Type: non-negative number
+///   - Required: No. Missing is equivalent to `4`.
+///   - Description: The distance between the credits and the edges of the canvas.
+/// - `attrs`
+///   - Type: object, with values either number or string
+///   - Required: No. Missing is equivalent to `{}`.
+///   - Description: Attributes of the credits' `<g>`, such as `fill`, `font-family`, or
+///     `opacity`, which style all of the credits. Variables (those of the root tag) are
+///     substituted, and palette colors resolved, as for any tag.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Credits {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	corner: Option<Corner>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	font_size: Option<f64>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	margin: Option<f64>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	attrs: Option<XmlAttrs>,
+}
+
+/// Either whether to write the credits or, to write them with non-default options,
+/// those options
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub(crate) enum CreditsOption {
+	Enabled(bool),
+	Options(Credits),
+}
+
+impl CreditsOption {
+	/// The options of the credits, or `None` if there shouldn't be any
+	pub(crate) fn credits(&self) -> Option<Cow<'_, Credits>> {
+		match self {
+			CreditsOption::Enabled(true) => Some(Cow::Owned(Credits::default())),
+			CreditsOption::Enabled(false) => None,
+			CreditsOption::Options(credits) => Some(Cow::Borrowed(credits)),
+		}
+	}
+}
+
+impl Credits {
+	/// Write `credits`, the credits of the assets embedded in the document, in the
+	/// corner of the canvas established by the root's (substituted) attributes
+	/// `root_attrs`. Nothing is written if there are no credits, but it's an error for
+	/// the options to be invalid regardless.
+	pub(crate) fn write(
+		&self,
+		credits: &[String],
+		root_attrs: &AttrKVValueVec,
+		context: &DecodingContext,
+		writer: &mut XmlWriter<impl std::io::Write>,
+	) -> ClgnDecodingResult<()> {
+		let credits_error = |msg: String| ClgnDecodingError::Credits {
+			msg: format!("Invalid `credits`: {}", msg),
+		};
+
+		let font_size = self.font_size.unwrap_or(DEFAULT_FONT_SIZE);
+		if !(font_size.is_finite() && font_size > 0.0) {
+			return Err(credits_error(format!(
+				"`font_size` must be a positive number, not {}",
+				font_size
+			)));
+		}
+		let margin = self.margin.unwrap_or(DEFAULT_MARGIN);
+		if !(margin.is_finite() && margin >= 0.0) {
+			return Err(credits_error(format!(
+				"`margin` must be a non-negative number, not {}",
+				margin
+			)));
+		}
+
+		let [min_x, min_y, width, height] = canvas(root_attrs).ok_or_else(|| {
+			credits_error(
+				"the size of the canvas can't be determined; give the root tag a `viewBox`, \
+				 or a `width` and `height`"
+					.to_owned(),
+			)
+		})?;
+
+		if credits.is_empty() {
+			return Ok(());
+		}
+
+		let corner = self.corner.unwrap_or(Corner::BottomRight);
+		let (right, bottom) = match corner {
+			Corner::TopLeft => (false, false),
+			Corner::TopRight => (true, false),
+			Corner::BottomLeft => (false, true),
+			Corner::BottomRight => (true, true),
+		};
+		let line_height = LINE_HEIGHT * font_size;
+		let x = if right {
+			min_x + width - margin
+		} else {
+			min_x + margin
+		};
+		let first_baseline = if bottom {
+			min_y + height - margin - (credits.len() - 1) as f64 * line_height
+		} else {
+			min_y + margin + font_size
+		};
+
+		let mut g_attrs = vec![
+			("class", "clgn-credits".to_owned()),
+			("font-size", font_size.to_string()),
+			(
+				"text-anchor",
+				if right { "end" } else { "start" }.to_owned(),
+			),
+		];
+		if let Some(attrs) = &self.attrs {
+			let mut attrs = context
+				.sub_vars_into_attrs(attrs.0.iter().map(|(k, v)| (k.as_ref(), Cow::Borrowed(v))))?;
+			context.resolve_palette_colors(&mut attrs)?;
+			g_attrs.retain(|(k, _)| !attrs.iter().any(|(name, _)| name == k));
+			g_attrs.extend(
+				attrs
+					.iter()
+					.filter_map(|(k, v)| v.to_maybe_string().map(|v| (*k, v.into_owned()))),
+			);
+		}
+
+		let mut g = BytesStart::borrowed_name(b"g");
+		g.extend_attributes(g_attrs.iter().map(|(k, v)| (*k, v.as_str())));
+		writer.write_event(XmlEvent::Start(g))?;
+		for (i, credit) in credits.iter().enumerate() {
+			let y = first_baseline + i as f64 * line_height;
+			let mut text = BytesStart::borrowed_name(b"text");
+			text.push_attribute(("x", x.to_string().as_str()));
+			text.push_attribute(("y", y.to_string().as_str()));
+			writer.write_event(XmlEvent::Start(text))?;
+			writer.write_event(XmlEvent::Text(BytesText::from_plain_str(credit)))?;
+			writer.write_event(XmlEvent::End(BytesEnd::borrowed(b"text")))?;
+		}
+		writer.write_event(XmlEvent::End(BytesEnd::borrowed(b"g")))?;
+
+		Ok(())
+	}
+}
+
+/// The `[min-x, min-y, width, height]` of the canvas established by a root tag with
+/// attributes `attrs`, taken from its `viewBox` if it has one and its `width` and
+/// `height` otherwise
+fn canvas(attrs: &AttrKVValueVec) -> Option<[f64; 4]> {
+	let attr = |name: &str| attrs.iter().find(|(k, _)| *k == name).map(|(_, v)| v);
+
+	if let Some(view_box) = attr("viewBox")
+		.and_then(|v| v.to_maybe_string())
+		.and_then(|view_box| parse_view_box(&view_box))
+	{
+		return Some(view_box);
+	}
+
+	Some([
+		0.0,
+		0.0,
+		attr("width")?.to_number()?,
+		attr("height")?.to_number()?,
+	])
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::fibroblast::Fibroblast;
+	use crate::to_svg::svg_writable::{ClgnDecodingError, ClgnDecodingResult};
+
+	#[test]
+	fn credits() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(skeleton_dir.path().join("a.svg"), "<svg/>").unwrap();
+		std::fs::write(skeleton_dir.path().join("b.svg"), "<svg/>").unwrap();
+		std::fs::write(skeleton_dir.path().join("f.woff2"), "wOF2").unwrap();
+		let render = |root: &str| -> ClgnDecodingResult<String> {
+			std::fs::write(
+				skeleton_dir.path().join("collagen.json"),
+				format!(
+					r#"{{
+						{},
+						"vars": {{ "author": "Ann" }},
+						"palette": {{ "muted": "gray" }},
+						"children": [
+							{{ "fonts": [{{ "name": "F", "path": "f.woff2", "credit": "F by {{author}}" }}] }},
+							{{ "image_path": "a.svg", "credit": "Photo by {{author}} (CC BY 4.0)" }},
+							{{ "image_path": "b.svg" }},
+							{{ "image_path": "a.svg", "credit": "Photo by {{author}} (CC BY 4.0)" }}
+						]
+					}}"#,
+					root
+				),
+			)
+			.unwrap();
+			let svg = Fibroblast::from_dir(skeleton_dir.path())?.to_svg_string()?;
+			Ok(svg[svg.rfind("</image>").unwrap() + "</image>".len()..].to_owned())
+		};
+
+		assert_eq!(
+			render(r#""attrs": { "width": 200, "height": 100 }, "credits": true"#).unwrap(),
+			r#"<g class="clgn-credits" font-size="10" text-anchor="end"><text x="196" y="72">F by Ann</text><text x="196" y="84">Photo by Ann (CC BY 4.0)</text><text x="196" y="96">b.svg</text></g></svg>"#
+		);
+		assert_eq!(
+			render(
+				r#""attrs": { "viewBox": "10 20 200 100" },
+				"credits": { "corner": "top-left", "font_size": 5, "margin": 0, "attrs": { "fill": "@muted", "font-family": "serif" } }"#
+			)
+			.unwrap(),
+			r#"<g class="clgn-credits" font-size="5" text-anchor="start" fill="gray" font-family="serif"><text x="10" y="25">F by Ann</text><text x="10" y="31">Photo by Ann (CC BY 4.0)</text><text x="10" y="37">b.svg</text></g></svg>"#
+		);
+		assert_eq!(
+			render(r#""attrs": { "width": 200, "height": 100 }, "credits": false"#).unwrap(),
+			"</svg>"
+		);
+
+		for root in vec![
+			r#""credits": true"#,
+			r#""attrs": { "width": 200, "height": 100 }, "credits": { "font_size": 0 }"#,
+			r#""attrs": { "width": 200, "height": 100 }, "credits": { "margin": -1 }"#,
+		]
+		.into_iter()
+		{
+			assert!(
+				matches!(render(root), Err(ClgnDecodingError::Credits { .. })),
+				"{}",
+				root
+			);
+		}
+	}
+}
@@ -1,11 +1,15 @@
 use super::{AnyChildTag, DecodingContext, TagVariables, XmlAttrs, EMPTY_ATTRS, EMPTY_VARS};
 use crate::{
-	fibroblast::data_types::{ConcreteNumber, Map},
-	to_svg::svg_writable::ClgnDecodingError,
+	fibroblast::data_types::{ConcreteNumber, Map, RawText},
+	to_svg::{
+		render_timings::RenderPhase, svg_writable::ClgnDecodingError, target::Feature, xml_text,
+	},
+	utils::file_source::FileSource,
 	ClgnDecodingResult,
 };
 use serde::{de, ser::SerializeMap, Deserialize, Serialize};
 use std::borrow::Cow;
+use std::path::{Component, Path, PathBuf};
 
 #[cfg(feature = "_any_bundled_font")]
 use crate::assets::fonts;
@@ -15,6 +19,7 @@ use crate::assets::fonts;
 pub(crate) enum FontAttr {
 	String(String),
 	Number(ConcreteNumber),
+	Raw(RawText),
 }
 
 enum CowishFontAttr<'a> {
@@ -23,13 +28,16 @@ enum CowishFontAttr<'a> {
 	BorrowedStr(&'a str),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct UserProvidedFontFace {
 	name: String,
 	path: String,
 
 	#[serde(default)]
 	attrs: Map<String, FontAttr>,
+
+	#[serde(default)]
+	credit: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -38,23 +46,91 @@ pub(crate) struct BundledFontFace {
 
 	#[serde(default)]
 	attrs: Map<String, FontAttr>,
+
+	#[serde(default)]
+	credit: Option<String>,
 }
 
-#[derive(Debug)]
+/// A font from [Google Fonts](https://fonts.google.com), read from the font cache
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct GoogleFontFace {
+	name: String,
+	family: String,
+	weights: Vec<u64>,
+
+	#[serde(default)]
+	attrs: Map<String, FontAttr>,
+
+	#[serde(default)]
+	credit: Option<String>,
+}
+
+impl GoogleFontFace {
+	const DEFAULT_WEIGHT: u64 = 400;
+
+	/// The path, inside the font cache `cache_dir`, of the file for this font at
+	/// `weight`. The family names a folder directly inside the cache, so it's an error
+	/// for it to be anything but a single folder name (e.g., `..` or `a/b`).
+	fn cached_path(&self, cache_dir: &Path, weight: u64) -> ClgnDecodingResult<PathBuf> {
+		let mut components = Path::new(&self.family).components();
+		let is_folder_name = matches!(
+			(components.next(), components.next()),
+			(Some(Component::Normal(_)), None)
+		) && !self.family.contains(&['/', '\\'][..]);
+		if !is_folder_name {
+			return Err(ClgnDecodingError::Font {
+				msg: format!(
+					"The Google font family {:?} is invalid; it must not be empty, `.`, or \
+					 `..`, or contain a path separator",
+					self.family
+				),
+			});
+		}
+
+		Ok(cache_dir
+			.join(&self.family)
+			.join(format!("{}.woff2", weight)))
+	}
+}
+
+#[derive(Debug, Clone)]
 pub(crate) enum FontFace {
 	UserProvided(UserProvidedFontFace),
 	#[cfg_attr(not(feature = "_any_bundled_font"), allow(dead_code))]
 	Bundled(BundledFontFace),
+	Google(GoogleFontFace),
 }
 
 impl FontFace {
-	const fn n_entries(&self) -> usize {
+	fn attrs(&self) -> &Map<String, FontAttr> {
+		use FontFace::*;
+		match self {
+			UserProvided(font) => &font.attrs,
+			Bundled(font) => &font.attrs,
+			Google(font) => &font.attrs,
+		}
+	}
+
+	fn credit(&self) -> Option<&String> {
 		use FontFace::*;
 		match self {
-			UserProvided(_) => 4,
-			Bundled(_) => 3,
+			UserProvided(font) => font.credit.as_ref(),
+			Bundled(font) => font.credit.as_ref(),
+			Google(font) => font.credit.as_ref(),
 		}
 	}
+
+	/// The number of entries in the serialized map; `attrs` is omitted when empty, and
+	/// `credit` when absent
+	fn n_entries(&self) -> usize {
+		use FontFace::*;
+		let n_required = match self {
+			UserProvided(_) => 3,
+			Bundled(_) => 2,
+			Google(_) => 3,
+		};
+		n_required + usize::from(!self.attrs().is_empty()) + usize::from(self.credit().is_some())
+	}
 }
 
 impl Serialize for FontFace {
@@ -67,21 +143,39 @@ impl Serialize for FontFace {
 
 		match self {
 			UserProvided(font) => {
-				let UserProvidedFontFace { name, path, attrs } = font;
+				let UserProvidedFontFace { name, path, .. } = font;
 
 				map.serialize_entry("bundled", &false)?;
 				map.serialize_entry("name", name)?;
 				map.serialize_entry("path", path)?;
-				map.serialize_entry("attrs", attrs)?;
 			}
 			Bundled(font) => {
-				let BundledFontFace { name, attrs } = font;
+				let BundledFontFace { name, .. } = font;
 
 				map.serialize_entry("bundled", &true)?;
 				map.serialize_entry("name", name)?;
-				map.serialize_entry("attrs", attrs)?;
+			}
+			Google(font) => {
+				let GoogleFontFace {
+					name,
+					family,
+					weights,
+					..
+				} = font;
+
+				map.serialize_entry("google", family)?;
+				map.serialize_entry("name", name)?;
+				map.serialize_entry("weights", weights)?;
 			}
 		}
+
+		let attrs = self.attrs();
+		if !attrs.is_empty() {
+			map.serialize_entry("attrs", attrs)?;
+		}
+		if let Some(credit) = self.credit() {
+			map.serialize_entry("credit", credit)?;
+		}
 		map.end()
 	}
 }
@@ -98,6 +192,9 @@ impl<'de> Deserialize<'de> for FontFace {
 			Name,
 			Path,
 			Attrs,
+			Google,
+			Weights,
+			Credit,
 		}
 
 		struct FontFaceVisitor;
@@ -126,6 +223,9 @@ impl<'de> Deserialize<'de> for FontFace {
 				let mut name = None;
 				let mut path = None;
 				let mut attrs = None;
+				let mut google: Option<String> = None;
+				let mut weights = None;
+				let mut credit = None;
 
 				while let Some(key) = map.next_key()? {
 					match key {
@@ -133,23 +233,56 @@ impl<'de> Deserialize<'de> for FontFace {
 						Field::Name => handle_key!(map, name, "name"),
 						Field::Path => handle_key!(map, path, "path"),
 						Field::Attrs => handle_key!(map, attrs, "attrs"),
+						Field::Google => handle_key!(map, google, "google"),
+						Field::Weights => handle_key!(map, weights, "weights"),
+						Field::Credit => handle_key!(map, credit, "credit"),
 					}
 				}
 
+				let attrs = attrs.unwrap_or_else(Map::new);
+
+				if let Some(family) = google {
+					if bundled.is_some() || path.is_some() {
+						return Err(de::Error::custom("You specified a `google` font along with `bundled` or a `path`. These are mutually exclusive options."));
+					}
+					let name = name.unwrap_or_else(|| family.clone());
+					let weights = weights.unwrap_or_else(|| vec![GoogleFontFace::DEFAULT_WEIGHT]);
+					return Ok(FontFace::Google(GoogleFontFace {
+						name,
+						family,
+						weights,
+						attrs,
+						credit,
+					}));
+				}
+				if weights.is_some() {
+					return Err(de::Error::custom(
+						"`weights` may only be given for a `google` font",
+					));
+				}
+
 				let bundled = bundled.unwrap_or(false);
 				let name = name.ok_or_else(|| de::Error::missing_field("name"))?;
-				let attrs = attrs.unwrap_or_else(Map::new);
 
 				let ff = match bundled {
 					true => {
 						if path.is_some() {
 							return Err(de::Error::custom("You specified both `bundled = true` and a `path` for your font. These are mutually exclusive options."));
 						}
-						FontFace::Bundled(BundledFontFace { name, attrs })
+						FontFace::Bundled(BundledFontFace {
+							name,
+							attrs,
+							credit,
+						})
 					}
 					false => {
 						let path = path.ok_or_else(|| de::Error::missing_field("path"))?;
-						FontFace::UserProvided(UserProvidedFontFace { name, path, attrs })
+						FontFace::UserProvided(UserProvidedFontFace {
+							name,
+							path,
+							attrs,
+							credit,
+						})
 					}
 				};
 
@@ -161,11 +294,65 @@ impl<'de> Deserialize<'de> for FontFace {
 	}
 }
 
-/// A tag for embedding woff2 font files within SVGs. (This is not widely supported by
-/// SVG viewers, but Collagen supports it nonetheless.) Fonts are specified either as a
-/// path to the woff2 file on disk, or are specified as one of the handful of fonts that
-/// come bundled with the `clgn` executable (assuming the executable was built with said
-/// font bundled).
+/// The formats of font file that can be embedded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FontFormat {
+	Woff2,
+	Woff,
+	TrueType,
+	OpenType,
+}
+
+impl FontFormat {
+	/// The format of the font file whose contents are `bytes`, according to its first
+	/// four bytes; `None` if it's not a font
+	fn from_magic_bytes(bytes: &[u8]) -> Option<Self> {
+		use FontFormat::*;
+		Some(match bytes.get(..4)? {
+			b"wOF2" => Woff2,
+			b"wOFF" => Woff,
+			b"\x00\x01\x00\x00" | b"true" => TrueType,
+			b"OTTO" => OpenType,
+			_ => return None,
+		})
+	}
+
+	fn mime_type(self) -> &'static str {
+		use FontFormat::*;
+		match self {
+			Woff2 => "font/woff2",
+			Woff => "font/woff",
+			TrueType => "font/ttf",
+			OpenType => "font/otf",
+		}
+	}
+
+	/// The feature used by an SVG that embeds a font of this format
+	fn feature(self) -> Feature {
+		use FontFormat::*;
+		match self {
+			Woff2 | Woff => Feature::WebFont,
+			TrueType | OpenType => Feature::SfntFont,
+		}
+	}
+
+	/// The name of the format in CSS's `format()`
+	fn css_format(self) -> &'static str {
+		use FontFormat::*;
+		match self {
+			Woff2 => "woff2",
+			Woff => "woff",
+			TrueType => "truetype",
+			OpenType => "opentype",
+		}
+	}
+}
+
+/// A tag for embedding font files within SVGs. (This is not widely supported by SVG
+/// viewers, but Collagen supports it nonetheless.) Fonts are specified either as a path
+/// to a WOFF2, WOFF, TrueType, or OpenType file on disk, as one of the handful of fonts
+/// that come bundled with the `clgn` executable (assuming the executable was built with
+/// said font bundled), or as a Google font in the font cache.
 ///
 /// In the resuling SVG, fonts are included in a `<style>` tag with a `@font-face {}`
 /// section. Multiple fonts in the same `FontTag` will reside in the same `<style>` tag
@@ -196,7 +383,7 @@ impl<'de> Deserialize<'de> for FontFace {
 /// boolean that tells `clgn` whether the font is bundled in the executable or not. If
 /// missing, it is treated as if it were `false`. The first kind of `FontFace`, the kind
 /// that exists on disk, also has a `path` field of type string to specify where the
-/// `woff2` exists (so that it may be embedded). So, in summary,
+/// font file exists (so that it may be embedded). So, in summary,
 ///
 /// ## Properties
 ///
@@ -210,19 +397,52 @@ impl<'de> Deserialize<'de> for FontFace {
 ///   - Type: boolean
 ///   - Required: No. Missing is equivalent to `false`.
 ///   - Description: Informs `clgn` whether this font is bundled with the executable or
-///     will be provided via path to the woff2 file on disk. `true` means the font's
+///     will be provided via path to the font file on disk. `true` means the font's
 ///     `name` must be the name that `clgn` calls its bundled font.
 /// - `path`
 ///   - Type: string
 ///   - Required: Yes if `bundled` is `false`. Must be absent if `bundled` is `true`.
 ///   - Description: The path to the font to be embedded, relative to the skeleton root.
 ///     May not be specified in conjunction with `"bundled": true`, as these contradict
-///     each other. It is an error if no file exists at the specified path.
+///     each other. It is an error if no file exists at the specified path, or if the
+///     file is not a WOFF2, WOFF, TrueType, or OpenType font (as determined by its
+///     contents, not its extension).
 /// - `attrs`
-///   - Type: object whose values are string
+///   - Type: object whose values are string, number, or `{ "raw": <string> }`
 ///   - Required: No. Missing is equivalent to `{}`.
 ///   - Description: Key-value pairs that will be inserted into the `@font-face`
-///     declaration, e.g., `{ "font-weight": 100 }` becomes `font-weight: 100;`
+///     declaration, e.g., `{ "font-weight": 100 }` becomes `font-weight: 100;`.
+///     Variables are substituted into string values, but not into raw ones.
+/// - `credit`
+///   - Type: string
+///   - Required: No. Missing is equivalent to `path` (or, for a bundled font, `name`).
+///   - Description: The attribution of the font, e.g., `"MyFont by Ann Lee (OFL)"`,
+///     which is listed in the root's `credits`, if it has any. Variables are
+///     substituted.
+///
+/// ## Google fonts
+///
+/// A [Google font](https://fonts.google.com) is given by its family name, e.g., `{
+/// "google": "Inter", "weights": [400, 700] }`, and is read from the font cache, a
+/// folder passed to `clgn` as `--font-cache <dir>`. The font at each weight must have
+/// been downloaded (as WOFF2) to `<dir>/<family>/<weight>.woff2`, e.g.,
+/// `<dir>/Inter/700.woff2`. (`clgn` does not download fonts itself.) One `@font-face` is
+/// emitted per weight.
+///
+/// - `google`
+///   - Type: string
+///   - Required: Yes, for a Google font. May not be given with `bundled` or `path`.
+///   - Description: The font's family name, as it appears on Google Fonts.
+/// - `weights`
+///   - Type: list of integer
+///   - Required: No. Missing is equivalent to `[400]`.
+///   - Description: The weights of the font to embed.
+/// - `name`
+///   - Type: string
+///   - Required: No. Missing is equivalent to the value of `google`.
+///   - Description: The `font-family` of the font in the `<style>` tag.
+/// - `attrs` and `credit`: As above. A Google font's `credit` defaults to its
+///   `google`.
 ///
 /// # Example
 ///
@@ -232,19 +452,20 @@ impl<'de> Deserialize<'de> for FontFace {
 /// {
 ///   "fonts": [
 ///     { "name": "Impact", "bundled": true },
-///     { "name": "MyThinFont", "path": "path/to/font.woff2", "attrs": { "font-weight": 100 } }
+///     { "name": "MyThinFont", "path": "path/to/font.woff2", "attrs": { "font-weight": 100 } },
+///     { "google": "Inter", "weights": [400, 700] }
 ///   ],
 ///   "vars": { "foo": "bar" }
 /// }
 /// ```
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FontTag {
 	fonts: Vec<FontFace>,
 
-	#[serde(default)]
+	#[serde(default, skip_serializing_if = "Option::is_none")]
 	vars: Option<TagVariables>,
 
-	#[serde(default)]
+	#[serde(default, skip_serializing_if = "Option::is_none")]
 	attrs: Option<XmlAttrs>,
 }
 
@@ -271,6 +492,49 @@ impl FontTag {
 		&[]
 	}
 
+	/// The credit of each font that this tag embeds, for the root's `credits`
+	pub(super) fn credits(&self, context: &DecodingContext) -> ClgnDecodingResult<Vec<String>> {
+		self.fonts
+			.iter()
+			.map(|font| {
+				Ok(match font.credit() {
+					Some(credit) => context.sub_vars_into_str(credit)?.into_owned(),
+					None => match font {
+						FontFace::UserProvided(font) => font.path.clone(),
+						FontFace::Bundled(font) => font.name.clone(),
+						FontFace::Google(font) => font.family.clone(),
+					},
+				})
+			})
+			.collect()
+	}
+
+	/// The paths of the font files on disk that this tag embeds. (A Google font is
+	/// omitted if there is no font cache to read it from.)
+	pub(super) fn asset_paths(
+		&self,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<Vec<PathBuf>> {
+		let mut paths = Vec::new();
+		for font in &self.fonts {
+			match font {
+				FontFace::UserProvided(font) => paths.push(
+					crate::utils::paths::pathsep_aware_join(&*context.get_root(), &font.path)?,
+				),
+				FontFace::Bundled(_) => {}
+				FontFace::Google(font) => {
+					if let Some(cache_dir) = context.font_cache_dir() {
+						for &weight in &font.weights {
+							paths.push(font.cached_path(&cache_dir, weight)?);
+						}
+					}
+				}
+			}
+		}
+
+		Ok(paths)
+	}
+
 	pub(super) fn get_font_path_attr_pair(
 		&self,
 		path: impl AsRef<str>,
@@ -278,14 +542,38 @@ impl FontTag {
 	) -> ClgnDecodingResult<String> {
 		let path = path.as_ref();
 		let abs_font_path = crate::utils::paths::pathsep_aware_join(&*context.get_root(), path)?;
+		self.get_font_src(abs_font_path, &context.file_source(), context)
+	}
 
-		let b64_string = base64::encode(
-			std::fs::read(abs_font_path.as_path())
-				.map_err(|e| ClgnDecodingError::Io(e, abs_font_path))?,
-		);
+	/// The value of the `src` descriptor that embeds the font at `abs_font_path`, which
+	/// is read from `files`
+	fn get_font_src(
+		&self,
+		abs_font_path: PathBuf,
+		files: &FileSource,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<String> {
+		let bytes =
+			context.time_phase(RenderPhase::AssetIo, || files.read_or_err(&abs_font_path))?;
+		let format = match FontFormat::from_magic_bytes(&bytes) {
+			Some(format) => format,
+			None => {
+				return Err(ClgnDecodingError::Font {
+					msg: format!(
+						"{:?} is not a font; expected a WOFF2, WOFF, TrueType, or OpenType file",
+						abs_font_path
+					),
+				})
+			}
+		};
+
+		context.note_feature(format.feature());
+		let b64_string = context.time_phase(RenderPhase::Base64, || base64::encode(bytes));
 		let src_str = format!(
-			"url('data:font/woff2;charset=utf-8;base64,{}') format('woff2')",
-			b64_string
+			"url('data:{};charset=utf-8;base64,{}') format('{}')",
+			format.mime_type(),
+			b64_string,
+			format.css_format()
 		);
 
 		Ok(src_str)
@@ -294,7 +582,7 @@ impl FontTag {
 	pub(super) fn font_embed_text(&self, context: &DecodingContext) -> ClgnDecodingResult<String> {
 		let mut text = String::from("<style>");
 		for font in &self.fonts {
-			let (mut all_attrs, self_attrs) = match font {
+			let faces = match font {
 				#[cfg_attr(
 					not(feature = "_any_bundled_font"),
 					allow(unused_variables, unreachable_code, unused_mut)
@@ -303,6 +591,7 @@ impl FontTag {
 					let BundledFontFace {
 						name: font_family,
 						attrs: self_attrs,
+						..
 					} = font;
 
 					let mut all_attrs =
@@ -310,8 +599,11 @@ impl FontTag {
 
 					match font_family.to_ascii_uppercase().as_str() {
 						#[cfg(feature = "font_impact")]
-						"IMPACT" => all_attrs
-							.push(("src", CowishFontAttr::BorrowedStr(fonts::IMPACT_WOFF2_B64))),
+						"IMPACT" => {
+							context.note_feature(Feature::WebFont);
+							all_attrs
+								.push(("src", CowishFontAttr::BorrowedStr(fonts::IMPACT_WOFF2_B64)))
+						}
 
 						_ => {
 							return Err(ClgnDecodingError::BundledFontNotFound {
@@ -320,13 +612,14 @@ impl FontTag {
 						}
 					}
 
-					(all_attrs, self_attrs)
+					vec![(all_attrs, self_attrs)]
 				}
 				FontFace::UserProvided(font) => {
 					let UserProvidedFontFace {
 						name: font_family,
 						path,
 						attrs: self_attrs,
+						..
 					} = font;
 
 					let mut all_attrs =
@@ -335,46 +628,190 @@ impl FontTag {
 
 					all_attrs.push(("src", CowishFontAttr::OwnedAttr(FontAttr::String(b64_font))));
 
-					(all_attrs, self_attrs)
+					vec![(all_attrs, self_attrs)]
 				}
-			};
+				FontFace::Google(font) => {
+					let cache_dir = match context.font_cache_dir() {
+						Some(dir) => dir,
+						None => {
+							return Err(ClgnDecodingError::Font {
+								msg: format!(
+									"The Google font {:?} must be read from a font cache; pass \
+									 `--font-cache <dir>`, where <dir>/{}/<weight>.woff2 is the \
+									 font at each weight",
+									font.family, font.family
+								),
+							})
+						}
+					};
+
+					let mut faces = Vec::new();
+					for &weight in &font.weights {
+						let path = font.cached_path(&cache_dir, weight)?;
+						if !path.is_file() {
+							return Err(ClgnDecodingError::Font {
+								msg: format!(
+									"The Google font {:?} at weight {} is not in the font cache; \
+									 download it to {:?}",
+									font.family, weight, path
+								),
+							});
+						}
 
-			all_attrs.extend(
-				self_attrs
-					.iter()
-					.map(|(k, v)| (k.as_ref(), CowishFontAttr::BorrowedAttr(v))),
-			);
+						let all_attrs = vec![
+							("font-family", CowishFontAttr::BorrowedStr(&font.name)),
+							(
+								"src",
+								CowishFontAttr::OwnedAttr(FontAttr::String(
+									// The font cache is on disk, even if the skeleton isn't
+									self.get_font_src(path, &FileSource::Disk, context)?,
+								)),
+							),
+							(
+								"font-weight",
+								CowishFontAttr::OwnedAttr(FontAttr::Number(ConcreteNumber::UInt(
+									weight,
+								))),
+							),
+						];
+						faces.push((all_attrs, &font.attrs));
+					}
 
-			text.push_str("@font-face{");
+					faces
+				}
+			};
 
-			for (k, v) in all_attrs {
-				let new_val = match &v {
-					CowishFontAttr::OwnedAttr(a) => match a {
-						FontAttr::String(text) => context.sub_vars_into_str(text.as_ref())?,
-						FontAttr::Number(n) => Cow::Owned(n.to_string()),
-					},
-					CowishFontAttr::BorrowedAttr(a) => match *a {
-						FontAttr::String(text) => context.sub_vars_into_str(text.as_ref())?,
-						FontAttr::Number(n) => Cow::Owned(n.to_string()),
-					},
-					CowishFontAttr::BorrowedStr(text) => context.sub_vars_into_str(text)?,
-				};
+			for (mut all_attrs, self_attrs) in faces {
+				all_attrs.extend(
+					self_attrs
+						.iter()
+						.map(|(k, v)| (k.as_ref(), CowishFontAttr::BorrowedAttr(v))),
+				);
+
+				text.push_str("@font-face{");
+
+				for (k, v) in all_attrs {
+					let new_val = match &v {
+						CowishFontAttr::OwnedAttr(a) => match a {
+							FontAttr::String(text) => context.sub_vars_into_str(text.as_ref())?,
+							FontAttr::Number(n) => Cow::Owned(n.to_string()),
+							FontAttr::Raw(RawText { raw }) => Cow::Borrowed(raw.as_ref()),
+						},
+						CowishFontAttr::BorrowedAttr(a) => match *a {
+							FontAttr::String(text) => context.sub_vars_into_str(text.as_ref())?,
+							FontAttr::Number(n) => Cow::Owned(n.to_string()),
+							FontAttr::Raw(RawText { raw }) => Cow::Borrowed(raw.as_ref()),
+						},
+						CowishFontAttr::BorrowedStr(text) => context.sub_vars_into_str(text)?,
+					};
+
+					text.push_str(k);
+					text.push(':');
+					text.push_str(&xml_text::escape_char_data(&new_val));
+					text.push(';');
+				}
 
-				text.push_str(k);
-				text.push(':');
-				text.push_str(&new_val);
-				text.push(';');
+				text.push('}');
 			}
-
-			text.push('}');
 		}
 
 		text.push_str("</style>");
 
 		Ok(text)
 	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn font_formats() {
+		use FontFormat::*;
+		assert_eq!(FontFormat::from_magic_bytes(b"wOF2...."), Some(Woff2));
+		assert_eq!(FontFormat::from_magic_bytes(b"wOFF...."), Some(Woff));
+		assert_eq!(
+			FontFormat::from_magic_bytes(b"\x00\x01\x00\x00...."),
+			Some(TrueType)
+		);
+		assert_eq!(FontFormat::from_magic_bytes(b"true...."), Some(TrueType));
+		assert_eq!(FontFormat::from_magic_bytes(b"OTTO...."), Some(OpenType));
+		assert_eq!(FontFormat::from_magic_bytes(b"<svg"), None);
+		assert_eq!(FontFormat::from_magic_bytes(b"wOF"), None);
+	}
+
+	#[test]
+	fn not_a_font() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(skeleton_dir.path().join("font.woff2"), "not a font").unwrap();
+		let tag: FontTag =
+			serde_json::from_str(r#"{ "fonts": [{ "name": "F", "path": "font.woff2" }] }"#)
+				.unwrap();
+		let context = DecodingContext::new_at_root(skeleton_dir.path());
+
+		match tag.font_embed_text(&context) {
+			Err(ClgnDecodingError::Font { msg }) => assert!(msg.contains("font.woff2")),
+			result => panic!("expected a Font error, got {:?}", result),
+		}
+	}
+
+	#[test]
+	fn google_fonts() {
+		let cache_dir = tempfile::tempdir().unwrap();
+		std::fs::create_dir(cache_dir.path().join("Inter")).unwrap();
+		std::fs::write(cache_dir.path().join("Inter").join("700.woff2"), "wOF2").unwrap();
+		let tag: FontTag = serde_json::from_str(
+			r#"{ "fonts": [{ "google": "Inter", "name": "Body", "weights": [700] }] }"#,
+		)
+		.unwrap();
+		let context = DecodingContext::new_empty();
+
+		assert!(matches!(
+			tag.font_embed_text(&context),
+			Err(ClgnDecodingError::Font { .. })
+		));
+
+		context.set_font_cache_dir(cache_dir.path());
+		assert_eq!(
+			tag.font_embed_text(&context).unwrap(),
+			"<style>@font-face{font-family:Body;src:url('data:font/woff2;charset=utf-8;\
+			 base64,d09GMg==') format('woff2');font-weight:700;}</style>"
+		);
+
+		let tag: FontTag =
+			serde_json::from_str(r#"{ "fonts": [{ "google": "Inter", "weights": [400] }] }"#)
+				.unwrap();
+		match tag.font_embed_text(&context) {
+			Err(ClgnDecodingError::Font { msg }) => assert!(msg.contains("400.woff2")),
+			result => panic!("expected a Font error, got {:?}", result),
+		}
 
-	pub(super) fn should_escape_text(&self) -> bool {
-		false
+		// The family can't name anything but a folder directly inside the cache
+		for family in [
+			"",
+			".",
+			"..",
+			"../Inter",
+			"Inter/..",
+			"Inter/",
+			"/Inter",
+			r"..\Inter",
+		] {
+			let tag: FontTag = serde_json::from_value(serde_json::json!({
+				"fonts": [{ "google": family, "weights": [700] }]
+			}))
+			.unwrap();
+			match tag.font_embed_text(&context) {
+				Err(ClgnDecodingError::Font { msg }) => assert!(msg.contains("invalid"), "{}", msg),
+				result => panic!("expected a Font error for {:?}, got {:?}", family, result),
+			}
+		}
+
+		for json in [
+			r#"{ "fonts": [{ "google": "Inter", "path": "inter.woff2" }] }"#,
+			r#"{ "fonts": [{ "name": "F", "path": "f.woff2", "weights": [400] }] }"#,
+		] {
+			assert!(serde_json::from_str::<FontTag>(json).is_err(), "{}", json);
+		}
 	}
 }
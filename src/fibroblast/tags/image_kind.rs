@@ -0,0 +1,116 @@
+//! The kinds of image that can be embedded, i.e., the `{TYPE}`s that may appear in a data
+//! URI `data:image/{TYPE};base64,...`. Browsers refuse to display an image whose media
+//! type they don't recognize, and they don't forgive typos: `image/jpg`, `image/jpeg `,
+//! and `image/jpeg2000` are all rejected. So an image's `kind` must be one of the
+//! media types below (compared case-insensitively), or one of their aliases, such as
+//! `jpg`, which are replaced with the media type they stand for.
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+
+/// Each media type that may be embedded, with its aliases
+const IMAGE_KINDS: [(&str, &[&str]); 9] = [
+	("png", &[]),
+	("jpeg", &["jpg", "jpe"]),
+	("gif", &[]),
+	("webp", &[]),
+	("avif", &[]),
+	("bmp", &[]),
+	("svg+xml", &["svg"]),
+	("x-icon", &["ico"]),
+	("tiff", &["tif"]),
+];
+
+/// The kind of an image, as the subtype of its media type (e.g., `jpeg` for
+/// `image/jpeg`). (De)serialized as a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ImageKind(&'static str);
+
+impl ImageKind {
+	pub(crate) const PNG: ImageKind = ImageKind("png");
+
+	pub(crate) fn media_subtype(self) -> &'static str {
+		self.0
+	}
+
+	/// The kinds that may be given, for use in error messages, e.g., `png, jpeg (or jpg,
+	/// jpe), ...`
+	fn accepted_kinds() -> String {
+		IMAGE_KINDS
+			.iter()
+			.map(|(kind, aliases)| {
+				if aliases.is_empty() {
+					(*kind).to_owned()
+				} else {
+					format!("{} (or {})", kind, aliases.join(", "))
+				}
+			})
+			.collect::<Vec<_>>()
+			.join(", ")
+	}
+}
+
+impl FromStr for ImageKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let lowercased = s.to_ascii_lowercase();
+		IMAGE_KINDS
+			.iter()
+			.find(|(kind, aliases)| *kind == lowercased || aliases.contains(&&*lowercased))
+			.map(|(kind, _)| ImageKind(kind))
+			.ok_or_else(|| {
+				format!(
+					"unknown image kind {:?}; expected one of {}",
+					s,
+					Self::accepted_kinds()
+				)
+			})
+	}
+}
+
+impl Serialize for ImageKind {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serializer.serialize_str(self.0)
+	}
+}
+
+impl<'de> Deserialize<'de> for ImageKind {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		String::deserialize(deserializer)?
+			.parse()
+			.map_err(de::Error::custom)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn image_kinds() {
+		let kind = |s: &str| s.parse::<ImageKind>().map(ImageKind::media_subtype);
+		assert_eq!(kind("png"), Ok("png"));
+		assert_eq!(kind("jpg"), Ok("jpeg"));
+		assert_eq!(kind("JPEG"), Ok("jpeg"));
+		assert_eq!(kind("svg"), Ok("svg+xml"));
+		assert_eq!(kind("ico"), Ok("x-icon"));
+
+		for bad in vec!["jpg ", "jpeg2000", "image/png", ""].into_iter() {
+			let msg = kind(bad).unwrap_err();
+			assert!(msg.contains(&format!("{:?}", bad)), "{}", msg);
+			assert!(msg.contains("png, jpeg (or jpg, jpe), gif"), "{}", msg);
+		}
+
+		// Aliases are normalized when (de)serializing
+		let kind: ImageKind = serde_json::from_str(r#""jpg""#).unwrap();
+		assert_eq!(serde_json::to_string(&kind).unwrap(), r#""jpeg""#);
+		assert!(serde_json::from_str::<ImageKind>(r#""jpeg2000""#).is_err());
+	}
+}
@@ -1,9 +1,32 @@
+use super::animation::Animation;
 use super::any_child_tag::AnyChildTag;
+use super::clip::{ClipKind, ClipShape};
 use super::common_tag_fields::CommonTagFields;
-use crate::fibroblast::data_types::{DecodingContext, SimpleValue, TagVariables, XmlAttrs};
+use super::image_kind::ImageKind;
+use super::link::Link;
+use crate::fibroblast::data_types::{
+	AttrKVValueVec, DecodingContext, SimpleValue, TagVariables, XmlAttrs,
+};
+use crate::to_svg::render_timings::RenderPhase;
 use crate::to_svg::svg_writable::{ClgnDecodingError, ClgnDecodingResult};
+use crate::to_svg::xml_text::{self, XmlText};
+use crate::utils::color_profile;
+use image::{GenericImageView, ImageOutputFormat};
 use serde::{Deserialize, Serialize};
-use std::{borrow::Cow, path::PathBuf};
+use std::{io, path::PathBuf};
+
+/// The size of a placeholder for a missing image whose `width` or `height` isn't given
+const DEFAULT_PLACEHOLDER_SIZE: f64 = 100.0;
+
+/// A rectangular region of an image, in pixels, whose top left corner is `(x, y)`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Crop {
+	x: u32,
+	y: u32,
+	w: u32,
+	h: u32,
+}
 
 /// A tag for handling images on disk. Collagen handles images specially, so we need a
 /// separate type for their tags. `ImageTag`s look more or less like the following:
@@ -47,7 +70,54 @@ use std::{borrow::Cow, path::PathBuf};
 ///   - Description: The "kind" of the image, e.g., "jpeg", "png", etc; usually
 ///     synonymous with file extension. If omitted, will be inferred from the file
 ///     extension of `image_path`. (An error will be raised if this inference is not
-///     possible, for instance if the image file lacks )
+///     possible, for instance if the image file lacks an extension.) The kind must be
+///     one of `png`, `jpeg`, `gif`, `webp`, `avif`, `bmp`, `svg+xml`, `x-icon`, or
+///     `tiff`, ignoring case, or one of the aliases `jpg`, `jpe`, `svg`, `ico`, or
+///     `tif`, which are replaced with the kind they stand for; anything else is an
+///     error, as browsers won't display it.
+/// - `crop`
+///   - Type: object with numeric keys `x`, `y`, `w`, and `h`
+///   - Required: No.
+///   - Description: The region of the image to embed, in pixels, whose top left corner
+///     is `(x, y)` and whose width and height are `w` and `h`. This allows many images
+///     to be sliced out of a single sprite sheet. The cropped image is embedded as a
+///     PNG, regardless of `kind`. Only raster images (PNG, JPEG, GIF, and BMP) can be
+///     cropped, and the region must lie entirely within the image. When run from the
+///     command line, cropped images are cached (see `clgn cache`), so an image is only
+///     cropped once.
+///
+/// If `clgn` is run with `--srgb`, a PNG or JPEG image with an embedded color profile
+/// (such as the Display P3 profile of many phones' photos) is converted to sRGB before
+/// it's embedded, so that its colors are the same in every viewer, including those that
+/// ignore color profiles. A converted PNG is embedded as an 8-bit PNG, and a converted
+/// JPEG is re-encoded as a JPEG. Images without a profile, or whose profile is already
+/// sRGB, are embedded as-is.
+///
+/// Normally it's an error for the file at `image_path` not to exist. But if `clgn` is
+/// run with `--placeholder-missing-assets`, a missing image is instead replaced with a
+/// placeholder: a dashed box, the size of the image's `width` and `height` (if given),
+/// labeled with `image_path`.
+/// - `alt`
+///   - Type: string
+///   - Required: No.
+///   - Description: A text alternative to the image, for those who can't see it. It's
+///     written as the `<image>`'s `aria-label`, which screen readers read out, and as
+///     its `<desc>`, for viewers that don't support `aria-label`. (So `attrs` may not
+///     contain `aria-label` if `alt` is given.)
+/// - `title`
+///   - Type: string
+///   - Required: No.
+///   - Description: A title for the image, which is written as the `<image>`'s
+///     `<title>`. Viewers show it as a tooltip, and screen readers read it out if
+///     there's no `alt`.
+///
+/// - `credit`
+///   - Type: string
+///   - Required: No. Missing is equivalent to `image_path`.
+///   - Description: The attribution of the image, e.g., `"Photo by Ann Lee (CC BY
+///     4.0)"`, which is listed in the root's `credits`, if it has any.
+///
+/// Variable substitution is performed on `alt`, `title`, and `credit`.
 /// - Other: `ImageTag` accepts all properties in [`CommonTagFields`].
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ImageTag<'a> {
@@ -56,38 +126,53 @@ pub struct ImageTag<'a> {
 
 	/// The image "kind" (usually synonymous with file extension). If `None`, will be
 	/// set to the file extension of `image_path`
-	#[serde(default)]
-	kind: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	kind: Option<ImageKind>,
+
+	/// The region of the image to embed. If `None`, the whole image is embedded
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	crop: Option<Crop>,
+
+	/// A text alternative to the image
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	alt: Option<String>,
+
+	/// The image's title, shown as a tooltip
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	title: Option<String>,
+
+	/// The attribution of the image. If `None`, the image is credited by its path
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	credit: Option<String>,
 
 	#[serde(flatten)]
 	common_tag_fields: CommonTagFields<'a>,
 }
 
 impl<'a> ImageTag<'a> {
-	/// The kind of the image (e.g., `"jpg"`, `"png"`). This corresponds to the `{TYPE}`
-	/// in the data URI `data:image/{TYPE};base64,...`. If `self.kind.is_none()`, the
-	/// `kind` will be inferred from the (lowercased) file extension of `image_path`.
-	pub(crate) fn kind(&'a self) -> Option<Cow<'a, str>> {
-		match &self.kind {
-			Some(kind) => Some(Cow::Borrowed(kind)),
-			None => {
-				let path = PathBuf::from(&self.image_path);
-				let extn = path.extension()?.to_str()?.to_ascii_lowercase();
-				Some(Cow::Owned(extn))
-			}
+	pub(crate) fn fresh_clone<'b>(&self) -> ImageTag<'b> {
+		ImageTag {
+			image_path: self.image_path.clone(),
+			kind: self.kind,
+			crop: self.crop,
+			alt: self.alt.clone(),
+			title: self.title.clone(),
+			credit: self.credit.clone(),
+			common_tag_fields: self.common_tag_fields.fresh_clone(),
 		}
 	}
 
-	/// Get the key-value pair (as a tuple) that makes the image actually work! (E.g.,
-	/// the tuple `("href", "data:image/jpeg;base64,...")`)
-	pub(super) fn get_image_attr_pair(
-		&'a self,
-		context: &DecodingContext,
-	) -> ClgnDecodingResult<(&'a str, SimpleValue)> {
-		let key = "href";
+	/// The kind of the image (e.g., `"jpeg"`, `"png"`). This corresponds to the `{TYPE}`
+	/// in the data URI `data:image/{TYPE};base64,...`. If `self.kind.is_none()`, the
+	/// `kind` will be inferred from the file extension of `image_path`.
+	pub(crate) fn kind(&self) -> ClgnDecodingResult<ImageKind> {
+		if let Some(kind) = self.kind {
+			return Ok(kind);
+		}
 
-		let kind = match self.kind() {
-			Some(kind) => kind,
+		let path = PathBuf::from(&self.image_path);
+		let extn = match path.extension().and_then(|extn| extn.to_str()) {
+			Some(extn) => extn,
 			None => {
 				return Err(ClgnDecodingError::Image {
 					msg: format!(
@@ -98,24 +183,223 @@ impl<'a> ImageTag<'a> {
 			}
 		};
 
+		extn.parse().map_err(|msg| ClgnDecodingError::Image {
+			msg: format!(
+				r#"Could not deduce the kind of {:?} from its extension, and no "kind" was given: {}"#,
+				self.image_path, msg
+			),
+		})
+	}
+
+	/// The path to the image, resolved against the skeleton that contains this tag
+	pub(super) fn abs_image_path(&self, context: &DecodingContext) -> ClgnDecodingResult<PathBuf> {
+		crate::utils::paths::pathsep_aware_join(&*context.get_root(), &self.image_path)
+	}
+
+	/// Get the key-value pair (as a tuple) that makes the image actually work! (E.g.,
+	/// the tuple `("href", "data:image/jpeg;base64,...")`)
+	///
+	/// `attrs` are the tag's (substituted) attributes, whose `width` and `height` give
+	/// the size of the placeholder used if the image is missing and placeholders are
+	/// enabled.
+	pub(super) fn get_image_attr_pair(
+		&'a self,
+		context: &DecodingContext,
+		attrs: &AttrKVValueVec,
+	) -> ClgnDecodingResult<(&'a str, SimpleValue)> {
+		let key = "href";
+
+		let kind = self.kind()?;
+
 		// I'd like to find the "right" way to reduce memory usage here. We're reading a
 		// file into memory and then storing its b64 string also in memory. That's
 		// O(2*n). Ideally none of this would reside in memory, and we'd stream directly
 		// to the output SVG. An intermediate step would be to stream the file into the
 		// b64 encoder, getting memory usage down to O(1*n).
 
-		let abs_image_path =
-			crate::utils::paths::pathsep_aware_join(&*context.get_root(), &self.image_path)?;
+		let abs_image_path = self.abs_image_path(context)?;
 
-		let b64_string = base64::encode(
-			std::fs::read(abs_image_path.as_path())
-				.map_err(|e| ClgnDecodingError::Io(e, abs_image_path))?,
-		);
-		let src_str = format!("data:image/{};base64,{}", kind, b64_string);
+		let files = context.file_source();
+		let bytes = match context.time_phase(RenderPhase::AssetIo, || files.read(&abs_image_path)) {
+			Ok(bytes) => bytes,
+			Err(e)
+				if e.kind() == io::ErrorKind::NotFound && context.placeholder_missing_assets() =>
+			{
+				let src_str = format!(
+					"data:image/svg+xml;base64,{}",
+					base64::encode(self.placeholder_svg(attrs))
+				);
+				return Ok((key, SimpleValue::Text(src_str)));
+			}
+			Err(e) => return Err(files.read_error(e, abs_image_path)),
+		};
+		let (kind, bytes) = if context.convert_to_srgb() {
+			match self.convert_to_srgb(context, &bytes)? {
+				Some((srgb_kind, srgb_bytes)) => (srgb_kind, srgb_bytes),
+				None => (kind, bytes),
+			}
+		} else {
+			(kind, bytes)
+		};
+		let (kind, bytes) = match self.crop {
+			None => (kind, bytes),
+			Some(crop) => {
+				let cropped = match context.asset_cache() {
+					Some(cache) => {
+						let region = [crop.x, crop.y, crop.w, crop.h].map(u32::to_le_bytes);
+						cache.get_or_insert_with("crop", &[&bytes, &region.concat()], || {
+							self.crop_image(&bytes, crop)
+						})?
+					}
+					None => self.crop_image(&bytes, crop)?,
+				};
+				(ImageKind::PNG, cropped)
+			}
+		};
+
+		let b64_string = context.time_phase(RenderPhase::Base64, || base64::encode(bytes));
+		let src_str = format!("data:image/{};base64,{}", kind.media_subtype(), b64_string);
 
 		Ok((key, SimpleValue::Text(src_str)))
 	}
 
+	/// The `aria-label` that gives the image's `alt`, if it has one
+	pub(super) fn get_aria_label_attr_pair(
+		&self,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<Option<(&'static str, SimpleValue)>> {
+		let alt = match &self.alt {
+			Some(alt) => alt,
+			None => return Ok(None),
+		};
+		if self.base_attrs().contains_key("aria-label") {
+			return Err(ClgnDecodingError::Image {
+				msg: format!(
+					"The image {:?} has an `alt`, so it may not set `aria-label` itself",
+					self.image_path
+				),
+			});
+		}
+
+		let alt = context.sub_vars_into_str(alt)?.into_owned();
+		Ok(Some(("aria-label", SimpleValue::Text(alt))))
+	}
+
+	/// The `<title>` and `<desc>` given by the image's `title` and `alt`, respectively
+	pub(super) fn descriptions(
+		&self,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<Vec<(&'static str, String)>> {
+		vec![("title", &self.title), ("desc", &self.alt)]
+			.into_iter()
+			.filter_map(|(name, text)| text.as_ref().map(|text| (name, text)))
+			.map(|(name, text)| Ok((name, context.sub_vars_into_str(text)?.into_owned())))
+			.collect()
+	}
+
+	/// The image's credit, for the root's `credits`
+	pub(super) fn credit(&self, context: &DecodingContext) -> ClgnDecodingResult<String> {
+		Ok(match &self.credit {
+			Some(credit) => context.sub_vars_into_str(credit)?.into_owned(),
+			None => self.image_path.clone(),
+		})
+	}
+
+	/// A standalone SVG document to stand in for this image, which is missing. It's the
+	/// size given by `attrs`' `width` and `height`, and consists of a dashed box with
+	/// `image_path` written in it.
+	fn placeholder_svg(&self, attrs: &AttrKVValueVec) -> String {
+		let size = |name: &str| {
+			attrs
+				.iter()
+				.find(|(k, _)| *k == name)
+				.and_then(|(_, v)| v.to_number())
+				.filter(|x| *x > 0.0)
+				.unwrap_or(DEFAULT_PLACEHOLDER_SIZE)
+		};
+		let (width, height) = (size("width"), size("height"));
+
+		let label = xml_text::escape(&self.image_path);
+		format!(
+			r##"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}"><rect width="{w}" height="{h}" fill="#eeeeee" stroke="#ff0000" stroke-width="2" stroke-dasharray="6 4"/><text x="{cx}" y="{cy}" text-anchor="middle" dominant-baseline="middle" font-family="sans-serif" font-size="{font_size}" fill="#ff0000">Missing: {label}</text></svg>"##,
+			w = width,
+			h = height,
+			cx = width / 2.0,
+			cy = height / 2.0,
+			font_size = (height / 8.0).min(16.0),
+			label = label,
+		)
+	}
+
+	/// If the image whose contents are `bytes` has an embedded color profile other than
+	/// sRGB, the image converted to sRGB, as the pair (kind, contents). Conversions are
+	/// cached in `context`'s asset cache, if it has one.
+	fn convert_to_srgb(
+		&self,
+		context: &DecodingContext,
+		bytes: &[u8],
+	) -> ClgnDecodingResult<Option<(ImageKind, Vec<u8>)>> {
+		let image_error = |msg: String| ClgnDecodingError::Image {
+			msg: format!("Could not convert {:?} to sRGB: {}", self.image_path, msg),
+		};
+		let convert = || {
+			color_profile::convert_to_srgb(bytes)
+				.map(|converted| converted.map(|(_, bytes)| bytes))
+				.map_err(image_error)
+		};
+
+		let converted = match context.asset_cache() {
+			Some(cache) => {
+				// An empty entry means there was nothing to convert
+				let converted = cache.get_or_insert_with("srgb", &[bytes], || {
+					convert().map(Option::unwrap_or_default)
+				})?;
+				Some(converted).filter(|converted| !converted.is_empty())
+			}
+			None => convert()?,
+		};
+		let converted = match converted {
+			Some(converted) => converted,
+			None => return Ok(None),
+		};
+
+		let kind = color_profile::converted_kind(bytes)
+			.parse()
+			.map_err(image_error)?;
+
+		Ok(Some((kind, converted)))
+	}
+
+	/// Crop the image whose contents are `bytes` to the region `crop`, returning the
+	/// contents of the cropped image as a PNG
+	fn crop_image(&self, bytes: &[u8], crop: Crop) -> ClgnDecodingResult<Vec<u8>> {
+		let image_error = |msg: String| ClgnDecodingError::Image {
+			msg: format!("Could not crop {:?}: {}", self.image_path, msg),
+		};
+
+		let image = image::load_from_memory(bytes).map_err(|e| image_error(e.to_string()))?;
+		let (width, height) = image.dimensions();
+		let Crop { x, y, w, h } = crop;
+		let fits = |start: u32, len: u32, size: u32| {
+			len > 0 && start.checked_add(len).map_or(false, |end| end <= size)
+		};
+		if !(fits(x, w, width) && fits(y, h, height)) {
+			return Err(image_error(format!(
+				"the region with top left corner ({}, {}), width {}, and height {} is not \
+				 a non-empty region of the {}x{} image",
+				x, y, w, h, width, height
+			)));
+		}
+
+		let mut cropped = Vec::new();
+		image
+			.crop_imm(x, y, w, h)
+			.write_to(&mut cropped, ImageOutputFormat::Png)
+			.map_err(|e| image_error(e.to_string()))?;
+
+		Ok(cropped)
+	}
+
 	pub(super) fn tag_name(&self) -> &str {
 		"image"
 	}
@@ -128,15 +412,225 @@ impl<'a> ImageTag<'a> {
 		self.common_tag_fields.base_attrs()
 	}
 
+	pub(super) fn base_style(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_style()
+	}
+
+	pub(super) fn base_class_styles(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_class_styles()
+	}
+
 	pub(super) fn base_children(&self) -> &[AnyChildTag<'a>] {
 		self.common_tag_fields.base_children()
 	}
 
-	pub(super) fn base_text(&self) -> &str {
-		self.common_tag_fields.base_text()
+	pub(super) fn text(&self, context: &DecodingContext) -> ClgnDecodingResult<XmlText<'_>> {
+		self.common_tag_fields.text(context)
+	}
+
+	pub(super) fn z(&self) -> f64 {
+		self.common_tag_fields.z()
+	}
+
+	pub(super) fn base_animations(&self) -> &[Animation] {
+		self.common_tag_fields.base_animations()
+	}
+
+	pub(super) fn base_clips(&self) -> Vec<(ClipKind, &ClipShape)> {
+		self.common_tag_fields.base_clips()
+	}
+
+	pub(super) fn base_link(&self) -> Option<&Link> {
+		self.common_tag_fields.base_link()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fibroblast::data_types::ConcreteNumber;
+	use crate::utils::cache::AssetCache;
+	use std::borrow::Cow;
+
+	#[test]
+	fn placeholder_missing_assets() {
+		let tag: ImageTag =
+			serde_json::from_str(r#"{ "image_path": "missing & gone.png" }"#).unwrap();
+		let context = DecodingContext::new_at_root(".");
+
+		let mut attrs = AttrKVValueVec::default();
+		attrs.push((
+			"width",
+			Cow::Owned(SimpleValue::Number(ConcreteNumber::UInt(200))),
+		));
+		attrs.push(("height", Cow::Owned(SimpleValue::Text("40".to_owned()))));
+
+		assert!(matches!(
+			tag.get_image_attr_pair(&context, &attrs),
+			Err(ClgnDecodingError::MissingPath { .. })
+		));
+
+		context.set_placeholder_missing_assets(true);
+		let (key, value) = tag.get_image_attr_pair(&context, &attrs).unwrap();
+		assert_eq!(key, "href");
+
+		let href = value.to_maybe_string().unwrap();
+		let b64 = href.strip_prefix("data:image/svg+xml;base64,").unwrap();
+		let svg = String::from_utf8(base64::decode(b64).unwrap()).unwrap();
+		assert!(svg.contains(r#"width="200" height="40" viewBox="0 0 200 40""#));
+		assert!(svg.contains("Missing: missing &amp; gone.png"));
+	}
+
+	#[test]
+	fn kinds() {
+		let context = DecodingContext::new_at_root("tests/examples/sprite-sheet/skeleton");
+		let attrs = AttrKVValueVec::default();
+		let href = |json: &str| {
+			let tag: ImageTag = serde_json::from_str(json).unwrap();
+			tag.get_image_attr_pair(&context, &attrs)
+				.map(|(_, value)| value.to_maybe_string().unwrap().into_owned())
+		};
+
+		assert!(href(r#"{ "image_path": "sprites.png" }"#)
+			.unwrap()
+			.starts_with("data:image/png;base64,"));
+		assert!(href(r#"{ "image_path": "sprites.png", "kind": "JPG" }"#)
+			.unwrap()
+			.starts_with("data:image/jpeg;base64,"));
+		assert!(serde_json::from_str::<ImageTag>(
+			r#"{ "image_path": "sprites.png", "kind": "jpg " }"#
+		)
+		.is_err());
+
+		// Kinds inferred from the extension must be valid too
+		for image_path in vec!["sprites", "sprites.jpeg2000"].into_iter() {
+			let json = format!(r#"{{ "image_path": {:?} }}"#, image_path);
+			assert!(
+				matches!(href(&json), Err(ClgnDecodingError::Image { .. })),
+				"{}",
+				image_path
+			);
+		}
+	}
+
+	#[test]
+	fn cached_crop() {
+		let tag: ImageTag = serde_json::from_str(
+			r#"{ "image_path": "sprites.png", "crop": { "x": 3, "y": 0, "w": 1, "h": 2 } }"#,
+		)
+		.unwrap();
+		let context = DecodingContext::new_at_root("tests/examples/sprite-sheet/skeleton");
+		let attrs = AttrKVValueVec::default();
+		let href = || {
+			let (_, value) = tag.get_image_attr_pair(&context, &attrs).unwrap();
+			value.to_maybe_string().unwrap().into_owned()
+		};
+
+		let uncached = href();
+
+		let cache_dir = tempfile::tempdir().unwrap();
+		context.set_asset_cache(Some(AssetCache::new(cache_dir.path())));
+		assert_eq!(href(), uncached);
+		let entries = std::fs::read_dir(cache_dir.path().join("crop"))
+			.unwrap()
+			.collect::<Result<Vec<_>, _>>()
+			.unwrap();
+		assert_eq!(entries.len(), 1);
+
+		// Later crops are read from the cache
+		std::fs::write(entries[0].path(), b"cached").unwrap();
+		assert_eq!(
+			href(),
+			format!("data:image/png;base64,{}", base64::encode(b"cached"))
+		);
+	}
+
+	#[test]
+	fn srgb() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("swapped.png"),
+			color_profile::tests::png_with_profile(
+				&[[255, 0, 0, 255]],
+				&color_profile::tests::swapped_profile(),
+			),
+		)
+		.unwrap();
+		let tag: ImageTag = serde_json::from_str(r#"{ "image_path": "swapped.png" }"#).unwrap();
+		let context = DecodingContext::new_at_root(skeleton_dir.path());
+		let attrs = AttrKVValueVec::default();
+		let pixel = || {
+			let (_, value) = tag.get_image_attr_pair(&context, &attrs).unwrap();
+			let href = value.to_maybe_string().unwrap().into_owned();
+			let b64 = href.strip_prefix("data:image/png;base64,").unwrap();
+			let image = image::load_from_memory(&base64::decode(b64).unwrap()).unwrap();
+			image.get_pixel(0, 0).0
+		};
+
+		// The profile is only applied when asked
+		assert_eq!(pixel(), [255, 0, 0, 255]);
+		context.set_convert_to_srgb(true);
+		assert_eq!(pixel(), [0, 255, 0, 255]);
+
+		let cache_dir = tempfile::tempdir().unwrap();
+		context.set_asset_cache(Some(AssetCache::new(cache_dir.path())));
+		assert_eq!(pixel(), [0, 255, 0, 255]);
+		assert_eq!(pixel(), [0, 255, 0, 255]);
+		assert_eq!(
+			std::fs::read_dir(cache_dir.path().join("srgb"))
+				.unwrap()
+				.count(),
+			1
+		);
 	}
+	#[test]
+	fn alt_and_title() {
+		use crate::fibroblast::Fibroblast;
+
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(skeleton_dir.path().join("dot.svg"), "<svg/>").unwrap();
+		let render = |image: &str| -> ClgnDecodingResult<String> {
+			std::fs::write(
+				skeleton_dir.path().join("collagen.json"),
+				format!(
+					r#"{{ "vars": {{ "who": "Ann & Bo" }}, "children": [{}] }}"#,
+					image
+				),
+			)
+			.unwrap();
+			Fibroblast::from_dir(skeleton_dir.path())?.to_svg_string()
+		};
+		let href = format!("data:image/svg+xml;base64,{}", base64::encode("<svg/>"));
+
+		assert_eq!(
+			render(r#"{ "image_path": "dot.svg", "alt": "A photo of {who}", "title": "{who}" }"#)
+				.unwrap(),
+			format!(
+				r#"<svg xmlns="http://www.w3.org/2000/svg"><image href="{}" aria-label="A photo of Ann &amp; Bo"><title>Ann &amp; Bo</title><desc>A photo of Ann &amp; Bo</desc></image></svg>"#,
+				href
+			)
+		);
+		assert_eq!(
+			render(r#"{ "image_path": "dot.svg", "title": "Dot" }"#).unwrap(),
+			format!(
+				r#"<svg xmlns="http://www.w3.org/2000/svg"><image href="{}"><title>Dot</title></image></svg>"#,
+				href
+			)
+		);
+
+		let snapshot = Fibroblast::from_dir(skeleton_dir.path())
+			.unwrap()
+			.snapshot()
+			.unwrap();
+		let image = &snapshot.children[0];
+		assert_eq!(image.children[0].tag, "title");
+		assert_eq!(image.children[0].text, "Dot");
 
-	pub(super) fn should_escape_text(&self) -> bool {
-		self.common_tag_fields.should_escape_text()
+		assert!(matches!(
+			render(
+				r#"{ "image_path": "dot.svg", "alt": "Dot", "attrs": { "aria-label": "Dot" } }"#
+			),
+			Err(ClgnDecodingError::Image { .. })
+		));
 	}
 }
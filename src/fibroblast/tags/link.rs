@@ -0,0 +1,285 @@
+//! The `link` field of a tag, which makes the tag a hyperlink by wrapping it in an `<a>`,
+//! without having to restructure the skeleton to make the tag the `<a>`'s child. The
+//! link's `title` is written as the `<a>`'s `<title>`, which viewers show as a tooltip
+//! and screen readers read out.
+//!
+//! Thin or small elements, such as lines and icons, are hard to click. So a link may
+//! also be given a `hit_padding`, in which case an invisible rectangle, the size of the
+//! tag plus that much padding on each side, is written in the `<a>` before the tag; the
+//! rectangle catches clicks that just miss the tag. The size of the tag is determined
+//! from its attributes, as for the endpoints of
+//! [`ConnectorTag`](super::ConnectorTag)s, so only some tags (such as `<rect>`,
+//! `<circle>`, and `<line>`) support it.
+
+use super::{AttrKVValueVec, ClgnDecodingResult, DecodingContext, TagLike};
+use crate::fibroblast::data_types::Anchor;
+use crate::fibroblast::ElementSnapshot;
+use crate::to_svg::svg_writable::ClgnDecodingError;
+use crate::to_svg::target::tag_features;
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event as XmlEvent};
+use quick_xml::Writer as XmlWriter;
+use serde::{Deserialize, Serialize};
+
+/// A hyperlink, given in a tag's `link` field.
+///
+/// # Properties
+///
+/// - `href`
+///   - Type: string
+///   - Required: Yes.
+///   - Description: The URL that the link points to.
+/// - `title`
+///   - Type: string
+///   - Required: No.
+///   - Description: A description of the link, which is written as the `<a>`'s
+///     `<title>`.
+/// - `target`
+///   - Type: string
+///   - Required: No.
+///   - Description: Where to open the link, e.g., `"_blank"` for a new tab or window.
+///     A link opened in a new tab is also given `rel="noopener noreferrer"`, so that
+///     the linked page can't get at the page the SVG is in.
+/// - `hit_padding`
+///   - Type: number
+///   - Required: No.
+///   - Description: How far beyond the tag's edges, on each side, clicks should follow
+///     the link. If given, an invisible rectangle, that much larger than the tag, is
+///     written underneath the tag. It's an error if the tag's size can't be determined
+///     from its attributes.
+///
+/// Variable substitution is performed on `href`, `title`, and `target`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Link {
+	href: String,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	title: Option<String>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	target: Option<String>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	hit_padding: Option<f64>,
+}
+
+/// A link whose variables have been substituted and whose hit area has been computed,
+/// ready to be written
+#[derive(Debug)]
+pub(crate) struct ResolvedLink {
+	/// The attributes of the `<a>`
+	attrs: Vec<(&'static str, String)>,
+	title: Option<String>,
+	/// The attributes of the invisible `<rect>` that enlarges the hit area, if any
+	hit_area: Option<Vec<(&'static str, String)>>,
+}
+
+/// Resolve the link of `tag`, whose (substituted) attributes are `attrs`, if it has one
+pub(crate) fn resolve_tag_link<'a, T: TagLike<'a> + ?Sized>(
+	tag: &T,
+	attrs: &AttrKVValueVec,
+	context: &DecodingContext,
+) -> ClgnDecodingResult<Option<ResolvedLink>> {
+	match tag.link() {
+		Some(_) if tag.is_root() => Err(ClgnDecodingError::Link {
+			msg: "The root tag may not have a `link`".to_owned(),
+		}),
+		Some(link) => Ok(Some(resolve_link(link, tag.tag_name(), attrs, context)?)),
+		None => Ok(None),
+	}
+}
+
+/// Resolve `link`, the link of the tag named `tag_name` whose (substituted) attributes
+/// are `attrs`
+fn resolve_link(
+	link: &Link,
+	tag_name: &str,
+	attrs: &AttrKVValueVec,
+	context: &DecodingContext,
+) -> ClgnDecodingResult<ResolvedLink> {
+	let link_error = |msg: String| ClgnDecodingError::Link {
+		msg: format!("Invalid `link` on a `<{}>`: {}", tag_name, msg),
+	};
+
+	let sub =
+		|s: &str| -> ClgnDecodingResult<String> { Ok(context.sub_vars_into_str(s)?.into_owned()) };
+	let href = sub(&link.href)?;
+	let mut a_attrs = vec![("href", href.clone())];
+	if context.xlink_href() {
+		a_attrs.push(("xlink:href", href));
+	}
+	if let Some(target) = &link.target {
+		let target = sub(target)?;
+		if target == "_blank" {
+			a_attrs.push(("rel", "noopener noreferrer".to_owned()));
+		}
+		a_attrs.push(("target", target));
+	}
+	for feature in tag_features("a", a_attrs.iter().map(|(k, _)| *k)) {
+		context.note_feature(feature);
+	}
+
+	let title = link.title.as_deref().map(sub).transpose()?;
+
+	let hit_area = match link.hit_padding {
+		None => None,
+		Some(padding) if !(padding.is_finite() && padding >= 0.0) => {
+			return Err(link_error(format!(
+				"`hit_padding` must be a non-negative number, not {}",
+				padding
+			)))
+		}
+		Some(padding) => {
+			let anchor = Anchor::from_attrs(tag_name, attrs).ok_or_else(|| {
+				link_error(
+					"`hit_padding` was given, but the tag's size can't be determined from its \
+					 attributes"
+						.to_owned(),
+				)
+			})?;
+			let (rx, ry) = (anchor.rx + padding, anchor.ry + padding);
+			let mut rect_attrs = vec![
+				("x", (anchor.cx - rx).to_string()),
+				("y", (anchor.cy - ry).to_string()),
+				("width", (2.0 * rx).to_string()),
+				("height", (2.0 * ry).to_string()),
+				("fill", "none".to_owned()),
+				("pointer-events", "all".to_owned()),
+			];
+			// The rectangle has to be moved along with the tag
+			if let Some(transform) = attrs
+				.iter()
+				.find(|(k, _)| *k == "transform")
+				.and_then(|(_, v)| v.to_maybe_string())
+			{
+				rect_attrs.push(("transform", transform.into_owned()));
+			}
+			Some(rect_attrs)
+		}
+	};
+
+	Ok(ResolvedLink {
+		attrs: a_attrs,
+		title,
+		hit_area,
+	})
+}
+
+impl ResolvedLink {
+	/// Write the opening `<a>`, followed by its `<title>` and hit area, if any
+	pub(crate) fn write_start(
+		&self,
+		writer: &mut XmlWriter<impl std::io::Write>,
+	) -> ClgnDecodingResult<()> {
+		let mut a = BytesStart::borrowed_name(b"a");
+		a.extend_attributes(self.attrs.iter().map(|(k, v)| (*k, v.as_str())));
+		writer.write_event(XmlEvent::Start(a))?;
+
+		if let Some(title) = &self.title {
+			writer.write_event(XmlEvent::Start(BytesStart::borrowed_name(b"title")))?;
+			writer.write_event(XmlEvent::Text(BytesText::from_plain_str(title)))?;
+			writer.write_event(XmlEvent::End(BytesEnd::borrowed(b"title")))?;
+		}
+
+		if let Some(hit_area) = &self.hit_area {
+			let mut rect = BytesStart::borrowed_name(b"rect");
+			rect.extend_attributes(hit_area.iter().map(|(k, v)| (*k, v.as_str())));
+			writer.write_event(XmlEvent::Start(rect))?;
+			writer.write_event(XmlEvent::End(BytesEnd::borrowed(b"rect")))?;
+		}
+
+		Ok(())
+	}
+
+	/// The snapshot of the `<a>`, whose tag's snapshot is `elem`
+	pub(crate) fn snapshot(self, elem: ElementSnapshot) -> ElementSnapshot {
+		let snapshot = |tag: &str, attrs: Vec<(&str, String)>, text| ElementSnapshot {
+			tag: tag.to_owned(),
+			attrs: attrs.into_iter().map(|(k, v)| (k.to_owned(), v)).collect(),
+			text,
+			children: Vec::new(),
+		};
+
+		let mut children = Vec::new();
+		if let Some(title) = self.title {
+			children.push(snapshot("title", Vec::new(), title));
+		}
+		if let Some(hit_area) = self.hit_area {
+			children.push(snapshot("rect", hit_area, String::new()));
+		}
+		children.push(elem);
+
+		ElementSnapshot {
+			children,
+			..snapshot("a", self.attrs, String::new())
+		}
+	}
+
+	/// Write the closing `</a>`
+	pub(crate) fn write_end(
+		&self,
+		writer: &mut XmlWriter<impl std::io::Write>,
+	) -> ClgnDecodingResult<()> {
+		writer.write_event(XmlEvent::End(BytesEnd::borrowed(b"a")))?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fibroblast::data_types::{ConcreteNumber, SimpleValue};
+	use std::borrow::Cow;
+
+	fn resolve(
+		link: &str,
+		tag_name: &str,
+		attrs: &[(&'static str, f64)],
+	) -> ClgnDecodingResult<String> {
+		let link: Link = serde_json::from_str(link).unwrap();
+		let mut tag_attrs = AttrKVValueVec::default();
+		for &(k, v) in attrs {
+			tag_attrs.push((k, Cow::Owned(SimpleValue::Number(ConcreteNumber::Float(v)))));
+		}
+		let resolved = resolve_link(&link, tag_name, &tag_attrs, &DecodingContext::new_empty())?;
+
+		let mut writer = XmlWriter::new(Vec::new());
+		resolved.write_start(&mut writer)?;
+		resolved.write_end(&mut writer)?;
+		Ok(String::from_utf8(writer.into_inner()).unwrap())
+	}
+
+	#[test]
+	fn links() {
+		assert_eq!(
+			resolve(
+				r#"{ "href": "https://example.com/?a=1&b=2", "title": "A & B", "target": "_blank" }"#,
+				"path",
+				&[]
+			)
+			.unwrap(),
+			r#"<a href="https://example.com/?a=1&amp;b=2" rel="noopener noreferrer" target="_blank"><title>A &amp; B</title></a>"#
+		);
+		assert_eq!(
+			resolve(
+				r##"{ "href": "#top", "hit_padding": 5 }"##,
+				"circle",
+				&[("cx", 10.0), ("cy", 20.0), ("r", 2.0)]
+			)
+			.unwrap(),
+			r##"<a href="#top"><rect x="3" y="13" width="14" height="14" fill="none" pointer-events="all"></rect></a>"##
+		);
+
+		for (link, tag_name) in vec![
+			(r##"{ "href": "#top", "hit_padding": 5 }"##, "path"),
+			(r##"{ "href": "#top", "hit_padding": -1 }"##, "circle"),
+		]
+		.into_iter()
+		{
+			assert!(matches!(
+				resolve(link, tag_name, &[("r", 1.0)]),
+				Err(ClgnDecodingError::Link { .. })
+			));
+		}
+	}
+}
@@ -30,25 +30,46 @@
 //! - [`AnyChildTag`]: An enum wrapping any one of a number of distinct kinds of child
 //!   tags. See its docs for more info.
 
-pub(self) use super::data_types::{AttrKVValueVec, Map, TagVariables, XmlAttrs};
-pub(self) use crate::fibroblast::data_types::DecodingContext;
-pub(self) use crate::to_svg::svg_writable::ClgnDecodingResult;
+use super::data_types::{AttrKVValueVec, Map, TagVariables, XmlAttrs};
+use crate::fibroblast::data_types::DecodingContext;
+use crate::to_svg::svg_writable::ClgnDecodingResult;
+use crate::to_svg::xml_text::XmlText;
+pub(crate) use animation::Animation;
+use clip::{ClipKind, ClipShape};
 use lazy_static::lazy_static;
-use std::borrow::Cow;
+use link::Link;
+pub(super) mod animation;
 pub(super) mod any_child_tag;
+pub(super) mod bidi;
+pub(crate) mod clip;
 pub(super) mod common_tag_fields;
+pub(super) mod connector_tag;
 pub(super) mod container_tag;
+pub(crate) mod credits;
 pub(super) mod font_tag;
+pub(super) mod image_kind;
 pub(super) mod image_tag;
+pub(crate) mod link;
 pub(super) mod other_tag;
+pub(super) mod repeat_tag;
 pub(super) mod root_tag;
+pub(super) mod shape_tag;
+pub(super) mod text_wrap;
+pub(super) mod use_from_tag;
 pub use any_child_tag::AnyChildTag;
 pub use common_tag_fields::CommonTagFields;
+pub use connector_tag::ConnectorTag;
+pub(crate) use connector_tag::ARROWHEAD_MARKER_DEFS;
 pub use container_tag::ContainerTag;
+pub(crate) use container_tag::SiblingExports;
 pub use font_tag::FontTag;
+pub(crate) use image_kind::ImageKind;
 pub use image_tag::ImageTag;
 pub use other_tag::OtherTag;
+pub use repeat_tag::RepeatTag;
 pub use root_tag::RootTag;
+pub use shape_tag::ShapeTag;
+pub use use_from_tag::UseFromTag;
 
 lazy_static! {
 	/// The `BTreeMap` equivalent of `&[]`, which sadly only exists for `Vec`. Since
@@ -60,8 +81,36 @@ lazy_static! {
 
 pub(crate) trait TagLike<'a> {
 	fn tag_name(&self) -> &str;
-	fn vars(&'a self, context: &DecodingContext<'a>) -> ClgnDecodingResult<&TagVariables>;
+	fn vars(&'a self, context: &DecodingContext<'a>) -> ClgnDecodingResult<&'a TagVariables>;
 	fn attrs(&'a self, context: &DecodingContext<'a>) -> ClgnDecodingResult<AttrKVValueVec<'a>>;
-	fn text(&'a self, context: &DecodingContext<'a>) -> ClgnDecodingResult<Cow<'a, str>>;
-	fn should_escape_text(&self) -> bool;
+	fn text(&'a self, context: &DecodingContext<'a>) -> ClgnDecodingResult<XmlText<'a>>;
+
+	/// The animations to write as children of this tag
+	fn animations(&self) -> &[Animation] {
+		&[]
+	}
+
+	/// The shapes to clip and mask this tag to
+	fn clips(&self) -> Vec<(ClipKind, &ClipShape)> {
+		Vec::new()
+	}
+
+	/// The link to wrap this tag in, if any
+	fn link(&self) -> Option<&Link> {
+		None
+	}
+
+	/// The `<title>` and `<desc>` to write as this tag's first children, as pairs of
+	/// (tag name, text), with variables substituted
+	fn descriptions(
+		&self,
+		_context: &DecodingContext,
+	) -> ClgnDecodingResult<Vec<(&'static str, String)>> {
+		Ok(Vec::new())
+	}
+
+	/// Whether this tag is the document root
+	fn is_root(&self) -> bool {
+		false
+	}
 }
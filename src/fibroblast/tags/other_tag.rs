@@ -1,5 +1,17 @@
-use super::{common_tag_fields::CommonTagFields, AnyChildTag, TagVariables, XmlAttrs};
+use super::animation::Animation;
+use super::clip::{ClipKind, ClipShape};
+use super::link::Link;
+use super::{
+	bidi,
+	common_tag_fields::CommonTagFields,
+	text_wrap::{TextWrap, WritingMode},
+	AnyChildTag, AttrKVValueVec, ClgnDecodingResult, DecodingContext, TagVariables, XmlAttrs,
+};
+use crate::fibroblast::data_types::SimpleValue;
+use crate::to_svg::svg_writable::ClgnDecodingError;
+use crate::to_svg::xml_text::XmlText;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 
 /// `OtherTag` is a generic tag that doesn't need to be handled specially, such as
 /// `<rect>`, which needs no special. This is different from, say, `<image>`, which
@@ -15,17 +27,64 @@ use serde::{Deserialize, Serialize};
 ///   - Required: Yes.
 ///   - Description: The tag's name. For instance, to make a `<rect>` tag, use
 ///     `"tag_name": "rect"`.
+/// - `wrap`
+///   - Type: object, as documented in [`TextWrap`]
+///   - Required: No.
+///   - Description: Only allowed when `tag_name` is `"text"`. Breaks `text` into lines
+///     no wider than a given width, each of which becomes a `<tspan>`. For instance, `{
+///     "tag": "text", "text": "some long text", "wrap": { "width": 300 } }`.
+/// - `writing_mode`
+///   - Type: string, one of `"horizontal"`, `"vertical"`, or `"vertical-upright"`, as
+///     documented in [`WritingMode`]
+///   - Required: No.
+///   - Description: Only allowed when `tag_name` is `"text"`. The direction that the
+///     text's lines run in, e.g., `"vertical"` for a vertical CJK caption. Sets the
+///     `writing-mode` (and `text-orientation`) attributes, which the tag may then not
+///     set itself, and determines how `wrap` lays out lines.
 /// - Other: `OtherTag` accepts all properties in [`CommonTagFields`].
+///
+/// A `<text>` or `<tspan>` whose text is in a right-to-left script, such as Hebrew or
+/// Arabic, is given `direction="rtl"` unless it sets `direction` itself; see
+/// [`bidi`](super::bidi).
 #[derive(Serialize, Deserialize, Debug)]
 pub struct OtherTag<'a> {
 	#[serde(rename = "tag")]
 	tag_name: String,
 
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	wrap: Option<TextWrap>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	writing_mode: Option<WritingMode>,
+
 	#[serde(flatten)]
 	common_tag_fields: CommonTagFields<'a>,
 }
 
 impl<'a> OtherTag<'a> {
+	/// A `<g>` with the given variables, attributes, and children
+	pub(super) fn group(
+		vars: TagVariables,
+		attrs: XmlAttrs,
+		children: Vec<AnyChildTag<'a>>,
+	) -> Self {
+		OtherTag {
+			tag_name: "g".to_owned(),
+			wrap: None,
+			writing_mode: None,
+			common_tag_fields: CommonTagFields::new(vars, attrs, children),
+		}
+	}
+
+	pub(crate) fn fresh_clone<'b>(&self) -> OtherTag<'b> {
+		OtherTag {
+			tag_name: self.tag_name.clone(),
+			wrap: self.wrap.clone(),
+			writing_mode: self.writing_mode,
+			common_tag_fields: self.common_tag_fields.fresh_clone(),
+		}
+	}
+
 	pub(super) fn tag_name(&self) -> &str {
 		self.tag_name.as_ref()
 	}
@@ -38,15 +97,100 @@ impl<'a> OtherTag<'a> {
 		self.common_tag_fields.base_attrs()
 	}
 
+	pub(super) fn base_style(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_style()
+	}
+
+	pub(super) fn base_class_styles(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_class_styles()
+	}
+
 	pub(super) fn base_children(&self) -> &[AnyChildTag<'a>] {
 		self.common_tag_fields.base_children()
 	}
 
-	pub(super) fn base_text(&self) -> &str {
-		self.common_tag_fields.base_text()
+	/// The text of this tag, after variable substitution and, if `wrap` was given,
+	/// wrapping. `attrs` must be this tag's (substituted) attributes.
+	pub(super) fn text(
+		&'a self,
+		context: &DecodingContext<'a>,
+		attrs: impl FnOnce() -> ClgnDecodingResult<AttrKVValueVec<'a>>,
+	) -> ClgnDecodingResult<XmlText<'a>> {
+		let text = self.common_tag_fields.text(context)?;
+
+		match &self.wrap {
+			None => Ok(text),
+			Some(wrap) => {
+				self.require_text_tag("wrap")?;
+				Ok(XmlText::Markup(Cow::Owned(wrap.to_tspans(
+					&text,
+					&attrs()?,
+					self.writing_mode.unwrap_or_default(),
+				)?)))
+			}
+		}
+	}
+
+	/// Fail unless this is a `<text>`, which the field `field` may only be used on
+	fn require_text_tag(&self, field: &str) -> ClgnDecodingResult<()> {
+		if self.tag_name == "text" {
+			return Ok(());
+		}
+
+		Err(ClgnDecodingError::Text {
+			msg: format!(
+				"`{}` may only be used on `<text>` tags, but was used on a `<{}>`",
+				field, self.tag_name
+			),
+		})
+	}
+
+	/// The attributes generated for this tag, whose (substituted) attributes are
+	/// `attrs`: those that set its `writing_mode`, if any, and those needed for its text
+	/// to be displayed in the right direction (see [`bidi`])
+	pub(super) fn generated_attrs(
+		&self,
+		context: &DecodingContext,
+		attrs: &AttrKVValueVec,
+	) -> ClgnDecodingResult<Vec<(&'static str, SimpleValue)>> {
+		let mut generated = Vec::new();
+		if let Some(writing_mode) = self.writing_mode {
+			self.require_text_tag("writing_mode")?;
+			for &(k, v) in writing_mode.attrs() {
+				if attrs.iter().any(|(attr, _)| *attr == k) {
+					return Err(ClgnDecodingError::Text {
+						msg: format!(
+							"A `<text>` with a `writing_mode` may not set `{}` itself",
+							k
+						),
+					});
+				}
+				generated.push((k, SimpleValue::Text(v.to_owned())));
+			}
+		}
+
+		generated.extend(bidi::direction_attrs(
+			&self.tag_name,
+			&self.common_tag_fields.text(context)?,
+			attrs,
+		));
+
+		Ok(generated)
+	}
+
+	pub(super) fn z(&self) -> f64 {
+		self.common_tag_fields.z()
+	}
+
+	pub(super) fn base_animations(&self) -> &[Animation] {
+		self.common_tag_fields.base_animations()
+	}
+
+	pub(super) fn base_clips(&self) -> Vec<(ClipKind, &ClipShape)> {
+		self.common_tag_fields.base_clips()
 	}
 
-	pub(super) fn should_escape_text(&self) -> bool {
-		self.common_tag_fields.should_escape_text()
+	pub(super) fn base_link(&self) -> Option<&Link> {
+		self.common_tag_fields.base_link()
 	}
 }
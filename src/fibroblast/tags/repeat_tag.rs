@@ -0,0 +1,363 @@
+use super::animation::Animation;
+use super::any_child_tag::AnyChildTag;
+use super::clip::{ClipKind, ClipShape};
+use super::common_tag_fields::CommonTagFields;
+use super::link::Link;
+use super::other_tag::OtherTag;
+use super::shape_tag::fmt_coord;
+use crate::fibroblast::data_types::{
+	AttrName, ConcreteNumber, DecodingContext, Map, SimpleValue, TagVariables, VariableValue,
+	XmlAttrs,
+};
+use crate::to_svg::svg_writable::{ClgnDecodingError, ClgnDecodingResult};
+use crate::to_svg::xml_text::XmlText;
+use lazycell::LazyCell;
+use serde::{Deserialize, Serialize};
+
+/// A number-valued parameter of a repetition. May be either a number or a string;
+/// strings undergo variable substitution and must then parse as a number.
+type RepeatParam = VariableValue;
+
+/// The most copies a [`RepeatTag`] may make of its template. Each copy is a full clone
+/// of the template, made up front, so an unbounded `count` could exhaust memory before
+/// anything was written.
+const MAX_COUNT: u32 = 100_000;
+
+/// How a [`RepeatTag`]'s template is repeated
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Repeat {
+	count: RepeatParam,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	dx: Option<RepeatParam>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	dy: Option<RepeatParam>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	rotate: Option<RepeatParam>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	cx: Option<RepeatParam>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	cy: Option<RepeatParam>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	var: Option<String>,
+}
+
+/// A tag that "stamps" a template tag several times, each copy offset from the last,
+/// such as a row of circles or a ring of tick marks on a dial. The copies are placed by
+/// giving each one a `transform`, so the template itself is written once, at the
+/// position of the first copy.
+///
+/// # Properties
+///
+/// - `repeat`
+///   - Type: object with the following keys, each of which (except `var`) is a number,
+///     or a string that is a number after variable substitution
+///     - `count` (integer; required): The number of copies, at most 100,000.
+///     - `dx`, `dy` (optional): How far each copy is translated from the previous one.
+///       Missing is equivalent to `0`.
+///     - `rotate` (optional): How far each copy is rotated from the previous one, in
+///       degrees (clockwise), about the point `(cx, cy)`. Missing is equivalent to `0`.
+///     - `cx`, `cy` (optional): The center of rotation. Missing is equivalent to `0`.
+///     - `var` (string; optional): The name of the variable that holds each copy's index
+///       (starting from 0), which the template may use. Like any variable name, it may
+///       only contain letters, digits, and underscores. Missing is equivalent to `"i"`.
+///   - Required: Yes.
+///   - Description: How the template is repeated. The `n`th copy (starting from 0) has
+///     `transform="translate(<n * dx> <n * dy>) rotate(<n * rotate> <cx> <cy>)"`.
+/// - `template`
+///   - Type: object interpretable as [`AnyChildTag`]
+///   - Required: Yes.
+///   - Description: The tag to repeat.
+/// - Other: `RepeatTag` accepts all properties in [`CommonTagFields`] except
+///   `children`; the tag is written as a `<g>` whose children are the copies, each of
+///   which is itself a `<g>` (with the copy's `transform`) holding the template.
+///
+/// # Example
+///
+/// ```json
+/// {
+///   "repeat": { "count": 12, "rotate": 30, "cx": 50, "cy": 50 },
+///   "template": { "tag": "line", "attrs": { "x1": 50, "y1": 5, "x2": 50, "y2": 10, "stroke": "black" } }
+/// }
+/// ```
+///
+/// draws the twelve tick marks of a clock face, and
+///
+/// ```json
+/// {
+///   "repeat": { "count": 5, "dx": 20 },
+///   "template": { "tag": "text", "attrs": { "y": 10 }, "text": "{i}" }
+/// }
+/// ```
+///
+/// writes the numbers 0 through 4 in a row.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RepeatTag<'a> {
+	repeat: Repeat,
+
+	template: Box<AnyChildTag<'a>>,
+
+	#[serde(flatten)]
+	common_tag_fields: CommonTagFields<'a>,
+
+	/// The copies of the template, filled in when they're first needed, since computing
+	/// them requires the variables in scope
+	#[serde(skip)]
+	#[serde(default)]
+	copies: LazyCell<Vec<AnyChildTag<'a>>>,
+}
+
+impl<'a> RepeatTag<'a> {
+	pub(crate) fn fresh_clone<'b>(&self) -> RepeatTag<'b> {
+		RepeatTag {
+			repeat: self.repeat.clone(),
+			template: Box::new(self.template.fresh_clone()),
+			common_tag_fields: self.common_tag_fields.fresh_clone(),
+			copies: LazyCell::new(),
+		}
+	}
+
+	fn error(&self, msg: impl std::fmt::Display) -> ClgnDecodingError {
+		ClgnDecodingError::Repeat {
+			msg: format!("In `repeat`: {}", msg),
+		}
+	}
+
+	fn resolve_param(
+		&self,
+		name: &str,
+		param: Option<&RepeatParam>,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<f64> {
+		match param {
+			None => Ok(0.0),
+			Some(VariableValue::Number(n)) => Ok(n.to_string().parse().unwrap()),
+			Some(VariableValue::String(s)) => {
+				let s = context.sub_vars_into_str(s)?;
+				match s.trim().parse::<f64>() {
+					Ok(x) if x.is_finite() => Ok(x),
+					_ => Err(self.error(format_args!("`{}` must be a number; got {:?}", name, s))),
+				}
+			}
+		}
+	}
+
+	/// Make the copies of the template, using the variables in `context`
+	fn make_copies(&self, context: &DecodingContext) -> ClgnDecodingResult<Vec<AnyChildTag<'a>>> {
+		if !self.common_tag_fields.base_children().is_empty() {
+			return Err(self.error(
+				"a repeated tag's children are the copies of its template, so it may not have \
+				 `children`",
+			));
+		}
+
+		let Repeat {
+			count,
+			dx,
+			dy,
+			rotate,
+			cx,
+			cy,
+			var,
+		} = &self.repeat;
+
+		let count = self.resolve_param("count", Some(count), context)?;
+		if count.fract() != 0.0 || count < 0.0 {
+			return Err(self.error(format_args!(
+				"`count` must be a non-negative integer; got {}",
+				count
+			)));
+		}
+		if count > f64::from(MAX_COUNT) {
+			return Err(self.error(format_args!(
+				"`count` may be at most {}; got {}",
+				MAX_COUNT, count
+			)));
+		}
+		let count = count as u32;
+
+		let param =
+			|name, value: &Option<RepeatParam>| self.resolve_param(name, value.as_ref(), context);
+		let (dx, dy) = (param("dx", dx)?, param("dy", dy)?);
+		let (rotate, cx, cy) = (param("rotate", rotate)?, param("cx", cx)?, param("cy", cy)?);
+		let var = var.as_deref().unwrap_or("i");
+
+		let copies = (0..count)
+			.map(|i| {
+				let n = f64::from(i);
+				let mut transform = Vec::new();
+				if dx != 0.0 || dy != 0.0 {
+					transform.push(format!(
+						"translate({} {})",
+						fmt_coord(n * dx),
+						fmt_coord(n * dy)
+					));
+				}
+				if rotate != 0.0 {
+					transform.push(format!(
+						"rotate({} {} {})",
+						fmt_coord(n * rotate),
+						fmt_coord(cx),
+						fmt_coord(cy)
+					));
+				}
+
+				let mut attrs = Map::new();
+				if !transform.is_empty() {
+					attrs.insert(
+						AttrName::new("transform"),
+						SimpleValue::Text(transform.join(" ")),
+					);
+				}
+				let mut vars = Map::new();
+				vars.insert(
+					var.to_owned(),
+					VariableValue::Number(ConcreteNumber::UInt(u64::from(i))),
+				);
+
+				AnyChildTag::Other(OtherTag::group(
+					TagVariables(vars),
+					XmlAttrs(attrs),
+					vec![self.template.fresh_clone()],
+				))
+			})
+			.collect();
+
+		Ok(copies)
+	}
+
+	/// The copies of the template, which are this tag's children
+	pub(super) fn children(
+		&'a self,
+		context: &DecodingContext<'a>,
+	) -> ClgnDecodingResult<&'a [AnyChildTag<'a>]> {
+		if let Some(copies) = self.copies.borrow() {
+			return Ok(copies);
+		}
+
+		let copies = self.make_copies(context)?;
+		// Can't fail, since `copies` was empty and filling it didn't borrow `self`
+		let _ = self.copies.fill(copies);
+		Ok(self.copies.borrow().unwrap())
+	}
+
+	pub(super) fn tag_name(&self) -> &str {
+		"g"
+	}
+
+	pub(super) fn base_vars(&self) -> &TagVariables {
+		self.common_tag_fields.base_vars()
+	}
+
+	pub(super) fn base_attrs(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_attrs()
+	}
+
+	pub(super) fn base_style(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_style()
+	}
+
+	pub(super) fn base_class_styles(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_class_styles()
+	}
+
+	pub(super) fn text(&self, context: &DecodingContext) -> ClgnDecodingResult<XmlText<'_>> {
+		self.common_tag_fields.text(context)
+	}
+
+	pub(super) fn z(&self) -> f64 {
+		self.common_tag_fields.z()
+	}
+
+	pub(super) fn base_animations(&self) -> &[Animation] {
+		self.common_tag_fields.base_animations()
+	}
+
+	pub(super) fn base_clips(&self) -> Vec<(ClipKind, &ClipShape)> {
+		self.common_tag_fields.base_clips()
+	}
+
+	pub(super) fn base_link(&self) -> Option<&Link> {
+		self.common_tag_fields.base_link()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn n_copies(repeat: &str) -> ClgnDecodingResult<usize> {
+		let tag: AnyChildTag = serde_json::from_str(repeat).unwrap();
+		let context = DecodingContext::new_empty();
+		tag.children(&context).map(|copies| copies.len())
+	}
+
+	#[test]
+	fn invalid_repeats() {
+		assert_eq!(
+			n_copies(r#"{ "repeat": { "count": "3" }, "template": { "tag": "rect" } }"#).unwrap(),
+			3
+		);
+
+		for repeat in [
+			r#"{ "repeat": { "count": 1.5 }, "template": { "tag": "rect" } }"#,
+			r#"{ "repeat": { "count": -1 }, "template": { "tag": "rect" } }"#,
+			r#"{ "repeat": { "count": 100001 }, "template": { "tag": "rect" } }"#,
+			r#"{ "repeat": { "count": 4294967295 }, "template": { "tag": "rect" } }"#,
+			r#"{ "repeat": { "count": "1e12" }, "template": { "tag": "rect" } }"#,
+			r#"{ "repeat": { "count": 2, "dx": "x" }, "template": { "tag": "rect" } }"#,
+			r#"{
+				"repeat": { "count": 2 },
+				"template": { "tag": "rect" },
+				"children": [{ "tag": "rect" }]
+			}"#,
+		] {
+			assert!(
+				matches!(n_copies(repeat), Err(ClgnDecodingError::Repeat { .. })),
+				"{}",
+				repeat
+			);
+		}
+	}
+
+	#[test]
+	fn repeat_vars() {
+		use crate::fibroblast::Fibroblast;
+
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		let render = |var: &str| {
+			std::fs::write(
+				skeleton_dir.path().join("collagen.json"),
+				format!(
+					r#"{{
+						"children": [{{
+							"tag": "g",
+							"children": [{{
+								"repeat": {{ "count": 2, "var": {:?} }},
+								"template": {{ "tag": "text", "text": "{{{}}}" }}
+							}}]
+						}}]
+					}}"#,
+					var, var
+				),
+			)
+			.unwrap();
+			Fibroblast::from_dir(skeleton_dir.path())?.to_svg_string()
+		};
+
+		assert!(render("row_2").unwrap().contains("<text>1</text>"));
+		for var in vec!["", "row 2", "row-2", "{row}"].into_iter() {
+			assert!(
+				matches!(render(var), Err(ClgnDecodingError::Repeat { .. })),
+				"{:?}",
+				var
+			);
+		}
+	}
+}
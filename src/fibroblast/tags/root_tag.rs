@@ -1,24 +1,326 @@
+use super::animation::Animation;
+use super::clip::{ClipKind, ClipShape};
+use super::credits::{Credits, CreditsOption};
+use super::link::Link;
+use super::use_from_tag::SymbolDef;
 use super::{
-	common_tag_fields::CommonTagFields, AnyChildTag, AttrKVValueVec, ClgnDecodingResult,
-	DecodingContext, TagLike, TagVariables, XmlAttrs,
+	common_tag_fields::{
+		add_class_styles_to_attrs, append_to_style, merge_style_into_attrs, CommonTagFields,
+	},
+	AnyChildTag, AttrKVValueVec, ClgnDecodingResult, DecodingContext, TagLike, TagVariables,
+	XmlAttrs,
 };
-use crate::fibroblast::data_types::SimpleValue;
+use crate::fibroblast::data_types::{Map, SimpleValue};
+use crate::to_svg::output_format::OutputFormat;
+use crate::to_svg::svg_writable::ClgnDecodingError;
+use crate::to_svg::xml_text::XmlText;
+use lazy_static::lazy_static;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::convert::TryFrom;
+
+lazy_static! {
+	static ref EMPTY_PALETTE: Map<String, String> = Map::new();
+	static ref NAMESPACE_PREFIX_RE: Regex = Regex::new(r"^[[:alpha:]_][\w.-]*$").unwrap();
+}
+
+pub(crate) const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+
+/// The XML namespaces declared by a root tag, as the attributes that declare them
+/// (e.g., `xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape"`). In JSON,
+/// this is a map from prefix to namespace name (URI).
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(try_from = "Map<String, String>", into = "Map<String, String>")]
+pub(crate) struct Namespaces(Vec<(String, SimpleValue)>);
+
+impl TryFrom<Map<String, String>> for Namespaces {
+	type Error = String;
+
+	fn try_from(map: Map<String, String>) -> Result<Self, Self::Error> {
+		let mut attrs = Vec::with_capacity(map.len());
+		for (prefix, uri) in map {
+			if !NAMESPACE_PREFIX_RE.is_match(&prefix)
+				|| prefix.to_ascii_lowercase().starts_with("xml")
+			{
+				return Err(format!("{:?} is not a valid namespace prefix", prefix));
+			}
+			attrs.push((format!("xmlns:{}", prefix), SimpleValue::Text(uri)));
+		}
+
+		Ok(Self(attrs))
+	}
+}
+
+impl From<Namespaces> for Map<String, String> {
+	fn from(namespaces: Namespaces) -> Self {
+		namespaces
+			.0
+			.into_iter()
+			.map(|(attr, uri)| {
+				let prefix = attr.trim_start_matches("xmlns:").to_owned();
+				let uri = uri.to_maybe_string().unwrap_or_default().into_owned();
+				(prefix, uri)
+			})
+			.collect()
+	}
+}
+
+/// The options of the XML declaration (`<?xml version="1.0" encoding="UTF-8"?>`) at the
+/// start of the document
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct XmlDeclaration {
+	/// The value of `standalone`; if `None`, `standalone` is omitted
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub(crate) standalone: Option<bool>,
+}
+
+/// Either whether to write an XML declaration or, to write one with non-default
+/// options, those options
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(untagged)]
+enum XmlDeclarationOption {
+	Enabled(bool),
+	Options(XmlDeclaration),
+}
 
 /// The document root (`<svg>...<svg>`). A `collagen.json` file is expected to contain a
 /// single object; that object is always implicitly of type `RootTag`. The set of keys
 /// does not matter — even `{}` is perfectly valid (it will be turned into simply `<svg
 /// xmlns="http://www.w3.org/2000/svg"></svg>`).
 ///
-/// `RootTag` accepts only the properties in [`CommonTagFields`](crate::fibroblast::tags::CommonTagFields).
+/// # Properties
+///
+/// - `palette`
+///   - Type: object, with string values
+///   - Required: No. Missing is equivalent to `{}`.
+///   - Description: A dictionary of named colors, e.g., `{ "primary": "#336699" }`.
+///     Anywhere in the document, an attribute (or `style`) value of the form `"@name"`,
+///     such as `"fill": "@primary"`, is replaced with the color named `name`; it is an
+///     error if there is no such color. The colors in a skeleton included via a
+///     [`ContainerTag`](super::ContainerTag) are available only inside that skeleton,
+///     and replace any colors of the same name for its duration. Colors can also be
+///     overridden from the command line with `--palette name=color`.
+/// - `xml_declaration`
+///   - Type: bool, or an object of the form `{ "standalone": <bool> }`
+///   - Required: No. Missing is equivalent to `false`.
+///   - Description: Whether to begin the document with an XML declaration, `<?xml
+///     version="1.0" encoding="UTF-8"?>`, which some tools require. The object form
+///     also sets the declaration's `standalone`. (The encoding is always UTF-8.) Passing
+///     `--xml-declaration` on the command line has the same effect as `true`.
+/// - `namespaces`
+///   - Type: object, with string values
+///   - Required: No. Missing is equivalent to `{}`.
+///   - Description: A dictionary mapping XML namespace prefixes to namespace names
+///     (URIs), e.g., `{ "inkscape": "http://www.inkscape.org/namespaces/inkscape" }`,
+///     each of which is declared on the root tag (as
+///     `xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape"`). It is an error
+///     for a tag or attribute name to have a prefix, as in `inkscape:label`, that has
+///     not been declared, either here or by an `xmlns:<prefix>` attribute on the tag or
+///     one of its ancestors. (`xml:` needs no declaration.) Passing `--xlink-href` on
+///     the command line declares `xlink` and gives each image an `xlink:href` in
+///     addition to its `href`, for consumers that only support the former.
+/// - `doctype`
+///   - Type: bool
+///   - Required: No. Missing is equivalent to `false`.
+///   - Description: Whether to write the SVG 1.1 `<!DOCTYPE>` before the root tag.
+/// - `output`
+///   - Type: object, as documented in [`OutputFormat`]
+///   - Required: No. Missing is equivalent to `{}`.
+///   - Description: How the SVG is laid out as text, e.g., `{ "indent": 2, "newline":
+///     "crlf" }` to indent it with two spaces and end its lines with CRLF. By default,
+///     it's written on a single line. Passing `--indent` or `--newline` on the command
+///     line overrides the corresponding option. See
+///     [`output_format`](crate::to_svg::output_format).
+/// - `symbols`
+///   - Type: object, whose values are as documented in [`SymbolDef`]
+///   - Required: No. Missing is equivalent to `{}`.
+///   - Description: The named symbols that this skeleton exports, making it a
+///     *library*, e.g., `{ "home": { "attrs": { "viewBox": "0 0 24 24" }, "children":
+///     [...] } }`. Other skeletons use them with a [`UseFromTag`](super::UseFromTag).
+///     Symbols are only written to the skeletons that use them, not to this one.
+/// - `credits`
+///   - Type: bool, or an object as documented in [`Credits`]
+///   - Required: No. Missing is equivalent to `false`.
+///   - Description: Whether to list the images and fonts that the document embeds in
+///     a corner of the canvas, each by its `credit` (e.g., `"Photo by Ann Lee (CC BY
+///     4.0)"`) or, if it has none, its path. The object form also sets where and how
+///     they're written. See [`credits`](super::credits).
+/// - Other: `RootTag` accepts all properties in
+///   [`CommonTagFields`](crate::fibroblast::tags::CommonTagFields).
+///
+/// If `clgn` is run with `--profile web|print|icon`, root attributes that affect how
+/// the SVG is sized and scaled (`viewBox`, `width`, `height`, etc.) that aren't given in
+/// `attrs` are filled in with the profile's defaults; see
+/// [`profile`](crate::to_svg::profile).
+///
+/// If `clgn` is run with `--var-export name1,name2,...`, each of the root tag's
+/// variables `name1`, `name2`, etc. is exported as a CSS custom property in the root
+/// tag's `style` (e.g., `style="--theme:dark;--width:400"`), so that the page an SVG is
+/// embedded in, and its scripts, can read the parameters it was generated with. It is an
+/// error for an exported variable not to exist, or for its value to contain a `;`, `{`,
+/// or `}`, which would end the custom property early.
+///
+/// If `clgn` is run with `--stamp-hash`, the root tag is given a `data-clgn-hash`: a
+/// hash of everything the SVG was rendered from (the manifest, the images and fonts it
+/// embeds, the command line options, etc.), which changes whenever any of them does. See
+/// [`Fibroblast::input_hash`](crate::Fibroblast::input_hash).
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RootTag<'a> {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	palette: Option<Map<String, String>>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	xml_declaration: Option<XmlDeclarationOption>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	doctype: Option<bool>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	namespaces: Option<Namespaces>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	output: Option<OutputFormat>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	symbols: Option<Map<String, SymbolDef<'a>>>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	credits: Option<CreditsOption>,
+
 	#[serde(flatten)]
 	common_tag_fields: CommonTagFields<'a>,
 }
 
 impl<'a> RootTag<'a> {
+	/// A deep copy of `self` that can be used under a different lifetime than `self`.
+	/// (`Clone` would tie the copy to `'a`, which, since `RootTag` is invariant in `'a`,
+	/// would make it impossible to render a copy of a tag that outlives the render.) Any
+	/// skeletons included via [`ContainerTag`](super::ContainerTag)s are not copied;
+	/// they will be read from disk again when the copy is rendered.
+	pub(crate) fn fresh_clone<'b>(&self) -> RootTag<'b> {
+		RootTag {
+			palette: self.palette.clone(),
+			xml_declaration: self.xml_declaration,
+			doctype: self.doctype,
+			namespaces: self.namespaces.clone(),
+			output: self.output,
+			symbols: self.symbols.as_ref().map(|symbols| {
+				symbols
+					.iter()
+					.map(|(name, symbol)| (name.clone(), symbol.fresh_clone()))
+					.collect()
+			}),
+			credits: self.credits.clone(),
+			common_tag_fields: self.common_tag_fields.fresh_clone(),
+		}
+	}
+
+	pub(crate) fn base_palette(&self) -> &Map<String, String> {
+		match &self.palette {
+			None => &EMPTY_PALETTE,
+			Some(palette) => palette,
+		}
+	}
+
+	/// The options of the XML declaration to begin the document with, or `None` if there
+	/// shouldn't be one
+	pub(crate) fn xml_declaration(&self, context: &DecodingContext) -> Option<XmlDeclaration> {
+		match self.xml_declaration {
+			Some(XmlDeclarationOption::Options(decl)) => Some(decl),
+			Some(XmlDeclarationOption::Enabled(true)) => Some(XmlDeclaration::default()),
+			_ if context.force_xml_declaration() => Some(XmlDeclaration::default()),
+			_ => None,
+		}
+	}
+
+	/// The format to write the SVG in, with the command line's options taking precedence
+	/// over `output`'s
+	pub(crate) fn output_format(&self, context: &DecodingContext) -> OutputFormat {
+		context.output_format().or(self.output.unwrap_or_default())
+	}
+
+	/// The options of the credits to write, or `None` if there shouldn't be any
+	pub(crate) fn credits(&self) -> Option<Cow<'_, Credits>> {
+		self.credits.as_ref()?.credits()
+	}
+
+	pub(crate) fn doctype(&self) -> bool {
+		self.doctype.unwrap_or(false)
+	}
+
+	/// The attributes that declare this tag's `namespaces` (and `xlink`, if `--xlink-href`
+	/// was passed), except those that `base_attrs` already declares
+	pub(super) fn namespace_attrs(
+		&self,
+		context: &DecodingContext,
+	) -> impl Iterator<Item = (&str, Cow<'_, SimpleValue>)> {
+		let base_attrs = self.base_attrs();
+		let xlink = if context.xlink_href() {
+			Some((
+				"xmlns:xlink",
+				Cow::Owned(SimpleValue::Text(XLINK_NAMESPACE.to_owned())),
+			))
+		} else {
+			None
+		};
+
+		self.namespaces
+			.iter()
+			.flat_map(|namespaces| namespaces.0.iter())
+			.map(|(k, v)| (k.as_str(), Cow::Borrowed(v)))
+			.chain(xlink)
+			.filter(move |(k, _)| !base_attrs.0.contains_key(*k))
+	}
+
+	/// The CSS declarations (e.g., `--theme:dark`) of the custom properties that export
+	/// the variables passed to `--var-export`
+	fn exported_var_declarations(&self, context: &DecodingContext) -> ClgnDecodingResult<String> {
+		let declarations = context
+			.exported_vars()
+			.iter()
+			.map(|name| {
+				let value = match context.get_var(name) {
+					Some(value) => value.as_str(),
+					None => {
+						return Err(ClgnDecodingError::VarExport {
+							msg: format!(
+								"Cannot export the variable {:?}, which the root tag does not define",
+								name
+							),
+						})
+					}
+				};
+				if value.contains(|c| matches!(c, ';' | '{' | '}')) {
+					return Err(ClgnDecodingError::VarExport {
+						msg: format!(
+							"Cannot export the variable {:?} as a CSS custom property, as its \
+							 value {:?} contains a `;`, `{{`, or `}}`",
+							name, value
+						),
+					});
+				}
+
+				Ok(format!("--{}:{}", name, value))
+			})
+			.collect::<ClgnDecodingResult<Vec<_>>>()?;
+
+		Ok(declarations.join(";"))
+	}
+
+	/// The symbol named `name` that this skeleton exports, if any
+	pub(crate) fn symbol(&self, name: &str) -> Option<&SymbolDef<'a>> {
+		self.symbols.as_ref()?.get(name)
+	}
+
+	pub(crate) fn symbol_names(&self) -> Vec<&str> {
+		self.symbols
+			.iter()
+			.flat_map(|symbols| symbols.keys().map(String::as_str))
+			.collect()
+	}
+
 	pub(super) fn base_vars(&self) -> &TagVariables {
 		self.common_tag_fields.base_vars()
 	}
@@ -27,15 +329,19 @@ impl<'a> RootTag<'a> {
 		self.common_tag_fields.base_attrs()
 	}
 
-	pub(super) fn base_children(&self) -> &[AnyChildTag<'a>] {
-		self.common_tag_fields.base_children()
+	pub(super) fn base_style(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_style()
 	}
 
-	pub(super) fn base_text(&self) -> &str {
-		self.common_tag_fields.base_text()
+	pub(super) fn base_class_styles(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_class_styles()
 	}
 
-	pub(crate) fn children(&'a self) -> &[AnyChildTag<'a>] {
+	pub(super) fn base_children(&self) -> &[AnyChildTag<'a>] {
+		self.common_tag_fields.base_children()
+	}
+
+	pub(crate) fn children(&'a self) -> &'a [AnyChildTag<'a>] {
 		self.base_children()
 	}
 }
@@ -57,6 +363,18 @@ impl<'a> TagLike<'a> for RootTag<'a> {
 				.iter()
 				.map(|(k, v)| (k.as_ref(), Cow::Borrowed(v))),
 		)?;
+		context.resolve_palette_colors(&mut new_attrs)?;
+		merge_style_into_attrs(&mut new_attrs, self.base_style(), context)?;
+		add_class_styles_to_attrs(&mut new_attrs, self.base_class_styles(), context)?;
+		let exported_vars = self.exported_var_declarations(context)?;
+		if !exported_vars.is_empty() {
+			append_to_style(&mut new_attrs, exported_vars);
+		}
+
+		if let Some(profile) = context.profile() {
+			let defaults = profile.default_attrs(&new_attrs);
+			new_attrs.extend(defaults.into_iter().map(|(k, v)| (k, Cow::Owned(v))));
+		}
 
 		if !base_attrs.0.contains_key("xmlns") {
 			new_attrs.push((
@@ -64,15 +382,71 @@ impl<'a> TagLike<'a> for RootTag<'a> {
 				Cow::Owned(SimpleValue::Text("http://www.w3.org/2000/svg".to_string())),
 			));
 		}
+		new_attrs.extend(self.namespace_attrs(context));
+		if let Some(hash) = context.stamped_hash() {
+			new_attrs.push(("data-clgn-hash", Cow::Owned(SimpleValue::Text(hash))));
+		}
 
 		Ok(new_attrs)
 	}
 
-	fn text(&'a self, _: &DecodingContext) -> ClgnDecodingResult<Cow<'a, str>> {
-		Ok(Cow::Borrowed(self.base_text()))
+	fn text(&'a self, _: &DecodingContext) -> ClgnDecodingResult<XmlText<'a>> {
+		Ok(self.common_tag_fields.base_xml_text())
 	}
 
-	fn should_escape_text(&self) -> bool {
-		self.common_tag_fields.should_escape_text()
+	fn is_root(&self) -> bool {
+		true
+	}
+
+	fn animations(&self) -> &[Animation] {
+		self.common_tag_fields.base_animations()
+	}
+
+	fn clips(&self) -> Vec<(ClipKind, &ClipShape)> {
+		self.common_tag_fields.base_clips()
+	}
+
+	fn link(&self) -> Option<&Link> {
+		self.common_tag_fields.base_link()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fibroblast::Fibroblast;
+
+	#[test]
+	fn exported_vars() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			r#"{
+				"vars": { "theme": "dark", "width": 400, "css": "a;b" },
+				"style": { "fill": "red" },
+				"children": [{ "tag": "rect", "vars": { "theme": "light" } }]
+			}"#,
+		)
+		.unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+		let export = |names: &[&str]| {
+			let names = names.iter().map(|&name| name.to_owned()).collect();
+			fibroblast.context.set_exported_vars(names);
+			fibroblast.to_svg_string()
+		};
+
+		assert!(export(&[])
+			.unwrap()
+			.starts_with(r#"<svg style="fill:red" "#));
+		assert!(export(&["theme", "width"])
+			.unwrap()
+			.starts_with(r#"<svg style="fill:red;--theme:dark;--width:400" "#));
+
+		for names in vec![["missing"], ["css"]].into_iter() {
+			assert!(matches!(
+				export(&names),
+				Err(ClgnDecodingError::VarExport { .. })
+			));
+		}
 	}
 }
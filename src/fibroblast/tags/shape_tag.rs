@@ -0,0 +1,524 @@
+use super::animation::Animation;
+use super::any_child_tag::AnyChildTag;
+use super::clip::{ClipKind, ClipShape};
+use super::common_tag_fields::CommonTagFields;
+use super::link::Link;
+use crate::fibroblast::data_types::{
+	Anchor, DecodingContext, SimpleValue, TagVariables, VariableValue, XmlAttrs,
+};
+use crate::to_svg::svg_writable::{ClgnDecodingError, ClgnDecodingResult};
+use crate::to_svg::xml_text::XmlText;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// A number-valued parameter of a shape. May be either a number or a string; strings
+/// undergo variable substitution and must then parse as a number.
+type ShapeParam = VariableValue;
+
+/// The most `points` a star, or `sides` a regular polygon, may have. Far fewer are
+/// already indistinguishable from a circle, and more would only bloat the SVG (or, with
+/// enough of them, exhaust memory).
+const MAX_COUNT: u32 = 10_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "shape", rename_all = "kebab-case")]
+pub(crate) enum Shape {
+	Arc {
+		cx: ShapeParam,
+		cy: ShapeParam,
+		r: ShapeParam,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		inner_r: Option<ShapeParam>,
+		start: ShapeParam,
+		end: ShapeParam,
+	},
+	Star {
+		cx: ShapeParam,
+		cy: ShapeParam,
+		r: ShapeParam,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		inner_r: Option<ShapeParam>,
+		points: ShapeParam,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		rotation: Option<ShapeParam>,
+	},
+	RegularPolygon {
+		cx: ShapeParam,
+		cy: ShapeParam,
+		r: ShapeParam,
+		sides: ShapeParam,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		rotation: Option<ShapeParam>,
+	},
+}
+
+/// A tag that generates the geometry of a shape that would be tedious to compute by
+/// hand. Arcs become `<path>`s and stars and regular polygons become `<polygon>`s.
+///
+/// Angles are in degrees and, as with SVG's `rotate()`, increase clockwise from the
+/// positive x axis (i.e., 3 o'clock). Every numeric property may instead be given as a
+/// string, which undergoes variable substitution and must then be a number, e.g., `"r":
+/// "{radius}"`.
+///
+/// # Properties
+///
+/// - `shape`
+///   - Type: string, one of `"arc"`, `"star"`, or `"regular-polygon"`
+///   - Required: Yes.
+///   - Description: The kind of shape to generate. The remaining properties depend on
+///     the kind of shape.
+/// - `cx`, `cy`
+///   - Type: number
+///   - Required: Yes.
+///   - Description: The center of the shape.
+/// - `r`
+///   - Type: number
+///   - Required: Yes.
+///   - Description: The (outer) radius of the shape.
+/// - Arcs (`"shape": "arc"`)
+///   - `start`, `end` (number; required): The angles at which the arc starts and ends.
+///     The arc is drawn clockwise if `end > start` and counterclockwise otherwise. A
+///     difference of 360 or more draws a full circle.
+///   - `inner_r` (number; optional): If missing, the arc is a plain (unfilled) curve. If
+///     given, the arc is a closed donut segment between radii `inner_r` and `r`; an
+///     `inner_r` of `0` makes a pie slice.
+/// - Stars (`"shape": "star"`)
+///   - `points` (integer; required): The number of points, at least 2 and at most
+///     10,000.
+///   - `inner_r` (number; optional): The radius of the star's inner vertices. Missing is
+///     equivalent to half of `r`.
+///   - `rotation` (number; optional): How far to rotate the star. Missing is equivalent
+///     to `0`, which points the first point straight up.
+/// - Regular polygons (`"shape": "regular-polygon"`)
+///   - `sides` (integer; required): The number of sides, at least 3 and at most 10,000.
+///   - `rotation` (number; optional): How far to rotate the polygon. Missing is
+///     equivalent to `0`, which puts the first vertex straight up.
+/// - Other: `ShapeTag` accepts all properties in [`CommonTagFields`], except that
+///   `attrs` may not contain the attribute that the shape generates (`d` for arcs and
+///   `points` for everything else).
+///
+/// # Example
+///
+/// ```json
+/// { "shape": "arc", "cx": 50, "cy": 50, "r": 40, "inner_r": 20, "start": -90, "end": 45, "attrs": { "fill": "teal" } }
+/// ```
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShapeTag<'a> {
+	#[serde(flatten)]
+	shape: Shape,
+
+	#[serde(flatten)]
+	common_tag_fields: CommonTagFields<'a>,
+}
+
+/// Format a coordinate, rounding away floating point noise (and negative zero)
+pub(super) fn fmt_coord(x: f64) -> String {
+	let rounded = (x * 1e6).round() / 1e6;
+	if rounded == 0.0 {
+		"0".to_owned()
+	} else {
+		rounded.to_string()
+	}
+}
+
+/// The point at angle `degrees` on the circle of radius `r` centered at `(cx, cy)`
+fn polar(cx: f64, cy: f64, r: f64, degrees: f64) -> (f64, f64) {
+	let radians = degrees * PI / 180.0;
+	(cx + r * radians.cos(), cy + r * radians.sin())
+}
+
+pub(super) fn fmt_point((x, y): (f64, f64)) -> String {
+	format!("{},{}", fmt_coord(x), fmt_coord(y))
+}
+
+/// The `points` of a polygon whose vertices are at the given (radius, angle) pairs
+fn polygon_points(cx: f64, cy: f64, vertices: impl Iterator<Item = (f64, f64)>) -> String {
+	vertices
+		.map(|(r, degrees)| fmt_point(polar(cx, cy, r, degrees)))
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+/// The `d` of an arc (or donut segment or pie slice, if `inner_r` is given). See
+/// [`ShapeTag`].
+fn arc_path(cx: f64, cy: f64, r: f64, inner_r: Option<f64>, start: f64, end: f64) -> String {
+	let sweep = (end - start).clamp(-360.0, 360.0);
+	let end = start + sweep;
+	let is_full_circle = sweep.abs() >= 360.0;
+	let sweep_flag = if sweep >= 0.0 { 1 } else { 0 };
+
+	// The `A` commands tracing the circle of radius `r` from `from` to `to` (which must
+	// be `start` and `end`, in some order). A single `A` can't trace a full circle, so
+	// in that case we go halfway around twice.
+	let arc_commands = |r: f64, from: f64, to: f64, sweep_flag: i32| -> String {
+		let arc_to = |degrees: f64, large_arc: bool| {
+			format!(
+				"A{},{} 0 {} {} {}",
+				fmt_coord(r),
+				fmt_coord(r),
+				large_arc as i32,
+				sweep_flag,
+				fmt_point(polar(cx, cy, r, degrees))
+			)
+		};
+
+		if is_full_circle {
+			format!("{} {}", arc_to((from + to) / 2.0, false), arc_to(to, false))
+		} else {
+			arc_to(to, sweep.abs() > 180.0)
+		}
+	};
+
+	let outer_start = fmt_point(polar(cx, cy, r, start));
+	let outer_arc = arc_commands(r, start, end, sweep_flag);
+
+	match inner_r {
+		None => format!("M{} {}", outer_start, outer_arc),
+		Some(inner_r) if inner_r <= 0.0 => {
+			if is_full_circle {
+				format!("M{} {} Z", outer_start, outer_arc)
+			} else {
+				let center = fmt_point((cx, cy));
+				format!("M{} L{} {} Z", center, outer_start, outer_arc)
+			}
+		}
+		Some(inner_r) => {
+			let inner_end = fmt_point(polar(cx, cy, inner_r, end));
+			let inner_arc = arc_commands(inner_r, end, start, 1 - sweep_flag);
+			if is_full_circle {
+				format!(
+					"M{} {} Z M{} {} Z",
+					outer_start, outer_arc, inner_end, inner_arc
+				)
+			} else {
+				format!(
+					"M{} {} L{} {} Z",
+					outer_start, outer_arc, inner_end, inner_arc
+				)
+			}
+		}
+	}
+}
+
+impl<'a> ShapeTag<'a> {
+	pub(crate) fn fresh_clone<'b>(&self) -> ShapeTag<'b> {
+		ShapeTag {
+			shape: self.shape.clone(),
+			common_tag_fields: self.common_tag_fields.fresh_clone(),
+		}
+	}
+
+	fn shape_name(&self) -> &'static str {
+		match self.shape {
+			Shape::Arc { .. } => "arc",
+			Shape::Star { .. } => "star",
+			Shape::RegularPolygon { .. } => "regular-polygon",
+		}
+	}
+
+	/// The name of the attribute that holds the generated geometry
+	fn geometry_attr_name(&self) -> &'static str {
+		match self.shape {
+			Shape::Arc { .. } => "d",
+			Shape::Star { .. } | Shape::RegularPolygon { .. } => "points",
+		}
+	}
+
+	fn error(&self, msg: impl std::fmt::Display) -> ClgnDecodingError {
+		ClgnDecodingError::Shape {
+			msg: format!("In shape {:?}: {}", self.shape_name(), msg),
+		}
+	}
+
+	fn resolve_param(
+		&self,
+		name: &str,
+		param: &ShapeParam,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<f64> {
+		match param {
+			VariableValue::Number(n) => match n.as_f64() {
+				x if x.is_finite() => Ok(x),
+				_ => Err(self.error(format_args!("`{}` must be finite; got {}", name, n))),
+			},
+			VariableValue::String(s) => {
+				let s = context.sub_vars_into_str(s)?;
+				match s.trim().parse::<f64>() {
+					Ok(x) if x.is_finite() => Ok(x),
+					_ => Err(self.error(format_args!("`{}` must be a number; got {:?}", name, s))),
+				}
+			}
+		}
+	}
+
+	fn resolve_count(
+		&self,
+		name: &str,
+		param: &ShapeParam,
+		min: u32,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<u32> {
+		let n = self.resolve_param(name, param, context)?;
+		if n.fract() != 0.0 || n < f64::from(min) || n > f64::from(MAX_COUNT) {
+			return Err(self.error(format_args!(
+				"`{}` must be an integer from {} to {}; got {}",
+				name, min, MAX_COUNT, n
+			)));
+		}
+		Ok(n as u32)
+	}
+
+	/// Get the key-value pair (as a tuple) holding this shape's geometry, e.g.,
+	/// `("points", "0,-10 9.510565,-3.090170 ...")`
+	pub(super) fn get_geometry_attr_pair(
+		&self,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<(&'static str, SimpleValue)> {
+		let key = self.geometry_attr_name();
+		if self.base_attrs().contains_key(key) {
+			return Err(self.error(format_args!(
+				"`{}` is generated from the shape's parameters, so it may not be given in `attrs`",
+				key
+			)));
+		}
+
+		let param = |name: &str, value: &ShapeParam| self.resolve_param(name, value, context);
+		let opt_param = |name: &str, value: &Option<ShapeParam>| {
+			value.as_ref().map(|v| param(name, v)).transpose()
+		};
+
+		let value = match &self.shape {
+			Shape::Arc {
+				cx,
+				cy,
+				r,
+				inner_r,
+				start,
+				end,
+			} => arc_path(
+				param("cx", cx)?,
+				param("cy", cy)?,
+				param("r", r)?,
+				opt_param("inner_r", inner_r)?,
+				param("start", start)?,
+				param("end", end)?,
+			),
+			Shape::Star {
+				cx,
+				cy,
+				r,
+				inner_r,
+				points,
+				rotation,
+			} => {
+				let r = param("r", r)?;
+				let inner_r = opt_param("inner_r", inner_r)?.unwrap_or(r / 2.0);
+				let n = self.resolve_count("points", points, 2, context)?;
+				let rotation = opt_param("rotation", rotation)?.unwrap_or(0.0);
+
+				let step = 180.0 / f64::from(n);
+				let vertices = (0..2 * n).map(|i| {
+					let radius = if i % 2 == 0 { r } else { inner_r };
+					(radius, rotation - 90.0 + f64::from(i) * step)
+				});
+				polygon_points(param("cx", cx)?, param("cy", cy)?, vertices)
+			}
+			Shape::RegularPolygon {
+				cx,
+				cy,
+				r,
+				sides,
+				rotation,
+			} => {
+				let r = param("r", r)?;
+				let n = self.resolve_count("sides", sides, 3, context)?;
+				let rotation = opt_param("rotation", rotation)?.unwrap_or(0.0);
+
+				let step = 360.0 / f64::from(n);
+				let vertices = (0..n).map(|i| (r, rotation - 90.0 + f64::from(i) * step));
+				polygon_points(param("cx", cx)?, param("cy", cy)?, vertices)
+			}
+		};
+
+		Ok((key, SimpleValue::Text(value)))
+	}
+
+	/// The circle in which this shape is inscribed
+	pub(super) fn anchor(&self, context: &DecodingContext) -> ClgnDecodingResult<Anchor> {
+		let (cx, cy, r) = match &self.shape {
+			Shape::Arc { cx, cy, r, .. }
+			| Shape::Star { cx, cy, r, .. }
+			| Shape::RegularPolygon { cx, cy, r, .. } => (cx, cy, r),
+		};
+
+		Ok(Anchor::circle(
+			self.resolve_param("cx", cx, context)?,
+			self.resolve_param("cy", cy, context)?,
+			self.resolve_param("r", r, context)?,
+		))
+	}
+
+	pub(super) fn tag_name(&self) -> &str {
+		match self.shape {
+			Shape::Arc { .. } => "path",
+			Shape::Star { .. } | Shape::RegularPolygon { .. } => "polygon",
+		}
+	}
+
+	pub(super) fn base_vars(&self) -> &TagVariables {
+		self.common_tag_fields.base_vars()
+	}
+
+	pub(super) fn base_attrs(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_attrs()
+	}
+
+	pub(super) fn base_style(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_style()
+	}
+
+	pub(super) fn base_class_styles(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_class_styles()
+	}
+
+	pub(super) fn base_children(&self) -> &[AnyChildTag<'a>] {
+		self.common_tag_fields.base_children()
+	}
+
+	pub(super) fn text(&self, context: &DecodingContext) -> ClgnDecodingResult<XmlText<'_>> {
+		self.common_tag_fields.text(context)
+	}
+
+	pub(super) fn z(&self) -> f64 {
+		self.common_tag_fields.z()
+	}
+
+	pub(super) fn base_animations(&self) -> &[Animation] {
+		self.common_tag_fields.base_animations()
+	}
+
+	pub(super) fn base_clips(&self) -> Vec<(ClipKind, &ClipShape)> {
+		self.common_tag_fields.base_clips()
+	}
+
+	pub(super) fn base_link(&self) -> Option<&Link> {
+		self.common_tag_fields.base_link()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn arcs() {
+		// Quarter circle, clockwise from 3 o'clock to 6 o'clock
+		assert_eq!(
+			arc_path(0.0, 0.0, 10.0, None, 0.0, 90.0),
+			"M10,0 A10,10 0 0 1 0,10"
+		);
+		// Same, but counterclockwise from 6 o'clock to 3 o'clock
+		assert_eq!(
+			arc_path(0.0, 0.0, 10.0, None, 90.0, 0.0),
+			"M0,10 A10,10 0 0 0 10,0"
+		);
+		// Large arc
+		assert_eq!(
+			arc_path(5.0, 5.0, 10.0, None, 0.0, 270.0),
+			"M15,5 A10,10 0 1 1 5,-5"
+		);
+		// Full circle
+		assert_eq!(
+			arc_path(0.0, 0.0, 10.0, None, 0.0, 400.0),
+			"M10,0 A10,10 0 0 1 -10,0 A10,10 0 0 1 10,0"
+		);
+	}
+
+	#[test]
+	fn pies_and_donuts() {
+		assert_eq!(
+			arc_path(0.0, 0.0, 10.0, Some(0.0), 0.0, 90.0),
+			"M0,0 L10,0 A10,10 0 0 1 0,10 Z"
+		);
+		assert_eq!(
+			arc_path(0.0, 0.0, 10.0, Some(5.0), 0.0, 90.0),
+			"M10,0 A10,10 0 0 1 0,10 L0,5 A5,5 0 0 0 5,0 Z"
+		);
+		assert_eq!(
+			arc_path(0.0, 0.0, 10.0, Some(5.0), 0.0, 360.0),
+			"M10,0 A10,10 0 0 1 -10,0 A10,10 0 0 1 10,0 Z M5,0 A5,5 0 0 0 -5,0 A5,5 0 0 0 5,0 Z"
+		);
+	}
+
+	#[test]
+	fn polygons() {
+		assert_eq!(
+			polygon_points(0.0, 0.0, (0..4).map(|i| (1.0, -90.0 + f64::from(i) * 90.0))),
+			"0,-1 1,0 0,1 -1,0"
+		);
+	}
+
+	#[test]
+	fn counts() {
+		let geometry = |shape: serde_json::Value| {
+			let tag: ShapeTag = serde_json::from_value(shape).unwrap();
+			tag.get_geometry_attr_pair(&DecodingContext::new_at_root("."))
+				.map(|(_, points)| points)
+		};
+		let star = |points: serde_json::Value| {
+			geometry(serde_json::json!({
+				"shape": "star", "cx": 0, "cy": 0, "r": 10, "points": points
+			}))
+		};
+		let polygon = |sides: serde_json::Value| {
+			geometry(serde_json::json!({
+				"shape": "regular-polygon", "cx": 0, "cy": 0, "r": 10, "sides": sides
+			}))
+		};
+
+		assert!(star(serde_json::json!(MAX_COUNT)).is_ok());
+		assert!(polygon(serde_json::json!(MAX_COUNT)).is_ok());
+		for count in [
+			serde_json::json!(1),
+			serde_json::json!(2.5),
+			serde_json::json!(MAX_COUNT + 1),
+			serde_json::json!(u32::MAX),
+			serde_json::json!(u64::MAX),
+			serde_json::json!("4294967295"),
+		] {
+			assert!(
+				matches!(star(count.clone()), Err(ClgnDecodingError::Shape { .. })),
+				"{}",
+				count
+			);
+			assert!(
+				matches!(polygon(count.clone()), Err(ClgnDecodingError::Shape { .. })),
+				"{}",
+				count
+			);
+		}
+	}
+
+	#[test]
+	fn non_finite_params() {
+		use crate::fibroblast::data_types::ConcreteNumber;
+
+		let tag: ShapeTag = serde_json::from_value(serde_json::json!({
+			"shape": "star", "cx": 0, "cy": 0, "r": 10, "points": 5
+		}))
+		.unwrap();
+		let context = DecodingContext::new_at_root(".");
+		let resolve =
+			|x: ConcreteNumber| tag.resolve_param("r", &VariableValue::Number(x), &context);
+
+		assert_eq!(resolve(ConcreteNumber::Int(-3)).unwrap(), -3.0);
+		assert_eq!(resolve(ConcreteNumber::UInt(3)).unwrap(), 3.0);
+		assert_eq!(resolve(ConcreteNumber::Float(2.5)).unwrap(), 2.5);
+		for x in [f64::INFINITY, f64::NEG_INFINITY, f64::NAN] {
+			assert!(matches!(
+				resolve(ConcreteNumber::Float(x)),
+				Err(ClgnDecodingError::Shape { .. })
+			));
+		}
+	}
+}
@@ -0,0 +1,342 @@
+//! SVG has no notion of word wrapping; a `<text>` element is a single line unless it's
+//! manually broken into `<tspan>`s. This file provides [`TextWrap`], which does that
+//! breaking on behalf of an [`OtherTag`](super::OtherTag) whose `tag` is `"text"`, and
+//! [`WritingMode`], which sets the direction that such a tag's lines run in.
+//!
+//! Since Collagen doesn't read font metrics, the width of a line is estimated as its
+//! number of characters times an average character width (a fraction of the font
+//! size). This is crude, but usually close enough to produce reasonable line breaks;
+//! `avg_char_width` can be tuned for fonts that are especially wide or narrow.
+
+use super::{AttrKVValueVec, ClgnDecodingResult};
+use crate::to_svg::svg_writable::ClgnDecodingError;
+use crate::to_svg::xml_text::XmlText;
+use serde::{Deserialize, Serialize};
+
+/// The font size assumed when a `<text>` doesn't specify a (numeric) `font-size`. This
+/// is what browsers use by default.
+const DEFAULT_FONT_SIZE: f64 = 16.0;
+
+/// The width of an average character, as a fraction of the font size
+const DEFAULT_AVG_CHAR_WIDTH: f64 = 0.55;
+
+/// The distance between consecutive lines' baselines, in `em`s
+const DEFAULT_LINE_HEIGHT: f64 = 1.2;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TextAlign {
+	Left,
+	Center,
+	Right,
+}
+
+impl Default for TextAlign {
+	fn default() -> Self {
+		Self::Left
+	}
+}
+
+impl TextAlign {
+	fn text_anchor(self) -> &'static str {
+		use TextAlign::*;
+		match self {
+			Left => "start",
+			Center => "middle",
+			Right => "end",
+		}
+	}
+}
+
+/// The direction that lines of text run in, given as a `<text>`'s `writing_mode`
+///
+/// - `"horizontal"`: Left to right (or right to left), with lines stacked top to bottom.
+///   This is the default.
+/// - `"vertical"`: Top to bottom, with lines stacked right to left, as is traditional
+///   for Chinese, Japanese, and Korean. CJK characters are upright, while others, such
+///   as Latin letters, are rotated 90° clockwise.
+/// - `"vertical-upright"`: As `"vertical"`, except that all characters are upright.
+///
+/// The tag is given the corresponding `writing-mode` (and `text-orientation`) attributes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum WritingMode {
+	Horizontal,
+	Vertical,
+	VerticalUpright,
+}
+
+impl Default for WritingMode {
+	fn default() -> Self {
+		Self::Horizontal
+	}
+}
+
+impl WritingMode {
+	fn is_vertical(self) -> bool {
+		self != WritingMode::Horizontal
+	}
+
+	/// The attributes that set this writing mode
+	pub(crate) fn attrs(self) -> &'static [(&'static str, &'static str)] {
+		use WritingMode::*;
+		match self {
+			Horizontal => &[("writing-mode", "horizontal-tb")],
+			Vertical => &[("writing-mode", "vertical-rl")],
+			VerticalUpright => &[
+				("writing-mode", "vertical-rl"),
+				("text-orientation", "upright"),
+			],
+		}
+	}
+
+	/// The width of an average character, as a fraction of the font size. Vertical text
+	/// is mostly CJK characters set upright, each of which advances a whole `em`.
+	fn default_avg_char_width(self) -> f64 {
+		if self.is_vertical() {
+			1.0
+		} else {
+			DEFAULT_AVG_CHAR_WIDTH
+		}
+	}
+}
+
+/// Options for wrapping the text of a `<text>` tag into lines no wider than `width`.
+///
+/// # Properties
+///
+/// - `width`
+///   - Type: number
+///   - Required: Yes.
+///   - Description: The maximum width of a line, in user units. Lines are broken at
+///     whitespace; a single word wider than `width` gets a line to itself. If the
+///     `<text>`'s `writing_mode` is vertical, this is the maximum height of a line.
+/// - `align`
+///   - Type: string, one of `"left"`, `"center"`, or `"right"`
+///   - Required: No. Missing is equivalent to `"left"`.
+///   - Description: How to align each line within the box of width `width` whose left
+///     edge is the `<text>`'s `x`. If the `<text>`'s `writing_mode` is vertical, `"left"`,
+///     `"center"`, and `"right"` align each line to the top, middle, and bottom of the
+///     box of height `width` whose top edge is the `<text>`'s `y`, and lines run from
+///     the `<text>`'s `x` leftward.
+/// - `line_height`
+///   - Type: number
+///   - Required: No. Missing is equivalent to `1.2`.
+///   - Description: The distance between consecutive lines, in `em`s.
+/// - `avg_char_width`
+///   - Type: number
+///   - Required: No. Missing is equivalent to `0.55`, or `1` if the `<text>`'s
+///     `writing_mode` is vertical.
+///   - Description: The width of an average character, as a fraction of the font size.
+///     Used to estimate the width of each line.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TextWrap {
+	width: f64,
+
+	#[serde(default)]
+	align: TextAlign,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	line_height: Option<f64>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	avg_char_width: Option<f64>,
+}
+
+impl TextWrap {
+	/// Split `text` into lines at whitespace such that each line's estimated width is at
+	/// most `self.width` (unless it consists of a single word that is itself too wide).
+	/// Newlines in `text` always start a new line.
+	pub(crate) fn break_lines<'t>(
+		&self,
+		text: &'t str,
+		font_size: f64,
+		writing_mode: WritingMode,
+	) -> Vec<Vec<&'t str>> {
+		let avg_char_width = self
+			.avg_char_width
+			.unwrap_or_else(|| writing_mode.default_avg_char_width());
+		let char_width = font_size * avg_char_width;
+		let line_width = |n_chars: usize| n_chars as f64 * char_width;
+
+		let mut lines = Vec::new();
+		for paragraph in text.lines() {
+			let mut line = Vec::<&str>::new();
+			let mut n_chars = 0;
+
+			for word in paragraph.split_whitespace() {
+				let word_len = word.chars().count();
+				// Account for the space separating this word from the previous one
+				let new_n_chars = if line.is_empty() {
+					word_len
+				} else {
+					n_chars + 1 + word_len
+				};
+
+				if !line.is_empty() && line_width(new_n_chars) > self.width {
+					lines.push(std::mem::take(&mut line));
+					n_chars = word_len;
+				} else {
+					n_chars = new_n_chars;
+				}
+				line.push(word);
+			}
+
+			lines.push(line);
+		}
+
+		lines
+	}
+
+	/// Produce the markup of the `<tspan>`s that make up the wrapped version of `text`.
+	/// `attrs` are the `<text>`'s attributes, from which the position and font size are
+	/// read, and `writing_mode` is its writing mode.
+	pub(crate) fn to_tspans(
+		&self,
+		text: &XmlText,
+		attrs: &AttrKVValueVec,
+		writing_mode: WritingMode,
+	) -> ClgnDecodingResult<String> {
+		let numeric_attr = |name: &str| -> ClgnDecodingResult<Option<f64>> {
+			match attrs.iter().find(|(k, _)| *k == name) {
+				None => Ok(None),
+				Some((_, v)) => match v.to_number() {
+					Some(x) => Ok(Some(x)),
+					None => Err(ClgnDecodingError::Text {
+						msg: format!(
+							"To wrap text, the `{}` of a `<text>` must be a number; got {:?}",
+							name,
+							v.to_maybe_string().unwrap_or_default()
+						),
+					}),
+				},
+			}
+		};
+
+		// Lines run along the x axis and are stacked downward, or, in vertical writing
+		// modes, run along the y axis and are stacked leftward
+		let (line_axis, stack_axis, stack_sign) = if writing_mode.is_vertical() {
+			("y", "x", -1.0)
+		} else {
+			("x", "y", 1.0)
+		};
+		let line_start = numeric_attr(line_axis)?.unwrap_or(0.0);
+		let font_size = numeric_attr("font-size")?.unwrap_or(DEFAULT_FONT_SIZE);
+		let line_height = self.line_height.unwrap_or(DEFAULT_LINE_HEIGHT);
+
+		let anchor_pos = match self.align {
+			TextAlign::Left => line_start,
+			TextAlign::Center => line_start + self.width / 2.0,
+			TextAlign::Right => line_start + self.width,
+		};
+		let anchor = self.align.text_anchor();
+
+		let mut tspans = String::new();
+		let lines = self.break_lines(text.as_str(), font_size, writing_mode);
+		let mut prev_i = 0;
+		for (i, line) in lines.into_iter().enumerate() {
+			// A `<tspan>` with no characters isn't moved by its `dy` (or `dx`), so, rather
+			// than writing one, the blank line's space is added to the next line's shift
+			if line.is_empty() {
+				continue;
+			}
+			let line = text.like(line.join(" "));
+			let shift = if i == 0 {
+				0.0
+			} else {
+				// Rounded so that, e.g., 3 × 1.2 isn't written as 3.5999999999999996
+				(stack_sign * line_height * (i - prev_i) as f64 * 1e9).round() / 1e9
+			};
+			prev_i = i;
+
+			tspans.push_str(&format!(
+				r#"<tspan {}="{}" d{}="{}em" text-anchor="{}">{}</tspan>"#,
+				line_axis,
+				anchor_pos,
+				stack_axis,
+				shift,
+				anchor,
+				line.to_markup()
+			));
+		}
+
+		Ok(tspans)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn wrap(width: f64) -> TextWrap {
+		TextWrap {
+			width,
+			align: TextAlign::Left,
+			line_height: None,
+			avg_char_width: Some(1.0),
+		}
+	}
+
+	#[track_caller]
+	fn test_lines(width: f64, text: &str, expected: &[&str]) {
+		let lines = wrap(width)
+			.break_lines(text, 1.0, WritingMode::Horizontal)
+			.into_iter()
+			.map(|line| line.join(" "))
+			.collect::<Vec<_>>();
+		assert_eq!(lines, expected);
+	}
+
+	#[test]
+	fn break_lines() {
+		test_lines(10.0, "", &[]);
+		test_lines(10.0, "abc", &["abc"]);
+		test_lines(10.0, "  abc  ", &["abc"]);
+		test_lines(7.0, "abc def", &["abc def"]);
+		test_lines(6.0, "abc def", &["abc", "def"]);
+		test_lines(6.0, "abc   def", &["abc", "def"]);
+		test_lines(2.0, "abc def", &["abc", "def"]);
+		test_lines(
+			11.0,
+			"the quick brown fox jumps over the lazy dog",
+			&["the quick", "brown fox", "jumps over", "the lazy", "dog"],
+		);
+
+		// Explicit line breaks are kept
+		test_lines(100.0, "abc\ndef", &["abc", "def"]);
+		test_lines(100.0, "abc\n\ndef", &["abc", "", "def"]);
+
+		// Width is measured in characters, not bytes
+		test_lines(3.0, "äöü äöü", &["äöü", "äöü"]);
+	}
+
+	#[test]
+	fn blank_lines() {
+		let tspans = |text: &str, writing_mode| {
+			wrap(100.0)
+				.to_tspans(
+					&XmlText::new(text, true),
+					&AttrKVValueVec::default(),
+					writing_mode,
+				)
+				.unwrap()
+		};
+
+		// Blank lines get no `<tspan>`s of their own, but still take up space
+		assert_eq!(
+			tspans("\nabc\n\n\ndef\n\n", WritingMode::Horizontal),
+			concat!(
+				r#"<tspan x="0" dy="1.2em" text-anchor="start">abc</tspan>"#,
+				r#"<tspan x="0" dy="3.6em" text-anchor="start">def</tspan>"#,
+			)
+		);
+		assert_eq!(
+			tspans("abc\n\ndef", WritingMode::Vertical),
+			concat!(
+				r#"<tspan y="0" dx="0em" text-anchor="start">abc</tspan>"#,
+				r#"<tspan y="0" dx="-2.4em" text-anchor="start">def</tspan>"#,
+			)
+		);
+	}
+}
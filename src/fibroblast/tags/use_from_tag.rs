@@ -0,0 +1,332 @@
+use super::animation::Animation;
+use super::any_child_tag::AnyChildTag;
+use super::clip::{ClipKind, ClipShape};
+use super::common_tag_fields::CommonTagFields;
+use super::link::Link;
+use crate::fibroblast::data_types::{
+	AttrKVValueVec, DecodingContext, SimpleValue, TagVariables, XmlAttrs,
+};
+use crate::fibroblast::Fibroblast;
+use crate::to_svg::svg_writable::{ClgnDecodingError, ClgnDecodingResult};
+use crate::to_svg::xml_text::XmlText;
+use lazycell::LazyCell;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+/// A symbol exported by a library skeleton, in its root tag's `symbols`. It's written
+/// as a `<symbol>` with the given attributes and children.
+///
+/// # Properties
+///
+/// - `attrs`
+///   - Type: object, with values either number or string
+///   - Required: No. Missing is equivalent to `{}`.
+///   - Description: The attributes of the `<symbol>`, such as its `viewBox`. Variables
+///     (those of the library's root tag) are substituted, and palette colors resolved,
+///     as for any tag. The `<symbol>`'s `id` is generated, so it may not be given here.
+/// - `children`
+///   - Type: list of child tags
+///   - Required: No. Missing is equivalent to `[]`.
+///   - Description: The contents of the `<symbol>`. Paths in them (e.g., `image_path`)
+///     are relative to the library skeleton.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SymbolDef<'a> {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	attrs: Option<XmlAttrs>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	children: Vec<AnyChildTag<'a>>,
+}
+
+impl<'a> SymbolDef<'a> {
+	pub(crate) fn fresh_clone<'b>(&self) -> SymbolDef<'b> {
+		SymbolDef {
+			attrs: self.attrs.clone(),
+			children: self.children.iter().map(AnyChildTag::fresh_clone).collect(),
+		}
+	}
+
+	/// The attributes of the `<symbol>`, with variables substituted
+	pub(crate) fn attrs(
+		&self,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<AttrKVValueVec<'_>> {
+		let attrs = match &self.attrs {
+			Some(attrs) => attrs,
+			None => return Ok(AttrKVValueVec::default()),
+		};
+		if attrs.contains_key("id") {
+			return Err(ClgnDecodingError::Library {
+				msg: "A symbol's `id` is generated, so it may not be given in its `attrs`"
+					.to_owned(),
+			});
+		}
+
+		let mut attrs = context
+			.sub_vars_into_attrs(attrs.0.iter().map(|(k, v)| (k.as_ref(), Cow::Borrowed(v))))?;
+		context.resolve_palette_colors(&mut attrs)?;
+
+		Ok(attrs)
+	}
+
+	pub(crate) fn children(&self) -> &[AnyChildTag<'a>] {
+		&self.children
+	}
+}
+
+/// Which symbol a [`UseFromTag`] uses, and which library it comes from
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SymbolRef {
+	skeleton: String,
+	symbol: String,
+}
+
+/// A tag that uses a symbol from a *library*: another skeleton whose root tag exports
+/// named symbols in its `symbols` (see [`RootTag`](super::RootTag)). The symbol is
+/// written once, as a `<symbol>` in a `<defs>`, right before the first tag that uses
+/// it, and each tag that uses it becomes a `<use>` that refers to it. So an icon used a
+/// dozen times is only embedded once.
+///
+/// This sits between a [`ContainerTag`](super::ContainerTag), which includes a whole
+/// skeleton (once per inclusion), and copying the icon's tags into each skeleton that
+/// needs it.
+///
+/// # Properties
+///
+/// - `use_from`
+///   - Type: object with the following keys
+///     - `skeleton` (string; required): The path to the library skeleton, relative to
+///       this skeleton.
+///     - `symbol` (string; required): The name of the symbol to use. It's an error for
+///       the library not to export a symbol of this name.
+///   - Required: Yes.
+/// - Other: `UseFromTag` accepts all properties in [`CommonTagFields`]. The `<use>`'s
+///   `x`, `y`, `width`, and `height` in `attrs` position and size the symbol. Since the
+///   `<use>` is given an `href` referring to the symbol, `attrs` may not contain
+///   `href`.
+///
+/// # Example
+///
+/// ```json
+/// { "use_from": { "skeleton": "../icons", "symbol": "home" }, "attrs": { "x": 10, "y": 10, "width": 24, "height": 24 } }
+/// ```
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UseFromTag<'a> {
+	use_from: SymbolRef,
+
+	#[serde(flatten)]
+	common_tag_fields: CommonTagFields<'a>,
+
+	#[serde(skip)]
+	#[serde(default)]
+	_library: LazyCell<Box<Fibroblast<'a>>>,
+}
+
+impl<'a> UseFromTag<'a> {
+	/// A copy of `self` whose library has not yet been loaded
+	pub(crate) fn fresh_clone<'b>(&self) -> UseFromTag<'b> {
+		UseFromTag {
+			use_from: self.use_from.clone(),
+			common_tag_fields: self.common_tag_fields.fresh_clone(),
+			_library: LazyCell::new(),
+		}
+	}
+
+	/// The library skeleton that the symbol comes from, which is loaded the first time
+	/// it's needed
+	pub(crate) fn library(
+		&self,
+		context: &DecodingContext<'a>,
+	) -> ClgnDecodingResult<&Fibroblast<'a>> {
+		if let Some(library) = self._library.borrow() {
+			return Ok(library);
+		}
+
+		let context = context.clone();
+		let abs_library_path = self.abs_library_path(&context)?;
+		context.replace_root(&abs_library_path);
+
+		let library = Fibroblast::from_dir_with_context(&abs_library_path, context)?;
+		// Can't fail, since the cell was empty and filling it didn't borrow `self`
+		let _ = self._library.fill(Box::new(library));
+		Ok(self._library.borrow().unwrap())
+	}
+
+	fn abs_library_path(&self, context: &DecodingContext) -> ClgnDecodingResult<PathBuf> {
+		crate::utils::paths::pathsep_aware_join(&*context.get_root(), &self.use_from.skeleton)
+	}
+
+	/// The definition of the symbol that this tag uses
+	pub(crate) fn symbol_def(
+		&self,
+		context: &DecodingContext<'a>,
+	) -> ClgnDecodingResult<&SymbolDef<'a>> {
+		let library = self.library(context)?;
+		library
+			.root
+			.symbol(&self.use_from.symbol)
+			.ok_or_else(|| ClgnDecodingError::Library {
+				msg: format!(
+					"The library {:?} has no symbol {:?}; its symbols are {:?}",
+					self.use_from.skeleton,
+					self.use_from.symbol,
+					library.root.symbol_names()
+				),
+			})
+	}
+
+	/// The `id` of the `<symbol>` that this tag uses, and whether it's the first time
+	/// that symbol has been used in the document being written (in which case it has yet
+	/// to be written)
+	pub(crate) fn symbol_id(
+		&self,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<(String, bool)> {
+		let library_path = self.abs_library_path(context)?;
+		// The same library may be referred to by different paths
+		let library_path = context.file_source().canonicalize(library_path);
+		Ok(context.import_symbol(library_path, &self.use_from.symbol))
+	}
+
+	/// The `href` (and, if requested, `xlink:href`) that refers to the symbol
+	pub(super) fn get_generated_attrs(
+		&self,
+		context: &DecodingContext,
+	) -> ClgnDecodingResult<Vec<(&'static str, SimpleValue)>> {
+		if self.base_attrs().contains_key("href") {
+			return Err(ClgnDecodingError::Library {
+				msg: format!(
+					"A tag using the symbol {:?} may not set `href` itself",
+					self.use_from.symbol
+				),
+			});
+		}
+
+		let (id, _) = self.symbol_id(context)?;
+		let href = SimpleValue::Text(format!("#{}", id));
+		Ok(if context.xlink_href() {
+			vec![("href", href.clone()), ("xlink:href", href)]
+		} else {
+			vec![("href", href)]
+		})
+	}
+
+	pub(super) fn tag_name(&self) -> &str {
+		"use"
+	}
+
+	pub(super) fn base_vars(&self) -> &TagVariables {
+		self.common_tag_fields.base_vars()
+	}
+
+	pub(super) fn base_attrs(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_attrs()
+	}
+
+	pub(super) fn base_style(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_style()
+	}
+
+	pub(super) fn base_class_styles(&self) -> &XmlAttrs {
+		self.common_tag_fields.base_class_styles()
+	}
+
+	pub(super) fn base_children(&self) -> &[AnyChildTag<'a>] {
+		self.common_tag_fields.base_children()
+	}
+
+	pub(super) fn text(&self, context: &DecodingContext) -> ClgnDecodingResult<XmlText<'_>> {
+		self.common_tag_fields.text(context)
+	}
+
+	pub(super) fn z(&self) -> f64 {
+		self.common_tag_fields.z()
+	}
+
+	pub(super) fn base_animations(&self) -> &[Animation] {
+		self.common_tag_fields.base_animations()
+	}
+
+	pub(super) fn base_clips(&self) -> Vec<(ClipKind, &ClipShape)> {
+		self.common_tag_fields.base_clips()
+	}
+
+	pub(super) fn base_link(&self) -> Option<&Link> {
+		self.common_tag_fields.base_link()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::path::Path;
+
+	fn render(skeleton_dir: &Path, manifest: &str) -> ClgnDecodingResult<String> {
+		std::fs::write(skeleton_dir.join("collagen.json"), manifest).unwrap();
+		Fibroblast::from_dir(skeleton_dir)?.to_svg_string()
+	}
+
+	#[test]
+	fn use_from() {
+		let dir = tempfile::tempdir().unwrap();
+		let icons = dir.path().join("icons");
+		std::fs::create_dir(&icons).unwrap();
+		std::fs::write(
+			icons.join("collagen.json"),
+			r#"{
+				"vars": { "color": "red" },
+				"symbols": {
+					"dot": {
+						"attrs": { "viewBox": "0 0 2 2" },
+						"children": [{ "tag": "circle", "attrs": { "cx": 1, "cy": 1, "r": 1, "fill": "{color}" } }]
+					}
+				}
+			}"#,
+		)
+		.unwrap();
+		let skeleton = dir.path().join("skeleton");
+		std::fs::create_dir(&skeleton).unwrap();
+
+		assert_eq!(
+			render(
+				&skeleton,
+				r#"{
+					"children": [
+						{ "use_from": { "skeleton": "../icons", "symbol": "dot" }, "attrs": { "width": 2 } },
+						{ "use_from": { "skeleton": "../icons/", "symbol": "dot" }, "attrs": { "x": 5 } }
+					]
+				}"#
+			)
+			.unwrap(),
+			r##"<svg xmlns="http://www.w3.org/2000/svg"><defs><symbol id="clgn-symbol-dot-0" viewBox="0 0 2 2"><circle cx="1" cy="1" fill="red" r="1"></circle></symbol></defs><use width="2" href="#clgn-symbol-dot-0"></use><use x="5" href="#clgn-symbol-dot-0"></use></svg>"##
+		);
+
+		for manifest in vec![
+			r#"{ "children": [{ "use_from": { "skeleton": "../icons", "symbol": "star" } }] }"#,
+			r##"{ "children": [{ "use_from": { "skeleton": "../icons", "symbol": "dot" }, "attrs": { "href": "#x" } }] }"##,
+		]
+		.into_iter()
+		{
+			assert!(matches!(
+				render(&skeleton, manifest),
+				Err(ClgnDecodingError::Library { .. })
+			));
+		}
+
+		std::fs::write(
+			icons.join("collagen.json"),
+			r#"{ "symbols": { "dot": { "attrs": { "id": "dot" } } } }"#,
+		)
+		.unwrap();
+		assert!(matches!(
+			render(
+				&skeleton,
+				r#"{ "children": [{ "use_from": { "skeleton": "../icons", "symbol": "dot" } }] }"#
+			),
+			Err(ClgnDecodingError::Library { .. })
+		));
+	}
+}
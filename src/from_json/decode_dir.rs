@@ -3,29 +3,345 @@
 //! and files)
 
 use super::decoding_error::{ClgnDecodingError, ClgnDecodingResult};
-use crate::fibroblast::{data_types::DecodingContext, tags::RootTag, Fibroblast};
+use super::include::expand_includes;
+use super::patch::ManifestPatch;
+use super::unique_keys;
+use crate::fibroblast::data_types::context::is_valid_var_name;
+use crate::fibroblast::data_types::DecodingContext;
+use crate::fibroblast::tags::{ImageKind, RootTag};
+use crate::fibroblast::Fibroblast;
+use crate::utils::file_source::FileSource;
+use crate::utils::paths::long_path_safe;
 use serde_json;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// The deepest that the expanded manifest's JSON may be nested. This is the limit that
+/// `serde_json` places on each file it parses, which the manifest's fragments, each
+/// within the limit on its own, may together exceed.
+const MAX_JSON_DEPTH: usize = 128;
+
+/// How deeply `value` is nested; a scalar's depth is 0, `[]`'s is 1, `[[]]`'s is 2, and
+/// so on. Computed without recursing, so that it can't overflow the stack.
+fn json_depth(value: &serde_json::Value) -> usize {
+	let mut max_depth = 0;
+	let mut stack = vec![(value, 0)];
+	while let Some((value, depth)) = stack.pop() {
+		let children: Box<dyn Iterator<Item = &serde_json::Value>> = match value {
+			serde_json::Value::Array(values) => Box::new(values.iter()),
+			serde_json::Value::Object(object) => Box::new(object.values()),
+			_ => continue,
+		};
+		max_depth = max_depth.max(depth + 1);
+		stack.extend(children.map(|child| (child, depth + 1)));
+	}
+
+	max_depth
+}
+
+/// Check the parts of the tags in `value` that `serde` can't explain the problems with.
+/// Since there are several kinds of child tag, for a tag that fails to decode, `serde`
+/// would only say that it didn't match any of them, rather than what was wrong and what's
+/// allowed instead. Specifically, check that:
+///
+/// - Each image tag (each object with an `image_path`) that has a `kind` has a valid one.
+///   An invalid kind would fail to decode anyway.
+/// - Each repeat tag's `var`, if given, is a valid variable name. An invalid name would
+///   decode, but since `"{...}"` can't refer to it, the template couldn't use it.
+/// - Each container tag's `name`, if given, is a valid variable name, since it's the
+///   first part of the names of the variables the container exports.
+/// - No tag defines a variable whose name is reserved for those set automatically
+///   ([`DecodingContext::RESERVED_VAR_NAMES`]), whether in its `vars` or as a repeat
+///   tag's `var`. Such a variable would silently shadow the one set automatically.
+fn check_tags(value: &serde_json::Value, manifest_path: &Path) -> ClgnDecodingResult<()> {
+	let mut stack = vec![value];
+	while let Some(value) = stack.pop() {
+		let object = match value {
+			serde_json::Value::Array(values) => {
+				stack.extend(values);
+				continue;
+			}
+			serde_json::Value::Object(object) => object,
+			_ => continue,
+		};
+		stack.extend(object.values());
+
+		if let (
+			Some(serde_json::Value::String(image_path)),
+			Some(serde_json::Value::String(kind)),
+		) = (object.get("image_path"), object.get("kind"))
+		{
+			if let Err(msg) = kind.parse::<ImageKind>() {
+				return Err(ClgnDecodingError::Image {
+					msg: format!(
+						"{:?}: the image {:?} has an {}",
+						manifest_path, image_path, msg
+					),
+				});
+			}
+		}
+
+		if let (Some(serde_json::Value::String(clgn_path)), Some(name)) =
+			(object.get("clgn_path"), object.get("name"))
+		{
+			if !name.as_str().map_or(false, is_valid_var_name) {
+				return Err(ClgnDecodingError::Container {
+					msg: format!(
+						"{:?}: the container of {:?} has the name {}, but its name must be a \
+						 valid variable name, consisting only of letters, digits, and \
+						 underscores",
+						manifest_path, clgn_path, name
+					),
+				});
+			}
+		}
+
+		let reserved_var_error = |name: &str| ClgnDecodingError::ReservedVariable {
+			name: name.to_owned(),
+			manifest_path: manifest_path.to_owned(),
+		};
+
+		if let Some(serde_json::Value::Object(vars)) = object.get("vars") {
+			if let Some(name) = vars
+				.keys()
+				.find(|name| DecodingContext::RESERVED_VAR_NAMES.contains(&name.as_str()))
+			{
+				return Err(reserved_var_error(name));
+			}
+		}
+
+		if let Some(serde_json::Value::String(var)) = object
+			.get("repeat")
+			.filter(|_| object.contains_key("template"))
+			.and_then(|repeat| repeat.get("var"))
+		{
+			if DecodingContext::RESERVED_VAR_NAMES.contains(&var.as_str()) {
+				return Err(reserved_var_error(var));
+			}
+			if !is_valid_var_name(var) {
+				return Err(ClgnDecodingError::Repeat {
+					msg: format!(
+						"{:?}: in `repeat`: `var` must be a valid variable name, consisting only \
+						 of letters, digits, and underscores; got {:?}",
+						manifest_path, var
+					),
+				});
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// The path to the manifest of the skeleton at `skeleton_path`: its `collagen.json`, or,
+/// if it has none, its `collagen.json5` (see [`super::json5`]). It's an error for a
+/// skeleton to have both. Whether each exists is checked in `files`.
+#[cfg_attr(not(feature = "json5"), allow(unused_variables))]
+pub(crate) fn manifest_path(
+	skeleton_path: impl AsRef<Path>,
+	files: &FileSource,
+) -> ClgnDecodingResult<PathBuf> {
+	let json_path = skeleton_path.as_ref().join("collagen.json");
+
+	#[cfg(feature = "json5")]
+	{
+		let json5_path = skeleton_path.as_ref().join("collagen.json5");
+		if files.exists(&json5_path) {
+			if files.exists(&json_path) {
+				return Err(ClgnDecodingError::AmbiguousManifest(vec![
+					json_path, json5_path,
+				]));
+			}
+			return Ok(long_path_safe(json5_path));
+		}
+	}
+
+	Ok(long_path_safe(json_path))
+}
+
+/// Read the contents of the manifest at `manifest_path` from `files` as JSON. A JSON5
+/// manifest is translated to JSON.
+pub(crate) fn read_manifest(
+	manifest_path: &Path,
+	files: &FileSource,
+) -> ClgnDecodingResult<Vec<u8>> {
+	let manifest = files.read_or_err(manifest_path)?;
+
+	#[cfg(feature = "json5")]
+	if manifest_path.extension() == Some("json5".as_ref()) {
+		return super::json5::to_json(&manifest)
+			.map_err(|e| ClgnDecodingError::JsonDecode(e, manifest_path.to_owned()));
+	}
+
+	Ok(manifest)
+}
+
+/// Decode the contents of a manifest (which were read from `manifest_path`) into a
+/// `RootTag`, expanding its include directives (see [`super::include`]), whose fragments
+/// are read from `files`, and then applying `patches` (see [`super::patch`]). Returns
+/// the `RootTag` along with the paths of the fragments that were included.
+pub(crate) fn decode_manifest<'a>(
+	manifest: &[u8],
+	manifest_path: &Path,
+	patches: &[ManifestPatch],
+	files: &FileSource,
+) -> ClgnDecodingResult<(RootTag<'a>, Vec<PathBuf>)> {
+	let json_error = |e| ClgnDecodingError::JsonDecode(e, manifest_path.to_owned());
+
+	let mut value = unique_keys::from_slice(manifest).map_err(json_error)?;
+	let skeleton_path = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+	let included = expand_includes(&mut value, skeleton_path, files)?;
+	for patch in patches {
+		patch.apply(&mut value)?;
+	}
+	check_tags(&value, manifest_path)?;
+
+	// Decoding from the original text gives errors with line and column numbers, so only
+	// decode the expanded manifest if something was actually included or patched
+	let root = if included.is_empty() && patches.is_empty() {
+		// The `Value` is a copy of everything in the manifest, so holding onto it while
+		// the manifest is decoded again would double the memory needed to decode a large
+		// manifest
+		drop(value);
+		serde_json::from_slice::<RootTag>(manifest)
+	} else {
+		let depth = json_depth(&value);
+		if depth > MAX_JSON_DEPTH {
+			return Err(ClgnDecodingError::Nesting {
+				msg: format!(
+					"The manifest {:?}, with its fragments included and patches applied, is \
+					 nested {} deep, but may be nested at most {} deep",
+					manifest_path, depth, MAX_JSON_DEPTH
+				),
+			});
+		}
+		serde_json::from_value::<RootTag>(value)
+	}
+	.map_err(json_error)?;
+
+	Ok((root, included))
+}
 
 impl<'a> Fibroblast<'a> {
 	pub fn from_dir(path: impl AsRef<Path>) -> ClgnDecodingResult<Self> {
+		Fibroblast::from_dir_with_patches(path, &[])
+	}
+
+	/// Like [`Fibroblast::from_dir`], except that `patches` are applied to the manifest
+	/// before it's decoded
+	pub fn from_dir_with_patches(
+		path: impl AsRef<Path>,
+		patches: &[ManifestPatch],
+	) -> ClgnDecodingResult<Self> {
 		let path = path.as_ref();
 		let context = DecodingContext::new_at_root(path);
-		Fibroblast::from_dir_with_context(path, context)
+		Fibroblast::decode(path, context, patches)
+	}
+
+	/// Like [`Fibroblast::from_dir_with_patches`], except that the skeleton's files are
+	/// read from `files`, a map from their paths (relative to the skeleton's folder) to
+	/// their contents, instead of from disk
+	pub(crate) fn from_files(
+		files: impl IntoIterator<Item = (PathBuf, Vec<u8>)>,
+		patches: &[ManifestPatch],
+	) -> ClgnDecodingResult<Self> {
+		let path = Path::new("");
+		let context = DecodingContext::new_at_root(path);
+		context.set_file_source(FileSource::in_memory(files));
+		Fibroblast::decode(path, context, patches)
 	}
 
 	pub fn from_dir_with_context(
 		path: impl AsRef<Path>,
 		context: DecodingContext<'a>,
 	) -> ClgnDecodingResult<Self> {
-		let path = path.as_ref();
+		Fibroblast::decode(path.as_ref(), context, &[])
+	}
 
-		let manifest_path = path.join("collagen.json");
-		let reader = std::fs::File::open(&manifest_path)
-			.map_err(|e| ClgnDecodingError::Io(e, manifest_path.clone()))?;
-		let root = serde_json::from_reader::<_, RootTag>(reader)
-			.map_err(|e| ClgnDecodingError::JsonDecode(e, manifest_path))?;
+	fn decode(
+		path: &Path,
+		context: DecodingContext<'a>,
+		patches: &[ManifestPatch],
+	) -> ClgnDecodingResult<Self> {
+		let files = context.file_source();
+		let manifest_path = manifest_path(path, &files)?;
+		let manifest = read_manifest(&manifest_path, &files)?;
+		let (root, _) = decode_manifest(&manifest, &manifest_path, patches, &files)?;
 
 		Ok(Fibroblast { root, context })
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reserved_vars() {
+		let decode = |manifest: &str| {
+			decode_manifest(
+				manifest.as_bytes(),
+				Path::new("collagen.json"),
+				&[],
+				&FileSource::Disk,
+			)
+			.map(|_| ())
+		};
+
+		assert!(decode(r#"{ "vars": { "width": 1 }, "children": [{ "tag": "rect" }] }"#).is_ok());
+		for manifest in vec![
+			r#"{ "vars": { "canvas_width": 1 } }"#,
+			r#"{ "children": [{ "tag": "g", "vars": { "parent_height": "1" } }] }"#,
+			r#"{
+				"children": [{
+					"repeat": { "count": 2, "var": "parent_width" },
+					"template": { "tag": "rect" }
+				}]
+			}"#,
+		]
+		.into_iter()
+		{
+			assert!(
+				matches!(
+					decode(manifest),
+					Err(ClgnDecodingError::ReservedVariable { .. })
+				),
+				"{}",
+				manifest
+			);
+		}
+	}
+	#[cfg(feature = "json5")]
+	#[test]
+	fn json5_manifests() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		let path = |name: &str| skeleton_dir.path().join(name);
+		std::fs::write(
+			path("collagen.json5"),
+			"// A comment\n{ children: [{ tag: 'rect', attrs: { width: .5, }, },], }",
+		)
+		.unwrap();
+		assert_eq!(
+			Fibroblast::from_dir(skeleton_dir.path())
+				.unwrap()
+				.to_svg_string()
+				.unwrap(),
+			r#"<svg xmlns="http://www.w3.org/2000/svg"><rect width="0.5"></rect></svg>"#
+		);
+
+		// Errors in decoding point to the line of the JSON5 they're on
+		std::fs::write(
+			path("collagen.json5"),
+			"{\n  children: [\n    { tag: 1 }] }",
+		)
+		.unwrap();
+		let err = Fibroblast::from_dir(skeleton_dir.path()).unwrap_err();
+		assert!(matches!(err, ClgnDecodingError::JsonDecode(..)));
+		assert!(err.to_string().contains("line 3"), "{}", err);
+
+		std::fs::write(path("collagen.json"), "{}").unwrap();
+		assert!(matches!(
+			Fibroblast::from_dir(skeleton_dir.path()),
+			Err(ClgnDecodingError::AmbiguousManifest(..))
+		));
+	}
+}
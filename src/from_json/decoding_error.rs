@@ -23,17 +23,119 @@ pub type ClgnDecodingResult<T> = Result<T, ClgnDecodingError>;
 #[derive(Debug)]
 pub enum ClgnDecodingError {
 	Parse(VariableSubstitutionError),
-	Io(io::Error, PathBuf),
+	/// Failing to read from the file (or stream) at the path
+	IoRead(io::Error, PathBuf),
+	/// Failing to write to (or create) the file (or stream) at the path
+	IoWrite(io::Error, PathBuf),
+	MissingPath {
+		path: PathBuf,
+		near_misses: Vec<PathBuf>,
+	},
 	InvalidPath(PathBuf),
 	Zip(ZipError),
 	JsonDecode(json::Error, PathBuf),
 	Xml(XmlError),
 	ToSvgString(Utf8Error),
-	Image { msg: String },
-	BundledFontNotFound { font_name: String },
+	Image {
+		msg: String,
+	},
+	Text {
+		msg: String,
+	},
+	Shape {
+		msg: String,
+	},
+	Connector {
+		msg: String,
+	},
+	UnknownPaletteColor {
+		name: String,
+	},
+	Theme {
+		msg: String,
+	},
+	Animation {
+		msg: String,
+	},
+	NonFiniteAttribute {
+		tag: String,
+		attr: String,
+		value: String,
+	},
+	JsonPointer {
+		pointer: String,
+		msg: String,
+	},
+	SizeLimit {
+		msg: String,
+	},
+	Font {
+		msg: String,
+	},
+	UndeclaredNamespace {
+		name: String,
+		prefix: String,
+	},
+	Clip {
+		msg: String,
+	},
+	Repeat {
+		msg: String,
+	},
+	Include {
+		msg: String,
+	},
+	Patch {
+		msg: String,
+	},
+	Nesting {
+		msg: String,
+	},
+	MalformedXml {
+		msg: String,
+	},
+	Compatibility {
+		msg: String,
+	},
+	VarExport {
+		msg: String,
+	},
+	Link {
+		msg: String,
+	},
+	Library {
+		msg: String,
+	},
+	Container {
+		msg: String,
+	},
+	/// A skeleton with more than one manifest, at the given paths
+	AmbiguousManifest(Vec<PathBuf>),
+	Credits {
+		msg: String,
+	},
+	/// A manifest defining a variable that's set automatically
+	ReservedVariable {
+		name: String,
+		manifest_path: PathBuf,
+	},
+	BundledFontNotFound {
+		font_name: String,
+	},
 }
 
 impl ClgnDecodingError {
+	/// The error for failing to read the file at `path`. If it doesn't exist, the error
+	/// lists the existing paths closest to it, since the likeliest cause is a typo.
+	pub(crate) fn read(err: io::Error, path: PathBuf) -> Self {
+		if err.kind() == io::ErrorKind::NotFound {
+			let near_misses = crate::utils::paths::near_misses(&path);
+			Self::MissingPath { path, near_misses }
+		} else {
+			Self::IoRead(err, path)
+		}
+	}
+
 	pub fn exit_code(&self) -> i32 {
 		use ClgnDecodingError::*;
 		match self {
@@ -41,11 +143,36 @@ impl ClgnDecodingError {
 			JsonDecode(..) => 4,
 			Xml(..) => 5,
 			InvalidPath(..) => 6,
-			Io(..) => 7,
+			IoRead(..) | IoWrite(..) | MissingPath { .. } => 7,
 			Image { .. } => 8,
+			Text { .. } => 9,
+			Shape { .. } => 10,
+			Connector { .. } => 11,
+			UnknownPaletteColor { .. } => 12,
+			Theme { .. } => 13,
+			Animation { .. } => 14,
+			NonFiniteAttribute { .. } => 15,
+			JsonPointer { .. } => 16,
+			SizeLimit { .. } => 17,
+			Font { .. } => 18,
 			ToSvgString(..) => 19,
+			UndeclaredNamespace { .. } => 20,
+			Clip { .. } => 21,
 			BundledFontNotFound { .. } => 22,
+			Repeat { .. } => 23,
+			Include { .. } => 24,
+			Patch { .. } => 25,
+			Nesting { .. } => 26,
+			MalformedXml { .. } => 27,
+			Compatibility { .. } => 28,
+			VarExport { .. } => 29,
+			Link { .. } => 30,
+			Library { .. } => 31,
+			ReservedVariable { .. } => 32,
 			Zip(..) => 33,
+			Container { .. } => 34,
+			AmbiguousManifest(..) => 35,
+			Credits { .. } => 36,
 		}
 	}
 }
@@ -55,7 +182,22 @@ impl Display for ClgnDecodingError {
 		use ClgnDecodingError::*;
 		match self {
 			Parse(e) => write!(f, "{:?}", e),
-			Io(e, path) => write!(f, "{:?}: {}", path, e),
+			IoRead(e, path) => write!(f, "Could not read {:?}: {}", path, e),
+			IoWrite(e, path) => write!(f, "Could not write to {:?}: {}", path, e),
+			MissingPath { path, near_misses } => {
+				write!(f, "{:?} does not exist", path)?;
+				if let Some((last, rest)) = near_misses.split_last() {
+					write!(f, "; did you mean ")?;
+					for near_miss in rest {
+						write!(f, "{:?}, ", near_miss)?;
+					}
+					if !rest.is_empty() {
+						write!(f, "or ")?;
+					}
+					write!(f, "{:?}?", last)?;
+				}
+				Ok(())
+			}
 			InvalidPath(p) => write!(f, "Invalid path: {:?}", p),
 			Zip(e) => write!(f, "{:?}", e),
 			JsonDecode(e, path) => write!(f, "{:?}: {}", path, e),
@@ -66,6 +208,65 @@ impl Display for ClgnDecodingError {
 				e
 			),
 			Image { msg } => write!(f, "{}", msg),
+			Text { msg } => write!(f, "{}", msg),
+			Shape { msg } => write!(f, "{}", msg),
+			Connector { msg } => write!(f, "{}", msg),
+			UnknownPaletteColor { name } => write!(
+				f,
+				"Unknown palette color '@{}'; it must be defined in the root tag's `palette`",
+				name
+			),
+			Theme { msg } => write!(f, "{}", msg),
+			Animation { msg } => write!(f, "{}", msg),
+			NonFiniteAttribute { tag, attr, value } => write!(
+				f,
+				"The attribute `{}` of a `<{}>` has value {}, which is not a finite number. \
+				 Check the variables and relative units (e.g., `%w`) it's computed from.",
+				attr, tag, value
+			),
+			JsonPointer { pointer, msg } => {
+				write!(f, "Invalid JSON pointer {:?}: {}", pointer, msg)
+			}
+			SizeLimit { msg } => write!(f, "{}", msg),
+			Font { msg } => write!(f, "{}", msg),
+			UndeclaredNamespace { name, prefix } => write!(
+				f,
+				"The namespace prefix `{}` of `{}` has not been declared; add it to the root \
+				 tag's `namespaces`",
+				prefix, name
+			),
+			Clip { msg } => write!(f, "{}", msg),
+			Repeat { msg } => write!(f, "{}", msg),
+			Include { msg } => write!(f, "{}", msg),
+			Patch { msg } => write!(f, "{}", msg),
+			Nesting { msg } => write!(f, "{}", msg),
+			MalformedXml { msg } => write!(f, "{}", msg),
+			Compatibility { msg } => write!(f, "{}", msg),
+			VarExport { msg } => write!(f, "{}", msg),
+			Link { msg } => write!(f, "{}", msg),
+			Library { msg } => write!(f, "{}", msg),
+			Container { msg } => write!(f, "{}", msg),
+			Credits { msg } => write!(f, "{}", msg),
+			AmbiguousManifest(paths) => write!(
+				f,
+				"A skeleton may have only one manifest, but there are {}; remove all but one",
+				paths
+					.iter()
+					.map(|p| format!("{:?}", p))
+					.collect::<Vec<_>>()
+					.join(" and ")
+			),
+			ReservedVariable {
+				name,
+				manifest_path,
+			} => write!(
+				f,
+				"{:?}: the variable `{}` is set automatically, so it may not be defined; the \
+				 names of such variables ({}) are reserved",
+				manifest_path,
+				name,
+				crate::fibroblast::DecodingContext::RESERVED_VAR_NAMES.join(", ")
+			),
 			BundledFontNotFound { font_name } => write!(
 				f,
 				"Requested bundled font '{}' not found; make sure it was bundled when `clgn` was built.",
@@ -98,3 +299,97 @@ impl From<XmlError> for ClgnDecodingError {
 		Self::Xml(err)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fibroblast::Fibroblast;
+	use std::path::Path;
+	use ClgnDecodingError::*;
+
+	/// Assert that `result` is an error of the kind matched by `is_kind` whose message
+	/// contains `path`
+	fn assert_error_names_path<T: std::fmt::Debug>(
+		result: ClgnDecodingResult<T>,
+		is_kind: impl FnOnce(&ClgnDecodingError) -> bool,
+		path: &Path,
+	) {
+		let err = result.unwrap_err();
+		assert!(is_kind(&err), "{:?}", err);
+		assert!(
+			err.to_string().contains(&format!("{:?}", path)),
+			"{:?} doesn't mention {:?}",
+			err.to_string(),
+			path
+		);
+	}
+
+	#[test]
+	fn io_errors_name_their_paths() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		let skeleton_path = skeleton_dir.path();
+		let manifest_path = skeleton_path.join("collagen.json");
+		let image_path = skeleton_path.join("image.png");
+		let font_path = skeleton_path.join("font.woff2");
+
+		// A manifest that's a folder can't be read
+		std::fs::create_dir(&manifest_path).unwrap();
+		assert_error_names_path(
+			Fibroblast::from_dir(skeleton_path),
+			|e| matches!(e, IoRead(..)),
+			&manifest_path,
+		);
+		std::fs::remove_dir(&manifest_path).unwrap();
+
+		std::fs::write(
+			&manifest_path,
+			r#"{ "children": [{ "image_path": "image.png" }] }"#,
+		)
+		.unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_path).unwrap();
+		assert_error_names_path(
+			fibroblast.to_svg_string(),
+			|e| matches!(e, MissingPath { .. }),
+			&image_path,
+		);
+		std::fs::create_dir(&image_path).unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_path).unwrap();
+		assert_error_names_path(
+			fibroblast.to_svg_string(),
+			|e| matches!(e, IoRead(..)),
+			&image_path,
+		);
+
+		std::fs::write(
+			&manifest_path,
+			r#"{ "children": [{ "fonts": [{ "name": "Font", "path": "font.woff2" }] }] }"#,
+		)
+		.unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_path).unwrap();
+		assert_error_names_path(
+			fibroblast.to_svg_string(),
+			|e| matches!(e, MissingPath { .. }),
+			&font_path,
+		);
+
+		std::fs::write(&manifest_path, r#"{ "children": [] }"#).unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_path).unwrap();
+		let out_path = skeleton_path.join("missing").join("out.svg");
+		assert_error_names_path(
+			crate::cli::write_fibroblast_to_file(&fibroblast, &out_path),
+			|e| matches!(e, IoWrite(..)),
+			&out_path,
+		);
+
+		// Failures to write the SVG itself, not just to open the file, name the file
+		#[cfg(target_os = "linux")]
+		{
+			let out_path = Path::new("/dev/full");
+			assert_error_names_path(
+				crate::cli::write_fibroblast_to_file(&fibroblast, out_path),
+				|e| matches!(e, IoWrite(..)),
+				out_path,
+			);
+		}
+	}
+}
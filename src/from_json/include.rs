@@ -0,0 +1,374 @@
+//! Include directives, which let a large manifest be split into several files. An
+//! element of any tag's `children` may be an object of the form
+//!
+//! ```json
+//! { "include": "parts/header.json" }
+//! ```
+//!
+//! where the path is relative to the skeleton folder (i.e., to `collagen.json`). The
+//! file it refers to, a *fragment*, is an object with (optional) `vars` and `children`:
+//!
+//! ```json
+//! {
+//!   "vars": { "header_height": 40 },
+//!   "children": [{ "tag": "rect", "attrs": { "width": "100%w", "height": "{header_height}" } }]
+//! }
+//! ```
+//!
+//! The directive is replaced with the fragment's children, and the fragment's variables
+//! are added to those of the tag whose `children` the directive was in, as though they
+//! had been written there all along. In particular, unlike a
+//! [`ContainerTag`](crate::fibroblast::tags::ContainerTag), an include adds no `<g>` and
+//! doesn't change the folder that asset paths are relative to. A fragment may itself
+//! include other fragments, whose paths are also relative to the skeleton folder.
+//!
+//! It's an error for a variable to be defined both by a fragment and by the tag that
+//! includes it (or by another fragment included in the same tag), since it would be
+//! unclear which definition was meant.
+
+use super::decoding_error::{ClgnDecodingError, ClgnDecodingResult};
+use super::unique_keys;
+use crate::utils::file_source::FileSource;
+use crate::utils::paths::pathsep_aware_join;
+use serde_json::{self as json, Value};
+use std::path::{Path, PathBuf};
+
+/// The key of an include directive
+const INCLUDE_KEY: &str = "include";
+
+/// The keys that a fragment may have
+const FRAGMENT_KEYS: [&str; 2] = ["vars", "children"];
+
+fn include_error(msg: impl std::fmt::Display) -> ClgnDecodingError {
+	ClgnDecodingError::Include {
+		msg: msg.to_string(),
+	}
+}
+
+/// If `value` is an include directive, the path it includes
+fn included_path(value: &Value) -> ClgnDecodingResult<Option<&str>> {
+	let object = match value.as_object() {
+		Some(object) if object.contains_key(INCLUDE_KEY) => object,
+		_ => return Ok(None),
+	};
+
+	if object.len() > 1 {
+		return Err(include_error(format!(
+			"An include directive may only have the key `{}`; got {}",
+			INCLUDE_KEY, value
+		)));
+	}
+	match &object[INCLUDE_KEY] {
+		Value::String(path) => Ok(Some(path)),
+		other => Err(include_error(format!(
+			"The path of an include directive must be a string; got {}",
+			other
+		))),
+	}
+}
+
+/// Expands the include directives in a manifest. See the module docs for more info.
+struct Expander<'p> {
+	skeleton_path: &'p Path,
+
+	/// Where the fragments are read from
+	files: &'p FileSource,
+
+	/// The fragments currently being expanded, outermost first, to catch cycles
+	stack: Vec<PathBuf>,
+
+	/// Every fragment read so far
+	included: Vec<PathBuf>,
+}
+
+impl<'p> Expander<'p> {
+	/// Read and parse the fragment at `path` (relative to the skeleton folder)
+	fn read_fragment(
+		&mut self,
+		path: &str,
+	) -> ClgnDecodingResult<(PathBuf, json::Map<String, Value>)> {
+		let abs_path = pathsep_aware_join(self.skeleton_path, path)?;
+		if self.stack.contains(&abs_path) {
+			let chain = self
+				.stack
+				.iter()
+				.chain(std::iter::once(&abs_path))
+				.map(|p| format!("{:?}", p))
+				.collect::<Vec<_>>();
+			return Err(include_error(format!(
+				"Fragments may not include themselves; {}",
+				chain.join(" includes ")
+			)));
+		}
+
+		let bytes = self.files.read_or_err(&abs_path)?;
+		let fragment = match unique_keys::from_slice(&bytes) {
+			Ok(Value::Object(fragment)) => fragment,
+			Ok(_) => {
+				return Err(include_error(format!(
+					"The fragment {:?} must be an object with keys `vars` and `children`",
+					abs_path
+				)))
+			}
+			Err(e) => return Err(ClgnDecodingError::JsonDecode(e, abs_path)),
+		};
+		if let Some(key) = fragment
+			.keys()
+			.find(|k| !FRAGMENT_KEYS.contains(&k.as_str()))
+		{
+			return Err(include_error(format!(
+				"The fragment {:?} has the key `{}`, but may only have the keys `vars` and \
+				 `children`",
+				abs_path, key
+			)));
+		}
+
+		self.included.push(abs_path.clone());
+		Ok((abs_path, fragment))
+	}
+
+	/// Add `new_vars`, defined by the fragment at `fragment_path`, to `vars`
+	fn merge_vars(
+		vars: &mut json::Map<String, Value>,
+		new_vars: json::Map<String, Value>,
+		fragment_path: &Path,
+	) -> ClgnDecodingResult<()> {
+		for (name, value) in new_vars {
+			if vars.contains_key(&name) {
+				return Err(include_error(format!(
+					"The variable `{}` of the fragment {:?} is already defined by the tag \
+					 that includes it",
+					name, fragment_path
+				)));
+			}
+			vars.insert(name, value);
+		}
+
+		Ok(())
+	}
+
+	/// Expand the include directives among `children`, adding the included variables to
+	/// `vars`
+	fn expand_children(
+		&mut self,
+		children: Vec<Value>,
+		vars: &mut json::Map<String, Value>,
+	) -> ClgnDecodingResult<Vec<Value>> {
+		let mut expanded = Vec::with_capacity(children.len());
+		for mut child in children {
+			let path = match included_path(&child)? {
+				Some(path) => path.to_owned(),
+				None => {
+					self.expand(&mut child)?;
+					expanded.push(child);
+					continue;
+				}
+			};
+
+			let (abs_path, mut fragment) = self.read_fragment(&path)?;
+			let fragment_vars = match fragment.remove("vars") {
+				None => json::Map::new(),
+				Some(Value::Object(fragment_vars)) => fragment_vars,
+				Some(other) => {
+					return Err(include_error(format!(
+						"The `vars` of the fragment {:?} must be an object; got {}",
+						abs_path, other
+					)))
+				}
+			};
+			let fragment_children = match fragment.remove("children") {
+				None => Vec::new(),
+				Some(Value::Array(fragment_children)) => fragment_children,
+				Some(other) => {
+					return Err(include_error(format!(
+						"The `children` of the fragment {:?} must be an array; got {}",
+						abs_path, other
+					)))
+				}
+			};
+
+			// The fragment's own includes add their variables to the fragment's, which
+			// then go to the including tag
+			self.stack.push(abs_path.clone());
+			let mut included_vars = fragment_vars;
+			let fragment_children = self.expand_children(fragment_children, &mut included_vars)?;
+			self.stack.pop();
+
+			Self::merge_vars(vars, included_vars, &abs_path)?;
+			expanded.extend(fragment_children);
+		}
+
+		Ok(expanded)
+	}
+
+	/// Expand the include directives in the `children` of `value` and its descendants
+	fn expand(&mut self, value: &mut Value) -> ClgnDecodingResult<()> {
+		match value {
+			Value::Object(object) => {
+				// If `vars` isn't an object, it's not ours to complain about; the tag will
+				// fail to decode
+				let has_valid_vars = object.get("vars").map_or(true, Value::is_object);
+				let children = match object.get_mut("children") {
+					Some(Value::Array(children)) if has_valid_vars => {
+						Some(std::mem::take(children))
+					}
+					_ => None,
+				};
+
+				if let Some(children) = children {
+					let mut vars = match object.remove("vars") {
+						Some(Value::Object(vars)) => vars,
+						_ => json::Map::new(),
+					};
+
+					let children = self.expand_children(children, &mut vars)?;
+					object.insert("children".to_owned(), Value::Array(children));
+					if !vars.is_empty() {
+						object.insert("vars".to_owned(), Value::Object(vars));
+					}
+				}
+
+				for (key, child) in object.iter_mut() {
+					if key != "children" {
+						self.expand(child)?;
+					}
+				}
+			}
+			Value::Array(values) => {
+				for value in values {
+					self.expand(value)?;
+				}
+			}
+			_ => {}
+		}
+
+		Ok(())
+	}
+}
+
+/// Replace the include directives in `manifest`, the parsed manifest of the skeleton at
+/// `skeleton_path`, with the fragments they refer to, which are read from `files`.
+/// Returns the paths of the fragments, in the order they were read.
+pub(crate) fn expand_includes(
+	manifest: &mut Value,
+	skeleton_path: &Path,
+	files: &FileSource,
+) -> ClgnDecodingResult<Vec<PathBuf>> {
+	let mut expander = Expander {
+		skeleton_path,
+		files,
+		stack: Vec::new(),
+		included: Vec::new(),
+	};
+	expander.expand(manifest)?;
+
+	Ok(expander.included)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn expand(files: &[(&str, &str)], manifest: &str) -> ClgnDecodingResult<Value> {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		for (path, contents) in files {
+			let path = skeleton_dir.path().join(path);
+			std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+			std::fs::write(path, contents).unwrap();
+		}
+
+		let mut manifest = json::from_str(manifest).unwrap();
+		expand_includes(&mut manifest, skeleton_dir.path(), &FileSource::Disk).map(|_| manifest)
+	}
+
+	#[test]
+	fn includes() {
+		let files = [
+			(
+				"parts/a.json",
+				r#"{ "vars": { "a": 1 }, "children": [{ "tag": "a" }, { "include": "parts/b.json" }] }"#,
+			),
+			(
+				"parts/b.json",
+				r#"{ "vars": { "b": 2 }, "children": [{ "tag": "b" }] }"#,
+			),
+			("empty.json", "{}"),
+		];
+
+		assert_eq!(
+			expand(
+				&files,
+				r#"{
+					"children": [
+						{ "include": "empty.json" },
+						{ "tag": "g", "children": [{ "include": "parts/a.json" }, { "tag": "c" }] }
+					]
+				}"#
+			)
+			.unwrap(),
+			json::json!({
+				"children": [{
+					"tag": "g",
+					"vars": { "a": 1, "b": 2 },
+					"children": [{ "tag": "a" }, { "tag": "b" }, { "tag": "c" }]
+				}]
+			})
+		);
+
+		for manifest in [
+			// Conflicting variables
+			r#"{ "vars": { "b": 0 }, "children": [{ "include": "parts/b.json" }] }"#,
+			r#"{ "children": [{ "include": "parts/b.json" }, { "include": "parts/b.json" }] }"#,
+			// Malformed directives
+			r#"{ "children": [{ "include": "empty.json", "tag": "g" }] }"#,
+			r#"{ "children": [{ "include": 1 }] }"#,
+		] {
+			assert!(
+				matches!(
+					expand(&files, manifest),
+					Err(ClgnDecodingError::Include { .. })
+				),
+				"{}",
+				manifest
+			);
+		}
+
+		assert!(matches!(
+			expand(
+				&[("a.json", r#"{ "children": [{ "include": "a.json" }] }"#)],
+				r#"{ "children": [{ "include": "a.json" }] }"#
+			),
+			Err(ClgnDecodingError::Include { .. })
+		));
+		assert!(matches!(
+			expand(&[], r#"{ "children": [{ "include": "missing.json" }] }"#),
+			Err(ClgnDecodingError::MissingPath { .. })
+		));
+	}
+
+	#[test]
+	fn deeply_nested_includes() {
+		// Each fragment is nested 2 * 40 deep, within serde_json's limit, but the manifest
+		// that includes them all is nested more deeply than it allows
+		let nested_g = |inner: &str| {
+			let mut g = inner.to_owned();
+			for _ in 0..40 {
+				g = format!(r#"{{ "tag": "g", "children": [{}] }}"#, g);
+			}
+			format!(r#"{{ "children": [{}] }}"#, g)
+		};
+
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		let write = |name: &str, contents: String| {
+			std::fs::write(skeleton_dir.path().join(name), contents).unwrap()
+		};
+		write("collagen.json", nested_g(r#"{ "include": "a.json" }"#));
+		write("a.json", nested_g(r#"{ "include": "b.json" }"#));
+		write("b.json", nested_g(r#"{ "tag": "rect" }"#));
+
+		assert!(matches!(
+			crate::fibroblast::Fibroblast::from_dir(skeleton_dir.path()),
+			Err(ClgnDecodingError::Nesting { .. })
+		));
+	}
+}
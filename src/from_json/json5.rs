@@ -0,0 +1,376 @@
+//! Manifests written in [JSON5](https://spec.json5.org), `collagen.json5`, for those who
+//! write their manifests by hand and would like comments, trailing commas, and unquoted
+//! keys. A JSON5 manifest is translated to JSON when it's read, and from then on is
+//! treated exactly like a `collagen.json`. (Only the manifest itself may be JSON5; the
+//! fragments it includes and the patches applied to it are JSON.)
+//!
+//! The translation keeps each value on the line it was on, so that errors in decoding
+//! the manifest point to the right line, although not necessarily the right column.
+//!
+//! Everything in JSON5 is supported except `Infinity` and `NaN`, since attribute values
+//! must be finite.
+
+use serde::de::Error as _;
+use serde_json as json;
+
+/// Translates a JSON5 document, one character at a time, to JSON
+struct Translator {
+	chars: Vec<char>,
+	pos: usize,
+	json: String,
+}
+
+impl Translator {
+	fn error(&self, msg: impl std::fmt::Display) -> json::Error {
+		let before = &self.chars[..self.pos.min(self.chars.len())];
+		let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+		let column = before.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+		json::Error::custom(format!(
+			"{} at line {} column {} (of the JSON5)",
+			msg, line, column
+		))
+	}
+
+	fn peek(&self) -> Option<char> {
+		self.chars.get(self.pos).copied()
+	}
+
+	fn peek_at(&self, offset: usize) -> Option<char> {
+		self.chars.get(self.pos + offset).copied()
+	}
+
+	/// Skip past the whitespace and comments starting at `pos`. If `keep_newlines`, the
+	/// newlines skipped are written to the JSON.
+	fn skip_trivia(&mut self, keep_newlines: bool) -> json::Result<()> {
+		loop {
+			match (self.peek(), self.peek_at(1)) {
+				(Some('/'), Some('/')) => {
+					while !matches!(self.peek(), None | Some('\n')) {
+						self.pos += 1;
+					}
+				}
+				(Some('/'), Some('*')) => {
+					let start = self.pos;
+					self.pos += 2;
+					loop {
+						match (self.peek(), self.peek_at(1)) {
+							(Some('*'), Some('/')) => {
+								self.pos += 2;
+								break;
+							}
+							(Some(c), _) => {
+								if c == '\n' && keep_newlines {
+									self.json.push('\n');
+								}
+								self.pos += 1;
+							}
+							(None, _) => {
+								self.pos = start;
+								return Err(self.error("unterminated comment"));
+							}
+						}
+					}
+				}
+				(Some(c), _) if c.is_whitespace() || c == '\u{feff}' => {
+					if c == '\n' && keep_newlines {
+						self.json.push('\n');
+					}
+					self.pos += 1;
+				}
+				_ => return Ok(()),
+			}
+		}
+	}
+
+	/// The next character that isn't whitespace or part of a comment, without
+	/// consuming anything
+	fn peek_significant(&mut self) -> json::Result<Option<char>> {
+		let (pos, len) = (self.pos, self.json.len());
+		let result = self.skip_trivia(false).map(|_| self.peek());
+		self.pos = pos;
+		self.json.truncate(len);
+		result
+	}
+
+	fn translate(mut self) -> json::Result<String> {
+		loop {
+			self.skip_trivia(true)?;
+			let c = match self.peek() {
+				Some(c) => c,
+				None => return Ok(self.json),
+			};
+
+			match c {
+				'{' | '}' | '[' | ']' | ':' => {
+					self.json.push(c);
+					self.pos += 1;
+				}
+				',' => {
+					self.pos += 1;
+					// Trailing commas are dropped
+					if !matches!(self.peek_significant()?, Some('}' | ']')) {
+						self.json.push(',');
+					}
+				}
+				'"' | '\'' => self.translate_string(c)?,
+				'+' | '-' | '.' | '0'..='9' => self.translate_number()?,
+				c if is_identifier_start(c) => self.translate_identifier()?,
+				c => return Err(self.error(format!("unexpected character {:?}", c))),
+			}
+		}
+	}
+
+	/// Translate the string starting at `pos`, which is quoted with `quote`. The line
+	/// breaks that the string continues past are written after it, to keep what
+	/// follows on the same line.
+	fn translate_string(&mut self, quote: char) -> json::Result<()> {
+		let start = self.pos;
+		let mut continuations = 0;
+		self.pos += 1;
+		self.json.push('"');
+		loop {
+			let c = match self.peek() {
+				Some(c) => c,
+				None => {
+					self.pos = start;
+					return Err(self.error("unterminated string"));
+				}
+			};
+			self.pos += 1;
+
+			match c {
+				c if c == quote => break,
+				'"' => self.json.push_str("\\\""),
+				'\n' | '\r' => {
+					self.pos -= 1;
+					return Err(self.error("unescaped line break in string"));
+				}
+				'\\' => {
+					if self.translate_escape()? {
+						continuations += 1;
+					}
+				}
+				c if (c as u32) < 0x20 => self.json.push_str(&format!("\\u{:04x}", c as u32)),
+				c => self.json.push(c),
+			}
+		}
+		self.json.push('"');
+		self.json
+			.extend(std::iter::repeat('\n').take(continuations));
+
+		Ok(())
+	}
+
+	/// Translate the escape sequence following a backslash in a string, returning
+	/// whether it was a line continuation
+	fn translate_escape(&mut self) -> json::Result<bool> {
+		let c = match self.peek() {
+			Some(c) => c,
+			None => return Err(self.error("unterminated string")),
+		};
+		self.pos += 1;
+
+		match c {
+			'"' | '\\' | '/' | 'b' | 'f' | 'n' | 'r' | 't' => {
+				self.json.push('\\');
+				self.json.push(c);
+			}
+			'u' => self.json.push_str("\\u"),
+			'\'' => self.json.push('\''),
+			'v' => self.json.push_str("\\u000b"),
+			'0' if !matches!(self.peek(), Some('0'..='9')) => self.json.push_str("\\u0000"),
+			'x' => {
+				let hex = self.chars[self.pos..].iter().take(2).collect::<String>();
+				match u8::from_str_radix(&hex, 16) {
+					Ok(byte) if hex.len() == 2 => {
+						self.json.push_str(&format!("\\u{:04x}", byte));
+						self.pos += 2;
+					}
+					_ => return Err(self.error("`\\x` must be followed by two hex digits")),
+				}
+			}
+			// A backslash before a line break continues the string onto the next line
+			'\n' => return Ok(true),
+			'\u{2028}' | '\u{2029}' => {}
+			'\r' => {
+				if self.peek() == Some('\n') {
+					self.pos += 1;
+					return Ok(true);
+				}
+			}
+			'1'..='9' | '0' => return Err(self.error("invalid escape sequence in string")),
+			c => self.json.push(c),
+		}
+
+		Ok(false)
+	}
+
+	/// Translate the number starting at `pos`
+	fn translate_number(&mut self) -> json::Result<()> {
+		let start = self.pos;
+		let mut token = String::new();
+		while let Some(c) = self.peek() {
+			if c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.') {
+				token.push(c);
+				self.pos += 1;
+			} else {
+				break;
+			}
+		}
+
+		let (sign, magnitude) = match token.strip_prefix('-') {
+			Some(magnitude) => ("-", magnitude),
+			None => ("", token.strip_prefix('+').unwrap_or(&token)),
+		};
+		let invalid = |this: &mut Self| {
+			this.pos = start;
+			Err(this.error(format!("invalid number {:?}", token)))
+		};
+
+		if magnitude == "Infinity" || magnitude == "NaN" {
+			self.pos = start;
+			return Err(self.error(format!(
+				"{:?} is not allowed, since attribute values must be finite",
+				token
+			)));
+		}
+
+		if let Some(hex) = magnitude
+			.strip_prefix("0x")
+			.or_else(|| magnitude.strip_prefix("0X"))
+		{
+			return match u64::from_str_radix(hex, 16) {
+				Ok(n) if !hex.starts_with('+') => {
+					self.json.push_str(&format!("{}{}", sign, n));
+					Ok(())
+				}
+				_ => invalid(self),
+			};
+		}
+
+		// JSON requires digits on both sides of a decimal point, while JSON5 requires
+		// them on only one
+		let (mantissa, exponent) = match magnitude.find(|c| c == 'e' || c == 'E') {
+			Some(i) => magnitude.split_at(i),
+			None => (magnitude, ""),
+		};
+		let mantissa = match mantissa.split_once('.') {
+			Some(("", "")) => return invalid(self),
+			Some((int, "")) => int.to_owned(),
+			Some(("", frac)) => format!("0.{}", frac),
+			_ => mantissa.to_owned(),
+		};
+		self.json.push_str(sign);
+		self.json.push_str(&mantissa);
+		self.json.push_str(exponent);
+
+		Ok(())
+	}
+
+	/// Translate the identifier starting at `pos`, which is either an unquoted key or
+	/// one of the literals `true`, `false`, and `null`
+	fn translate_identifier(&mut self) -> json::Result<()> {
+		let start = self.pos;
+		while matches!(self.peek(), Some(c) if is_identifier_part(c)) {
+			self.pos += 1;
+		}
+		let identifier = self.chars[start..self.pos].iter().collect::<String>();
+
+		if self.peek_significant()? == Some(':') {
+			self.json.push_str(&json::to_string(&identifier).unwrap());
+			return Ok(());
+		}
+
+		match identifier.as_str() {
+			"true" | "false" | "null" => {
+				self.json.push_str(&identifier);
+				Ok(())
+			}
+			"Infinity" | "NaN" => {
+				self.pos = start;
+				Err(self.error(format!(
+					"{:?} is not allowed, since attribute values must be finite",
+					identifier
+				)))
+			}
+			_ => {
+				self.pos = start;
+				Err(self.error(format!(
+					"unexpected identifier {:?}; strings must be quoted",
+					identifier
+				)))
+			}
+		}
+	}
+}
+
+fn is_identifier_start(c: char) -> bool {
+	c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_identifier_part(c: char) -> bool {
+	c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Translate `json5`, the contents of a JSON5 document, to JSON
+pub(crate) fn to_json(json5: &[u8]) -> json::Result<Vec<u8>> {
+	let json5 = std::str::from_utf8(json5).map_err(json::Error::custom)?;
+	let json = Translator {
+		chars: json5.chars().collect(),
+		pos: 0,
+		json: String::with_capacity(json5.len()),
+	}
+	.translate()?;
+
+	Ok(json.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn json5() {
+		let translate =
+			|json5: &str| to_json(json5.as_bytes()).map(|v| String::from_utf8(v).unwrap());
+
+		let json5 = r#"// A comment
+{
+	vars: { side: 0x1A, ratio: .5, scale: +2., $big: 1e3, "quoted": -0XFF, },
+	/* A comment
+	   on two lines */
+	children: [
+		{ tag: 'text', text: 'It\'s "quoted" \x41\
+nd continued' },
+	],
+}"#;
+		let json = translate(json5).unwrap();
+		assert_eq!(json.lines().count(), json5.lines().count());
+		assert_eq!(
+			json::from_str::<json::Value>(&json).unwrap(),
+			json::json!({
+				"vars": { "side": 26, "ratio": 0.5, "scale": 2, "$big": 1e3, "quoted": -255 },
+				"children": [{ "tag": "text", "text": "It's \"quoted\" And continued" }]
+			})
+		);
+
+		for json5 in [
+			"{ a: Infinity }",
+			"{ a: -NaN }",
+			"{ a: b }",
+			"{ a: 'unterminated }",
+			"{ a: 1 } /* unterminated",
+			"{ a: 0x }",
+			"{ a: . }",
+			"{ a: '\\x4' }",
+			"{ a: 'line\nbreak' }",
+		] {
+			assert!(translate(json5).is_err(), "{}", json5);
+		}
+
+		let err = translate("{\n  a: b }").unwrap_err();
+		assert!(err
+			.to_string()
+			.ends_with("at line 2 column 6 (of the JSON5)"));
+	}
+}
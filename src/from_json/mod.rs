@@ -1,4 +1,10 @@
 pub mod decode_dir;
 pub mod decoding_error;
+pub mod include;
+#[cfg(feature = "json5")]
+pub(crate) mod json5;
+pub mod patch;
+pub mod template_cache;
+pub(crate) mod unique_keys;
 
 pub use decoding_error::ClgnDecodingResult;
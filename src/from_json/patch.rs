@@ -0,0 +1,426 @@
+//! Patches, which customize a skeleton without editing it: `clgn -i skeleton --patch
+//! overrides.json` renders `skeleton` as though its manifest had been changed as
+//! described by `overrides.json`. This lets someone swap out a logo or a color in a
+//! skeleton they don't own, without forking it.
+//!
+//! A patch file is either:
+//!
+//! - A [JSON Merge Patch](https://www.rfc-editor.org/rfc/rfc7386) (an object), which
+//!   is merged into the manifest key by key, with `null` removing a key. For instance,
+//!   `{ "vars": { "accent": "red" } }` sets the root's `accent` variable. Arrays, such as
+//!   `children`, are replaced wholesale.
+//! - A [JSON Patch](https://www.rfc-editor.org/rfc/rfc6902) (an array of operations),
+//!   which can reach into arrays. For instance, `[{ "op": "replace", "path":
+//!   "/children/0/image_path", "value": "my-logo.png" }]` swaps out the first child's
+//!   image.
+//!
+//! Patches are applied to the manifest after its include directives have been expanded
+//! (see [`super::include`]), and before it's decoded, so the patched manifest must be
+//! valid. `clgn normalize` shows the manifest that a patch applies to.
+//!
+//! A *vars file* (`--vars-file vars.json`) is a shorthand for the most common patch, one
+//! that sets the root's variables: it's an object of variables, such as `{ "accent":
+//! "red", "size": 12 }`, which is equivalent to the merge patch `{ "vars": { "accent":
+//! "red", "size": 12 } }`. This is handy when there are many variables to set, or when
+//! their values would be awkward to write in a shell. Vars files are applied after
+//! `--patch`es, so their variables take precedence.
+
+use super::decoding_error::{ClgnDecodingError, ClgnDecodingResult};
+use super::unique_keys;
+use crate::fibroblast::data_types::context::is_valid_var_name;
+use serde_json::{self as json, Value};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// A patch to apply to a manifest. See the module docs for more info.
+#[derive(Debug, Clone)]
+pub struct ManifestPatch {
+	path: PathBuf,
+	patch: Value,
+}
+
+impl ManifestPatch {
+	/// Read the patch in the file at `path`
+	pub fn from_file(path: impl AsRef<Path>) -> ClgnDecodingResult<Self> {
+		let path = path.as_ref().to_owned();
+		let bytes = std::fs::read(&path).map_err(|e| ClgnDecodingError::read(e, path.clone()))?;
+		let patch = unique_keys::from_slice(&bytes)
+			.map_err(|e| ClgnDecodingError::JsonDecode(e, path.clone()))?;
+
+		Ok(Self { path, patch })
+	}
+
+	/// Read the vars file at `path`, an object of variables, as the patch that sets the
+	/// root's variables to them
+	pub fn from_vars_file(path: impl AsRef<Path>) -> ClgnDecodingResult<Self> {
+		let Self { path, patch: vars } = Self::from_file(path)?;
+		Self::from_vars(path, vars)
+	}
+
+	/// The patch that sets the root's variables to `vars`, an object of variables that
+	/// came from `path` (which is only used in error messages)
+	pub fn from_vars(path: impl Into<PathBuf>, vars: Value) -> ClgnDecodingResult<Self> {
+		let vars_patch = Self {
+			path: path.into(),
+			patch: json::json!({ "vars": vars }),
+		};
+
+		let vars = match &vars_patch.patch["vars"] {
+			Value::Object(vars) => vars,
+			_ => return Err(vars_patch.error("the variables must be an object of variables")),
+		};
+		for (name, value) in vars {
+			if !is_valid_var_name(name) {
+				return Err(vars_patch.error(format_args!(
+					"{:?} is not a valid variable name, which consists only of letters, \
+					 digits, and underscores",
+					name
+				)));
+			}
+			if !(value.is_string() || value.is_number()) {
+				return Err(vars_patch.error(format_args!(
+					"the variable {:?} must be a number or a string; got {}",
+					name, value
+				)));
+			}
+		}
+
+		Ok(vars_patch)
+	}
+
+	fn error(&self, msg: impl std::fmt::Display) -> ClgnDecodingError {
+		ClgnDecodingError::Patch {
+			msg: format!("In the patch {:?}: {}", self.path, msg),
+		}
+	}
+
+	/// Apply this patch to `manifest`
+	pub(crate) fn apply(&self, manifest: &mut Value) -> ClgnDecodingResult<()> {
+		match &self.patch {
+			Value::Object(_) => {
+				merge_patch(manifest, &self.patch);
+				Ok(())
+			}
+			Value::Array(ops) => {
+				for (i, op) in ops.iter().enumerate() {
+					self.apply_op(manifest, op)
+						.map_err(|msg| self.error(format_args!("operation {}: {}", i, msg)))?;
+				}
+				Ok(())
+			}
+			_ => Err(self.error(
+				"a patch must be an object (a JSON Merge Patch) or an array (a JSON Patch)",
+			)),
+		}
+	}
+
+	/// Apply the JSON Patch operation `op` to `manifest`
+	fn apply_op(&self, manifest: &mut Value, op: &Value) -> Result<(), String> {
+		let str_field = |name: &str| match op.get(name) {
+			Some(Value::String(s)) => Ok(s.as_str()),
+			_ => Err(format!("expected a string `{}`; got {}", name, op)),
+		};
+		let value = || {
+			op.get("value")
+				.cloned()
+				.ok_or_else(|| format!("expected a `value`; got {}", op))
+		};
+
+		let path = parse_pointer(str_field("path")?)?;
+		match str_field("op")? {
+			"add" => add(manifest, &path, value()?),
+			"remove" => remove(manifest, &path).map(|_| ()),
+			"replace" => {
+				remove(manifest, &path)?;
+				add(manifest, &path, value()?)
+			}
+			"move" => {
+				let from = parse_pointer(str_field("from")?)?;
+				if path.len() > from.len() && path.starts_with(&from) {
+					return Err("can't move a value into one of its own children".to_owned());
+				}
+				let moved = remove(manifest, &from)?;
+				add(manifest, &path, moved)
+			}
+			"copy" => {
+				let from = parse_pointer(str_field("from")?)?;
+				let copied = get(manifest, &from)?.clone();
+				add(manifest, &path, copied)
+			}
+			"test" => {
+				if *get(manifest, &path)? == value()? {
+					Ok(())
+				} else {
+					Err(format!("test failed: {}", op))
+				}
+			}
+			other => Err(format!(
+				"unknown op {:?}; expected one of add, remove, replace, move, copy, or test",
+				other
+			)),
+		}
+	}
+}
+
+// `Value` isn't `Hash`, but its serialization is a faithful stand-in
+impl Hash for ManifestPatch {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.path.hash(state);
+		self.patch.to_string().hash(state);
+	}
+}
+
+/// Merge `patch` into `target`, as described by RFC 7386
+fn merge_patch(target: &mut Value, patch: &Value) {
+	let patch = match patch {
+		Value::Object(patch) => patch,
+		_ => {
+			*target = patch.clone();
+			return;
+		}
+	};
+
+	if !target.is_object() {
+		*target = Value::Object(json::Map::new());
+	}
+	if let Value::Object(target) = target {
+		for (key, value) in patch {
+			if value.is_null() {
+				target.remove(key);
+			} else {
+				merge_patch(target.entry(key).or_insert(Value::Null), value);
+			}
+		}
+	}
+}
+
+/// Split the JSON pointer `pointer` into its (unescaped) reference tokens
+fn parse_pointer(pointer: &str) -> Result<Vec<String>, String> {
+	if pointer.is_empty() {
+		return Ok(Vec::new());
+	}
+
+	match pointer.strip_prefix('/') {
+		Some(rest) => Ok(rest
+			.split('/')
+			.map(|token| token.replace("~1", "/").replace("~0", "~"))
+			.collect()),
+		None => Err(format!(
+			"invalid JSON pointer {:?}; it must be empty or start with `/`",
+			pointer
+		)),
+	}
+}
+
+/// The index into `array` referred to by `token`; `-` refers to the end of the array,
+/// which is only valid when `allow_end`
+fn array_index(array: &[Value], token: &str, allow_end: bool) -> Result<usize, String> {
+	let len = array.len();
+	let index = if token == "-" {
+		len
+	} else if token == "0" || (!token.starts_with('0') && !token.starts_with('+')) {
+		token
+			.parse::<usize>()
+			.map_err(|_| format!("expected an array index; got {:?}", token))?
+	} else {
+		return Err(format!("expected an array index; got {:?}", token));
+	};
+
+	if index < len || (allow_end && index == len) {
+		Ok(index)
+	} else {
+		Err(format!(
+			"index {} is out of bounds of an array of length {}",
+			token, len
+		))
+	}
+}
+
+/// The value at `path` in `doc`
+fn get<'v>(doc: &'v Value, path: &[String]) -> Result<&'v Value, String> {
+	let mut value = doc;
+	for token in path {
+		value = match value {
+			Value::Object(object) => object
+				.get(token)
+				.ok_or_else(|| format!("no key {:?}", token))?,
+			Value::Array(array) => &array[array_index(array, token, false)?],
+			_ => return Err(format!("can't look up {:?} in {}", token, value)),
+		}
+	}
+
+	Ok(value)
+}
+
+/// The value at `path` in `doc`
+fn get_mut<'v>(doc: &'v mut Value, path: &[String]) -> Result<&'v mut Value, String> {
+	let mut value = doc;
+	for token in path {
+		value = match value {
+			Value::Object(object) => object
+				.get_mut(token)
+				.ok_or_else(|| format!("no key {:?}", token))?,
+			Value::Array(array) => {
+				let index = array_index(array, token, false)?;
+				&mut array[index]
+			}
+			other => return Err(format!("can't look up {:?} in {}", token, other)),
+		}
+	}
+
+	Ok(value)
+}
+
+/// Add `new_value` at `path` in `doc`: set the key of an object, or insert into an array
+fn add(doc: &mut Value, path: &[String], new_value: Value) -> Result<(), String> {
+	let (last, parent_path) = match path.split_last() {
+		Some(split) => split,
+		None => {
+			*doc = new_value;
+			return Ok(());
+		}
+	};
+
+	match get_mut(doc, parent_path)? {
+		Value::Object(object) => {
+			object.insert(last.clone(), new_value);
+		}
+		Value::Array(array) => {
+			let index = array_index(array, last, true)?;
+			array.insert(index, new_value);
+		}
+		other => return Err(format!("can't add {:?} to {}", last, other)),
+	}
+
+	Ok(())
+}
+
+/// Remove the value at `path` in `doc`, returning it
+fn remove(doc: &mut Value, path: &[String]) -> Result<Value, String> {
+	let (last, parent_path) = match path.split_last() {
+		Some(split) => split,
+		None => return Ok(std::mem::take(doc)),
+	};
+
+	match get_mut(doc, parent_path)? {
+		Value::Object(object) => object
+			.remove(last)
+			.ok_or_else(|| format!("no key {:?}", last)),
+		Value::Array(array) => {
+			let index = array_index(array, last, false)?;
+			Ok(array.remove(index))
+		}
+		other => Err(format!("can't remove {:?} from {}", last, other)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use json::json;
+
+	fn patched(patch: Value) -> ClgnDecodingResult<Value> {
+		let mut manifest = json!({
+			"vars": { "accent": "blue", "size": 10 },
+			"children": [
+				{ "image_path": "logo.png" },
+				{ "tag": "rect", "attrs": { "fill": "{accent}" } }
+			]
+		});
+		ManifestPatch {
+			path: PathBuf::from("patch.json"),
+			patch,
+		}
+		.apply(&mut manifest)
+		.map(|_| manifest)
+	}
+
+	#[test]
+	fn merge_patches() {
+		assert_eq!(
+			patched(json!({ "vars": { "accent": "red", "size": null }, "attrs": { "x": 1 } }))
+				.unwrap(),
+			json!({
+				"vars": { "accent": "red" },
+				"attrs": { "x": 1 },
+				"children": [
+					{ "image_path": "logo.png" },
+					{ "tag": "rect", "attrs": { "fill": "{accent}" } }
+				]
+			})
+		);
+	}
+
+	#[test]
+	fn json_patches() {
+		assert_eq!(
+			patched(json!([
+				{ "op": "test", "path": "/vars/size", "value": 10 },
+				{ "op": "replace", "path": "/children/0/image_path", "value": "mine.png" },
+				{ "op": "add", "path": "/children/-", "value": { "tag": "circle" } },
+				{ "op": "move", "from": "/vars/accent", "path": "/vars/color" },
+				{ "op": "copy", "from": "/vars/color", "path": "/children/1/attrs/stroke" },
+				{ "op": "remove", "path": "/vars/size" }
+			]))
+			.unwrap(),
+			json!({
+				"vars": { "color": "blue" },
+				"children": [
+					{ "image_path": "mine.png" },
+					{ "tag": "rect", "attrs": { "fill": "{accent}", "stroke": "blue" } },
+					{ "tag": "circle" }
+				]
+			})
+		);
+
+		for patch in [
+			json!([{ "op": "test", "path": "/vars/size", "value": 11 }]),
+			json!([{ "op": "remove", "path": "/children/2" }]),
+			json!([{ "op": "remove", "path": "/children/01" }]),
+			json!([{ "op": "replace", "path": "/vars/missing", "value": 1 }]),
+			json!([{ "op": "move", "from": "/children", "path": "/children/0" }]),
+			json!([{ "op": "add", "path": "vars", "value": 1 }]),
+			json!([{ "op": "frobnicate", "path": "" }]),
+			json!("not a patch"),
+		] {
+			assert!(
+				matches!(patched(patch.clone()), Err(ClgnDecodingError::Patch { .. })),
+				"{}",
+				patch
+			);
+		}
+	}
+
+	#[test]
+	fn vars_files() {
+		let dir = tempfile::tempdir().unwrap();
+		let vars_file = |vars: &str| {
+			let path = dir.path().join("vars.json");
+			std::fs::write(&path, vars).unwrap();
+			ManifestPatch::from_vars_file(path)
+		};
+
+		let mut manifest = json!({ "vars": { "accent": "blue", "size": 10 } });
+		vars_file(r#"{ "accent": "red", "label": "a \"quoted\" label" }"#)
+			.unwrap()
+			.apply(&mut manifest)
+			.unwrap();
+		assert_eq!(
+			manifest,
+			json!({ "vars": { "accent": "red", "size": 10, "label": "a \"quoted\" label" } })
+		);
+
+		for vars in [
+			r#"[]"#,
+			r#"{ "size": null }"#,
+			r#"{ "size": { "light": 1, "dark": 2 } }"#,
+			r#"{ "font size": 1 }"#,
+		] {
+			assert!(
+				matches!(vars_file(vars), Err(ClgnDecodingError::Patch { .. })),
+				"{}",
+				vars
+			);
+		}
+	}
+}
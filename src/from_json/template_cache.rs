@@ -0,0 +1,205 @@
+//! When the same skeleton is rendered over and over (e.g., by `clgn daemon`), there's
+//! no sense in re-decoding its manifest every time. [`TemplateCache`] holds on to the
+//! decoded [`RootTag`] of each skeleton it's asked for, keyed by the hash of the
+//! manifest's contents (and those of the fragments it includes, and of the patches
+//! applied to it), and hands out fresh copies of it. The manifest and fragments are
+//! still read on every request, so that edits to them are picked up, but the manifest is
+//! only decoded again if their contents, or the patches, have changed.
+
+use super::decode_dir::{decode_manifest, manifest_path, read_manifest};
+use super::decoding_error::ClgnDecodingResult;
+use super::patch::ManifestPatch;
+use crate::fibroblast::{data_types::DecodingContext, tags::RootTag, Fibroblast};
+use crate::utils::file_source::FileSource;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+struct CachedTemplate {
+	manifest_hash: u64,
+	included: Vec<PathBuf>,
+	root: RootTag<'static>,
+}
+
+/// The hash of `manifest`, the contents of the fragments at `included`, and `patches`,
+/// or `None` if one of the fragments can't be read (in which case the manifest must be
+/// decoded again to find out what went wrong)
+fn manifest_hash(manifest: &[u8], included: &[PathBuf], patches: &[ManifestPatch]) -> Option<u64> {
+	let mut hasher = DefaultHasher::new();
+	manifest.hash(&mut hasher);
+	patches.hash(&mut hasher);
+	for path in included {
+		std::fs::read(path).ok()?.hash(&mut hasher);
+	}
+	Some(hasher.finish())
+}
+
+/// A cache of decoded manifests, keyed by the path to their manifest. See the module
+/// docs for more info.
+#[derive(Debug, Default)]
+pub struct TemplateCache {
+	templates: HashMap<PathBuf, CachedTemplate>,
+}
+
+impl TemplateCache {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Like [`Fibroblast::from_dir`], except that the skeleton's manifest is only
+	/// decoded if it hasn't been seen before or its contents have changed since it was
+	/// last seen
+	pub fn fibroblast<'a>(
+		&mut self,
+		skeleton_path: impl AsRef<Path>,
+	) -> ClgnDecodingResult<Fibroblast<'a>> {
+		self.fibroblast_with_patches(skeleton_path, &[])
+	}
+
+	/// Like [`Fibroblast::from_dir_with_patches`], except that the skeleton's manifest is
+	/// only decoded if it hasn't been seen before with the same `patches` or its
+	/// contents have changed since it was last seen. Only the most recent patches are
+	/// remembered for each skeleton, so alternating between patches decodes the manifest
+	/// every time.
+	pub fn fibroblast_with_patches<'a>(
+		&mut self,
+		skeleton_path: impl AsRef<Path>,
+		patches: &[ManifestPatch],
+	) -> ClgnDecodingResult<Fibroblast<'a>> {
+		let skeleton_path = skeleton_path.as_ref();
+		let manifest_path = manifest_path(skeleton_path, &FileSource::Disk)?;
+
+		let manifest = read_manifest(&manifest_path, &FileSource::Disk)?;
+
+		let root = match self.templates.get(&manifest_path) {
+			Some(cached)
+				if manifest_hash(&manifest, &cached.included, patches)
+					== Some(cached.manifest_hash) =>
+			{
+				cached.root.fresh_clone()
+			}
+			_ => {
+				let (root, included) =
+					decode_manifest(&manifest, &manifest_path, patches, &FileSource::Disk)?;
+				let fresh_root = root.fresh_clone();
+				// Only cache the manifest if the fragments didn't change out from under us
+				if let Some(manifest_hash) = manifest_hash(&manifest, &included, patches) {
+					self.templates.insert(
+						manifest_path,
+						CachedTemplate {
+							manifest_hash,
+							included,
+							root,
+						},
+					);
+				}
+				fresh_root
+			}
+		};
+
+		Ok(Fibroblast {
+			root,
+			context: DecodingContext::new_at_root(skeleton_path),
+		})
+	}
+
+	/// The number of skeletons whose manifests are currently cached
+	pub fn len(&self) -> usize {
+		self.templates.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.templates.is_empty()
+	}
+
+	pub fn clear(&mut self) {
+		self.templates.clear()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn matches_uncached() {
+		let mut cache = TemplateCache::new();
+
+		for skeleton in [
+			"tests/examples/basic-smiley-pure-svg/skeleton",
+			"tests/examples/kitty-nesting-smiley/skeleton",
+		] {
+			let expected = Fibroblast::from_dir(skeleton)
+				.unwrap()
+				.to_svg_string()
+				.unwrap();
+
+			// Once to fill the cache, once to read from it
+			for _ in 0..2 {
+				let actual = cache.fibroblast(skeleton).unwrap().to_svg_string().unwrap();
+				assert_eq!(actual, expected);
+			}
+		}
+
+		assert_eq!(cache.len(), 2);
+	}
+
+	#[test]
+	fn invalidated_by_changes() {
+		let skeleton = tempfile::tempdir().unwrap();
+		let manifest = skeleton.path().join("collagen.json");
+		let mut cache = TemplateCache::new();
+
+		std::fs::write(&manifest, r#"{ "text": "one" }"#).unwrap();
+		let svg = cache.fibroblast(skeleton.path()).unwrap().to_svg_string();
+		assert_eq!(
+			svg.unwrap(),
+			r#"<svg xmlns="http://www.w3.org/2000/svg">one</svg>"#
+		);
+
+		std::fs::write(&manifest, r#"{ "text": "two" }"#).unwrap();
+		let svg = cache.fibroblast(skeleton.path()).unwrap().to_svg_string();
+		assert_eq!(
+			svg.unwrap(),
+			r#"<svg xmlns="http://www.w3.org/2000/svg">two</svg>"#
+		);
+
+		assert_eq!(cache.len(), 1);
+	}
+
+	#[test]
+	fn invalidated_by_changed_includes() {
+		let skeleton = tempfile::tempdir().unwrap();
+		let fragment = skeleton.path().join("fragment.json");
+		let mut cache = TemplateCache::new();
+
+		std::fs::write(
+			skeleton.path().join("collagen.json"),
+			r#"{ "children": [{ "include": "fragment.json" }] }"#,
+		)
+		.unwrap();
+
+		for text in ["one", "two"] {
+			std::fs::write(
+				&fragment,
+				format!(
+					r#"{{ "children": [{{ "tag": "g", "text": "{}" }}] }}"#,
+					text
+				),
+			)
+			.unwrap();
+			let svg = cache.fibroblast(skeleton.path()).unwrap().to_svg_string();
+			assert_eq!(
+				svg.unwrap(),
+				format!(
+					r#"<svg xmlns="http://www.w3.org/2000/svg"><g>{}</g></svg>"#,
+					text
+				)
+			);
+		}
+
+		assert_eq!(cache.len(), 1);
+	}
+}
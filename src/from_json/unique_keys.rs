@@ -0,0 +1,187 @@
+//! Parsing JSON that may not contain duplicate keys. `serde_json` silently keeps the
+//! last of an object's duplicate keys, so a manifest with, say, two `"children"` in one
+//! tag would lose the first list of children without a word. Manifests (and the
+//! fragments they include, and patches) are parsed with [`from_slice`] instead, which
+//! rejects them.
+
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{self as json, Map, Number, Value};
+use std::fmt;
+
+/// Where a value is in the document being parsed, as a linked list leading back to the
+/// root, so that nothing need be allocated unless there's an error
+enum Location<'p> {
+	Root,
+	Key(&'p Location<'p>, &'p str),
+	Index(&'p Location<'p>, usize),
+}
+
+impl Location<'_> {
+	/// The JSON pointer to this location
+	fn pointer(&self) -> String {
+		let mut tokens = Vec::new();
+		let mut location = self;
+		loop {
+			location = match location {
+				Location::Root => break,
+				Location::Key(parent, key) => {
+					tokens.push(key.replace('~', "~0").replace('/', "~1"));
+					parent
+				}
+				Location::Index(parent, index) => {
+					tokens.push(index.to_string());
+					parent
+				}
+			}
+		}
+
+		tokens
+			.iter()
+			.rev()
+			.map(|token| format!("/{}", token))
+			.collect()
+	}
+}
+
+/// Deserializes a [`Value`] at `location`, rejecting objects with duplicate keys
+struct UniqueKeysValue<'p> {
+	location: &'p Location<'p>,
+}
+
+impl<'de> DeserializeSeed<'de> for UniqueKeysValue<'_> {
+	type Value = Value;
+
+	fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+		deserializer.deserialize_any(self)
+	}
+}
+
+impl<'de> Visitor<'de> for UniqueKeysValue<'_> {
+	type Value = Value;
+
+	fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		formatter.write_str("any valid JSON value")
+	}
+
+	fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+		Ok(Value::Bool(b))
+	}
+
+	fn visit_i64<E>(self, n: i64) -> Result<Value, E> {
+		Ok(Value::Number(n.into()))
+	}
+
+	fn visit_u64<E>(self, n: u64) -> Result<Value, E> {
+		Ok(Value::Number(n.into()))
+	}
+
+	fn visit_f64<E>(self, x: f64) -> Result<Value, E> {
+		Ok(Number::from_f64(x).map_or(Value::Null, Value::Number))
+	}
+
+	fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+		Ok(Value::String(s.to_owned()))
+	}
+
+	fn visit_string<E>(self, s: String) -> Result<Value, E> {
+		Ok(Value::String(s))
+	}
+
+	fn visit_unit<E>(self) -> Result<Value, E> {
+		Ok(Value::Null)
+	}
+
+	fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+		let mut values = Vec::new();
+		loop {
+			let location = Location::Index(self.location, values.len());
+			match seq.next_element_seed(UniqueKeysValue {
+				location: &location,
+			})? {
+				Some(value) => values.push(value),
+				None => break,
+			}
+		}
+
+		Ok(Value::Array(values))
+	}
+
+	fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+		let mut object = Map::new();
+		while let Some(key) = map.next_key::<String>()? {
+			if object.contains_key(&key) {
+				let pointer = self.location.pointer();
+				return Err(de::Error::custom(format!(
+					"the object at {} has the key {:?} more than once",
+					if pointer.is_empty() {
+						"the root".to_owned()
+					} else {
+						format!("{:?}", pointer)
+					},
+					key
+				)));
+			}
+
+			let value = map.next_value_seed(UniqueKeysValue {
+				location: &Location::Key(self.location, &key),
+			})?;
+			object.insert(key, value);
+		}
+
+		Ok(Value::Object(object))
+	}
+}
+
+/// Like [`serde_json::from_slice::<Value>`], except that it's an error for an object to
+/// have the same key more than once. The error says which object and which key.
+pub(crate) fn from_slice(bytes: &[u8]) -> json::Result<Value> {
+	let mut deserializer = json::Deserializer::from_slice(bytes);
+	let value = UniqueKeysValue {
+		location: &Location::Root,
+	}
+	.deserialize(&mut deserializer)?;
+	deserializer.end()?;
+
+	Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn duplicate_keys() {
+		let manifest = br#"{
+			"vars": { "a/b": 1, "c": [1.5, null, true, "d"] },
+			"children": [{ "tag": "g", "children": [] }]
+		}"#;
+		assert_eq!(
+			from_slice(manifest).unwrap(),
+			json::from_slice::<Value>(manifest).unwrap()
+		);
+
+		for (json, expected_msg) in vec![
+			(
+				r#"{ "children": [], "children": [] }"#,
+				r#"the object at the root has the key "children" more than once at line 1 column 28"#,
+			),
+			(
+				r#"{ "children": [{ "tag": "g" }, { "tag": "g", "attrs": {}, "tag": "rect" }] }"#,
+				r#"the object at "/children/1" has the key "tag" more than once at line 1 column 63"#,
+			),
+			(
+				r#"{ "vars": { "a/b": { "x": 1, "x": 2 } } }"#,
+				r#"the object at "/vars/a~1b" has the key "x" more than once at line 1 column 32"#,
+			),
+		]
+		.into_iter()
+		{
+			assert_eq!(
+				from_slice(json.as_bytes()).unwrap_err().to_string(),
+				expected_msg
+			);
+		}
+
+		assert!(from_slice(b"{} {}").is_err());
+	}
+}
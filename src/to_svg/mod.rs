@@ -1 +1,10 @@
+pub(crate) mod output_format;
+pub(crate) mod profile;
+pub(crate) mod raw_xml;
+pub mod render_timings;
+pub(crate) mod size_limits;
+pub(crate) mod subtree;
 pub mod svg_writable;
+pub mod tag_sizes;
+pub(crate) mod target;
+pub(crate) mod xml_text;
@@ -0,0 +1,258 @@
+//! How the SVG is laid out as text: whether (and with what) it's indented, and which
+//! newlines it uses. By default the SVG is written on a single line, which is smallest.
+//! Projects that check generated SVGs into version control may prefer them indented, so
+//! that a change to one tag is a change to one line, and may need CRLF newlines to match
+//! the rest of their files.
+//!
+//! The format is given by the root tag's `output`, e.g., `"output": { "indent": 2,
+//! "newline": "crlf" }`, or on the command line by `--indent` and `--newline`, which take
+//! precedence.
+//!
+//! When indenting, each tag starts on a new line, except those that follow text, so that
+//! no whitespace is added to (or removed from) text. Whitespace between tags is ignored
+//! when rendering, with one exception: inside a `<text>` with `<tspan>` children, where
+//! the line breaks between the `<tspan>`s may be rendered as spaces.
+
+use quick_xml::Writer as XmlWriter;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// How each level of nesting is indented
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "IndentRepr", into = "IndentRepr")]
+pub(crate) enum Indent {
+	Tab,
+	Spaces(u8),
+}
+
+/// An [`Indent`] as it's written in JSON: `"tab"`, or the number of spaces
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum IndentRepr {
+	Spaces(u8),
+	Name(String),
+}
+
+impl FromStr for Indent {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"tab" => Ok(Indent::Tab),
+			_ => s.parse().map(Indent::Spaces).map_err(|_| {
+				format!(
+					"invalid indent {:?}; expected \"tab\" or a number of spaces (at most 255)",
+					s
+				)
+			}),
+		}
+	}
+}
+
+impl TryFrom<IndentRepr> for Indent {
+	type Error = String;
+
+	fn try_from(repr: IndentRepr) -> Result<Self, Self::Error> {
+		match repr {
+			IndentRepr::Spaces(n) => Ok(Indent::Spaces(n)),
+			IndentRepr::Name(name) if name == "tab" => Ok(Indent::Tab),
+			IndentRepr::Name(name) => Err(format!(
+				"invalid indent {:?}; expected \"tab\" or a number of spaces",
+				name
+			)),
+		}
+	}
+}
+
+impl From<Indent> for IndentRepr {
+	fn from(indent: Indent) -> Self {
+		match indent {
+			Indent::Tab => IndentRepr::Name("tab".to_owned()),
+			Indent::Spaces(n) => IndentRepr::Spaces(n),
+		}
+	}
+}
+
+/// The newline to write at the end of each line
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Newline {
+	Lf,
+	Crlf,
+}
+
+/// The names of the newline styles, as passed on the command line
+pub(crate) const NEWLINE_NAMES: [&str; 2] = ["lf", "crlf"];
+
+impl FromStr for Newline {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"lf" => Ok(Newline::Lf),
+			"crlf" => Ok(Newline::Crlf),
+			_ => Err(format!(
+				"invalid newline style {:?}; expected one of {}",
+				s,
+				NEWLINE_NAMES.join(", ")
+			)),
+		}
+	}
+}
+
+/// The format of the SVG, given in the root tag's `output`.
+///
+/// # Properties
+///
+/// - `indent`
+///   - Type: `"tab"` or a non-negative integer
+///   - Required: No. Missing means that the SVG is written on a single line.
+///   - Description: What to indent each level of nesting with: a tab, or that many
+///     spaces.
+/// - `newline`
+///   - Type: `"lf"` or `"crlf"`
+///   - Required: No. Missing is equivalent to `"lf"`.
+///   - Description: The newline to end lines with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct OutputFormat {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub(crate) indent: Option<Indent>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub(crate) newline: Option<Newline>,
+}
+
+impl OutputFormat {
+	/// This format, with each option that it doesn't set taken from `fallback`
+	pub(crate) fn or(self, fallback: OutputFormat) -> OutputFormat {
+		OutputFormat {
+			indent: self.indent.or(fallback.indent),
+			newline: self.newline.or(fallback.newline),
+		}
+	}
+
+	/// A writer that writes XML to `inner` in this format
+	pub(crate) fn xml_writer<W: Write>(self, inner: W) -> XmlWriter<NewlineWriter<W>> {
+		let inner = NewlineWriter {
+			inner,
+			crlf: self.newline == Some(Newline::Crlf),
+			prev_was_cr: false,
+		};
+		match self.indent {
+			None => XmlWriter::new(inner),
+			Some(Indent::Tab) => XmlWriter::new_with_indent(inner, b'\t', 1),
+			Some(Indent::Spaces(n)) => XmlWriter::new_with_indent(inner, b' ', n.into()),
+		}
+	}
+
+	/// End the document written to `writer` (made by [`Self::xml_writer`]). An indented
+	/// document, like any other text file with multiple lines, ends with a newline.
+	pub(crate) fn finish<W: Write>(self, writer: &mut XmlWriter<W>) -> quick_xml::Result<()> {
+		if self.indent.is_some() {
+			writer.write(b"\n")?;
+		}
+		Ok(())
+	}
+}
+
+/// A writer that, if `crlf`, replaces each `\n` written to it (that isn't already
+/// preceded by a `\r`) with `\r\n`
+pub(crate) struct NewlineWriter<W> {
+	inner: W,
+	crlf: bool,
+	prev_was_cr: bool,
+}
+
+impl<W: Write> Write for NewlineWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if !self.crlf {
+			return self.inner.write(buf);
+		}
+
+		for (i, line) in buf.split(|&b| b == b'\n').enumerate() {
+			if i > 0 {
+				self.inner
+					.write_all(if self.prev_was_cr { b"\n" } else { b"\r\n" })?;
+				self.prev_was_cr = false;
+			}
+			if let Some(&last) = line.last() {
+				self.inner.write_all(line)?;
+				self.prev_was_cr = last == b'\r';
+			}
+		}
+
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fibroblast::Fibroblast;
+	use crate::to_svg::svg_writable::ClgnDecodingResult;
+
+	#[test]
+	fn output_formats() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		let render = |output: &str| -> ClgnDecodingResult<String> {
+			std::fs::write(
+				skeleton_dir.path().join("collagen.json"),
+				format!(
+					r#"{{
+						"output": {},
+						"children": [
+							{{ "tag": "g", "children": [{{ "tag": "rect" }}] }},
+							{{ "tag": "text", "text": "a\r\nb" }}
+						]
+					}}"#,
+					output
+				),
+			)
+			.unwrap();
+			let fibroblast = Fibroblast::from_dir(skeleton_dir.path())?;
+			let svg = fibroblast.to_svg_string()?;
+			Ok(svg)
+		};
+
+		assert_eq!(
+			render("{}").unwrap(),
+			"<svg xmlns=\"http://www.w3.org/2000/svg\"><g><rect></rect></g><text>a\r\nb</text></svg>"
+		);
+		assert_eq!(
+			render(r#"{ "indent": 2 }"#).unwrap(),
+			"<svg xmlns=\"http://www.w3.org/2000/svg\">\n  <g>\n    <rect></rect>\n  </g>\n  <text>a\r\nb</text>\n</svg>\n"
+		);
+		assert_eq!(
+			render(r#"{ "indent": "tab", "newline": "crlf" }"#).unwrap(),
+			"<svg xmlns=\"http://www.w3.org/2000/svg\">\r\n\t<g>\r\n\t\t<rect></rect>\r\n\t</g>\r\n\t<text>a\r\nb</text>\r\n</svg>\r\n"
+		);
+
+		// The command line takes precedence
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+		fibroblast.context.set_output_format(OutputFormat {
+			indent: Some(Indent::Spaces(1)),
+			newline: None,
+		});
+		assert!(fibroblast
+			.to_svg_string()
+			.unwrap()
+			.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\">\r\n <g>"));
+
+		for output in vec![
+			r#"{ "indent": "tabs" }"#,
+			r#"{ "indent": -1 }"#,
+			r#"{ "newline": "cr" }"#,
+		]
+		.into_iter()
+		{
+			assert!(render(output).is_err(), "{}", output);
+		}
+	}
+}
@@ -0,0 +1,169 @@
+//! Profiles of root `<svg>` attributes suited to where the SVG will be used, for `clgn
+//! --profile <profile>`. Getting an SVG to scale properly depends on a handful of root
+//! attributes whose interplay is easy to get wrong, resulting in output that's blurry,
+//! cropped, or the wrong size. A profile fills in those attributes with sensible
+//! defaults; any attribute that the root tag sets itself is left alone.
+//!
+//! Every profile adds a `viewBox` of `0 0 <width> <height>` if the root has a numeric
+//! `width` and `height` but no `viewBox`, since without one the SVG can't be scaled. In
+//! addition:
+//!
+//! - `web`: the SVG fills the width of its container and keeps its aspect ratio
+//!   (`width="100%"`, unless `width` or `height` is given, and `preserveAspectRatio="xMidYMid
+//!   meet"`)
+//! - `print`: the SVG has a fixed size (`width` and `height` taken from the `viewBox`,
+//!   unless either is given) and shapes and text are rendered as precisely as possible
+//!   (`shape-rendering` and `text-rendering` of `geometricPrecision`)
+//! - `icon`: the SVG has a fixed size (as with `print`), keeps its aspect ratio, and
+//!   has crisp edges at small sizes (`shape-rendering="crispEdges"`)
+
+use crate::fibroblast::data_types::context::parse_view_box;
+use crate::fibroblast::data_types::{AttrKVValueVec, SimpleValue};
+use std::str::FromStr;
+
+/// The names of the profiles, as passed on the command line
+pub(crate) const PROFILE_NAMES: [&str; 3] = ["web", "print", "icon"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Profile {
+	Web,
+	Print,
+	Icon,
+}
+
+impl FromStr for Profile {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"web" => Ok(Profile::Web),
+			"print" => Ok(Profile::Print),
+			"icon" => Ok(Profile::Icon),
+			_ => Err(format!(
+				"unknown profile {:?}; expected one of {}",
+				s,
+				PROFILE_NAMES.join(", ")
+			)),
+		}
+	}
+}
+
+impl Profile {
+	/// The attributes this profile would give a root tag whose attributes are `attrs`,
+	/// excluding those that `attrs` already has
+	pub(crate) fn default_attrs(&self, attrs: &AttrKVValueVec) -> Vec<(&'static str, SimpleValue)> {
+		let attr = |name: &str| attrs.iter().find(|(k, _)| *k == name).map(|(_, v)| v);
+		let number = |name: &str| attr(name).and_then(|v| v.to_number());
+		let text = |s: &str| SimpleValue::Text(s.to_owned());
+
+		let mut defaults = Vec::new();
+
+		let view_box_size = match attr("viewBox") {
+			Some(view_box) => view_box
+				.to_maybe_string()
+				.and_then(|view_box| parse_view_box(&view_box))
+				.map(|[_, _, width, height]| (width, height)),
+			None => {
+				let size = number("width").zip(number("height"));
+				if let Some((width, height)) = size {
+					defaults.push(("viewBox", text(&format!("0 0 {} {}", width, height))));
+				}
+				size
+			}
+		};
+		let has_size = attr("width").is_some() || attr("height").is_some();
+
+		match self {
+			Profile::Web => {
+				if !has_size {
+					defaults.push(("width", text("100%")));
+				}
+				defaults.push(("preserveAspectRatio", text("xMidYMid meet")));
+			}
+			Profile::Print => {
+				if let (false, Some((width, height))) = (has_size, view_box_size) {
+					defaults.push(("width", text(&width.to_string())));
+					defaults.push(("height", text(&height.to_string())));
+				}
+				defaults.push(("shape-rendering", text("geometricPrecision")));
+				defaults.push(("text-rendering", text("geometricPrecision")));
+			}
+			Profile::Icon => {
+				if let (false, Some((width, height))) = (has_size, view_box_size) {
+					defaults.push(("width", text(&width.to_string())));
+					defaults.push(("height", text(&height.to_string())));
+				}
+				defaults.push(("preserveAspectRatio", text("xMidYMid meet")));
+				defaults.push(("shape-rendering", text("crispEdges")));
+			}
+		}
+
+		defaults.retain(|(k, _)| attr(k).is_none());
+		defaults
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::borrow::Cow;
+
+	fn attrs_of(pairs: &[(&'static str, &str)]) -> AttrKVValueVec<'static> {
+		let mut attrs = AttrKVValueVec::default();
+		for (k, v) in pairs {
+			attrs.push((k, Cow::Owned(SimpleValue::Text((*v).to_owned()))));
+		}
+		attrs
+	}
+
+	fn defaults(profile: &str, pairs: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+		profile
+			.parse::<Profile>()
+			.unwrap()
+			.default_attrs(&attrs_of(pairs))
+			.into_iter()
+			.map(|(k, v)| (k, v.to_maybe_string().unwrap().into_owned()))
+			.collect()
+	}
+
+	fn owned(pairs: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+		pairs.iter().map(|(k, v)| (*k, (*v).to_owned())).collect()
+	}
+
+	#[test]
+	fn profiles() {
+		assert_eq!(
+			defaults("web", &[("width", "40"), ("height", "30")]),
+			owned(&[
+				("viewBox", "0 0 40 30"),
+				("preserveAspectRatio", "xMidYMid meet")
+			])
+		);
+		assert_eq!(
+			defaults("web", &[("viewBox", "0 0 40 30")]),
+			owned(&[("width", "100%"), ("preserveAspectRatio", "xMidYMid meet")])
+		);
+		assert_eq!(
+			defaults("print", &[("viewBox", "0 0 40 30")]),
+			owned(&[
+				("width", "40"),
+				("height", "30"),
+				("shape-rendering", "geometricPrecision"),
+				("text-rendering", "geometricPrecision")
+			])
+		);
+		assert_eq!(
+			defaults(
+				"icon",
+				&[
+					("viewBox", "0 0 24 24"),
+					("width", "48"),
+					("shape-rendering", "auto")
+				]
+			),
+			owned(&[("preserveAspectRatio", "xMidYMid meet")])
+		);
+
+		assert!("poster".parse::<Profile>().is_err());
+	}
+}
@@ -0,0 +1,214 @@
+//! Checks on text that's written without being escaped, which happens when a tag has
+//! `"should_escape_text": false` (so that its text can contain markup, such as
+//! `<tspan>`s). Such text goes into the SVG verbatim, so if it isn't well-formed XML —
+//! say, it has an unclosed tag or a stray `&` — then neither is the SVG, and viewers
+//! refuse to open it without saying why. Checking the text as it's written lets the
+//! error point to the tag it came from.
+//!
+//! As with size limits, a problem only prints a warning, unless in strict mode.
+
+use crate::to_svg::svg_writable::{ClgnDecodingError, ClgnDecodingResult};
+use quick_xml::events::{BytesStart, Event as XmlEvent};
+use quick_xml::Reader as XmlReader;
+
+/// The entities that XML defines; any others would need a DTD to declare them
+const XML_ENTITIES: [&str; 5] = ["lt", "gt", "amp", "apos", "quot"];
+
+/// The longest excerpt of a tag's text to show in a message
+const MAX_EXCERPT_CHARS: usize = 40;
+
+/// The first problem with the entity references in `text`, which is either character
+/// data or an attribute value
+fn entity_error(text: &str) -> Option<String> {
+	for (i, _) in text.match_indices('&') {
+		let rest = &text[i + 1..];
+		let reference = match rest.find(';') {
+			Some(end) => &rest[..end],
+			None => return Some("a stray `&` (write `&amp;` for a literal `&`)".to_owned()),
+		};
+
+		let is_valid = if let Some(hex) = reference.strip_prefix("#x") {
+			!hex.is_empty()
+				&& u32::from_str_radix(hex, 16).map_or(false, |n| char::from_u32(n).is_some())
+		} else if let Some(dec) = reference.strip_prefix('#') {
+			!dec.is_empty()
+				&& dec
+					.parse::<u32>()
+					.map_or(false, |n| char::from_u32(n).is_some())
+		} else {
+			XML_ENTITIES.contains(&reference)
+		};
+
+		if !is_valid {
+			return Some(
+				if reference
+					.chars()
+					.all(|c| c.is_alphanumeric() || c == '#' || c == '_' || c == '-')
+				{
+					format!(
+						"the unknown entity `&{};` (only {} are defined in XML; use a \
+						 character reference such as `&#160;` instead)",
+						reference,
+						XML_ENTITIES
+							.iter()
+							.map(|e| format!("`&{};`", e))
+							.collect::<Vec<_>>()
+							.join(", ")
+					)
+				} else {
+					"a stray `&` (write `&amp;` for a literal `&`)".to_owned()
+				},
+			);
+		}
+	}
+
+	None
+}
+
+/// The name of the start (or empty) tag `elem`, or the first problem with its
+/// attributes
+fn start_tag_error(elem: &BytesStart) -> Result<String, String> {
+	let name = String::from_utf8_lossy(elem.name()).into_owned();
+	for attr in elem.attributes() {
+		let attr = attr.map_err(|e| format!("in the tag `<{}>`, {}", name, e))?;
+		if let Some(msg) = entity_error(&String::from_utf8_lossy(&attr.value)) {
+			return Err(format!(
+				"the attribute `{}` of `<{}>` contains {}",
+				String::from_utf8_lossy(attr.key),
+				name,
+				msg
+			));
+		}
+	}
+
+	Ok(name)
+}
+
+/// The first reason that `fragment` isn't well-formed XML (content, that is, such as
+/// may appear between a start tag and its end tag), if any
+pub(crate) fn xml_fragment_error(fragment: &str) -> Option<String> {
+	let mut reader = XmlReader::from_str(fragment);
+	// Mismatched end tags are reported below, more helpfully than `quick_xml` would
+	reader.check_end_names(false);
+
+	let mut open_tags = Vec::<String>::new();
+	let mut buf = Vec::new();
+	loop {
+		let event = match reader.read_event(&mut buf) {
+			Ok(event) => event,
+			Err(e) => return Some(e.to_string()),
+		};
+
+		match event {
+			XmlEvent::Start(elem) => match start_tag_error(&elem) {
+				Ok(name) => open_tags.push(name),
+				Err(msg) => return Some(msg),
+			},
+			XmlEvent::Empty(elem) => {
+				if let Err(msg) = start_tag_error(&elem) {
+					return Some(msg);
+				}
+			}
+			XmlEvent::End(elem) => {
+				let name = String::from_utf8_lossy(elem.name());
+				match open_tags.pop() {
+					Some(open) if open == name => {}
+					Some(open) => {
+						return Some(format!(
+							"`<{}>` is closed by `</{}>` instead of `</{}>`",
+							open, name, open
+						))
+					}
+					None => {
+						return Some(format!("`</{}>` closes a tag that was never opened", name))
+					}
+				}
+			}
+			XmlEvent::Text(text) => {
+				if let Some(msg) = entity_error(&String::from_utf8_lossy(&text)) {
+					return Some(format!("it contains {}", msg));
+				}
+			}
+			XmlEvent::Eof => break,
+			_ => {}
+		}
+		buf.clear();
+	}
+
+	open_tags
+		.last()
+		.map(|open| format!("`<{}>` is never closed", open))
+}
+
+/// Check that `text`, the unescaped text of a tag named `tag_name`, is well-formed XML.
+/// If it isn't, it's an error if `strict`, and a warning otherwise.
+pub(crate) fn check_unescaped_text(
+	tag_name: &str,
+	text: &str,
+	strict: bool,
+) -> ClgnDecodingResult<()> {
+	let problem = match xml_fragment_error(text) {
+		Some(problem) => problem,
+		None => return Ok(()),
+	};
+
+	let mut excerpt = text.chars().take(MAX_EXCERPT_CHARS).collect::<String>();
+	if excerpt.len() < text.len() {
+		excerpt.push('…');
+	}
+	let msg = format!(
+		"The unescaped text of a `<{}>`, {:?}, is not well-formed XML, so neither is the SVG: \
+		 {}. Fix the text, or remove the tag's `\"should_escape_text\": false` if it's not \
+		 meant to contain markup.",
+		tag_name, excerpt, problem
+	);
+
+	if strict {
+		Err(ClgnDecodingError::MalformedXml { msg })
+	} else {
+		eprintln!("Warning: {}", msg);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn well_formed_fragments() {
+		for fragment in [
+			"",
+			"plain text",
+			"a <tspan font-weight=\"bold\">bold</tspan> word",
+			"<br/>&amp; &lt;&#160;&#xA0; <![CDATA[ & < ]]> <!-- & -->",
+			"<a href=\"?x=1&amp;y=2\"><b>nested</b></a>",
+		] {
+			assert_eq!(xml_fragment_error(fragment), None, "{}", fragment);
+		}
+	}
+
+	#[test]
+	fn malformed_fragments() {
+		for fragment in [
+			"<tspan>unclosed",
+			"<a><b></a></b>",
+			"closed</tspan>",
+			"fish & chips",
+			"&nbsp;",
+			"&#xZZ;",
+			"<a href=\"?x=1&y=2\">link</a>",
+			"<a x=\"1\" x=\"2\"></a>",
+			"1 < 2",
+		] {
+			assert!(xml_fragment_error(fragment).is_some(), "{}", fragment);
+		}
+
+		assert!(check_unescaped_text("text", "<b>", false).is_ok());
+		assert!(matches!(
+			check_unescaped_text("text", "<b>", true),
+			Err(ClgnDecodingError::MalformedXml { .. })
+		));
+		assert!(check_unescaped_text("text", "<b></b>", true).is_ok());
+	}
+}
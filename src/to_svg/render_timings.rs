@@ -0,0 +1,250 @@
+//! Timing how long each tag in the skeleton takes to render, for `clgn
+//! --profile-render <FILE>`. When a build is slow, this shows which tags (and which kinds
+//! of tags) are responsible, and whether the time goes to reading assets from disk,
+//! base64-encoding them, or everything else (variable substitution, writing the SVG,
+//! etc.).
+//!
+//! Tags are identified by JSON pointers into the skeleton, just as in
+//! [`tag_sizes`](super::tag_sizes). Each tag's time includes that of its descendants;
+//! its *own* time excludes them, so the own times of all tags add up to the time taken
+//! to render the whole document.
+
+use super::svg_writable::ClgnDecodingResult;
+use super::tag_sizes::child_pointer;
+use crate::fibroblast::Fibroblast;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+/// A part of rendering a tag whose time is reported separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RenderPhase {
+	/// Reading images and fonts from disk
+	AssetIo,
+	/// Base64-encoding images and fonts
+	Base64,
+}
+
+/// The time it took to render a tag, in microseconds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TagTiming {
+	/// A JSON pointer to the tag, e.g., `/children/0`; the root's is `""`
+	pub pointer: String,
+	pub tag_name: String,
+	/// The kind of tag, e.g., `image` for an [`ImageTag`](crate::fibroblast::tags::ImageTag)
+	pub kind: &'static str,
+	/// The time taken to render the tag, including its descendants
+	pub total_us: u64,
+	/// The time taken to render the tag, excluding its descendants
+	pub own_us: u64,
+	/// The part of `own_us` spent reading assets from disk
+	pub asset_io_us: u64,
+	/// The part of `own_us` spent base64-encoding assets
+	pub base64_us: u64,
+}
+
+/// The time taken by all tags of one kind, in microseconds
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct KindTiming {
+	pub count: usize,
+	pub own_us: u64,
+	pub asset_io_us: u64,
+	pub base64_us: u64,
+}
+
+/// A report of the time taken to decode and render a skeleton
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RenderProfile {
+	/// The time taken to read and decode the manifest
+	pub decode_us: u64,
+	/// The time taken to render the document
+	pub render_us: u64,
+	/// The time taken by each kind of tag
+	pub by_kind: BTreeMap<&'static str, KindTiming>,
+	/// The time taken by each tag, in the order the tags were written
+	pub tags: Vec<TagTiming>,
+}
+
+impl RenderProfile {
+	pub fn new(decode_time: Duration, render_time: Duration, tags: Vec<TagTiming>) -> Self {
+		let mut by_kind = BTreeMap::<_, KindTiming>::new();
+		for tag in &tags {
+			let kind = by_kind.entry(tag.kind).or_default();
+			kind.count += 1;
+			kind.own_us += tag.own_us;
+			kind.asset_io_us += tag.asset_io_us;
+			kind.base64_us += tag.base64_us;
+		}
+
+		Self {
+			decode_us: micros(decode_time),
+			render_us: micros(render_time),
+			by_kind,
+			tags,
+		}
+	}
+}
+
+fn micros(duration: Duration) -> u64 {
+	u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
+
+/// A tag that's being written
+#[derive(Debug, Clone)]
+struct OpenTag {
+	/// The index into `RenderTimer::timings` of this tag's `TagTiming`
+	slot: usize,
+	start: Instant,
+	/// The total time taken by this tag's children written so far
+	children_time: Duration,
+	asset_io_time: Duration,
+	base64_time: Duration,
+}
+
+/// Records the time taken to write tags as they're written
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RenderTimer {
+	open_tags: Vec<OpenTag>,
+	timings: Vec<TagTiming>,
+}
+
+impl RenderTimer {
+	/// Note that the tag named `tag_name`, of kind `kind`, is about to be written.
+	/// `index` is its index among its parent's children, or `None` if it's the root.
+	pub(crate) fn open(&mut self, index: Option<usize>, tag_name: &str, kind: &'static str) {
+		let parent_pointer = self
+			.open_tags
+			.last()
+			.map(|parent| self.timings[parent.slot].pointer.as_str());
+		let pointer = child_pointer(parent_pointer, index);
+
+		self.open_tags.push(OpenTag {
+			slot: self.timings.len(),
+			start: Instant::now(),
+			children_time: Duration::ZERO,
+			asset_io_time: Duration::ZERO,
+			base64_time: Duration::ZERO,
+		});
+		self.timings.push(TagTiming {
+			pointer,
+			tag_name: tag_name.to_owned(),
+			kind,
+			total_us: 0,
+			own_us: 0,
+			asset_io_us: 0,
+			base64_us: 0,
+		});
+	}
+
+	/// Note that the most recently opened tag has been written
+	pub(crate) fn close(&mut self) {
+		let tag = match self.open_tags.pop() {
+			Some(tag) => tag,
+			None => return,
+		};
+
+		let total_time = tag.start.elapsed();
+		let timing = &mut self.timings[tag.slot];
+		timing.total_us = micros(total_time);
+		timing.own_us = micros(total_time.saturating_sub(tag.children_time));
+		timing.asset_io_us = micros(tag.asset_io_time);
+		timing.base64_us = micros(tag.base64_time);
+
+		if let Some(parent) = self.open_tags.last_mut() {
+			parent.children_time += total_time;
+		}
+	}
+
+	/// Attribute `time`, spent in `phase`, to the tag currently being written
+	pub(crate) fn add_phase_time(&mut self, phase: RenderPhase, time: Duration) {
+		if let Some(tag) = self.open_tags.last_mut() {
+			match phase {
+				RenderPhase::AssetIo => tag.asset_io_time += time,
+				RenderPhase::Base64 => tag.base64_time += time,
+			}
+		}
+	}
+}
+
+impl<'a> Fibroblast<'a> {
+	/// Call `f`, which renders this skeleton, timing each tag as it's written. Returns
+	/// `f`'s result along with the timings, in the order the tags were written.
+	pub fn with_render_timings<T>(
+		&self,
+		f: impl FnOnce() -> ClgnDecodingResult<T>,
+	) -> ClgnDecodingResult<(T, Vec<TagTiming>)> {
+		self.context
+			.replace_render_timer(Some(RenderTimer::default()));
+		let result = f();
+
+		// Always remove the timer so that later renders aren't timed
+		let timer = self.context.replace_render_timer(None);
+		let timings = timer.map_or_else(Vec::new, |timer| timer.timings);
+
+		Ok((result?, timings))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn render_timings() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			r#"{
+				"children": [
+					{ "tag": "g", "z": 1, "children": [{ "tag": "rect" }] },
+					{ "image_path": "sprites.png" }
+				]
+			}"#,
+		)
+		.unwrap();
+		std::fs::copy(
+			"tests/examples/sprite-sheet/skeleton/sprites.png",
+			skeleton_dir.path().join("sprites.png"),
+		)
+		.unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+
+		let svg = fibroblast.to_svg_string().unwrap();
+		let (timed_svg, timings) = fibroblast
+			.with_render_timings(|| fibroblast.to_svg_string())
+			.unwrap();
+		assert_eq!(timed_svg, svg);
+
+		assert_eq!(
+			timings
+				.iter()
+				.map(|t| (t.pointer.as_str(), t.tag_name.as_str(), t.kind))
+				.collect::<Vec<_>>(),
+			vec![
+				("", "svg", "root"),
+				("/children/1", "image", "image"),
+				("/children/0", "g", "other"),
+				("/children/0/children/0", "rect", "other"),
+			]
+		);
+		for timing in &timings {
+			assert!(timing.own_us <= timing.total_us);
+			assert!(timing.asset_io_us + timing.base64_us <= timing.own_us);
+		}
+		// Each time is rounded down to the microsecond
+		let own_us = timings.iter().map(|t| t.own_us).sum::<u64>();
+		assert!(
+			own_us <= timings[0].total_us && timings[0].total_us - own_us < timings.len() as u64,
+			"{:?}",
+			timings
+		);
+
+		let profile = RenderProfile::new(Duration::ZERO, Duration::ZERO, timings);
+		assert_eq!(profile.by_kind["other"].count, 2);
+		assert_eq!(profile.by_kind["image"].count, 1);
+
+		// Timing doesn't affect later renders
+		assert_eq!(fibroblast.to_svg_string().unwrap(), svg);
+	}
+}
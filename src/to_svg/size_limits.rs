@@ -0,0 +1,145 @@
+//! Guardrails on the size of the SVG being written. Embedding a few large images can
+//! easily produce an SVG of tens of megabytes, which many sites refuse to accept; it's
+//! better to find out when the SVG is generated than when it's uploaded.
+//!
+//! By default, exceeding a limit only prints a warning. In strict mode, it's an error:
+//! writing the SVG stops as soon as it exceeds the output limit, and `clgn` removes the
+//! files that the SVG was being written to.
+
+use crate::to_svg::svg_writable::{ClgnDecodingError, ClgnDecodingResult};
+
+/// The default maximum length, in bytes, of a single attribute value (roughly the size
+/// of a 7.5 MB image once base64 encoded)
+pub(crate) const DEFAULT_MAX_ATTR_BYTES: usize = 10_000_000;
+
+/// The default maximum size, in bytes, of the whole SVG
+pub(crate) const DEFAULT_MAX_OUTPUT_BYTES: usize = 25_000_000;
+
+const SUGGESTION: &str = "Consider downscaling embedded images, or using `crop` to embed only \
+                          the part of an image that's needed.";
+
+/// Size limits, in bytes, and what to do when they're exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SizeLimits {
+	pub(crate) max_attr_bytes: usize,
+	pub(crate) max_output_bytes: usize,
+	/// Whether exceeding a limit is an error (as opposed to a warning)
+	pub(crate) strict: bool,
+}
+
+impl Default for SizeLimits {
+	fn default() -> Self {
+		Self {
+			max_attr_bytes: DEFAULT_MAX_ATTR_BYTES,
+			max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+			strict: false,
+		}
+	}
+}
+
+impl SizeLimits {
+	fn exceeded(&self, msg: String) -> ClgnDecodingResult<()> {
+		let msg = format!("{} {}", msg, SUGGESTION);
+		if self.strict {
+			Err(ClgnDecodingError::SizeLimit { msg })
+		} else {
+			eprintln!("Warning: {}", msg);
+			Ok(())
+		}
+	}
+
+	/// Check the length of the value of the attribute `attr` of a tag named `tag_name`
+	pub(crate) fn check_attr(
+		&self,
+		tag_name: &str,
+		attr: &str,
+		len: usize,
+	) -> ClgnDecodingResult<()> {
+		if len <= self.max_attr_bytes {
+			return Ok(());
+		}
+
+		self.exceeded(format!(
+			"The attribute `{}` of a `<{}>` is {} bytes long, more than the limit of {} bytes.",
+			attr, tag_name, len, self.max_attr_bytes
+		))
+	}
+
+	/// Check the size of the whole SVG, which is `len` bytes
+	pub(crate) fn check_output(&self, len: usize) -> ClgnDecodingResult<()> {
+		if len <= self.max_output_bytes {
+			return Ok(());
+		}
+
+		self.exceeded(format!(
+			"The SVG is {} bytes, more than the limit of {} bytes.",
+			len, self.max_output_bytes
+		))
+	}
+
+	/// The most bytes the SVG may be written with before writing should stop, which is
+	/// only the case in strict mode; otherwise an SVG that's too big is written in full
+	pub(crate) fn strict_max_output(&self) -> Option<usize> {
+		if self.strict {
+			Some(self.max_output_bytes)
+		} else {
+			None
+		}
+	}
+
+	/// Check the outcome, `result`, of writing an SVG through a writer that stops at
+	/// [`SizeLimits::strict_max_output`] and had counted `len` bytes when it finished, or
+	/// stopped. A writer that stopped fails with an I/O error, which is reported as the
+	/// SVG exceeding the limit instead.
+	pub(crate) fn check_written_output(
+		&self,
+		len: usize,
+		result: ClgnDecodingResult<()>,
+	) -> ClgnDecodingResult<()> {
+		match self.strict_max_output() {
+			Some(max_bytes) if len > max_bytes => self.exceeded(format!(
+				"The SVG is at least {} bytes, more than the limit of {} bytes.",
+				len, max_bytes
+			)),
+			_ => {
+				result?;
+				self.check_output(len)
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn limits() {
+		let lenient = SizeLimits {
+			max_attr_bytes: 10,
+			max_output_bytes: 100,
+			strict: false,
+		};
+		let strict = SizeLimits {
+			strict: true,
+			..lenient
+		};
+
+		for limits in [lenient, strict] {
+			assert!(limits.check_attr("image", "href", 10).is_ok());
+			assert!(limits.check_output(100).is_ok());
+		}
+
+		assert!(lenient.check_attr("image", "href", 11).is_ok());
+		assert!(lenient.check_output(101).is_ok());
+
+		assert!(matches!(
+			strict.check_attr("image", "href", 11),
+			Err(ClgnDecodingError::SizeLimit { .. })
+		));
+		assert!(matches!(
+			strict.check_output(101),
+			Err(ClgnDecodingError::SizeLimit { .. })
+		));
+	}
+}
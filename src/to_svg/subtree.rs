@@ -0,0 +1,289 @@
+//! Rendering a single tag of a skeleton (and its descendants), for `clgn render --select
+//! <selector>`. When working on one part of a large collage, this avoids re-rendering
+//! (and re-encoding the images of) everything else.
+//!
+//! The selected tag is rendered just as it would be in the whole document, with the
+//! variables, palette, etc. of its ancestors in scope, but is wrapped in an `<svg>`
+//! that has only the root's `viewBox`, `width`, and `height` and the namespace
+//! declarations of the root and the tag's other ancestors.
+//!
+//! A selector is either a JSON pointer, such as `/children/2/children/0`, or the
+//! equivalent `children[2].children[0]`.
+
+use super::svg_writable::{
+	check_namespaces, write_arrowhead_defs, write_theme_style, ByteCounter, ClgnDecodingResult,
+	SvgWritableTag,
+};
+use crate::fibroblast::data_types::{Anchors, AttrKVValueVec, DecodingContext, SimpleValue};
+use crate::fibroblast::scope_inspector::{parse_pointer, pointer_error};
+use crate::fibroblast::tags::{AnyChildTag, SiblingExports};
+use crate::fibroblast::{Fibroblast, TagLike};
+use quick_xml::events::{BytesEnd, BytesStart, Event as XmlEvent};
+use quick_xml::Writer as XmlWriter;
+use std::borrow::Cow;
+
+/// The attributes of the `<svg>` that wraps the selected tag, as owned (name, value)
+/// pairs
+type WrapperAttrs = Vec<(String, String)>;
+
+/// Parse `selector`, either a JSON pointer of the form `/children/i/children/j/...` or
+/// `children[i].children[j]...`, into the list of child indices `[i, j, ...]`
+fn parse_selector(selector: &str) -> ClgnDecodingResult<Vec<usize>> {
+	if selector.is_empty() || selector.starts_with('/') {
+		return parse_pointer(selector);
+	}
+
+	selector
+		.split('.')
+		.map(|component| {
+			component
+				.strip_prefix("children[")
+				.and_then(|rest| rest.strip_suffix(']'))
+				.and_then(|index| index.parse::<usize>().ok())
+				.ok_or_else(|| {
+					pointer_error(
+						selector,
+						format!("expected `children[<index>]`, not {:?}", component),
+					)
+				})
+		})
+		.collect()
+}
+
+/// Add the attributes among `attrs` that belong on the wrapper `<svg>` to
+/// `wrapper_attrs`: namespace declarations and, if `is_root`, the attributes that
+/// determine the size of the SVG
+fn extend_wrapper_attrs(wrapper_attrs: &mut WrapperAttrs, attrs: &AttrKVValueVec, is_root: bool) {
+	let keep = |k: &str| {
+		k == "xmlns"
+			|| k.starts_with("xmlns:")
+			|| (is_root && matches!(k, "viewBox" | "width" | "height"))
+	};
+	wrapper_attrs.extend(attrs.iter().filter(|(k, _)| keep(k)).filter_map(|(k, v)| {
+		v.to_maybe_string()
+			.map(|v| ((*k).to_owned(), v.into_owned()))
+	}));
+}
+
+/// Write `tag` inside an `<svg>` with attributes `wrapper_attrs`
+fn write_wrapped<'a>(
+	tag: &'a AnyChildTag<'a>,
+	wrapper_attrs: &WrapperAttrs,
+	context: &'a DecodingContext<'a>,
+	writer: &mut XmlWriter<impl std::io::Write>,
+) -> ClgnDecodingResult<()> {
+	let mut attrs = AttrKVValueVec::default();
+	for (k, v) in wrapper_attrs {
+		attrs.push((k.as_str(), Cow::Owned(SimpleValue::Text(v.clone()))));
+	}
+
+	context.with_namespaces(&attrs, || {
+		check_namespaces("svg", &attrs, context)?;
+
+		let mut svg = BytesStart::borrowed_name(b"svg");
+		svg.extend_attributes(wrapper_attrs.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+		writer.write_event(XmlEvent::Start(svg))?;
+
+		write_arrowhead_defs(context, writer)?;
+		tag.to_svg_through_writer(context, writer)?;
+		write_theme_style(context, writer)?;
+
+		writer.write_event(XmlEvent::End(BytesEnd::borrowed(b"svg")))?;
+		Ok(())
+	})
+}
+
+/// Enter the scopes of the child of `children` at `indices[0]` (whose parent is at
+/// `pointer`), then that child's child at `indices[1]`, etc., and write the last one
+fn write_selected<'a>(
+	children: &'a [AnyChildTag<'a>],
+	indices: &[usize],
+	pointer: String,
+	context: &'a DecodingContext<'a>,
+	wrapper_attrs: &mut WrapperAttrs,
+	writer: &mut XmlWriter<impl std::io::Write>,
+) -> ClgnDecodingResult<()> {
+	let (&index, rest) = match indices.split_first() {
+		Some(split) => split,
+		None => return Err(pointer_error(&pointer, "no tag was selected")),
+	};
+
+	let pointer = format!("{}/children/{}", pointer, index);
+	let child = match children.get(index) {
+		Some(child) => child,
+		None => {
+			return Err(pointer_error(
+				&pointer,
+				format!("its parent has only {} children", children.len()),
+			))
+		}
+	};
+	let exports = SiblingExports::of(children, context)?;
+	exports.with_exports_for(index, context, || {
+		if rest.is_empty() {
+			return write_wrapped(child, wrapper_attrs, context, writer);
+		}
+
+		context.with_new_vars(child.vars(context)?, || {
+			let attrs = child.attrs(context)?;
+			extend_wrapper_attrs(wrapper_attrs, &attrs, false);
+
+			context.with_viewport_vars(child.tag_name(), false, &attrs, || match child {
+				AnyChildTag::Container(container) => {
+					let fb = container.as_fibroblast();
+					context.with_new_root(fb.context.get_root().as_path(), || {
+						context.with_palette(fb.root.base_palette(), || {
+							write_selected(
+								child.children(context)?,
+								rest,
+								pointer,
+								context,
+								wrapper_attrs,
+								writer,
+							)
+						})
+					})
+				}
+				_ => write_selected(
+					child.children(context)?,
+					rest,
+					pointer,
+					context,
+					wrapper_attrs,
+					writer,
+				),
+			})
+		})
+	})
+}
+
+impl<'a> Fibroblast<'a> {
+	/// Write only the tag selected by `selector` (see the [module docs](self)), wrapped in
+	/// an `<svg>`, as SVG. The empty selector selects the root, i.e., the whole
+	/// document.
+	pub fn subtree_to_svg_through_writer(
+		&'a self,
+		selector: &str,
+		writer: &mut XmlWriter<impl std::io::Write>,
+	) -> ClgnDecodingResult<()> {
+		let indices = parse_selector(selector)?;
+		if indices.is_empty() {
+			return self.to_svg_through_writer(writer);
+		}
+
+		let context = &self.context;
+		let root = &self.root;
+		let format = root.output_format(context);
+		let limits = context.size_limits();
+		let counter = ByteCounter::new(writer.inner()).with_max_bytes(limits.strict_max_output());
+		let n_bytes = counter.n_bytes();
+		let mut counting_writer = format.xml_writer(counter);
+
+		// Themed values and class styles seen during a previous render of this document
+		// are stale
+		context.take_themes();
+		context.take_class_styles();
+		context.reset_generated_ids();
+		context.reset_used_features();
+		let result = context.with_palette(root.base_palette(), || {
+			context.with_new_vars(root.vars(context)?, || {
+				let root_attrs = root.attrs(context)?;
+				let mut wrapper_attrs = WrapperAttrs::new();
+				extend_wrapper_attrs(&mut wrapper_attrs, &root_attrs, true);
+
+				// The selected tag may contain connectors to tags outside of it
+				let mut anchors = Anchors::default();
+				AnyChildTag::collect_all_anchors(root.children(), context, &mut anchors)?;
+				context.replace_anchors(anchors);
+
+				context.with_viewport_vars(root.tag_name(), true, &root_attrs, || {
+					write_selected(
+						root.children(),
+						&indices,
+						String::new(),
+						context,
+						&mut wrapper_attrs,
+						&mut counting_writer,
+					)
+				})
+			})
+		});
+		let result = result.and_then(|()| Ok(format.finish(&mut counting_writer)?));
+
+		limits.check_written_output(n_bytes.get(), result)?;
+		context.check_target()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::to_svg::svg_writable::ClgnDecodingError;
+
+	#[test]
+	fn selectors() {
+		assert_eq!(parse_selector("").unwrap(), Vec::<usize>::new());
+		assert_eq!(parse_selector("/children/1").unwrap(), vec![1]);
+		assert_eq!(
+			parse_selector("children[2].children[0]").unwrap(),
+			vec![2, 0]
+		);
+
+		for selector in ["children", "children[x]", "attrs[0]", "children[1]."] {
+			assert!(
+				matches!(
+					parse_selector(selector),
+					Err(ClgnDecodingError::JsonPointer { .. })
+				),
+				"{:?}",
+				selector
+			);
+		}
+	}
+
+	#[test]
+	fn subtree() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			r#"{
+				"vars": { "color": "red" },
+				"attrs": { "viewBox": "0 0 100 50", "fill": "blue" },
+				"namespaces": { "a": "urn:a" },
+				"children": [
+					{ "tag": "rect", "attrs": { "width": 10, "height": 10 } },
+					{
+						"tag": "g",
+						"vars": { "side": "50%w" },
+						"attrs": { "xmlns:b": "urn:b" },
+						"children": [
+							{
+								"tag": "circle",
+								"attrs": { "r": "{side}", "fill": "{color}", "b:x": 1 }
+							}
+						]
+					}
+				]
+			}"#,
+		)
+		.unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+
+		let render = |selector| {
+			let mut writer = XmlWriter::new(Vec::new());
+			fibroblast
+				.subtree_to_svg_through_writer(selector, &mut writer)
+				.map(|_| String::from_utf8(writer.into_inner()).unwrap())
+		};
+
+		assert_eq!(
+			render("children[1].children[0]").unwrap(),
+			r#"<svg viewBox="0 0 100 50" xmlns="http://www.w3.org/2000/svg" xmlns:a="urn:a" xmlns:b="urn:b"><circle b:x="1" fill="red" r="50"></circle></svg>"#
+		);
+		assert_eq!(render("").unwrap(), fibroblast.to_svg_string().unwrap());
+		assert!(matches!(
+			render("/children/2"),
+			Err(ClgnDecodingError::JsonPointer { .. })
+		));
+	}
+}
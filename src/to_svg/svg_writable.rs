@@ -3,17 +3,27 @@
 //! JSON to SVG (XML). I don't think it should be *that* hard.
 
 use crate::fibroblast::{
-	data_types::DecodingContext,
-	tags::{AnyChildTag, RootTag},
+	data_types::{Anchors, AttrKVValueVec, DecodingContext},
+	tags::{
+		clip::{resolve_clips, write_clip_defs},
+		link::resolve_tag_link,
+		Animation, AnyChildTag, RootTag, SiblingExports, UseFromTag, ARROWHEAD_MARKER_DEFS,
+	},
 	Fibroblast, TagLike,
 };
 pub(crate) use crate::from_json::decoding_error::{ClgnDecodingError, ClgnDecodingResult};
+use crate::to_svg::target::tag_features;
 
-use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event as XmlEvent};
+/// The document type declaration of SVG 1.1 (less the `<!DOCTYPE` and `>`)
+const SVG_DOCTYPE: &str =
+	r#" svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd""#;
+
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event as XmlEvent};
 use quick_xml::Writer as XmlWriter;
 
+use std::cell::Cell;
 use std::fmt::Debug;
-use std::io::Cursor;
+use std::rc::Rc;
 
 pub(crate) trait SvgWritableTag<'a>: TagLike<'a> {
 	/// Writes `tag` to SVG (aka XML) through an `XmlWriter`, with a `DecodingContext`.
@@ -35,33 +45,87 @@ pub(crate) trait SvgWritableTag<'a>: TagLike<'a> {
 
 		// Write the tag's children and text
 		context.with_new_vars(self.vars(context)?, || {
-			let attr_values = self.attrs(context)?;
+			let mut attr_values = self.attrs(context)?;
+			let clips = resolve_clips(&self.clips(), context, &mut attr_values)?;
+			check_finite(self.tag_name(), &attr_values)?;
+			let link = resolve_tag_link(self, &attr_values, context)?;
 			let attr_strings = attr_values
 				.iter()
 				.filter_map(|(k, v)| v.to_maybe_string().map(|s| (*k, s)))
 				.collect::<Vec<_>>();
+			for (k, v) in &attr_strings {
+				context
+					.size_limits()
+					.check_attr(self.tag_name(), k, v.len())?;
+			}
+			for feature in tag_features(self.tag_name(), attr_strings.iter().map(|(k, _)| *k)) {
+				context.note_feature(feature);
+			}
 
-			curr_elem.extend_attributes(attr_strings.iter().map(|(k, v)| (*k, v.as_ref())));
-			writer.write_event(XmlEvent::Start(curr_elem))?;
+			// Namespaces declared by this tag are in scope for the tag itself as well as its
+			// descendants
+			context.with_namespaces(&attr_values, || {
+				check_namespaces(self.tag_name(), &attr_values, context)?;
+				let descriptions = self.descriptions(context)?;
 
-			write_children(writer)?;
+				if let Some(link) = &link {
+					link.write_start(writer)?;
+				}
 
-			let text = self.text(context)?;
-			writer.write_event(XmlEvent::Text(if self.should_escape_text() {
-				BytesText::from_plain_str(text.as_ref())
-			} else {
-				BytesText::from_escaped(text.as_bytes())
-			}))?;
+				// The root's clips have to go inside it, but other tags' clips go before them,
+				// since not every tag can have a `<defs>` child
+				if !self.is_root() {
+					write_clip_defs(&clips, writer)?;
+				}
+				curr_elem.extend_attributes(attr_strings.iter().map(|(k, v)| (*k, v.as_ref())));
+				writer.write_event(XmlEvent::Start(curr_elem))?;
+				if self.is_root() {
+					write_clip_defs(&clips, writer)?;
+				}
 
-			Ok(())
-		})?;
+				for (name, text) in &descriptions {
+					writer
+						.write_event(XmlEvent::Start(BytesStart::borrowed_name(name.as_bytes())))?;
+					writer.write_event(XmlEvent::Text(BytesText::from_plain_str(text)))?;
+					writer.write_event(XmlEvent::End(BytesEnd::borrowed(name.as_bytes())))?;
+				}
 
-		// Close the tag
-		writer.write_event(XmlEvent::End(BytesEnd::borrowed(tag_name_bytes)))?;
+				for animation in self.animations() {
+					write_animation(animation, context, writer)?;
+				}
+
+				context.with_viewport_vars(
+					self.tag_name(),
+					self.is_root(),
+					&attr_values,
+					|| write_children(writer),
+				)?;
+
+				// An indenting writer puts the closing tag on a line of its own unless it follows
+				// text. That's what we want if anything was written inside the tag, but an
+				// empty tag should stay on one line, so it gets its (empty) text written.
+				let text = self.text(context)?;
+				let is_empty = descriptions.is_empty()
+					&& self.animations().is_empty()
+					&& !self.has_children(context)?;
+				if is_empty || !text.as_str().is_empty() {
+					text.write(self.tag_name(), context.size_limits().strict, writer)?;
+				}
 
-		Ok(())
+				// Close the tag
+				writer.write_event(XmlEvent::End(BytesEnd::borrowed(tag_name_bytes)))?;
+				if let Some(link) = &link {
+					link.write_end(writer)?;
+				}
+
+				Ok(())
+			})
+		})
 	}
 
+	/// Whether anything other than text and animations is written inside this tag
+	fn has_children(&'a self, context: &'a DecodingContext<'a>) -> ClgnDecodingResult<bool>;
+
 	/// Convert the in-memory representation of a Fibroblast to SVG. `writer` determines
 	/// where the output goes -- a `String`, to a file, etc.
 	fn to_svg_through_writer(
@@ -69,19 +133,170 @@ pub(crate) trait SvgWritableTag<'a>: TagLike<'a> {
 		context: &'a DecodingContext<'a>,
 		writer: &mut XmlWriter<impl std::io::Write>,
 	) -> ClgnDecodingResult<()>;
+}
+
+/// Make sure that no numeric attribute value in `attrs`, which belong to a tag named
+/// `tag_name`, is infinite or NaN. Browsers silently fail to render such values, so
+/// it's better to fail loudly here.
+fn check_finite(tag_name: &str, attrs: &AttrKVValueVec) -> ClgnDecodingResult<()> {
+	match attrs.iter().find(|(_, v)| v.is_non_finite()) {
+		Some((attr, value)) => Err(ClgnDecodingError::NonFiniteAttribute {
+			tag: tag_name.to_owned(),
+			attr: (*attr).to_owned(),
+			value: value.to_maybe_string().unwrap_or_default().into_owned(),
+		}),
+		None => Ok(()),
+	}
+}
+
+/// Make sure that `tag_name`, and the name of each attribute in `attrs`, either has no
+/// namespace prefix or has one that's in scope. Otherwise the output wouldn't be
+/// well-formed XML.
+pub(crate) fn check_namespaces(
+	tag_name: &str,
+	attrs: &AttrKVValueVec,
+	context: &DecodingContext,
+) -> ClgnDecodingResult<()> {
+	let names = std::iter::once(tag_name).chain(attrs.iter().map(|(k, _)| *k));
+	for name in names {
+		if let Some((prefix, _)) = name.split_once(':') {
+			if !context.is_namespace_declared(prefix) {
+				return Err(ClgnDecodingError::UndeclaredNamespace {
+					name: name.to_owned(),
+					prefix: prefix.to_owned(),
+				});
+			}
+		}
+	}
+
+	Ok(())
+}
 
-	fn to_svg_string(&'a self, context: &'a DecodingContext<'a>) -> ClgnDecodingResult<String> {
-		let mut writer = XmlWriter::new(Cursor::new(Vec::new()));
-		self.to_svg_through_writer(context, &mut writer)?;
+/// Write `animation` as an `<animate>` or `<animateTransform>` tag
+fn write_animation(
+	animation: &Animation,
+	context: &DecodingContext,
+	writer: &mut XmlWriter<impl std::io::Write>,
+) -> ClgnDecodingResult<()> {
+	let tag_name_bytes = animation.tag_name().as_bytes();
+	let attrs = animation.attrs(context)?;
+	check_finite(animation.tag_name(), &attrs)?;
+	let attr_strings = attrs
+		.iter()
+		.filter_map(|(k, v)| v.to_maybe_string().map(|s| (*k, s)))
+		.collect::<Vec<_>>();
 
-		let buf = writer.into_inner().into_inner();
-		let out_string = std::str::from_utf8(&buf)?.to_owned();
+	let mut elem = BytesStart::borrowed_name(tag_name_bytes);
+	elem.extend_attributes(attr_strings.iter().map(|(k, v)| (*k, v.as_ref())));
+	writer.write_event(XmlEvent::Start(elem))?;
+	writer.write_event(XmlEvent::End(BytesEnd::borrowed(tag_name_bytes)))?;
 
-		Ok(out_string)
+	Ok(())
+}
+
+/// Write the defs of the arrowhead marker that connectors use, if any connector needs
+/// it
+pub(crate) fn write_arrowhead_defs(
+	context: &DecodingContext,
+	writer: &mut XmlWriter<impl std::io::Write>,
+) -> ClgnDecodingResult<()> {
+	if context.needs_arrowhead() {
+		writer.write_event(XmlEvent::Text(BytesText::from_escaped(
+			ARROWHEAD_MARKER_DEFS.as_bytes(),
+		)))?;
 	}
+
+	Ok(())
+}
+
+/// Write the `<style>` that defines the themed values and class styles written so far,
+/// if there were any. A `<style>` applies to the whole document wherever it appears, so
+/// it can come after the elements whose themed values and classes it defines.
+pub(crate) fn write_theme_style(
+	context: &DecodingContext,
+	writer: &mut XmlWriter<impl std::io::Write>,
+) -> ClgnDecodingResult<()> {
+	let themes = context.take_themes();
+	let class_styles = context.take_class_styles();
+	if !(themes.is_empty() && class_styles.is_empty()) {
+		let mut css = String::new();
+		if !themes.is_empty() {
+			css.push_str(&themes.to_css());
+		}
+		css.push_str(&class_styles.to_css());
+
+		writer.write_event(XmlEvent::Start(BytesStart::borrowed_name(b"style")))?;
+		writer.write_event(XmlEvent::Text(BytesText::from_plain_str(&css)))?;
+		writer.write_event(XmlEvent::End(BytesEnd::borrowed(b"style")))?;
+	}
+
+	Ok(())
+}
+
+/// Write `children` in paint order, recording each one's size if sizes are being
+/// recorded
+fn write_children<'a>(
+	children: &'a [AnyChildTag<'a>],
+	context: &'a DecodingContext<'a>,
+	writer: &mut XmlWriter<impl std::io::Write>,
+) -> ClgnDecodingResult<()> {
+	let exports = SiblingExports::of(children, context)?;
+	for (index, child) in AnyChildTag::in_paint_order(children) {
+		context.with_nested_tag(|| {
+			exports.with_exports_for(index, context, || {
+				context.with_recorded_tag(Some(index), child.tag_name(), child.kind(), || {
+					child.to_svg_through_writer(context, writer)
+				})
+			})
+		})?;
+	}
+
+	Ok(())
+}
+
+/// Write the `<symbol>` that `tag` uses, in a `<defs>`, unless it's already been written
+/// to this document. The symbol is written in the context of the library it's from.
+fn write_symbol_def<'a>(
+	tag: &'a UseFromTag<'a>,
+	context: &'a DecodingContext<'a>,
+	writer: &mut XmlWriter<impl std::io::Write>,
+) -> ClgnDecodingResult<()> {
+	let (id, is_new) = tag.symbol_id(context)?;
+	if !is_new {
+		return Ok(());
+	}
+
+	let library = tag.library(context)?;
+	let symbol = tag.symbol_def(context)?;
+	context.with_new_root(library.context.get_root().as_path(), || {
+		context.with_palette(library.root.base_palette(), || {
+			context.with_new_vars(library.vars()?, || {
+				let attrs = symbol.attrs(context)?;
+				let mut elem = BytesStart::borrowed_name(b"symbol");
+				elem.push_attribute(("id", id.as_str()));
+				for (k, v) in attrs.iter() {
+					if let Some(v) = v.to_maybe_string() {
+						elem.push_attribute((*k, v.as_ref()));
+					}
+				}
+
+				writer.write_event(XmlEvent::Start(BytesStart::borrowed_name(b"defs")))?;
+				writer.write_event(XmlEvent::Start(elem))?;
+				write_children(symbol.children(), context, writer)?;
+				writer.write_event(XmlEvent::End(BytesEnd::borrowed(b"symbol")))?;
+				writer.write_event(XmlEvent::End(BytesEnd::borrowed(b"defs")))?;
+
+				Ok(())
+			})
+		})
+	})
 }
 
 impl<'a> SvgWritableTag<'a> for AnyChildTag<'a> {
+	fn has_children(&'a self, context: &'a DecodingContext<'a>) -> ClgnDecodingResult<bool> {
+		Ok(!self.children(context)?.is_empty())
+	}
+
 	fn to_svg_through_writer(
 		&'a self,
 		context: &'a DecodingContext<'a>,
@@ -90,27 +305,38 @@ impl<'a> SvgWritableTag<'a> for AnyChildTag<'a> {
 	where
 		Self: Debug,
 	{
-		self.to_svg_through_writer_with(context, writer, |writer| match &self {
-			AnyChildTag::Container(container) => {
-				let fb = container.as_fibroblast();
-				context.with_new_root(fb.context.get_root().as_path(), || {
-					for child in self.children(context)? {
-						child.to_svg_through_writer(context, writer)?;
-					}
-					Ok(())
-				})
+		if let AnyChildTag::UseFrom(tag) = self {
+			write_symbol_def(tag, context, writer)?;
+		}
+
+		self.to_svg_through_writer_with(context, writer, |writer| {
+			for credit in self.credits(context)? {
+				context.note_credit(credit);
 			}
-			_ => context.with_new_vars(self.vars(context)?, || {
-				for child in self.children(context)? {
-					child.to_svg_through_writer(context, writer)?;
+
+			match &self {
+				AnyChildTag::Container(container) => {
+					let fb = container.as_fibroblast();
+					context.with_new_root(fb.context.get_root().as_path(), || {
+						context.with_palette(fb.root.base_palette(), || {
+							write_children(self.children(context)?, context, writer)
+						})
+					})
 				}
-				Ok(())
-			}),
+				_ => context.with_new_vars(self.vars(context)?, || {
+					write_children(self.children(context)?, context, writer)
+				}),
+			}
 		})
 	}
 }
 
 impl<'a> SvgWritableTag<'a> for RootTag<'a> {
+	// Even without children, the root may contain generated `<defs>` and `<style>`s
+	fn has_children(&'a self, _: &'a DecodingContext<'a>) -> ClgnDecodingResult<bool> {
+		Ok(true)
+	}
+
 	fn to_svg_through_writer(
 		&'a self,
 		context: &'a DecodingContext<'a>,
@@ -119,12 +345,62 @@ impl<'a> SvgWritableTag<'a> for RootTag<'a> {
 	where
 		Self: Debug,
 	{
-		self.to_svg_through_writer_with(context, writer, |writer| {
-			for child in self.children() {
-				child.to_svg_through_writer(context, writer)?;
-			}
+		context.with_recorded_tag(None, self.tag_name(), "root", || {
+			self.write_document(context, writer)
+		})
+	}
+}
+
+impl<'a> RootTag<'a> {
+	/// Write the whole document: the XML declaration and DOCTYPE, if any, followed by
+	/// the root tag
+	fn write_document(
+		&'a self,
+		context: &'a DecodingContext<'a>,
+		writer: &mut XmlWriter<impl std::io::Write>,
+	) -> ClgnDecodingResult<()> {
+		// Themed values and class styles seen during a previous render of this document
+		// are stale
+		context.take_themes();
+		context.take_class_styles();
+		context.take_credits();
+		context.reset_generated_ids();
+		context.reset_used_features();
 
-			Ok(())
+		if let Some(decl) = self.xml_declaration(context) {
+			let standalone = decl
+				.standalone
+				.map(|standalone| if standalone { &b"yes"[..] } else { &b"no"[..] });
+			writer.write_event(XmlEvent::Decl(BytesDecl::new(
+				b"1.0",
+				Some(b"UTF-8"),
+				standalone,
+			)))?;
+		}
+		if self.doctype() {
+			writer.write_event(XmlEvent::DocType(BytesText::from_escaped_str(SVG_DOCTYPE)))?;
+		}
+
+		context.with_palette(self.base_palette(), || {
+			self.to_svg_through_writer_with(context, writer, |writer| {
+				// Connectors may refer to elements anywhere in the document, so find out where
+				// everything is before writing any of it
+				let mut anchors = Anchors::default();
+				AnyChildTag::collect_all_anchors(self.children(), context, &mut anchors)?;
+				context.replace_anchors(anchors);
+
+				write_arrowhead_defs(context, writer)?;
+				write_children(self.children(), context, writer)?;
+				if let Some(credits) = self.credits() {
+					credits.write(
+						&context.take_credits(),
+						&self.attrs(context)?,
+						context,
+						writer,
+					)?;
+				}
+				write_theme_style(context, writer)
+			})
 		})
 	}
 }
@@ -134,6 +410,241 @@ impl<'a> Fibroblast<'a> {
 		&'a self,
 		writer: &mut XmlWriter<impl std::io::Write>,
 	) -> ClgnDecodingResult<()> {
-		self.root.to_svg_through_writer(&self.context, writer)
+		let format = self.root.output_format(&self.context);
+		let limits = self.context.size_limits();
+		let counter = ByteCounter::new(writer.inner()).with_max_bytes(limits.strict_max_output());
+		let n_bytes = counter.n_bytes();
+		let mut formatted_writer = format.xml_writer(counter);
+		let result = self
+			.root
+			.to_svg_through_writer(&self.context, &mut formatted_writer)
+			.and_then(|()| Ok(format.finish(&mut formatted_writer)?));
+
+		limits.check_written_output(n_bytes.get(), result)?;
+		self.context.check_target()
+	}
+
+	pub fn to_svg_string(&'a self) -> ClgnDecodingResult<String> {
+		let mut writer = XmlWriter::new(Vec::new());
+		self.to_svg_through_writer(&mut writer)?;
+		String::from_utf8(writer.into_inner()).map_err(|e| e.utf8_error().into())
+	}
+}
+
+/// A writer that counts the bytes written through it
+pub(crate) struct ByteCounter<W> {
+	inner: W,
+	n_bytes: Rc<Cell<usize>>,
+	/// If set, a write that would take the count past this fails, without writing
+	/// anything, although it's still counted
+	max_bytes: Option<usize>,
+}
+
+impl<W> ByteCounter<W> {
+	pub(crate) fn new(inner: W) -> Self {
+		Self {
+			inner,
+			n_bytes: Rc::new(Cell::new(0)),
+			max_bytes: None,
+		}
+	}
+
+	/// A counter that fails writes that would take the count past `max_bytes`, if given,
+	/// e.g., so as not to keep writing an SVG that a strict size limit will reject
+	pub(crate) fn with_max_bytes(self, max_bytes: Option<usize>) -> Self {
+		Self { max_bytes, ..self }
+	}
+
+	/// The number of bytes written so far, which stays up to date as more are written
+	pub(crate) fn n_bytes(&self) -> Rc<Cell<usize>> {
+		Rc::clone(&self.n_bytes)
+	}
+}
+
+impl<W: std::io::Write> std::io::Write for ByteCounter<W> {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		if let Some(max_bytes) = self.max_bytes {
+			let n_bytes = self.n_bytes.get() + buf.len();
+			if n_bytes > max_bytes {
+				self.n_bytes.set(n_bytes);
+				return Err(std::io::Error::new(
+					std::io::ErrorKind::Other,
+					format!("more than {} bytes written", max_bytes),
+				));
+			}
+		}
+
+		let n = self.inner.write(buf)?;
+		self.n_bytes.set(self.n_bytes.get() + n);
+		Ok(n)
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::to_svg::size_limits::SizeLimits;
+
+	#[test]
+	fn non_finite_attributes() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			r#"{
+				"attrs": { "viewBox": "0 0 100 100" },
+				"children": [{ "tag": "rect", "attrs": { "width": "1e400%w", "height": 10 } }]
+			}"#,
+		)
+		.unwrap();
+
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+		match fibroblast.to_svg_string() {
+			Err(ClgnDecodingError::NonFiniteAttribute { tag, attr, value }) => {
+				assert_eq!(tag, "rect");
+				assert_eq!(attr, "width");
+				assert_eq!(value, "inf");
+			}
+			result => panic!("expected a NonFiniteAttribute error, got {:?}", result),
+		}
+	}
+
+	#[test]
+	fn nesting_limit() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		let nested = skeleton_dir.path().join("nested");
+		std::fs::create_dir(&nested).unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			r#"{ "children": [{ "tag": "g", "children": [{ "clgn_path": "nested" }] }] }"#,
+		)
+		.unwrap();
+		std::fs::write(
+			nested.join("collagen.json"),
+			r#"{ "children": [{ "tag": "rect" }] }"#,
+		)
+		.unwrap();
+
+		// The g is at depth 1, the container at depth 2, and the rect at depth 3
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+		fibroblast.context.set_max_nesting_depth(3);
+		fibroblast.to_svg_string().unwrap();
+		fibroblast.context.set_max_nesting_depth(2);
+		assert!(matches!(
+			fibroblast.to_svg_string(),
+			Err(ClgnDecodingError::Nesting { .. })
+		));
+
+		// A skeleton that contains itself would otherwise recurse forever
+		std::fs::write(
+			nested.join("collagen.json"),
+			r#"{ "children": [{ "tag": "rect" }, { "clgn_path": "." }] }"#,
+		)
+		.unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+		assert!(matches!(
+			fibroblast.to_svg_string(),
+			Err(ClgnDecodingError::Nesting { .. })
+		));
+		assert!(matches!(
+			fibroblast.snapshot(),
+			Err(ClgnDecodingError::Nesting { .. })
+		));
+	}
+
+	#[test]
+	fn size_limits() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			r#"{ "children": [{ "tag": "path", "attrs": { "d": "M 0 0 L 100 100 L 0 100 Z" } }] }"#,
+		)
+		.unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+		let defaults = SizeLimits {
+			strict: true,
+			..SizeLimits::default()
+		};
+
+		fibroblast.context.set_size_limits(defaults);
+		let svg = fibroblast.to_svg_string().unwrap();
+
+		for limits in [
+			SizeLimits {
+				max_attr_bytes: 10,
+				..defaults
+			},
+			SizeLimits {
+				max_output_bytes: svg.len() - 1,
+				..defaults
+			},
+		] {
+			fibroblast.context.set_size_limits(limits);
+			assert!(matches!(
+				fibroblast.to_svg_string(),
+				Err(ClgnDecodingError::SizeLimit { .. })
+			));
+			assert!(matches!(
+				fibroblast.to_svg_through_writer(&mut XmlWriter::new(Vec::new())),
+				Err(ClgnDecodingError::SizeLimit { .. })
+			));
+		}
+
+		// Writing stops as soon as the output limit is crossed
+		fibroblast.context.set_size_limits(SizeLimits {
+			max_output_bytes: 10,
+			..defaults
+		});
+		let mut writer = XmlWriter::new(Vec::new());
+		assert!(matches!(
+			fibroblast.to_svg_through_writer(&mut writer),
+			Err(ClgnDecodingError::SizeLimit { .. })
+		));
+		assert!(writer.into_inner().len() <= 10);
+	}
+	#[test]
+	fn namespaces() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		let write_skeleton = |json: &str| {
+			std::fs::write(skeleton_dir.path().join("collagen.json"), json).unwrap();
+			Fibroblast::from_dir(skeleton_dir.path()).unwrap()
+		};
+
+		let fibroblast = write_skeleton(
+			r#"{
+				"children": [
+					{ "tag": "g", "attrs": { "xmlns:a": "urn:a" }, "children": [{ "tag": "a:x" }] },
+					{ "tag": "a:x" }
+				]
+			}"#,
+		);
+		match fibroblast.to_svg_string() {
+			Err(ClgnDecodingError::UndeclaredNamespace { name, prefix }) => {
+				assert_eq!(name, "a:x");
+				assert_eq!(prefix, "a");
+			}
+			result => panic!("expected an UndeclaredNamespace error, got {:?}", result),
+		}
+
+		let fibroblast = write_skeleton(
+			r#"{
+				"namespaces": { "a": "urn:a" },
+				"children": [{ "image_path": "missing.png", "attrs": { "a:y": 1 } }]
+			}"#,
+		);
+		fibroblast.context.set_placeholder_missing_assets(true);
+		fibroblast.context.set_xlink_href(true);
+		let svg = fibroblast.to_svg_string().unwrap();
+		assert!(svg.contains(r#"xmlns:a="urn:a" xmlns:xlink="http://www.w3.org/1999/xlink""#));
+		assert!(svg.contains(r#"<image a:y="1" href="data:image/svg+xml;base64,"#));
+		assert!(svg.contains(r#"xlink:href="data:image/svg+xml;base64,"#));
+
+		assert!(serde_json::from_str::<crate::fibroblast::tags::RootTag>(
+			r#"{ "namespaces": { "xmlfoo": "urn:a" } }"#
+		)
+		.is_err());
 	}
 }
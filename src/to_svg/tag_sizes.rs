@@ -0,0 +1,192 @@
+//! Attributing the size of the output to the tags in the skeleton, for `clgn stats
+//! --by-tag`. When an SVG is too large, this shows which tags (usually ones embedding
+//! images or fonts) are responsible.
+//!
+//! Sizes are measured by counting the bytes that pass through the writer while each tag
+//! is being written, so they're exact. Tags are identified by JSON pointers into the
+//! skeleton, just as in [`Fibroblast::variables_at`].
+
+use super::svg_writable::{ByteCounter, ClgnDecodingResult, SvgWritableTag};
+use crate::fibroblast::Fibroblast;
+use quick_xml::Writer as XmlWriter;
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// The number of bytes of output that a tag is responsible for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagSize {
+	/// A JSON pointer to the tag, e.g., `/children/0`; the root's is `""`
+	pub pointer: String,
+	pub tag_name: String,
+	/// The size of the tag, including its descendants
+	pub total_bytes: usize,
+	/// The size of the tag, excluding its descendants. This includes the tag's share of
+	/// embedded assets, such as the base64-encoded image of an `<image>`.
+	pub own_bytes: usize,
+}
+
+/// The JSON pointer to the child at `index` of the tag at `parent_pointer`, or to the
+/// root if `index` is `None`
+pub(crate) fn child_pointer(parent_pointer: Option<&str>, index: Option<usize>) -> String {
+	match (index, parent_pointer) {
+		(Some(index), Some(parent_pointer)) => format!("{}/children/{}", parent_pointer, index),
+		(Some(index), None) => format!("/children/{}", index),
+		(None, _) => String::new(),
+	}
+}
+
+/// A tag that's being written
+#[derive(Debug, Clone)]
+struct OpenTag {
+	/// The index into `TagSizeRecorder::sizes` of this tag's `TagSize`
+	slot: usize,
+	/// The byte count when this tag started to be written
+	start: usize,
+	/// The total size of this tag's children written so far
+	children_bytes: usize,
+}
+
+/// Records the sizes of tags as they're written
+#[derive(Debug, Clone)]
+pub(crate) struct TagSizeRecorder {
+	/// The number of bytes written so far, shared with the [`ByteCounter`] being written
+	/// to
+	n_bytes: Rc<Cell<usize>>,
+	open_tags: Vec<OpenTag>,
+	sizes: Vec<TagSize>,
+}
+
+impl TagSizeRecorder {
+	fn new(n_bytes: Rc<Cell<usize>>) -> Self {
+		Self {
+			n_bytes,
+			open_tags: Vec::new(),
+			sizes: Vec::new(),
+		}
+	}
+
+	/// Note that the tag named `tag_name` is about to be written. `index` is its index
+	/// among its parent's children, or `None` if it's the root.
+	pub(crate) fn open(&mut self, index: Option<usize>, tag_name: &str) {
+		let parent_pointer = self
+			.open_tags
+			.last()
+			.map(|parent| self.sizes[parent.slot].pointer.as_str());
+		let pointer = child_pointer(parent_pointer, index);
+
+		self.open_tags.push(OpenTag {
+			slot: self.sizes.len(),
+			start: self.n_bytes.get(),
+			children_bytes: 0,
+		});
+		self.sizes.push(TagSize {
+			pointer,
+			tag_name: tag_name.to_owned(),
+			total_bytes: 0,
+			own_bytes: 0,
+		});
+	}
+
+	/// Note that the most recently opened tag has been written
+	pub(crate) fn close(&mut self) {
+		let tag = match self.open_tags.pop() {
+			Some(tag) => tag,
+			None => return,
+		};
+
+		let total_bytes = self.n_bytes.get() - tag.start;
+		let size = &mut self.sizes[tag.slot];
+		size.total_bytes = total_bytes;
+		size.own_bytes = total_bytes - tag.children_bytes;
+
+		if let Some(parent) = self.open_tags.last_mut() {
+			parent.children_bytes += total_bytes;
+		}
+	}
+}
+
+impl<'a> Fibroblast<'a> {
+	/// The size of the SVG that each tag in this skeleton is responsible for, in the
+	/// order the tags are written. The SVG itself is discarded.
+	pub fn tag_sizes(&'a self) -> ClgnDecodingResult<Vec<TagSize>> {
+		let counter = ByteCounter::new(std::io::sink());
+		self.context
+			.replace_tag_size_recorder(Some(TagSizeRecorder::new(counter.n_bytes())));
+
+		let mut writer = XmlWriter::new(counter);
+		let result = self.root.to_svg_through_writer(&self.context, &mut writer);
+
+		// Always remove the recorder so that later renders aren't recorded
+		let recorder = self.context.replace_tag_size_recorder(None);
+		result?;
+
+		Ok(recorder.map_or_else(Vec::new, |recorder| recorder.sizes))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tag_sizes() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			r#"{
+				"children": [
+					{ "tag": "g", "z": 1, "children": [{ "tag": "rect" }] },
+					{ "tag": "circle", "attrs": { "r": 5 } }
+				]
+			}"#,
+		)
+		.unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+
+		let svg = fibroblast.to_svg_string().unwrap();
+		let sizes = fibroblast
+			.tag_sizes()
+			.unwrap()
+			.into_iter()
+			.map(|size| {
+				(
+					size.pointer,
+					size.tag_name,
+					size.total_bytes,
+					size.own_bytes,
+				)
+			})
+			.collect::<Vec<_>>();
+
+		let circle = r#"<circle r="5"></circle>"#.len();
+		let rect = "<rect></rect>".len();
+		let g = "<g></g>".len() + rect;
+		assert_eq!(
+			sizes,
+			vec![
+				(
+					"".to_owned(),
+					"svg".to_owned(),
+					svg.len(),
+					svg.len() - circle - g
+				),
+				(
+					"/children/1".to_owned(),
+					"circle".to_owned(),
+					circle,
+					circle
+				),
+				("/children/0".to_owned(), "g".to_owned(), g, g - rect),
+				(
+					"/children/0/children/0".to_owned(),
+					"rect".to_owned(),
+					rect,
+					rect
+				),
+			]
+		);
+
+		// Recording sizes doesn't affect later renders
+		assert_eq!(fibroblast.to_svg_string().unwrap(), svg);
+	}
+}
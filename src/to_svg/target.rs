@@ -0,0 +1,285 @@
+//! Targets, the viewers that an SVG is meant for, for `clgn --target <target>`. Viewers
+//! differ in which SVG features they support, and an SVG that looks right in a browser
+//! can be missing its fonts or gradients in, say, Inkscape. Given a target, Collagen
+//! warns (or, with `--strict`, fails) when the SVG uses a feature that the target
+//! doesn't support.
+//!
+//! The features checked, and the targets that support them, are:
+//!
+//! | Feature                                | `svg1.1` | `svg2` | `browser` | `inkscape` |
+//! | -------------------------------------- | -------- | ------ | --------- | ---------- |
+//! | Embedded WOFF2 or WOFF fonts           | No       | Yes    | Yes       | No         |
+//! | Embedded TrueType or OpenType fonts    | Yes      | Yes    | Yes       | No         |
+//! | `href` without `xlink:href`            | No       | Yes    | Yes       | Yes        |
+//! | Mesh gradients (`<meshgradient>`)      | No       | No     | No        | Yes        |
+//!
+//! The `browser` target also adds some attributes for SVGs that are served as part of a
+//! web page. Each `<image>` gets `loading="lazy"` and `decoding="async"`, so that large
+//! images don't hold up the rest of the page, and each `<g>` that's a child of the root
+//! is treated as a layer and given an `id` (such as `layer-0`) by which the page can
+//! find it and toggle it. Attributes that a tag sets itself are left alone.
+
+use crate::fibroblast::data_types::{AttrKVValueVec, DecodingContext, SimpleValue};
+use crate::to_svg::svg_writable::{ClgnDecodingError, ClgnDecodingResult};
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+/// The names of the targets, as passed on the command line
+pub(crate) const TARGET_NAMES: [&str; 4] = ["svg1.1", "svg2", "browser", "inkscape"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Target {
+	Svg11,
+	Svg2,
+	Browser,
+	Inkscape,
+}
+
+impl FromStr for Target {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"svg1.1" => Ok(Target::Svg11),
+			"svg2" => Ok(Target::Svg2),
+			"browser" => Ok(Target::Browser),
+			"inkscape" => Ok(Target::Inkscape),
+			_ => Err(format!(
+				"unknown target {:?}; expected one of {}",
+				s,
+				TARGET_NAMES.join(", ")
+			)),
+		}
+	}
+}
+
+/// A feature of SVG that not every target supports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Feature {
+	/// A WOFF2 or WOFF font embedded with `@font-face`
+	WebFont,
+	/// A TrueType or OpenType font embedded with `@font-face`
+	SfntFont,
+	/// An `href` attribute without a matching `xlink:href`
+	PlainHref,
+	/// A `<meshgradient>`
+	MeshGradient,
+}
+
+impl Feature {
+	fn description(self) -> &'static str {
+		match self {
+			Feature::WebFont => "embeds WOFF2 or WOFF fonts",
+			Feature::SfntFont => "embeds TrueType or OpenType fonts",
+			Feature::PlainHref => "has `href` attributes without a matching `xlink:href`",
+			Feature::MeshGradient => "has mesh gradients",
+		}
+	}
+
+	/// What to do about `target`'s lack of support for this feature
+	fn suggestion(self, target: Target) -> &'static str {
+		match self {
+			Feature::WebFont if target.supports(Feature::SfntFont) => {
+				"Convert the fonts to TrueType or OpenType, or convert the text to paths."
+			}
+			Feature::WebFont | Feature::SfntFont => "Convert the text to paths.",
+			Feature::PlainHref => "Pass --xlink-href to give each image an `xlink:href`.",
+			Feature::MeshGradient => "Use a linear or radial gradient instead.",
+		}
+	}
+}
+
+/// The features used by a tag named `tag_name` whose attributes are named `attr_names`
+pub(crate) fn tag_features<'b>(
+	tag_name: &str,
+	attr_names: impl Iterator<Item = &'b str> + Clone,
+) -> Vec<Feature> {
+	let mut features = Vec::new();
+	let has_attr = |name| attr_names.clone().any(|attr| attr == name);
+	if has_attr("href") && !has_attr("xlink:href") {
+		features.push(Feature::PlainHref);
+	}
+	if tag_name == "meshgradient" {
+		features.push(Feature::MeshGradient);
+	}
+
+	features
+}
+
+impl Target {
+	fn name(self) -> &'static str {
+		match self {
+			Target::Svg11 => "svg1.1",
+			Target::Svg2 => "svg2",
+			Target::Browser => "browser",
+			Target::Inkscape => "inkscape",
+		}
+	}
+
+	/// Whether this target supports `feature`
+	pub(crate) fn supports(self, feature: Feature) -> bool {
+		use Target::*;
+		match feature {
+			Feature::WebFont => matches!(self, Svg2 | Browser),
+			Feature::SfntFont => !matches!(self, Inkscape),
+			Feature::PlainHref => !matches!(self, Svg11),
+			Feature::MeshGradient => matches!(self, Inkscape),
+		}
+	}
+
+	/// The attributes that this target adds to a tag named `tag_name` whose attributes
+	/// are `attrs`
+	pub(crate) fn generated_attrs(
+		self,
+		context: &DecodingContext,
+		tag_name: &str,
+		attrs: &AttrKVValueVec,
+	) -> Vec<(&'static str, SimpleValue)> {
+		if self != Target::Browser {
+			return Vec::new();
+		}
+
+		let mut generated = Vec::new();
+		let mut add = |k: &'static str, v: String| {
+			if !attrs.iter().any(|(attr, _)| *attr == k) {
+				generated.push((k, SimpleValue::Text(v)));
+			}
+		};
+		match tag_name {
+			"image" => {
+				add("loading", "lazy".to_owned());
+				add("decoding", "async".to_owned());
+			}
+			"g" if context.is_top_level() && !attrs.iter().any(|(k, _)| *k == "id") => {
+				add("id", context.generate_id("layer"));
+			}
+			_ => {}
+		}
+
+		generated
+	}
+
+	/// Check that this target supports each of `features`, which the SVG uses. If it
+	/// doesn't, it's an error if `strict`, and a warning otherwise.
+	pub(crate) fn check_features(
+		self,
+		features: &BTreeSet<Feature>,
+		strict: bool,
+	) -> ClgnDecodingResult<()> {
+		for &feature in features {
+			if self.supports(feature) {
+				continue;
+			}
+
+			let msg = format!(
+				"The SVG {}, which the target {} does not support. {}",
+				feature.description(),
+				self.name(),
+				feature.suggestion(self)
+			);
+			if strict {
+				return Err(ClgnDecodingError::Compatibility { msg });
+			}
+			eprintln!("Warning: {}", msg);
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fibroblast::Fibroblast;
+
+	#[test]
+	fn targets() {
+		for name in TARGET_NAMES {
+			assert_eq!(name.parse::<Target>().unwrap().name(), name);
+		}
+
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			r#"{
+				"children": [
+					{ "image_path": "sprites.png" },
+					{ "tag": "meshgradient" }
+				]
+			}"#,
+		)
+		.unwrap();
+		std::fs::copy(
+			"tests/examples/sprite-sheet/skeleton/sprites.png",
+			skeleton_dir.path().join("sprites.png"),
+		)
+		.unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+		fibroblast.to_svg_string().unwrap();
+		assert_eq!(
+			fibroblast.context.used_features(),
+			vec![Feature::PlainHref, Feature::MeshGradient]
+				.into_iter()
+				.collect()
+		);
+
+		let is_compatible = |target: Target| {
+			let result = target.check_features(&fibroblast.context.used_features(), true);
+			match result {
+				Ok(()) => true,
+				Err(ClgnDecodingError::Compatibility { .. }) => false,
+				Err(e) => panic!("{:?}", e),
+			}
+		};
+		assert!(is_compatible(Target::Inkscape));
+		assert!(!is_compatible(Target::Svg11));
+		assert!(!is_compatible(Target::Browser));
+
+		// With `xlink:href`s, only the mesh gradient is a problem
+		fibroblast.context.set_xlink_href(true);
+		fibroblast.to_svg_string().unwrap();
+		assert_eq!(
+			fibroblast.context.used_features(),
+			vec![Feature::MeshGradient].into_iter().collect()
+		);
+	}
+
+	#[test]
+	fn browser_attrs() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			r#"{
+				"children": [
+					{ "image_path": "sprites.png" },
+					{ "image_path": "sprites.png", "attrs": { "loading": "eager" } },
+					{ "tag": "g", "children": [{ "tag": "g" }] },
+					{ "tag": "g", "attrs": { "id": "background" } },
+					{ "tag": "g" }
+				]
+			}"#,
+		)
+		.unwrap();
+		std::fs::copy(
+			"tests/examples/sprite-sheet/skeleton/sprites.png",
+			skeleton_dir.path().join("sprites.png"),
+		)
+		.unwrap();
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+
+		let svg = fibroblast.to_svg_string().unwrap();
+		assert!(!svg.contains("lazy") && !svg.contains("layer-"));
+
+		fibroblast.context.set_target(Some(Target::Browser));
+		let svg = fibroblast.to_svg_string().unwrap();
+		assert_eq!(svg.matches(r#"loading="lazy""#).count(), 1);
+		assert_eq!(svg.matches(r#"loading="eager""#).count(), 1);
+		assert_eq!(svg.matches(r#"decoding="async""#).count(), 2);
+		assert!(svg
+			.contains(r#"<g id="layer-0"><g></g></g><g id="background"></g><g id="layer-1"></g>"#));
+
+		// Other targets don't add anything
+		fibroblast.context.set_target(Some(Target::Svg2));
+		assert!(!fibroblast.to_svg_string().unwrap().contains("layer-"));
+	}
+}
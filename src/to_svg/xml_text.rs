@@ -0,0 +1,255 @@
+//! The text content of tags, and the one place where it's escaped. Text must go through
+//! exactly one round of escaping on its way into the SVG: none, and a `<` or `&` in it
+//! breaks the document; two, and `&` comes out as `&amp;amp;`. So rather than passing
+//! around a string and, separately, whether it's been escaped, each tag produces an
+//! [`XmlText`], which says which kind of text it is, and which is written (or turned
+//! into markup to be spliced into other markup) by the methods here.
+//!
+//! (This is unrelated to the `raw` of [`RawText`](crate::fibroblast::data_types::RawText),
+//! which is text that variables aren't substituted into.)
+
+use super::raw_xml::check_unescaped_text;
+use super::svg_writable::ClgnDecodingResult;
+use quick_xml::events::{BytesText, Event as XmlEvent};
+use quick_xml::Writer as XmlWriter;
+use std::borrow::Cow;
+
+/// Escape `text`, which is plain text, for inclusion in XML as character data or as an
+/// attribute value
+pub(crate) fn escape(text: &str) -> Cow<'_, str> {
+	match quick_xml::escape::escape(text.as_bytes()) {
+		Cow::Borrowed(_) => Cow::Borrowed(text),
+		// Escaping only ever replaces ASCII with ASCII, so this can't fail
+		Cow::Owned(escaped) => Cow::Owned(String::from_utf8(escaped).unwrap()),
+	}
+}
+
+/// Escape `text`, which is plain text, for inclusion in XML as character data. Unlike
+/// [`escape`], this leaves quotes alone, which only need escaping in attribute values.
+pub(crate) fn escape_char_data(text: &str) -> Cow<'_, str> {
+	if !text.contains(|c| matches!(c, '&' | '<' | '>')) {
+		return Cow::Borrowed(text);
+	}
+
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			c => escaped.push(c),
+		}
+	}
+
+	Cow::Owned(escaped)
+}
+
+/// The text content of a tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum XmlText<'a> {
+	/// Plain text, which is escaped when it's written
+	Plain(Cow<'a, str>),
+	/// Markup (which is to say, already-escaped text), which is written as is
+	Markup(Cow<'a, str>),
+}
+
+impl<'a> XmlText<'a> {
+	/// `text`, which is plain text if `should_escape`, and markup otherwise
+	pub(crate) fn new(text: impl Into<Cow<'a, str>>, should_escape: bool) -> Self {
+		if should_escape {
+			XmlText::Plain(text.into())
+		} else {
+			XmlText::Markup(text.into())
+		}
+	}
+
+	/// The text, as given: unescaped if plain, and markup otherwise
+	pub(crate) fn as_str(&self) -> &str {
+		match self {
+			XmlText::Plain(text) | XmlText::Markup(text) => text,
+		}
+	}
+
+	pub(crate) fn is_markup(&self) -> bool {
+		matches!(self, XmlText::Markup(_))
+	}
+
+	/// Text of the same kind as this, plain or markup, consisting of `text`, e.g., a
+	/// piece of this text
+	pub(crate) fn like<'b>(&self, text: impl Into<Cow<'b, str>>) -> XmlText<'b> {
+		XmlText::new(text, !self.is_markup())
+	}
+
+	/// The text as it appears in XML
+	pub(crate) fn to_markup(&self) -> Cow<'_, str> {
+		match self {
+			XmlText::Plain(text) => escape(text),
+			XmlText::Markup(text) => Cow::Borrowed(text),
+		}
+	}
+
+	/// Write the text as the content of a tag named `tag_name`. Markup is checked to be
+	/// well-formed first; if it isn't, it's an error if `strict`, and a warning otherwise.
+	pub(crate) fn write(
+		&self,
+		tag_name: &str,
+		strict: bool,
+		writer: &mut XmlWriter<impl std::io::Write>,
+	) -> ClgnDecodingResult<()> {
+		let event = match self {
+			XmlText::Plain(text) => BytesText::from_plain_str(text),
+			XmlText::Markup(text) => {
+				check_unescaped_text(tag_name, text, strict)?;
+				BytesText::from_escaped(text.as_bytes())
+			}
+		};
+		writer.write_event(XmlEvent::Text(event))?;
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fibroblast::Fibroblast;
+	use quick_xml::Reader as XmlReader;
+
+	#[test]
+	fn escaping() {
+		assert!(matches!(escape("plain ünïcödé"), Cow::Borrowed(_)));
+		assert_eq!(
+			escape(r#"a & b < c > d "e" 'f' ü"#),
+			"a &amp; b &lt; c &gt; d &quot;e&quot; &apos;f&apos; ü"
+		);
+		assert!(matches!(escape_char_data("'plain'"), Cow::Borrowed(_)));
+		assert_eq!(
+			escape_char_data(r#"a & b < c > d "e" 'f' ü"#),
+			r#"a &amp; b &lt; c &gt; d "e" 'f' ü"#
+		);
+
+		let plain = XmlText::new("a & <b>", true);
+		let markup = XmlText::new("a &amp; <b/>", false);
+		assert_eq!(plain.to_markup(), "a &amp; &lt;b&gt;");
+		assert_eq!(markup.to_markup(), "a &amp; <b/>");
+		assert_eq!(plain.like("<").to_markup(), "&lt;");
+		assert_eq!(markup.like("<b/>").to_markup(), "<b/>");
+
+		let written = |text: &XmlText| {
+			let mut writer = XmlWriter::new(Vec::new());
+			text.write("text", true, &mut writer).unwrap();
+			String::from_utf8(writer.into_inner()).unwrap()
+		};
+		assert_eq!(written(&plain), plain.to_markup());
+		assert_eq!(written(&markup), markup.to_markup());
+	}
+
+	/// Text that has to be escaped, and that would be mangled by escaping it twice
+	const TRICKY: &str = r#"Tom & Jerry <3 "quotes" 'apostrophes' ünïcödé &amp;"#;
+
+	/// The tag name and (unescaped) text of each element in `svg` that has text, in
+	/// document order
+	fn element_texts(svg: &str) -> Vec<(String, String)> {
+		let mut reader = XmlReader::from_str(svg);
+		let mut elements = Vec::<(String, String)>::new();
+		let mut open_elements = Vec::new();
+		let mut buf = Vec::new();
+		loop {
+			match reader.read_event(&mut buf).unwrap() {
+				XmlEvent::Start(elem) => {
+					open_elements.push(elements.len());
+					let name = String::from_utf8(elem.name().to_vec()).unwrap();
+					elements.push((name, String::new()));
+				}
+				XmlEvent::End(_) => {
+					open_elements.pop();
+				}
+				XmlEvent::Text(text) => {
+					let text = String::from_utf8(text.unescaped().unwrap().into_owned()).unwrap();
+					if let Some(&i) = open_elements.last() {
+						elements[i].1.push_str(&text);
+					}
+				}
+				XmlEvent::Eof => break,
+				_ => {}
+			}
+			buf.clear();
+		}
+
+		elements.retain(|(_, text)| !text.is_empty());
+		elements
+	}
+
+	#[test]
+	fn every_tag_escapes_once() {
+		let skeleton_dir = tempfile::tempdir().unwrap();
+		let tricky = serde_json::to_string(TRICKY).unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("collagen.json"),
+			format!(
+				r#"{{
+					"text": {tricky},
+					"children": [
+						{{ "tag": "text", "text": {tricky} }},
+						{{ "tag": "text", "text": {tricky}, "wrap": {{ "width": 50 }} }},
+						{{ "tag": "text", "text": {{ "raw": {tricky} }} }},
+						{{ "image_path": "sprites.png", "text": {tricky} }},
+						{{ "shape": "star", "cx": 0, "cy": 0, "r": 5, "points": 5, "text": {tricky} }},
+						{{ "tag": "rect", "attrs": {{ "id": "a", "x": 0, "y": 0, "width": 1, "height": 1 }} }},
+						{{ "connect": {{ "from": "a", "to": "a" }}, "text": {tricky} }},
+						{{
+							"repeat": {{ "count": 1 }},
+							"text": {tricky},
+							"template": {{ "tag": "circle" }}
+						}},
+						{{ "clgn_path": "nested" }},
+						{{ "fonts": [{{ "name": {tricky}, "path": "font.woff2" }}] }}
+					]
+				}}"#,
+				tricky = tricky
+			),
+		)
+		.unwrap();
+		std::fs::create_dir(skeleton_dir.path().join("nested")).unwrap();
+		std::fs::write(
+			skeleton_dir.path().join("nested/collagen.json"),
+			format!(r#"{{ "text": {} }}"#, tricky),
+		)
+		.unwrap();
+		std::fs::copy(
+			"tests/examples/sprite-sheet/skeleton/sprites.png",
+			skeleton_dir.path().join("sprites.png"),
+		)
+		.unwrap();
+		std::fs::copy(
+			"tests/examples/drake-user-specified-font/skeleton/impact.woff2",
+			skeleton_dir.path().join("font.woff2"),
+		)
+		.unwrap();
+
+		let fibroblast = Fibroblast::from_dir(skeleton_dir.path()).unwrap();
+		let texts = element_texts(&fibroblast.to_svg_string().unwrap());
+		let tags = texts
+			.iter()
+			.map(|(tag, _)| tag.as_str())
+			.collect::<Vec<_>>();
+		assert_eq!(
+			tags,
+			[
+				"svg", "text", "tspan", "tspan", "tspan", "tspan", "tspan", "tspan", "tspan",
+				"text", "image", "polygon", "path", "g", "g", "style"
+			]
+		);
+
+		let wrapped = texts[2..9]
+			.iter()
+			.map(|(_, text)| text.as_str())
+			.collect::<Vec<_>>()
+			.join(" ");
+		assert_eq!(wrapped, TRICKY);
+		for (tag, text) in texts[..2].iter().chain(&texts[9..15]) {
+			assert_eq!(text, TRICKY, "<{}>", tag);
+		}
+		assert!(texts[15].1.contains(&format!("font-family:{};", TRICKY)));
+	}
+}
@@ -0,0 +1,175 @@
+//! An on-disk cache of expensive derived artifacts, such as cropped images, shared by
+//! all skeletons and all invocations of `clgn`. Repeated builds of related skeletons
+//! would otherwise redo all of their asset processing.
+//!
+//! An artifact is stored in a file named after a hash of everything it was derived from
+//! (e.g., the contents of the original image and the region it was cropped to), in a
+//! folder named after the kind of artifact. So an artifact is reused whenever its inputs
+//! are the same, regardless of which skeleton they came from or where they were on disk,
+//! and a changed input simply results in a new entry.
+//!
+//! The cache is only ever an optimization: if it can't be read from or written to, the
+//! artifact is derived from scratch as though it weren't there.
+
+use super::hash::ContentHasher;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The folder, relative to the user's cache folder, in which the cache lives
+const CACHE_DIR_NAME: &str = "clgn";
+
+/// Bumped whenever the way artifacts are derived changes, so that entries written by
+/// older versions aren't reused
+const CACHE_VERSION: &[u8] = b"1";
+
+/// The content hash of `inputs`
+fn content_hash(inputs: &[&[u8]]) -> u128 {
+	let mut hasher = ContentHasher::new();
+	hasher.write_raw(CACHE_VERSION);
+	for input in inputs {
+		hasher.write_input(input);
+	}
+
+	hasher.finish()
+}
+
+/// The on-disk cache. See the module docs for more info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AssetCache {
+	dir: PathBuf,
+}
+
+impl AssetCache {
+	pub(crate) fn new(dir: impl Into<PathBuf>) -> Self {
+		Self { dir: dir.into() }
+	}
+
+	/// The cache in the user's cache folder: `$XDG_CACHE_HOME/clgn` if
+	/// `$XDG_CACHE_HOME` is set, and `~/.cache/clgn` otherwise. `None` if neither
+	/// `$XDG_CACHE_HOME` nor `$HOME` is set.
+	pub(crate) fn in_user_cache_dir() -> Option<Self> {
+		let non_empty_var = |name| std::env::var_os(name).filter(|v| !v.is_empty());
+
+		let user_cache_dir = match non_empty_var("XDG_CACHE_HOME") {
+			Some(dir) => PathBuf::from(dir),
+			None => PathBuf::from(non_empty_var("HOME")?).join(".cache"),
+		};
+
+		Some(Self::new(user_cache_dir.join(CACHE_DIR_NAME)))
+	}
+
+	pub(crate) fn dir(&self) -> &Path {
+		&self.dir
+	}
+
+	fn entry_path(&self, kind: &str, inputs: &[&[u8]]) -> PathBuf {
+		self.dir
+			.join(kind)
+			.join(format!("{:032x}", content_hash(inputs)))
+	}
+
+	/// The artifact of kind `kind` derived from `inputs`. If it's in the cache, it's
+	/// read from there; otherwise it's derived by calling `derive`, and then stored in
+	/// the cache for next time.
+	pub(crate) fn get_or_insert_with<E>(
+		&self,
+		kind: &str,
+		inputs: &[&[u8]],
+		derive: impl FnOnce() -> Result<Vec<u8>, E>,
+	) -> Result<Vec<u8>, E> {
+		let path = self.entry_path(kind, inputs);
+		if let Ok(bytes) = std::fs::read(&path) {
+			return Ok(bytes);
+		}
+
+		let bytes = derive()?;
+		// Failing to store the artifact only means it'll have to be derived again
+		let _ = Self::store(&path, &bytes);
+
+		Ok(bytes)
+	}
+
+	/// Write `bytes` to `path` atomically, so that a concurrent reader never sees a
+	/// partially written entry
+	fn store(path: &Path, bytes: &[u8]) -> io::Result<()> {
+		let parent = path.parent().unwrap_or_else(|| Path::new("."));
+		std::fs::create_dir_all(parent)?;
+
+		let mut file = tempfile::NamedTempFile::new_in(parent)?;
+		file.write_all(bytes)?;
+		file.persist(path).map_err(|e| e.error)?;
+
+		Ok(())
+	}
+
+	/// Remove everything in the cache, returning the number of bytes freed
+	pub(crate) fn clean(&self) -> io::Result<u64> {
+		fn size_of(path: &Path) -> io::Result<u64> {
+			let metadata = std::fs::symlink_metadata(path)?;
+			if !metadata.is_dir() {
+				return Ok(metadata.len());
+			}
+
+			let mut size = 0;
+			for entry in std::fs::read_dir(path)? {
+				size += size_of(&entry?.path())?;
+			}
+			Ok(size)
+		}
+
+		let size = match size_of(&self.dir) {
+			Ok(size) => size,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+			Err(e) => return Err(e),
+		};
+		std::fs::remove_dir_all(&self.dir)?;
+
+		Ok(size)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cell::Cell;
+
+	#[test]
+	fn cache() {
+		let cache_dir = tempfile::tempdir().unwrap();
+		let cache = AssetCache::new(cache_dir.path().join("clgn"));
+
+		let n_derivations = Cell::new(0);
+		let get = |kind, inputs: &[&[u8]]| {
+			cache
+				.get_or_insert_with(kind, inputs, || {
+					n_derivations.set(n_derivations.get() + 1);
+					Ok::<_, ()>(inputs.concat())
+				})
+				.unwrap()
+		};
+
+		assert_eq!(get("crop", &[b"ab", b"c"]), b"abc");
+		assert_eq!(get("crop", &[b"ab", b"c"]), b"abc");
+		assert_eq!(n_derivations.get(), 1);
+
+		// Different inputs, even if they have the same concatenation, and different
+		// kinds are different entries
+		assert_eq!(get("crop", &[b"a", b"bc"]), b"abc");
+		assert_eq!(get("font", &[b"ab", b"c"]), b"abc");
+		assert_eq!(n_derivations.get(), 3);
+
+		// Errors aren't cached
+		assert_eq!(
+			cache.get_or_insert_with("crop", &[b"error"], || Err("failed")),
+			Err("failed")
+		);
+		assert_eq!(get("crop", &[b"error"]), b"error");
+
+		assert_eq!(cache.clean().unwrap(), 3 * 3 + 5);
+		assert!(!cache.dir().exists());
+		assert_eq!(cache.clean().unwrap(), 0);
+
+		get("crop", &[b"ab", b"c"]);
+		assert_eq!(n_derivations.get(), 5);
+	}
+}
@@ -0,0 +1,619 @@
+//! Converting raster images with embedded ICC color profiles to sRGB, for `clgn
+//! --srgb`. Browsers honor an embedded profile (such as the Display P3 profile of
+//! photos taken on many phones), but many other programs that rasterize SVGs don't,
+//! and render such images with shifted colors. Converting the pixels to sRGB, the color
+//! space assumed when there's no profile, makes the image look the same everywhere.
+//!
+//! Only profiles of the "matrix/TRC" kind are supported: those that map RGB to CIE XYZ
+//! with a tone response curve (TRC) per channel followed by a 3x3 matrix. This includes
+//! virtually all RGB profiles embedded in photos (sRGB, Display P3, Adobe RGB, etc.).
+//! Profiles are read from PNG (`iCCP` chunk) and JPEG (`APP2` segments) images.
+
+use flate2::read::ZlibDecoder;
+use image::{DynamicImage, ImageOutputFormat};
+use std::io::Read;
+
+/// The matrix that maps CIE XYZ, relative to the D50 white point of the ICC profile
+/// connection space, to linear sRGB. (From Bruce Lindbloom's tables, using Bradford
+/// adaptation.)
+const XYZ_D50_TO_SRGB: Matrix = [
+	[3.133_856_1, -1.616_866_7, -0.490_614_6],
+	[-0.978_768_4, 1.916_141_5, 0.033_454_0],
+	[0.071_945_3, -0.228_991_4, 1.405_242_7],
+];
+
+/// How far an image's transform may be from the identity for the image to be
+/// considered sRGB already (and therefore left alone)
+const SRGB_TOLERANCE: f64 = 2e-3;
+
+/// The number of entries in the lookup table used to encode linear values as sRGB
+const ENCODING_LUT_SIZE: usize = 4096;
+
+/// The quality with which converted JPEGs are re-encoded
+const JPEG_QUALITY: u8 = 95;
+
+/// The largest ICC profile that's decompressed from a PNG. Real profiles are at most a
+/// few hundred KB, while a small `iCCP` chunk can decompress to gigabytes.
+const MAX_ICC_PROFILE_BYTES: usize = 4_000_000;
+
+type Matrix = [[f64; 3]; 3];
+
+fn mat_mul(a: &Matrix, b: &Matrix) -> Matrix {
+	let mut product = [[0.0; 3]; 3];
+	for (i, row) in product.iter_mut().enumerate() {
+		for (j, entry) in row.iter_mut().enumerate() {
+			*entry = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+		}
+	}
+	product
+}
+
+fn srgb_decode(v: f64) -> f64 {
+	if v <= 0.040_45 {
+		v / 12.92
+	} else {
+		((v + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+fn srgb_encode(v: f64) -> f64 {
+	if v <= 0.003_130_8 {
+		v * 12.92
+	} else {
+		1.055 * v.powf(1.0 / 2.4) - 0.055
+	}
+}
+
+/// A tone response curve, which maps an encoded channel value in `[0, 1]` to a linear
+/// one
+#[derive(Debug, Clone, PartialEq)]
+enum Curve {
+	Gamma(f64),
+	Table(Vec<f64>),
+	/// An ICC parametric curve, whose parameters are `[g, a, b, c, d, e, f]`
+	Parametric([f64; 7]),
+}
+
+impl Curve {
+	fn eval(&self, x: f64) -> f64 {
+		match self {
+			Curve::Gamma(g) => x.powf(*g),
+			Curve::Table(table) => {
+				let pos = x.clamp(0.0, 1.0) * (table.len() - 1) as f64;
+				let i = (pos.floor() as usize).min(table.len() - 2);
+				let frac = pos - i as f64;
+				table[i] * (1.0 - frac) + table[i + 1] * frac
+			}
+			Curve::Parametric([g, a, b, c, d, e, f]) => {
+				if x >= *d {
+					(a * x + b).max(0.0).powf(*g) + e
+				} else {
+					c * x + f
+				}
+			}
+		}
+	}
+}
+
+/// Reads big-endian numbers out of an ICC profile, failing (rather than panicking) on
+/// truncated data
+struct ProfileReader<'a> {
+	bytes: &'a [u8],
+}
+
+impl<'a> ProfileReader<'a> {
+	fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8], String> {
+		offset
+			.checked_add(len)
+			.and_then(|end| self.bytes.get(offset..end))
+			.ok_or_else(|| "the profile is truncated".to_owned())
+	}
+
+	fn u16(&self, offset: usize) -> Result<u16, String> {
+		let b = self.slice(offset, 2)?;
+		Ok(u16::from_be_bytes([b[0], b[1]]))
+	}
+
+	fn u32(&self, offset: usize) -> Result<u32, String> {
+		let b = self.slice(offset, 4)?;
+		Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+	}
+
+	/// An `s15Fixed16Number`
+	fn fixed(&self, offset: usize) -> Result<f64, String> {
+		Ok(f64::from(self.u32(offset)? as i32) / 65536.0)
+	}
+
+	/// The (offset, size) of the tag with signature `sig`
+	fn tag(&self, sig: &[u8; 4]) -> Result<(usize, usize), String> {
+		let n_tags = self.u32(128)? as usize;
+		for i in 0..n_tags {
+			let entry = 132 + 12 * i;
+			if self.slice(entry, 4)? == sig {
+				return Ok((self.u32(entry + 4)? as usize, self.u32(entry + 8)? as usize));
+			}
+		}
+		Err(format!(
+			"the profile has no {:?} tag, and only matrix/TRC profiles are supported",
+			String::from_utf8_lossy(sig)
+		))
+	}
+
+	fn xyz(&self, sig: &[u8; 4]) -> Result<[f64; 3], String> {
+		let (offset, _) = self.tag(sig)?;
+		Ok([
+			self.fixed(offset + 8)?,
+			self.fixed(offset + 12)?,
+			self.fixed(offset + 16)?,
+		])
+	}
+
+	fn curve(&self, sig: &[u8; 4]) -> Result<Curve, String> {
+		let (offset, _) = self.tag(sig)?;
+		match self.slice(offset, 4)? {
+			b"curv" => {
+				let n_entries = self.u32(offset + 8)? as usize;
+				match n_entries {
+					0 => Ok(Curve::Gamma(1.0)),
+					1 => Ok(Curve::Gamma(f64::from(self.u16(offset + 12)?) / 256.0)),
+					_ => (0..n_entries)
+						.map(|i| Ok(f64::from(self.u16(offset + 12 + 2 * i)?) / 65535.0))
+						.collect::<Result<_, String>>()
+						.map(Curve::Table),
+				}
+			}
+			b"para" => {
+				let n_params = match self.u16(offset + 8)? {
+					0 => 1,
+					1 => 3,
+					2 => 4,
+					3 => 5,
+					4 => 7,
+					function_type => {
+						return Err(format!(
+							"unknown parametric curve function type {}",
+							function_type
+						))
+					}
+				};
+				let mut given = [0.0; 7];
+				for (i, param) in given.iter_mut().take(n_params).enumerate() {
+					*param = self.fixed(offset + 12 + 4 * i)?;
+				}
+
+				// Express every function type as type 4: Y = (aX + b)^g + e if X >= d,
+				// else cX + f
+				let [g, a, b, c, d, e, f] = given;
+				Ok(Curve::Parametric(match n_params {
+					1 => [g, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+					3 => [g, a, b, 0.0, -b / a, 0.0, 0.0],
+					4 => [g, a, b, 0.0, -b / a, c, c],
+					5 => [g, a, b, c, d, 0.0, 0.0],
+					_ => [g, a, b, c, d, e, f],
+				}))
+			}
+			other => Err(format!(
+				"unknown curve type {:?}",
+				String::from_utf8_lossy(other)
+			)),
+		}
+	}
+}
+
+/// The conversion from an image's color space, as described by its profile, to sRGB
+#[derive(Debug, Clone, PartialEq)]
+struct Transform {
+	/// The curve of each of the red, green, and blue channels
+	curves: [Curve; 3],
+	/// Maps linear RGB in the image's color space to linear sRGB
+	matrix: Matrix,
+}
+
+impl Transform {
+	fn from_profile(profile: &[u8]) -> Result<Self, String> {
+		let reader = ProfileReader { bytes: profile };
+		let color_space = reader.slice(16, 4)?;
+		if color_space != b"RGB " {
+			return Err(format!(
+				"the profile's color space is {:?}, not RGB",
+				String::from_utf8_lossy(color_space).trim_end()
+			));
+		}
+		if reader.slice(20, 4)? != b"XYZ " {
+			return Err("only profiles whose connection space is XYZ are supported".to_owned());
+		}
+
+		let [r, g, b] = [
+			reader.xyz(b"rXYZ")?,
+			reader.xyz(b"gXYZ")?,
+			reader.xyz(b"bXYZ")?,
+		];
+		let to_xyz = [[r[0], g[0], b[0]], [r[1], g[1], b[1]], [r[2], g[2], b[2]]];
+
+		Ok(Self {
+			curves: [
+				reader.curve(b"rTRC")?,
+				reader.curve(b"gTRC")?,
+				reader.curve(b"bTRC")?,
+			],
+			matrix: mat_mul(&XYZ_D50_TO_SRGB, &to_xyz),
+		})
+	}
+
+	/// Whether this transform does (next to) nothing, i.e., the profile is sRGB
+	fn is_identity(&self) -> bool {
+		let matrix_is_identity = (0..3).all(|i| {
+			(0..3).all(|j| {
+				let identity = if i == j { 1.0 } else { 0.0 };
+				(self.matrix[i][j] - identity).abs() < SRGB_TOLERANCE
+			})
+		});
+		let curves_are_srgb = self.curves.iter().all(|curve| {
+			(0..=255).all(|v| {
+				let x = f64::from(v) / 255.0;
+				(curve.eval(x) - srgb_decode(x)).abs() < SRGB_TOLERANCE
+			})
+		});
+
+		matrix_is_identity && curves_are_srgb
+	}
+
+	/// Convert the 8-bit RGB values in `pixels` (with `stride` bytes per pixel, of which
+	/// the first three are red, green, and blue) to sRGB in place
+	fn apply(&self, pixels: &mut [u8], stride: usize) {
+		let decode_luts = [0, 1, 2].map(|c| {
+			(0..=255)
+				.map(|v| self.curves[c].eval(f64::from(v) / 255.0))
+				.collect::<Vec<_>>()
+		});
+		let encode_lut = (0..ENCODING_LUT_SIZE)
+			.map(|i| {
+				let v = srgb_encode(i as f64 / (ENCODING_LUT_SIZE - 1) as f64);
+				(v * 255.0).round() as u8
+			})
+			.collect::<Vec<_>>();
+
+		for pixel in pixels.chunks_exact_mut(stride) {
+			let linear = [0, 1, 2].map(|c| decode_luts[c][usize::from(pixel[c])]);
+			for (c, row) in self.matrix.iter().enumerate() {
+				let v = (0..3)
+					.map(|k| row[k] * linear[k])
+					.sum::<f64>()
+					.clamp(0.0, 1.0);
+				pixel[c] = encode_lut[(v * (ENCODING_LUT_SIZE - 1) as f64).round() as usize];
+			}
+		}
+	}
+}
+
+/// Iterate over the (type, data) of the chunks of the PNG `bytes`
+fn png_chunks(bytes: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+	let mut rest = bytes.get(8..).unwrap_or_default();
+	std::iter::from_fn(move || {
+		let len = match *rest.get(..4)? {
+			[a, b, c, d] => u32::from_be_bytes([a, b, c, d]) as usize,
+			_ => return None,
+		};
+		let chunk_type = rest.get(4..8)?;
+		let data = rest.get(8..8usize.checked_add(len)?)?;
+		rest = rest.get(12 + len..)?;
+		Some((chunk_type, data))
+	})
+}
+
+/// Iterate over the (marker, data) of the segments of the JPEG `bytes` that come before
+/// the image data
+fn jpeg_segments(bytes: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+	let mut rest = bytes.get(2..).unwrap_or_default();
+	std::iter::from_fn(move || loop {
+		if *rest.first()? != 0xFF {
+			return None;
+		}
+		let marker = *rest.get(1)?;
+		match marker {
+			// Fill byte
+			0xFF => rest = &rest[1..],
+			// Markers without data
+			0x01 | 0xD0..=0xD7 => rest = &rest[2..],
+			// Start of scan (after which comes the image data) and end of image
+			0xDA | 0xD9 => return None,
+			_ => {
+				let len = usize::from(u16::from_be_bytes([*rest.get(2)?, *rest.get(3)?]));
+				let data = rest.get(4..2 + len)?;
+				rest = rest.get(2 + len..)?;
+				return Some((marker, data));
+			}
+		}
+	})
+}
+
+/// The ICC profile embedded in the PNG or JPEG image `bytes`, if any
+fn embedded_profile(bytes: &[u8]) -> Result<Option<Vec<u8>>, String> {
+	if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+		let iccp = png_chunks(bytes)
+			.take_while(|(chunk_type, _)| *chunk_type != b"IDAT")
+			.find(|(chunk_type, _)| *chunk_type == b"iCCP");
+		let data = match iccp {
+			Some((_, data)) => data,
+			None => return Ok(None),
+		};
+
+		// The profile's name, a null byte, the compression method (always 0, zlib), and
+		// the compressed profile
+		let compressed = data
+			.iter()
+			.position(|&b| b == 0)
+			.and_then(|name_end| data.get(name_end + 2..))
+			.ok_or_else(|| "the iCCP chunk is malformed".to_owned())?;
+		let mut profile = Vec::new();
+		ZlibDecoder::new(compressed)
+			.take(MAX_ICC_PROFILE_BYTES as u64 + 1)
+			.read_to_end(&mut profile)
+			.map_err(|e| format!("the embedded profile could not be decompressed: {}", e))?;
+		if profile.len() > MAX_ICC_PROFILE_BYTES {
+			return Err(format!(
+				"the embedded profile is more than {} bytes once decompressed",
+				MAX_ICC_PROFILE_BYTES
+			));
+		}
+
+		Ok(Some(profile))
+	} else if bytes.starts_with(&[0xFF, 0xD8]) {
+		// The profile may be split across several APP2 segments, each of which begins
+		// with `ICC_PROFILE\0`, its sequence number, and the total number of segments
+		let mut chunks = jpeg_segments(bytes)
+			.filter(|(marker, _)| *marker == 0xE2)
+			.filter_map(|(_, data)| data.strip_prefix(b"ICC_PROFILE\0"))
+			.filter_map(|data| Some((*data.first()?, data.get(2..)?)))
+			.collect::<Vec<_>>();
+		if chunks.is_empty() {
+			return Ok(None);
+		}
+		chunks.sort_by_key(|(seq, _)| *seq);
+
+		Ok(Some(
+			chunks
+				.into_iter()
+				.flat_map(|(_, chunk)| chunk)
+				.copied()
+				.collect(),
+		))
+	} else {
+		Ok(None)
+	}
+}
+
+/// The kind of image that the image `bytes` is converted to: a JPEG is converted to a
+/// JPEG, and anything else to a PNG
+pub(crate) fn converted_kind(bytes: &[u8]) -> &'static str {
+	if bytes.starts_with(&[0xFF, 0xD8]) {
+		"jpeg"
+	} else {
+		"png"
+	}
+}
+
+/// If the PNG or JPEG image `bytes` has an embedded ICC profile other than sRGB, the
+/// image converted to sRGB, as the pair (kind, bytes) (see [`converted_kind`]). A PNG
+/// is converted to 8 bits per channel. If the image has no profile, or its profile is
+/// sRGB, `None`.
+pub(crate) fn convert_to_srgb(bytes: &[u8]) -> Result<Option<(&'static str, Vec<u8>)>, String> {
+	let profile = match embedded_profile(bytes)? {
+		Some(profile) => profile,
+		None => return Ok(None),
+	};
+	let transform = Transform::from_profile(&profile)
+		.map_err(|msg| format!("could not read the embedded color profile: {}", msg))?;
+	if transform.is_identity() {
+		return Ok(None);
+	}
+
+	let image = image::load_from_memory(bytes).map_err(|e| e.to_string())?;
+	let kind = converted_kind(bytes);
+
+	let mut converted = Vec::new();
+	let result = if kind == "jpeg" {
+		let mut rgb = image.into_rgb8();
+		transform.apply(&mut rgb, 3);
+		DynamicImage::ImageRgb8(rgb).write_to(&mut converted, ImageOutputFormat::Jpeg(JPEG_QUALITY))
+	} else {
+		let mut rgba = image.into_rgba8();
+		transform.apply(&mut rgba, 4);
+		DynamicImage::ImageRgba8(rgba).write_to(&mut converted, ImageOutputFormat::Png)
+	};
+	result.map_err(|e| e.to_string())?;
+
+	Ok(Some((kind, converted)))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+	use image::{GenericImageView, Rgba, RgbaImage};
+
+	/// The inverse of [`XYZ_D50_TO_SRGB`]
+	const SRGB_TO_XYZ_D50: Matrix = [
+		[0.436_074_7, 0.385_064_9, 0.143_080_4],
+		[0.222_504_5, 0.716_878_6, 0.060_616_9],
+		[0.013_932_2, 0.097_104_5, 0.714_173_3],
+	];
+
+	/// The sRGB curve, as a type 3 parametric curve
+	const SRGB_CURVE: [f64; 5] = [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.040_45];
+
+	/// A matrix/TRC profile whose primaries are the columns of `to_xyz` and whose curves
+	/// are all `curve`, a type 3 parametric curve
+	fn profile(color_space: &[u8; 4], to_xyz: &Matrix, curve: [f64; 5]) -> Vec<u8> {
+		let fixed = |x: f64| ((x * 65536.0).round() as i32).to_be_bytes();
+
+		let mut xyz_tags = Vec::new();
+		for j in 0..3 {
+			let mut tag = b"XYZ \0\0\0\0".to_vec();
+			for row in to_xyz {
+				tag.extend(fixed(row[j]));
+			}
+			xyz_tags.push(tag);
+		}
+		let mut curve_tag = b"para\0\0\0\0\0\x03\0\0".to_vec();
+		for param in curve {
+			curve_tag.extend(fixed(param));
+		}
+
+		let tags: [(&[u8; 4], &[u8]); 6] = [
+			(b"rXYZ", &xyz_tags[0]),
+			(b"gXYZ", &xyz_tags[1]),
+			(b"bXYZ", &xyz_tags[2]),
+			(b"rTRC", &curve_tag),
+			(b"gTRC", &curve_tag),
+			(b"bTRC", &curve_tag),
+		];
+
+		let mut header = vec![0; 128];
+		header[16..20].copy_from_slice(color_space);
+		header[20..24].copy_from_slice(b"XYZ ");
+		header[36..40].copy_from_slice(b"acsp");
+
+		let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+		let mut data = Vec::new();
+		for (sig, tag) in tags {
+			let offset = header.len() + 4 + 12 * tags.len() + data.len();
+			table.extend(sig);
+			table.extend((offset as u32).to_be_bytes());
+			table.extend((tag.len() as u32).to_be_bytes());
+			data.extend(tag);
+		}
+
+		let mut profile = [header, table, data].concat();
+		let size = (profile.len() as u32).to_be_bytes();
+		profile[..4].copy_from_slice(&size);
+		profile
+	}
+
+	/// A PNG of `pixels` (a single row) with the profile `profile` embedded in it
+	pub(crate) fn png_with_profile(pixels: &[[u8; 4]], profile: &[u8]) -> Vec<u8> {
+		let mut image = RgbaImage::new(pixels.len() as u32, 1);
+		for (x, pixel) in pixels.iter().enumerate() {
+			image.put_pixel(x as u32, 0, Rgba(*pixel));
+		}
+		let mut png = Vec::new();
+		DynamicImage::ImageRgba8(image)
+			.write_to(&mut png, ImageOutputFormat::Png)
+			.unwrap();
+
+		let mut iccp_data = b"test\0\0".to_vec();
+		let mut encoder =
+			flate2::write::ZlibEncoder::new(&mut iccp_data, flate2::Compression::default());
+		std::io::Write::write_all(&mut encoder, profile).unwrap();
+		encoder.finish().unwrap();
+
+		let mut chunk = (iccp_data.len() as u32).to_be_bytes().to_vec();
+		let mut crc = flate2::Crc::new();
+		crc.update(b"iCCP");
+		crc.update(&iccp_data);
+		chunk.extend(b"iCCP");
+		chunk.extend(&iccp_data);
+		chunk.extend(crc.sum().to_be_bytes());
+
+		// After the signature (8 bytes) and IHDR chunk (25 bytes)
+		[&png[..33], &chunk, &png[33..]].concat()
+	}
+
+	/// A profile for an RGB color space that's sRGB with red and green swapped
+	pub(crate) fn swapped_profile() -> Vec<u8> {
+		let m = SRGB_TO_XYZ_D50;
+		let swapped = [
+			[m[0][1], m[0][0], m[0][2]],
+			[m[1][1], m[1][0], m[1][2]],
+			[m[2][1], m[2][0], m[2][2]],
+		];
+		profile(b"RGB ", &swapped, SRGB_CURVE)
+	}
+
+	fn pixels(png: &[u8]) -> Vec<[u8; 4]> {
+		image::load_from_memory(png)
+			.unwrap()
+			.pixels()
+			.map(|(_, _, Rgba(pixel))| pixel)
+			.collect()
+	}
+
+	#[test]
+	fn conversion() {
+		let original = [[255, 0, 0, 255], [0, 64, 200, 128]];
+
+		// Red and green are swapped back; alpha is unaffected
+		let png = png_with_profile(&original, &swapped_profile());
+		let (kind, converted) = convert_to_srgb(&png).unwrap().unwrap();
+		assert_eq!(kind, "png");
+		let converted = pixels(&converted);
+		for (expected, actual) in [[0_u8, 255, 0, 255], [64, 0, 200, 128]]
+			.iter()
+			.zip(&converted)
+		{
+			for (e, a) in expected.iter().zip(actual) {
+				assert!(
+					(i16::from(*e) - i16::from(*a)).abs() <= 1,
+					"{:?}",
+					converted
+				);
+			}
+		}
+
+		// Images that are already sRGB, or have no profile, are left alone
+		let srgb = profile(b"RGB ", &SRGB_TO_XYZ_D50, SRGB_CURVE);
+		assert_eq!(
+			convert_to_srgb(&png_with_profile(&original, &srgb)).unwrap(),
+			None
+		);
+		let mut plain = Vec::new();
+		DynamicImage::ImageRgba8(RgbaImage::new(1, 1))
+			.write_to(&mut plain, ImageOutputFormat::Png)
+			.unwrap();
+		assert_eq!(convert_to_srgb(&plain).unwrap(), None);
+
+		// Unsupported profiles are an error
+		let cmyk = profile(b"CMYK", &SRGB_TO_XYZ_D50, SRGB_CURVE);
+		assert!(convert_to_srgb(&png_with_profile(&original, &cmyk)).is_err());
+		let truncated = &srgb[..200];
+		assert!(convert_to_srgb(&png_with_profile(&original, truncated)).is_err());
+	}
+
+	#[test]
+	fn jpeg_profile() {
+		let srgb = profile(b"RGB ", &SRGB_TO_XYZ_D50, SRGB_CURVE);
+		let (first, second) = srgb.split_at(100);
+
+		let app2 = |seq: u8, chunk: &[u8]| {
+			let mut data = b"ICC_PROFILE\0".to_vec();
+			data.extend([seq, 2]);
+			data.extend(chunk);
+			let mut segment = vec![0xFF, 0xE2];
+			segment.extend(((data.len() + 2) as u16).to_be_bytes());
+			segment.extend(data);
+			segment
+		};
+		// Segments out of order, then the start of scan
+		let jpeg = [
+			&[0xFF, 0xD8][..],
+			&app2(2, second),
+			&app2(1, first),
+			&[0xFF, 0xDA],
+		]
+		.concat();
+
+		assert_eq!(embedded_profile(&jpeg).unwrap(), Some(srgb));
+	}
+
+	#[test]
+	fn oversized_profile() {
+		let pixels = [[0, 0, 0, 255]];
+		let max_profile = vec![0; MAX_ICC_PROFILE_BYTES];
+		let oversized_profile = vec![0; MAX_ICC_PROFILE_BYTES + 1];
+
+		assert_eq!(
+			embedded_profile(&png_with_profile(&pixels, &max_profile)).unwrap(),
+			Some(max_profile)
+		);
+		assert!(embedded_profile(&png_with_profile(&pixels, &oversized_profile)).is_err());
+	}
+}
@@ -0,0 +1,121 @@
+//! Where a skeleton's files are read from. Usually that's the file system, but a
+//! skeleton can also be given as a map from the paths of its files (relative to the
+//! skeleton's folder) to their contents, e.g., by `clgn daemon`'s `files`, in which case
+//! nothing is read from disk. Every read of a skeleton's files — its manifest, the
+//! fragments it includes, and the images and fonts it embeds — goes through a
+//! [`FileSource`].
+
+use super::paths::normalize_lexically;
+use crate::{to_svg::svg_writable::ClgnDecodingError, ClgnDecodingResult};
+use std::collections::BTreeMap as Map;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[derive(Debug, Clone)]
+pub(crate) enum FileSource {
+	Disk,
+	/// The contents of each file, keyed by its path with `.` and `..` normalized away
+	/// (see [`normalize_lexically`]). Paths not in the map don't exist.
+	InMemory(Rc<Map<PathBuf, Vec<u8>>>),
+}
+
+impl Default for FileSource {
+	fn default() -> Self {
+		Self::Disk
+	}
+}
+
+impl FileSource {
+	pub(crate) fn in_memory(files: impl IntoIterator<Item = (PathBuf, Vec<u8>)>) -> Self {
+		Self::InMemory(Rc::new(
+			files
+				.into_iter()
+				.map(|(path, contents)| (normalize_lexically(&path), contents))
+				.collect(),
+		))
+	}
+
+	/// The contents of the file at `path`, like [`std::fs::read`]
+	pub(crate) fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+		match self {
+			Self::Disk => std::fs::read(path),
+			Self::InMemory(files) => files
+				.get(&normalize_lexically(path))
+				.cloned()
+				.ok_or_else(|| io::ErrorKind::NotFound.into()),
+		}
+	}
+
+	/// Whether there's a file at `path`, like [`Path::exists`]
+	#[cfg_attr(not(feature = "json5"), allow(dead_code))]
+	pub(crate) fn exists(&self, path: &Path) -> bool {
+		match self {
+			Self::Disk => path.exists(),
+			Self::InMemory(files) => files.contains_key(&normalize_lexically(path)),
+		}
+	}
+
+	/// The contents of the file at `path`, or, if it can't be read, the error that says
+	/// so (see [`FileSource::read_error`])
+	pub(crate) fn read_or_err(&self, path: &Path) -> ClgnDecodingResult<Vec<u8>> {
+		self.read(path)
+			.map_err(|e| self.read_error(e, path.to_owned()))
+	}
+
+	/// The error for having failed to read the file at `path` with `err`. Like
+	/// [`ClgnDecodingError::read`], except that the near misses of a missing in-memory
+	/// file aren't looked for on disk.
+	pub(crate) fn read_error(&self, err: io::Error, path: PathBuf) -> ClgnDecodingError {
+		match self {
+			Self::Disk => ClgnDecodingError::read(err, path),
+			Self::InMemory(_) if err.kind() == io::ErrorKind::NotFound => {
+				ClgnDecodingError::MissingPath {
+					path,
+					near_misses: Vec::new(),
+				}
+			}
+			Self::InMemory(_) => ClgnDecodingError::IoRead(err, path),
+		}
+	}
+
+	/// `path` in a canonical form, so that different paths to the same file compare
+	/// equal. Falls back to `path` itself if it can't be canonicalized.
+	pub(crate) fn canonicalize(&self, path: PathBuf) -> PathBuf {
+		match self {
+			Self::Disk => std::fs::canonicalize(&path).unwrap_or(path),
+			Self::InMemory(_) => normalize_lexically(&path),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn in_memory() {
+		let files = FileSource::in_memory(vec![
+			(PathBuf::from("collagen.json"), b"{}".to_vec()),
+			(PathBuf::from("./images/../images/cat.png"), b"cat".to_vec()),
+		]);
+
+		assert_eq!(files.read(Path::new("collagen.json")).unwrap(), b"{}");
+		assert_eq!(files.read(Path::new("images/cat.png")).unwrap(), b"cat");
+		assert_eq!(files.read(Path::new("images/./cat.png")).unwrap(), b"cat");
+		assert_eq!(
+			files.canonicalize(PathBuf::from("images/../collagen.json")),
+			PathBuf::from("collagen.json")
+		);
+
+		assert!(files.exists(Path::new("./collagen.json")));
+		assert!(!files.exists(Path::new("images")));
+
+		// Even if it exists on disk, a file not in the map doesn't exist
+		assert!(!files.exists(Path::new("Cargo.toml")));
+		assert!(matches!(
+			files.read_or_err(Path::new("Cargo.toml")),
+			Err(ClgnDecodingError::MissingPath { near_misses, .. }) if near_misses.is_empty()
+		));
+	}
+}
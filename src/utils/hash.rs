@@ -0,0 +1,39 @@
+//! A stable content hash, for naming cache entries and fingerprinting renders. The
+//! standard library's hashers aren't guaranteed to be stable across Rust versions, which
+//! would make a hash computed by one build of `clgn` meaningless to the next.
+
+const FNV_OFFSET_BASIS: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+const FNV_PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+/// A 128-bit FNV-1a hasher
+#[derive(Debug, Clone)]
+pub(crate) struct ContentHasher {
+	hash: u128,
+}
+
+impl ContentHasher {
+	pub(crate) fn new() -> Self {
+		Self {
+			hash: FNV_OFFSET_BASIS,
+		}
+	}
+
+	/// Hash `bytes` as-is
+	pub(crate) fn write_raw(&mut self, bytes: &[u8]) {
+		for &byte in bytes {
+			self.hash ^= u128::from(byte);
+			self.hash = self.hash.wrapping_mul(FNV_PRIME);
+		}
+	}
+
+	/// Hash `input` along with its length, so that, e.g., the inputs `"ab"` and `"c"`
+	/// hash differently from `"a"` and `"bc"`
+	pub(crate) fn write_input(&mut self, input: &[u8]) {
+		self.write_raw(&(input.len() as u64).to_le_bytes());
+		self.write_raw(input);
+	}
+
+	pub(crate) fn finish(&self) -> u128 {
+		self.hash
+	}
+}
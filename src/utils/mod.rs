@@ -1 +1,5 @@
+pub(crate) mod cache;
+pub(crate) mod color_profile;
+pub(crate) mod file_source;
+pub(crate) mod hash;
 pub(crate) mod paths;
@@ -1,7 +1,16 @@
-use std::path::{Path, PathBuf};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
 
 use crate::{to_svg::svg_writable::ClgnDecodingError, ClgnDecodingResult};
 
+/// The most near misses that [`near_misses`] returns
+const MAX_NEAR_MISSES: usize = 5;
+
+/// The length, in UTF-16 code units, at which paths stop working on Windows unless they
+/// have the `\\?\` prefix
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
 /// Like `p.as_ref().join(s.as_ref())` (see
 /// [`std::path::PathBuf::join()`](https://doc.rust-lang.org/std/path/struct.PathBuf.html#method.join)),
 /// except that this converts forward slashes in `s` to platform-specific the path
@@ -34,7 +43,202 @@ pub(crate) fn pathsep_aware_join(
 	for part in s.split('/') {
 		p.push(part)
 	}
-	Ok(p)
+	Ok(long_path_safe(p))
+}
+
+/// `path` with its `.` components removed and each `..` component that follows a named
+/// component removed along with it, without consulting the file system. (So if a
+/// component before a `..` is a symlink, the result may refer to a different file.)
+pub(crate) fn normalize_lexically(path: &Path) -> PathBuf {
+	let mut normalized = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::CurDir => {}
+			Component::ParentDir => match normalized.components().next_back() {
+				Some(Component::Normal(_)) => {
+					normalized.pop();
+				}
+				// The parent of the root is the root
+				Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+				Some(Component::ParentDir) | Some(Component::CurDir) | None => {
+					normalized.push(component)
+				}
+			},
+			_ => normalized.push(component),
+		}
+	}
+
+	normalized
+}
+
+/// `path`, in a form that can be opened even if it's too long for Windows' `MAX_PATH`.
+/// On Windows, a path that long is made absolute, normalized (see
+/// [`normalize_lexically`]; Windows normalizes shorter paths itself, but not those with
+/// the prefix), and given the `\\?\` prefix (`\\?\UNC\` for a network path). Other
+/// paths, and all paths on other platforms, are returned unchanged.
+pub(crate) fn long_path_safe(path: PathBuf) -> PathBuf {
+	#[cfg(windows)]
+	{
+		use std::ffi::OsString;
+		use std::os::windows::ffi::OsStrExt;
+		use std::path::Prefix;
+
+		if path.as_os_str().encode_wide().count() < MAX_PATH {
+			return path;
+		}
+
+		let abs_path = if path.is_absolute() {
+			path
+		} else {
+			match std::env::current_dir() {
+				Ok(dir) => dir.join(path),
+				Err(_) => return path,
+			}
+		};
+		let normalized = normalize_lexically(&abs_path);
+
+		let mut components = normalized.components();
+		let mut long_path = match components.next() {
+			Some(Component::Prefix(prefix)) => match prefix.kind() {
+				Prefix::Disk(_) => {
+					let mut long_path = OsString::from(r"\\?\");
+					long_path.push(prefix.as_os_str());
+					long_path.push(r"\");
+					PathBuf::from(long_path)
+				}
+				Prefix::UNC(server, share) => {
+					let mut long_path = OsString::from(r"\\?\UNC\");
+					long_path.push(server);
+					long_path.push(r"\");
+					long_path.push(share);
+					long_path.push(r"\");
+					PathBuf::from(long_path)
+				}
+				// Already verbatim, or a device path
+				_ => return normalized,
+			},
+			_ => return normalized,
+		};
+		for component in components {
+			if let Component::Normal(part) = component {
+				long_path.push(part);
+			}
+		}
+
+		long_path
+	}
+
+	#[cfg(not(windows))]
+	path
+}
+
+/// Write `path` to `writer`, followed by a newline. On Unix, a path that isn't valid
+/// UTF-8 is written byte for byte, so that a script reading it gets the path itself;
+/// elsewhere, such a path is written with its invalid parts replaced.
+pub(crate) fn write_path_line(mut writer: impl Write, path: &Path) -> io::Result<()> {
+	#[cfg(unix)]
+	{
+		use std::os::unix::ffi::OsStrExt;
+		writer.write_all(path.as_os_str().as_bytes())?;
+	}
+	#[cfg(not(unix))]
+	write!(writer, "{}", path.display())?;
+
+	writeln!(writer)
+}
+
+/// The number of single-character insertions, deletions, and substitutions it takes to
+/// turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+	let b = b.chars().collect::<Vec<_>>();
+	let mut prev_row = (0..=b.len()).collect::<Vec<_>>();
+	let mut row = vec![0; b.len() + 1];
+
+	for (i, a_char) in a.chars().enumerate() {
+		row[0] = i + 1;
+		for (j, &b_char) in b.iter().enumerate() {
+			let substitution = prev_row[j] + usize::from(a_char != b_char);
+			row[j + 1] = substitution.min(prev_row[j + 1] + 1).min(row[j] + 1);
+		}
+		std::mem::swap(&mut row, &mut prev_row);
+	}
+
+	prev_row[b.len()]
+}
+
+/// How closely the file name `candidate` matches `name`, the name of a missing file, or
+/// `None` if it's not close enough to suggest. Lower is closer. Names are compared
+/// case-insensitively, and a candidate with the same stem (e.g., `cat.jpg` for
+/// `cat.png`) is always close enough.
+fn near_miss_distance(name: &str, candidate: &str) -> Option<usize> {
+	let (name, candidate) = (name.to_lowercase(), candidate.to_lowercase());
+	let stem = |s: &str| -> String {
+		match s.rfind('.') {
+			Some(i) if i > 0 => s[..i].to_owned(),
+			_ => s.to_owned(),
+		}
+	};
+
+	let distance = edit_distance(&name, &candidate);
+	let max_distance = (name.chars().count() / 3).max(1);
+	if distance <= max_distance || stem(&name) == stem(&candidate) {
+		Some(distance)
+	} else {
+		None
+	}
+}
+
+/// The existing paths whose names are closest to that of the missing `path`, closest
+/// first, for suggesting in an error message. Only the first component of `path` that
+/// doesn't exist is compared against its siblings; e.g., if `path` is
+/// `skeleton/imgaes/cat.png` and the folder is actually `images`, then
+/// `skeleton/images/cat.png` is suggested (if it exists).
+pub(crate) fn near_misses(path: &Path) -> Vec<PathBuf> {
+	let mut existing = path;
+	let mut missing = Vec::new();
+	while !existing.exists() {
+		match (existing.parent(), existing.file_name()) {
+			(Some(parent), Some(name)) => {
+				missing.push(name);
+				existing = parent;
+			}
+			_ => return Vec::new(),
+		}
+	}
+	let (name, rest) = match missing.split_last() {
+		Some((name, rest)) => (name.to_string_lossy(), rest),
+		None => return Vec::new(),
+	};
+
+	let dir = if existing.as_os_str().is_empty() {
+		Path::new(".")
+	} else {
+		existing
+	};
+	let entries = match std::fs::read_dir(dir) {
+		Ok(entries) => entries,
+		Err(_) => return Vec::new(),
+	};
+
+	let mut candidates = entries
+		.filter_map(|entry| {
+			let entry_name = entry.ok()?.file_name();
+			let distance = near_miss_distance(&name, &entry_name.to_string_lossy())?;
+
+			let mut candidate = existing.join(entry_name);
+			for component in rest.iter().rev() {
+				candidate.push(component);
+			}
+			candidate.exists().then(|| (distance, candidate))
+		})
+		.collect::<Vec<_>>();
+	candidates.sort();
+
+	candidates
+		.into_iter()
+		.take(MAX_NEAR_MISSES)
+		.map(|(_, candidate)| candidate)
+		.collect()
 }
 
 #[cfg(test)]
@@ -255,6 +459,73 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn normalize() {
+		for (path, expected) in vec![
+			("a/./b/../c", "a/c"),
+			("./a/b/../../..", ".."),
+			("../../a/..", "../.."),
+			("/a/../..", "/"),
+			("/a/b/", "/a/b"),
+		]
+		.into_iter()
+		{
+			assert_eq!(
+				normalize_lexically(Path::new(path)),
+				PathBuf::from(expected),
+				"{}",
+				path
+			);
+		}
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn non_utf8_paths() {
+		use std::ffi::OsStr;
+		use std::os::unix::ffi::OsStrExt;
+
+		let path = Path::new(OsStr::from_bytes(b"dir/caf\xe9"));
+		let mut line = Vec::new();
+		write_path_line(&mut line, path).unwrap();
+		assert_eq!(line, b"dir/caf\xe9\n");
+
+		assert_eq!(
+			pathsep_aware_join(path, "a/b").unwrap(),
+			path.join("a").join("b")
+		);
+		assert_eq!(long_path_safe(path.to_owned()), path);
+	}
+
+	#[test]
+	fn near_misses() {
+		assert_eq!(edit_distance("", "abc"), 3);
+		assert_eq!(edit_distance("kitten", "sitting"), 3);
+		assert_eq!(edit_distance("cat.png", "cat.png"), 0);
+
+		let dir = tempfile::tempdir().unwrap();
+		let dir = dir.path();
+		std::fs::create_dir(dir.join("images")).unwrap();
+		for name in ["Cat.png", "cat.jpg", "bat.png", "dog.png", "images/cat.png"] {
+			std::fs::write(dir.join(name), "").unwrap();
+		}
+
+		assert_eq!(
+			super::near_misses(&dir.join("cat.png")),
+			vec![
+				dir.join("Cat.png"),
+				dir.join("bat.png"),
+				dir.join("cat.jpg")
+			]
+		);
+		assert_eq!(
+			super::near_misses(&dir.join("imgaes").join("cat.png")),
+			vec![dir.join("images").join("cat.png")]
+		);
+		assert!(super::near_misses(&dir.join("imgaes").join("dog.png")).is_empty());
+		assert!(super::near_misses(&dir.join("zebra.svg")).is_empty());
+	}
+
 	mod errors {
 		use super::*;
 
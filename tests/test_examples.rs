@@ -129,3 +129,25 @@ test_input_output!(
 	"./tests/examples/drake-manually-specified-font"
 );
 test_input_output!(drake_no_font, "./tests/examples/drake-no-font");
+test_input_output!(text_wrap, "./tests/examples/text-wrap");
+test_input_output!(shapes, "./tests/examples/shapes");
+test_input_output!(connectors, "./tests/examples/connectors");
+test_input_output!(z_order, "./tests/examples/z-order");
+test_input_output!(style, "./tests/examples/style");
+test_input_output!(relative_units, "./tests/examples/relative-units");
+test_input_output!(palette, "./tests/examples/palette");
+test_input_output!(dark_mode, "./tests/examples/dark-mode");
+test_input_output!(animation, "./tests/examples/animation");
+test_input_output!(sprite_sheet, "./tests/examples/sprite-sheet");
+test_input_output!(class_styles, "./tests/examples/class-styles");
+test_input_output!(raw_text, "./tests/examples/raw-text");
+test_input_output!(xml_declaration, "./tests/examples/xml-declaration");
+test_input_output!(namespaces, "./tests/examples/namespaces");
+test_input_output!(clip, "./tests/examples/clip");
+test_input_output!(repeat, "./tests/examples/repeat");
+test_input_output!(include, "./tests/examples/include");
+test_input_output!(rtl_text, "./tests/examples/rtl-text");
+test_input_output!(vertical_text, "./tests/examples/vertical-text");
+test_input_output!(links, "./tests/examples/links");
+test_input_output!(symbol_library, "./tests/examples/symbol-library");
+test_input_output!(exported_sizes, "./tests/examples/exported-sizes");